@@ -0,0 +1,145 @@
+//! Advisory file locks on disk.
+//!
+//! std only locks files since Rust 1.89, so we lock them the same way it does: with `flock(2)` on
+//! Unix and `LockFileEx` on Windows. Locks are held by the open file and released when it is
+//! unlocked or closed.
+
+pub(crate) use self::sys::{lock, try_lock, unlock};
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::raw::c_int;
+    use std::os::unix::io::AsRawFd;
+
+    use fs::TryLockError;
+
+    const LOCK_SH: c_int = 1;
+    const LOCK_EX: c_int = 2;
+    const LOCK_NB: c_int = 4;
+    const LOCK_UN: c_int = 8;
+
+    extern "C" {
+        fn flock(fd: c_int, operation: c_int) -> c_int;
+    }
+
+    // flock_file retries operation on file until it is not interrupted by a signal.
+    fn flock_file(file: &File, operation: c_int) -> Result<()> {
+        loop {
+            if unsafe { flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(());
+            }
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    fn operation(exclusive: bool) -> c_int {
+        if exclusive { LOCK_EX } else { LOCK_SH }
+    }
+
+    pub(crate) fn lock(file: &File, exclusive: bool) -> Result<()> {
+        flock_file(file, operation(exclusive))
+    }
+
+    pub(crate) fn try_lock(file: &File, exclusive: bool)
+        -> ::std::result::Result<(), TryLockError>
+    {
+        flock_file(file, operation(exclusive) | LOCK_NB).map_err(|err| {
+            if err.kind() == ErrorKind::WouldBlock {
+                TryLockError::WouldBlock
+            } else {
+                TryLockError::Error(err)
+            }
+        })
+    }
+
+    pub(crate) fn unlock(file: &File) -> Result<()> {
+        flock_file(file, LOCK_UN)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs::File;
+    use std::io::{Error, Result};
+    use std::os::raw::c_void;
+    use std::os::windows::io::AsRawHandle;
+    use std::ptr;
+
+    use fs::TryLockError;
+
+    const LOCKFILE_FAIL_IMMEDIATELY: u32 = 1;
+    const LOCKFILE_EXCLUSIVE_LOCK: u32 = 2;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+    const ERROR_IO_PENDING: i32 = 997;
+
+    // Overlapped is OVERLAPPED with its offset union spelled as the offset. Locks cover the whole
+    // file, from offset zero.
+    #[repr(C)]
+    struct Overlapped {
+        internal:      usize,
+        internal_high: usize,
+        offset:        u32,
+        offset_high:   u32,
+        event:         *mut c_void,
+    }
+
+    extern "system" {
+        fn LockFileEx(file: *mut c_void, flags: u32, reserved: u32, len_low: u32, len_high: u32,
+                      overlapped: *mut Overlapped) -> i32;
+        fn UnlockFile(file: *mut c_void, offset_low: u32, offset_high: u32, len_low: u32,
+                      len_high: u32) -> i32;
+    }
+
+    fn lock_file(file: &File, flags: u32) -> Result<()> {
+        let mut overlapped = Overlapped {
+            internal:      0,
+            internal_high: 0,
+            offset:        0,
+            offset_high:   0,
+            event:         ptr::null_mut(),
+        };
+        let ret = unsafe {
+            LockFileEx(file.as_raw_handle() as *mut c_void, flags, 0, u32::MAX, u32::MAX,
+                       &mut overlapped)
+        };
+        if ret == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn flags(exclusive: bool) -> u32 {
+        if exclusive { LOCKFILE_EXCLUSIVE_LOCK } else { 0 }
+    }
+
+    pub(crate) fn lock(file: &File, exclusive: bool) -> Result<()> {
+        lock_file(file, flags(exclusive))
+    }
+
+    pub(crate) fn try_lock(file: &File, exclusive: bool)
+        -> ::std::result::Result<(), TryLockError>
+    {
+        lock_file(file, flags(exclusive) | LOCKFILE_FAIL_IMMEDIATELY).map_err(|err| {
+            match err.raw_os_error() {
+                Some(ERROR_LOCK_VIOLATION) | Some(ERROR_IO_PENDING) => TryLockError::WouldBlock,
+                _ => TryLockError::Error(err),
+            }
+        })
+    }
+
+    // unlock releases the lock on the whole file.
+    pub(crate) fn unlock(file: &File) -> Result<()> {
+        let ret = unsafe {
+            UnlockFile(file.as_raw_handle() as *mut c_void, 0, 0, u32::MAX, u32::MAX)
+        };
+        if ret == 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,946 @@
+//! A zero cost wrapper around [`std::fs`].
+//!
+//! The [`FS`] struct is an empty struct. All methods on it use `std::fs` functions. The intent of
+//! this module is to set the filesystem you use to `rsfs::disk::FS` in `main.rs` and to set the
+//! filesystem to `rsfs::mem::test::FS` in your tests.
+//!
+//! Every type in this module wraps the corresponding type in `std::fs`, meaning the behavior (and
+//! errors) of this module is exactly what the standard library provides.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::{Read, Write};
+//!
+//! use rsfs::*;
+//! # fn foo() -> std::io::Result<()> {
+//! let fs = rsfs::disk::FS;
+//!
+//! let mut wf = fs.create_file("hello.txt")?;
+//! wf.write_all(b"hello")?;
+//!
+//! let mut rf = fs.open_file("hello.txt")?;
+//! let mut output = String::new();
+//! rf.read_to_string(&mut output)?;
+//! assert_eq!(output, "hello");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`std::fs`]: https://doc.rust-lang.org/std/fs/
+//! [`FS`]: struct.FS.html
+
+use std::env;
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs as rs_fs;
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, DirEntryExt, FileExt, FileTypeExt, MetadataExt,
+                        OpenOptionsExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use fs;
+#[cfg(unix)]
+use unix_ext;
+#[cfg(windows)]
+use windows_ext;
+
+mod lock;
+#[cfg(unix)]
+mod node;
+#[cfg(unix)]
+mod sparse;
+#[cfg(unix)]
+mod xattr;
+
+/// A builder used to create directories in various manners.
+///
+/// This builder wraps [`std::fs::DirBuilder`], implements [`rsfs::DirBuilder`] and supports
+/// [unix extensions].
+///
+/// [`std::fs::DirBuilder`]: https://doc.rust-lang.org/std/fs/struct.DirBuilder.html
+/// [`rsfs::DirBuilder`]: ../trait.DirBuilder.html
+/// [unix extensions]: ../unix_ext/trait.DirBuilderExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = rsfs::disk::FS;
+/// let db = fs.new_dirbuilder();
+/// db.create("dir")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DirBuilder(rs_fs::DirBuilder);
+
+impl fs::DirBuilder for DirBuilder {
+    fn recursive(&mut self, recursive: bool) -> &mut Self {
+        self.0.recursive(recursive); self
+    }
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.0.create(path)
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::DirBuilderExt for DirBuilder {
+    fn mode(&mut self, mode: u32) -> &mut Self {
+        self.0.mode(mode); self
+    }
+}
+
+/// Entries returned by the [`ReadDir`] iterator.
+///
+/// An instance of `DirEntry` wraps [`std::fs::DirEntry`], implements [`rsfs::DirEntry`] and
+/// represents an entry inside a directory on disk. It also has [unix extensions].
+///
+/// [`ReadDir`]: struct.ReadDir.html
+/// [`std::fs::DirEntry`]: https://doc.rust-lang.org/std/fs/struct.DirEntry.html
+/// [`rsfs::DirEntry`]: ../trait.DirEntry.html
+/// [unix extensions]: ../unix_ext/trait.DirEntryExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = rsfs::disk::FS;
+/// for entry in fs.read_dir(".")? {
+///     let entry = entry?;
+///     println!("{:?}: {:?}", entry.path(), entry.metadata()?.permissions());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DirEntry(rs_fs::DirEntry);
+
+impl fs::DirEntry for DirEntry {
+    type Metadata = Metadata;
+    type FileType = FileType;
+
+    fn path(&self) -> PathBuf {
+        self.0.path()
+    }
+    fn metadata(&self) -> Result<Self::Metadata> {
+        self.0.metadata().map(Metadata)
+    }
+    fn file_type(&self) -> Result<Self::FileType> {
+        self.0.file_type().map(FileType)
+    }
+    fn file_name(&self) -> OsString {
+        self.0.file_name()
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::DirEntryExt for DirEntry {
+    fn ino(&self) -> u64 {
+        self.0.ino()
+    }
+}
+
+/// A view into a file on disk.
+///
+/// An instance of `File` wraps [`std::fs::File`] and can be read or written to depending on the
+/// options it was opened with. Files also implement `Seek` to alter the logical cursor position
+/// of the internal file.
+///
+/// This struct implements [`rsfs::File`] and has [unix extensions].
+///
+/// [`std::fs::File`]: https://doc.rust-lang.org/std/fs/struct.File.html
+/// [`rsfs::File`]: ../trait.File.html
+/// [unix extensions]: ../unix_ext/trait.FileExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use std::io::Write;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = rsfs::disk::FS;
+/// let mut f = fs.create_file("f")?;
+/// assert_eq!(f.write(&[1, 2, 3])?, 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct File(rs_fs::File);
+
+impl fs::File for File {
+    type Metadata    = Metadata;
+    type Permissions = Permissions;
+
+    fn sync_all(&self) -> Result<()> {
+        self.0.sync_all()
+    }
+    fn sync_data(&self) -> Result<()> {
+        self.0.sync_data()
+    }
+    fn set_len(&self, size: u64) -> Result<()> {
+        self.0.set_len(size)
+    }
+    fn metadata(&self) -> Result<Self::Metadata> {
+        self.0.metadata().map(Metadata)
+    }
+    fn try_clone(&self) -> Result<Self> {
+        self.0.try_clone().map(File)
+    }
+    fn set_permissions(&self, perm: Self::Permissions) -> Result<()> {
+        self.0.set_permissions(perm.0)
+    }
+    fn set_times(&self, times: fs::FileTimes) -> Result<()> {
+        self.0.set_times(std_times(times))
+    }
+    fn lock(&self) -> Result<()> {
+        lock::lock(&self.0, true)
+    }
+    fn lock_shared(&self) -> Result<()> {
+        lock::lock(&self.0, false)
+    }
+    fn try_lock(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        lock::try_lock(&self.0, true)
+    }
+    fn try_lock_shared(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        lock::try_lock(&self.0, false)
+    }
+    fn unlock(&self) -> Result<()> {
+        lock::unlock(&self.0)
+    }
+}
+
+fn std_times(times: fs::FileTimes) -> rs_fs::FileTimes {
+    let mut std = rs_fs::FileTimes::new();
+    if let Some(t) = times.accessed() {
+        std = std.set_accessed(t);
+    }
+    if let Some(t) = times.modified() {
+        std = std.set_modified(t);
+    }
+    std
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+}
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl Read for &File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (&self.0).read(buf)
+    }
+}
+impl Write for &File {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (&self.0).write(buf)
+    }
+    fn flush(&mut self) -> Result<()> {
+        (&self.0).flush()
+    }
+}
+impl Seek for &File {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        (&self.0).seek(pos)
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::FileExt for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.0.read_at(buf, offset)
+    }
+    fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        self.0.write_at(buf, offset)
+    }
+    fn seek_sparse(&self, pos: unix_ext::SparseSeek) -> Result<u64> {
+        sparse::seek(self.0.as_raw_fd(), pos)
+    }
+    fn get_xattr<N: AsRef<OsStr>>(&self, name: N) -> Result<Vec<u8>> {
+        xattr::get(xattr::Target::Fd(self.0.as_raw_fd()), name.as_ref())
+    }
+    fn set_xattr<N: AsRef<OsStr>>(&self, name: N, value: &[u8]) -> Result<()> {
+        xattr::set(xattr::Target::Fd(self.0.as_raw_fd()), name.as_ref(), value)
+    }
+    fn list_xattr(&self) -> Result<Vec<OsString>> {
+        xattr::list(xattr::Target::Fd(self.0.as_raw_fd()))
+    }
+    fn remove_xattr<N: AsRef<OsStr>>(&self, name: N) -> Result<()> {
+        xattr::remove(xattr::Target::Fd(self.0.as_raw_fd()), name.as_ref())
+    }
+}
+
+/// Returned from [`Metadata::file_type`], this structure represents the type of a file.
+///
+/// This structure wraps [`std::fs::FileType`] and implements [`rsfs::FileType`].
+///
+/// [`Metadata::file_type`]: ../trait.Metadata.html#tymethod.file_type
+/// [`std::fs::FileType`]: https://doc.rust-lang.org/std/fs/struct.FileType.html
+/// [`rsfs::FileType`]: ../trait.FileType.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = rsfs::disk::FS;
+/// assert!(fs.metadata(".")?.file_type().is_dir());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileType(rs_fs::FileType);
+
+impl fs::FileType for FileType {
+    fn is_dir(&self) -> bool {
+        self.0.is_dir()
+    }
+    fn is_file(&self) -> bool {
+        self.0.is_file()
+    }
+    fn is_symlink(&self) -> bool {
+        self.0.is_symlink()
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::FileTypeExt for FileType {
+    fn is_block_device(&self) -> bool {
+        self.0.is_block_device()
+    }
+    fn is_char_device(&self) -> bool {
+        self.0.is_char_device()
+    }
+    fn is_fifo(&self) -> bool {
+        self.0.is_fifo()
+    }
+    fn is_socket(&self) -> bool {
+        self.0.is_socket()
+    }
+}
+
+#[cfg(windows)]
+impl windows_ext::FileTypeExt for FileType {
+    fn is_symlink_dir(&self) -> bool {
+        ::std::os::windows::fs::FileTypeExt::is_symlink_dir(&self.0)
+    }
+    fn is_symlink_file(&self) -> bool {
+        ::std::os::windows::fs::FileTypeExt::is_symlink_file(&self.0)
+    }
+}
+
+/// Metadata information about a file.
+///
+/// This structure wraps [`std::fs::Metadata`], implements [`rsfs::Metadata`] and has
+/// [unix extensions].
+///
+/// [`std::fs::Metadata`]: https://doc.rust-lang.org/std/fs/struct.Metadata.html
+/// [`rsfs::Metadata`]: ../trait.Metadata.html
+/// [unix extensions]: ../unix_ext/trait.MetadataExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = rsfs::disk::FS;
+/// println!("{:?}", fs.metadata(".")?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Metadata(rs_fs::Metadata);
+
+impl fs::Metadata for Metadata {
+    type Permissions = Permissions;
+    type FileType    = FileType;
+
+    fn file_type(&self) -> Self::FileType {
+        FileType(self.0.file_type())
+    }
+    fn is_dir(&self) -> bool {
+        self.0.is_dir()
+    }
+    fn is_file(&self) -> bool {
+        self.0.is_file()
+    }
+    fn len(&self) -> u64 {
+        self.0.len()
+    }
+    fn permissions(&self) -> Self::Permissions {
+        Permissions(self.0.permissions())
+    }
+    fn modified(&self) -> Result<SystemTime> {
+        self.0.modified()
+    }
+    fn accessed(&self) -> Result<SystemTime> {
+        self.0.accessed()
+    }
+    fn created(&self) -> Result<SystemTime> {
+        self.0.created()
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::MetadataExt for Metadata {
+    fn dev(&self) -> u64 {
+        self.0.dev()
+    }
+    fn ino(&self) -> u64 {
+        self.0.ino()
+    }
+    fn mode(&self) -> u32 {
+        self.0.mode()
+    }
+    fn nlink(&self) -> u64 {
+        self.0.nlink()
+    }
+    fn uid(&self) -> u32 {
+        self.0.uid()
+    }
+    fn gid(&self) -> u32 {
+        self.0.gid()
+    }
+    fn rdev(&self) -> u64 {
+        self.0.rdev()
+    }
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+    fn atime(&self) -> i64 {
+        self.0.atime()
+    }
+    fn atime_nsec(&self) -> i64 {
+        self.0.atime_nsec()
+    }
+    fn mtime(&self) -> i64 {
+        self.0.mtime()
+    }
+    fn mtime_nsec(&self) -> i64 {
+        self.0.mtime_nsec()
+    }
+    fn ctime(&self) -> i64 {
+        self.0.ctime()
+    }
+    fn ctime_nsec(&self) -> i64 {
+        self.0.ctime_nsec()
+    }
+    fn blksize(&self) -> u64 {
+        self.0.blksize()
+    }
+    fn blocks(&self) -> u64 {
+        self.0.blocks()
+    }
+}
+
+/// Options and flags which can be used to configure how a file is opened.
+///
+/// This builder, created from `GenFS`s [`new_openopts`], wraps [`std::fs::OpenOptions`]. It
+/// implements [`rsfs::OpenOptions`] and supports [unix extensions].
+///
+/// [`new_openopts`]: ../trait.GenFS.html#tymethod.new_openopts
+/// [`std::fs::OpenOptions`]: https://doc.rust-lang.org/std/fs/struct.OpenOptions.html
+/// [`rsfs::OpenOptions`]: ../trait.OpenOptions.html
+/// [unix extensions]: ../unix_ext/trait.OpenOptionsExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = rsfs::disk::FS;
+/// let f = fs.new_openopts()
+///           .read(true)
+///           .open("f")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct OpenOptions(rs_fs::OpenOptions);
+
+impl fs::OpenOptions for OpenOptions {
+    type File = File;
+
+    fn read(&mut self, read: bool) -> &mut Self {
+        self.0.read(read); self
+    }
+    fn write(&mut self, write: bool) -> &mut Self {
+        self.0.write(write); self
+    }
+    fn append(&mut self, append: bool) -> &mut Self {
+        self.0.append(append); self
+    }
+    fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.0.truncate(truncate); self
+    }
+    fn create(&mut self, create: bool) -> &mut Self {
+        self.0.create(create); self
+    }
+    fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.0.create_new(create_new); self
+    }
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        self.0.open(path).map(File)
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::OpenOptionsExt for OpenOptions {
+    fn mode(&mut self, mode: u32) -> &mut Self {
+        self.0.mode(mode); self
+    }
+    fn custom_flags(&mut self, flags: i32) -> &mut Self {
+        self.0.custom_flags(flags); self
+    }
+}
+
+/// Representation of the various permissions on a file.
+///
+/// This struct wraps [`std::fs::Permissions`], implements [`rsfs::Permissions`] and has
+/// [unix extensions].
+///
+/// [`std::fs::Permissions`]: https://doc.rust-lang.org/std/fs/struct.Permissions.html
+/// [`rsfs::Permissions`]: ../trait.Permissions.html
+/// [unix extensions]: ../unix_ext/trait.PermissionsExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// use rsfs::unix_ext::*;
+/// use rsfs::disk::Permissions;
+/// # fn foo() -> std::io::Result<()> {
+/// # let fs = rsfs::disk::FS;
+/// # fs.create_file("foo.txt")?;
+///
+/// fs.set_permissions("foo.txt", Permissions::from_mode(0o400))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permissions(rs_fs::Permissions);
+
+impl fs::Permissions for Permissions {
+    fn readonly(&self) -> bool {
+        self.0.readonly()
+    }
+    fn set_readonly(&mut self, readonly: bool) {
+        self.0.set_readonly(readonly)
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::PermissionsExt for Permissions {
+    fn mode(&self) -> u32 {
+        self.0.mode()
+    }
+    fn set_mode(&mut self, mode: u32) {
+        self.0.set_mode(mode)
+    }
+    fn from_mode(mode: u32) -> Self {
+        Permissions(rs_fs::Permissions::from_mode(mode))
+    }
+}
+
+/// Iterator over entries in a directory.
+///
+/// This is returned from the [`read_dir`] method of `GenFS` and yields instances of
+/// `io::Result<DirEntry>`. Through a [`DirEntry`], information about contents of a directory can
+/// be learned.
+///
+/// [`read_dir`]: struct.FS.html#method.read_dir
+/// [`DirEntry`]: struct.DirEntry.html
+#[derive(Debug)]
+pub struct ReadDir(rs_fs::ReadDir);
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|res| res.map(DirEntry))
+    }
+}
+
+/// An empty struct that satisfies [`rsfs::GenFS`] by calling [`std::fs`] functions.
+///
+/// Because this is an empty struct, it is inherently thread safe and copyable. The power of using
+/// `rsfs` comes from the ability to choose what filesystem you want to use where: your main can
+/// use a disk backed filesystem, but your tests can use a test filesystem with injected errors.
+///
+/// Alternatively, the in-memory filesystem could suit your needs without forcing you to use disk.
+///
+/// [`rsfs::GenFS`]: ../trait.GenFS.html
+/// [`std::fs`]: https://doc.rust-lang.org/std/fs/
+///
+/// # Examples
+///
+/// ```
+/// use rsfs::*;
+///
+/// let fs = rsfs::disk::FS;
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct FS;
+
+impl fs::GenFS for FS {
+    type DirBuilder  = DirBuilder;
+    type DirEntry    = DirEntry;
+    type File        = File;
+    type Metadata    = Metadata;
+    type OpenOptions = OpenOptions;
+    type Permissions = Permissions;
+    type ReadDir     = ReadDir;
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        rs_fs::canonicalize(path)
+    }
+    fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> {
+        rs_fs::copy(from, to)
+    }
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        rs_fs::create_dir(path)
+    }
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        rs_fs::create_dir_all(path)
+    }
+    fn current_dir(&self) -> Result<PathBuf> {
+        env::current_dir()
+    }
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        rs_fs::hard_link(src, dst)
+    }
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        rs_fs::metadata(path).map(Metadata)
+    }
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir> {
+        rs_fs::read_dir(path).map(ReadDir)
+    }
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        rs_fs::read_link(path)
+    }
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        rs_fs::remove_dir(path)
+    }
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        rs_fs::remove_dir_all(path)
+    }
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        rs_fs::remove_file(path)
+    }
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        rs_fs::rename(from, to)
+    }
+    fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        env::set_current_dir(path)
+    }
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()> {
+        rs_fs::set_permissions(path, perm.0)
+    }
+    #[cfg(not(windows))]
+    fn set_times<P: AsRef<Path>>(&self, path: P, times: fs::FileTimes) -> Result<()> {
+        rs_fs::File::open(path)?.set_times(std_times(times))
+    }
+    #[cfg(windows)]
+    fn set_times<P: AsRef<Path>>(&self, path: P, times: fs::FileTimes) -> Result<()> {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_FLAG_BACKUP_SEMANTICS allows opening directories.
+        rs_fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(0x0200_0000)
+            .open(path)?
+            .set_times(std_times(times))
+    }
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        rs_fs::symlink_metadata(path).map(Metadata)
+    }
+
+    fn new_openopts(&self) -> Self::OpenOptions {
+        OpenOptions(rs_fs::OpenOptions::new())
+    }
+    fn new_dirbuilder(&self) -> Self::DirBuilder {
+        DirBuilder(rs_fs::DirBuilder::new())
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        rs_fs::File::open(path).map(File)
+    }
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        rs_fs::File::create(path).map(File)
+    }
+
+    fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        rs_fs::read(path)
+    }
+    fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        rs_fs::read_to_string(path)
+    }
+    fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<()> {
+        rs_fs::write(path, contents)
+    }
+    fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        path.as_ref().exists()
+    }
+    fn try_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        path.as_ref().try_exists()
+    }
+}
+
+#[cfg(unix)]
+impl unix_ext::GenFSExt for FS {
+    fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        ::std::os::unix::fs::symlink(src, dst)
+    }
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        ::std::os::unix::fs::chown(path, uid, gid)
+    }
+    fn mkfifo<P: AsRef<Path>>(&self, path: P, mode: u32) -> Result<()> {
+        node::mknod(path.as_ref(), 0o010000 | (mode & 0o7777), 0)
+    }
+    fn mknod<P: AsRef<Path>>(&self, path: P, mode: u32, dev: u64) -> Result<()> {
+        node::mknod(path.as_ref(), mode, dev)
+    }
+    fn link_file<P: AsRef<Path>>(&self, file: &File, dst: P) -> Result<()> {
+        node::link_fd(file.0.as_raw_fd(), dst.as_ref())
+    }
+    fn rename_with_flags<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q, flags: u32)
+        -> Result<()>
+    {
+        if flags == 0 {
+            return rs_fs::rename(from, to);
+        }
+        node::rename(from.as_ref(), to.as_ref(), flags)
+    }
+    fn get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N) -> Result<Vec<u8>> {
+        xattr::get(xattr::Target::Path(path.as_ref()), name.as_ref())
+    }
+    fn set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N, value: &[u8])
+        -> Result<()>
+    {
+        xattr::set(xattr::Target::Path(path.as_ref()), name.as_ref(), value)
+    }
+    fn list_xattr<P: AsRef<Path>>(&self, path: P) -> Result<Vec<OsString>> {
+        xattr::list(xattr::Target::Path(path.as_ref()))
+    }
+    fn remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N) -> Result<()> {
+        xattr::remove(xattr::Target::Path(path.as_ref()), name.as_ref())
+    }
+    fn symlink_get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N)
+        -> Result<Vec<u8>>
+    {
+        xattr::get(xattr::Target::SymlinkPath(path.as_ref()), name.as_ref())
+    }
+    fn symlink_set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N, value: &[u8])
+        -> Result<()>
+    {
+        xattr::set(xattr::Target::SymlinkPath(path.as_ref()), name.as_ref(), value)
+    }
+    fn symlink_list_xattr<P: AsRef<Path>>(&self, path: P) -> Result<Vec<OsString>> {
+        xattr::list(xattr::Target::SymlinkPath(path.as_ref()))
+    }
+    fn symlink_remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N)
+        -> Result<()>
+    {
+        xattr::remove(xattr::Target::SymlinkPath(path.as_ref()), name.as_ref())
+    }
+}
+
+#[cfg(windows)]
+impl windows_ext::GenFSExt for FS {
+    fn symlink_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        ::std::os::windows::fs::symlink_file(src, dst)
+    }
+    fn symlink_dir<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        ::std::os::windows::fs::symlink_dir(src, dst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::io::{Read, Write};
+    use std::process;
+
+    use fs::{DirBuilder as DirBuilderTrait, DirEntry as DirEntryTrait, File as FileTrait, FileType as FileTypeTrait,
+             GenFS, Metadata as MetadataTrait};
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let fs = FS;
+        let dir = env::temp_dir().join(format!("rsfs-disk-test-{}", process::id()));
+        assert!(fs.create_dir_all(dir.join("a/b")).is_ok());
+
+        let mut wf = fs.create_file(dir.join("a/f")).unwrap();
+        assert_eq!(wf.write(b"hello").unwrap(), 5);
+        assert!(wf.sync_all().is_ok());
+        assert_eq!(wf.metadata().unwrap().len(), 5);
+
+        let mut rf = fs.open_file(dir.join("a/f")).unwrap();
+        let mut output = String::new();
+        assert_eq!(rf.read_to_string(&mut output).unwrap(), 5);
+        assert_eq!(output, "hello");
+
+        let names: Vec<_> = fs.read_dir(dir.join("a"))
+                              .unwrap()
+                              .map(|ent| ent.unwrap().file_name())
+                              .collect();
+        assert_eq!(names.len(), 2);
+        assert!(fs.metadata(dir.join("a/b")).unwrap().is_dir());
+
+        let epoch = ::std::time::UNIX_EPOCH;
+        assert!(wf.set_modified(epoch).is_ok());
+        assert_eq!(fs.metadata(dir.join("a/f")).unwrap().modified().unwrap(), epoch);
+        assert!(fs.set_times(dir.join("a/b"), fs::FileTimes::new().set_modified(epoch)).is_ok());
+        assert_eq!(fs.metadata(dir.join("a/b")).unwrap().modified().unwrap(), epoch);
+
+        assert!(fs.remove_dir_all(&dir).is_ok());
+        assert!(fs.metadata(&dir).is_err());
+    }
+
+    #[test]
+    fn lock() {
+        use fs::TryLockError;
+
+        let fs = FS;
+        let dir = env::temp_dir().join(format!("rsfs-disk-test-lock-{}", process::id()));
+        assert!(fs.create_dir(&dir).is_ok());
+        let (a, b) = (fs.create_file(dir.join("l")).unwrap(), fs.open_file(dir.join("l")).unwrap());
+
+        assert!(a.try_lock().is_ok());
+        assert!(matches!(b.try_lock_shared(), Err(TryLockError::WouldBlock)));
+        assert!(a.unlock().is_ok());
+        assert!(a.lock_shared().is_ok() && b.try_lock_shared().is_ok());
+        assert!(matches!(b.try_lock(), Err(TryLockError::WouldBlock)));
+        assert!(a.unlock().is_ok() && b.unlock().is_ok());
+        assert!(b.lock().is_ok() && b.unlock().is_ok());
+
+        drop((a, b));
+        assert!(fs.remove_dir_all(&dir).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix() {
+        use unix_ext::*;
+
+        let fs = FS;
+        let dir = env::temp_dir().join(format!("rsfs-disk-test-unix-{}", process::id()));
+        assert!(fs.new_dirbuilder().mode(0o700).recursive(true).create(&dir).is_ok());
+        assert_eq!(fs.metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        assert!(fs.create_file(dir.join("f")).is_ok());
+        assert!(fs.symlink("f", dir.join("sl")).is_ok());
+        assert!(fs.symlink_metadata(dir.join("sl")).unwrap().file_type().is_symlink());
+        assert_eq!(fs.read_link(dir.join("sl")).unwrap(), PathBuf::from("f"));
+
+        assert!(fs.hard_link(dir.join("f"), dir.join("hl")).is_ok());
+        let (f, hl) = (fs.metadata(dir.join("f")).unwrap(), fs.metadata(dir.join("hl")).unwrap());
+        assert_eq!((f.dev(), f.ino(), f.nlink()), (hl.dev(), hl.ino(), 2));
+        assert_eq!(f.mode() & 0o170000, 0o100000);
+
+        let file = fs.create_file(dir.join("g")).unwrap();
+        assert!(file.write_at(b"data", 10).is_ok());
+        assert_eq!(file.seek_sparse(SparseSeek::Data(14)).unwrap_err().raw_os_error(), Some(6));
+        assert_eq!(file.seek_sparse(SparseSeek::Hole(10)).unwrap(), 14);
+        // Filesystems that do not track holes report all data.
+        let data = file.seek_sparse(SparseSeek::Data(0)).unwrap();
+        let hole = file.seek_sparse(SparseSeek::Hole(0)).unwrap();
+        assert!((data, hole) == (10, 0) || (data, hole) == (0, 14));
+        assert_eq!((&file).stream_position().unwrap(), hole);
+
+        assert!(fs.mkfifo(dir.join("fifo"), 0o600).is_ok());
+        let fifo = fs.symlink_metadata(dir.join("fifo")).unwrap();
+        assert!(fifo.file_type().is_fifo());
+        assert_eq!((fifo.mode() & 0o170777, fifo.rdev()), (0o010600, 0));
+        assert!(fs.mknod(dir.join("dir"), 0o040700, 0).is_err());
+        assert_eq!(fs.mknod(dir.join("big"), 0o020600, 1 << 40).unwrap_err().raw_os_error(),
+                   Some(22));
+        // Devices need privileges.
+        if fs.mknod(dir.join("null"), 0o020600, 0x103).is_ok() {
+            assert_eq!(fs.symlink_metadata(dir.join("null")).unwrap().rdev(), 0x103);
+        }
+
+        // Not every filesystem supports user attributes.
+        if file.set_xattr("user.rsfs", b"1").is_ok() {
+            assert_eq!(fs.get_xattr(dir.join("g"), "user.rsfs").unwrap(), b"1");
+            assert!(fs.list_xattr(dir.join("g")).unwrap().contains(&"user.rsfs".into()));
+            assert!(fs.remove_xattr(dir.join("g"), "user.rsfs").is_ok());
+            assert_eq!(file.get_xattr("user.rsfs").unwrap_err().raw_os_error(), Some(61));
+            assert_eq!(fs.symlink_set_xattr(dir.join("sl"), "user.rsfs", b"")
+                         .unwrap_err()
+                         .raw_os_error(),
+                       Some(1));
+        }
+
+        // Nor renaming with flags.
+        if fs.rename_with_flags(dir.join("f"), dir.join("g"), RENAME_EXCHANGE).is_ok() {
+            assert_eq!(fs.metadata(dir.join("f")).unwrap().len(), 14);
+            assert_eq!(fs.rename_with_flags(dir.join("f"), dir.join("g"), RENAME_NOREPLACE)
+                         .unwrap_err()
+                         .raw_os_error(),
+                       Some(17));
+        }
+
+        assert!(fs.remove_dir_all(&dir).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_flags() {
+        use fs::OpenOptions as OpenOptionsTrait;
+        use unix_ext::*;
+
+        let fs = FS;
+        let dir = env::temp_dir().join(format!("rsfs-disk-test-flags-{}", process::id()));
+        assert!(fs.create_dir(&dir).is_ok());
+        assert!(fs.write(dir.join("f"), b"f").is_ok());
+        assert!(fs.symlink("f", dir.join("sl")).is_ok());
+
+        // The exported flags are the host's.
+        let open = |flags, path: &str| {
+            fs.new_openopts().read(true).custom_flags(flags).open(dir.join(path))
+        };
+        assert_eq!(open(O_NOFOLLOW, "sl").unwrap_err().raw_os_error(), Some(40));
+        assert_eq!(open(O_DIRECTORY, "f").unwrap_err().raw_os_error(), Some(20));
+        assert!(open(O_DIRECTORY | O_NOFOLLOW | O_NOATIME | O_CLOEXEC, ".").is_ok());
+        let opts = |flags| fs.new_openopts().write(true).custom_flags(flags).clone();
+        assert_eq!(opts(O_CREAT | O_EXCL).open(dir.join("f")).unwrap_err().raw_os_error(),
+                   Some(17));
+        assert!(opts(O_CREAT).open(dir.join("g")).is_ok());
+        assert!((&opts(O_APPEND | O_SYNC).open(dir.join("f")).unwrap()).write_all(b"!").is_ok());
+        assert_eq!(fs.read(dir.join("f")).unwrap(), b"f!");
+        assert!(opts(O_TRUNC).open(dir.join("f")).is_ok());
+        assert_eq!(fs.read(dir.join("f")).unwrap(), b"");
+
+        // Not every filesystem supports O_TMPFILE.
+        let tmp = opts(O_TMPFILE).open(&dir);
+        if let Ok(tmp) = tmp {
+            assert!((&tmp).write_all(b"tmp").is_ok());
+            assert!(fs.link_file(&tmp, dir.join("tmp")).is_ok());
+            assert_eq!(fs.read(dir.join("tmp")).unwrap(), b"tmp");
+        }
+
+        assert!(fs.remove_dir_all(&dir).is_ok());
+    }
+}
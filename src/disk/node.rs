@@ -0,0 +1,171 @@
+//! Creating, naming, and renaming files on disk beyond what std offers.
+//!
+//! std cannot create FIFOs, sockets, or devices, so on Linux we make the `mknodat(2)` system call
+//! directly: glibc only exports `mknod` and `mknodat` as symbols since 2.33, and wraps them in
+//! inline functions before that. Renaming with flags makes the `renameat2(2)` system call for the
+//! same reason, as glibc only wraps it since 2.28. System call numbers differ by architecture, and
+//! both calls are reported as unsupported on architectures we do not know the numbers of, as well
+//! as on other Unix systems, which disagree on the types of the arguments. Naming an open file
+//! goes through `linkat(2)` and `/proc`, which only Linux has.
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::CString;
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::raw::{c_char, c_int, c_long, c_uint};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    const AT_FDCWD: c_int = -100;
+    const AT_SYMLINK_FOLLOW: c_int = 0x400;
+
+    // The numbers of the mknodat and renameat2 system calls. x32 shares the x86-64 numbers with
+    // the x32 bit set, and riscv and loongarch use the generic numbers.
+    #[cfg(all(target_arch = "x86_64", target_pointer_width = "64"))]
+    const SYS: Option<(c_long, c_long)> = Some((259, 316));
+    #[cfg(all(target_arch = "x86_64", target_pointer_width = "32"))]
+    const SYS: Option<(c_long, c_long)> = Some((0x4000_0000 + 259, 0x4000_0000 + 316));
+    #[cfg(target_arch = "x86")]
+    const SYS: Option<(c_long, c_long)> = Some((297, 353));
+    #[cfg(target_arch = "arm")]
+    const SYS: Option<(c_long, c_long)> = Some((324, 382));
+    #[cfg(any(target_arch = "aarch64", target_arch = "riscv32", target_arch = "riscv64",
+              target_arch = "loongarch64"))]
+    const SYS: Option<(c_long, c_long)> = Some((33, 276));
+    #[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+    const SYS: Option<(c_long, c_long)> = Some((288, 357));
+    #[cfg(target_arch = "s390x")]
+    const SYS: Option<(c_long, c_long)> = Some((290, 347));
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86", target_arch = "arm",
+                  target_arch = "aarch64", target_arch = "riscv32", target_arch = "riscv64",
+                  target_arch = "loongarch64", target_arch = "powerpc",
+                  target_arch = "powerpc64", target_arch = "s390x")))]
+    const SYS: Option<(c_long, c_long)> = None;
+
+    extern "C" {
+        fn syscall(number: c_long, ...) -> c_long;
+        fn linkat(olddirfd: c_int, oldpath: *const c_char, newdirfd: c_int,
+                  newpath: *const c_char, flags: c_int) -> c_int;
+    }
+
+    // unsupported is the error for system calls we do not know the number of.
+    fn unsupported(what: &str) -> Error {
+        Error::new(ErrorKind::Unsupported,
+                   format!("{} is not supported on this architecture", what))
+    }
+
+    fn c_path(path: &Path) -> Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+    }
+
+    // mknod creates a special file. The kernel takes dev as 32 bits, which hold every device
+    // whose major and minor numbers fit the kernel's own limits; glibc rejects larger devices the
+    // same way.
+    pub(crate) fn mknod(path: &Path, mode: u32, dev: u64) -> Result<()> {
+        let nr = SYS.ok_or_else(|| unsupported("creating special files"))?.0;
+        let path = c_path(path)?;
+        if dev > u64::from(c_uint::MAX) {
+            return Err(::errors::EINVAL());
+        }
+        let ret = unsafe { syscall(nr, AT_FDCWD, path.as_ptr(), mode as c_uint, dev as c_uint) };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // link_fd names the open file fd. Unlike AT_EMPTY_PATH, following the file's link in /proc
+    // does not need CAP_DAC_READ_SEARCH.
+    pub(crate) fn link_fd(fd: c_int, dst: &Path) -> Result<()> {
+        let src = c_path(Path::new(&format!("/proc/self/fd/{}", fd)))?;
+        let dst = c_path(dst)?;
+        let ret = unsafe {
+            linkat(AT_FDCWD, src.as_ptr(), AT_FDCWD, dst.as_ptr(), AT_SYMLINK_FOLLOW)
+        };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn rename(from: &Path, to: &Path, flags: u32) -> Result<()> {
+        let nr = SYS.ok_or_else(|| unsupported("renaming with flags"))?.1;
+        let (from, to) = (c_path(from)?, c_path(to)?);
+        let ret = unsafe {
+            syscall(nr, AT_FDCWD, from.as_ptr(), AT_FDCWD, to.as_ptr(), flags as c_uint)
+        };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::Path;
+
+    pub(crate) fn mknod(_: &Path, _: u32, _: u64) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported,
+                       "creating special files is only supported on Linux"))
+    }
+
+    pub(crate) fn link_fd(_: i32, _: &Path) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "naming open files is only supported on Linux"))
+    }
+
+    pub(crate) fn rename(_: &Path, _: &Path, _: u32) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "renaming with flags is only supported on Linux"))
+    }
+}
+
+pub(crate) use self::sys::{link_fd, mknod, rename};
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn mknodat() {
+        let dir = env::temp_dir().join(format!("rsfs-disk-node-test-mknodat-{}", process::id()));
+        assert!(fs::create_dir(&dir).is_ok());
+
+        assert!(mknod(&dir.join("fifo"), 0o010640, 0).is_ok());
+        let fifo = fs::symlink_metadata(dir.join("fifo")).unwrap();
+        assert!(fifo.file_type().is_fifo());
+        assert_eq!(fifo.mode() & 0o170000, 0o010000);
+        assert_eq!(mknod(&dir.join("fifo"), 0o010600, 0).unwrap_err().raw_os_error(), Some(17));
+        assert_eq!(mknod(&dir.join("big"), 0o020600, 1 << 32).unwrap_err().raw_os_error(),
+                   Some(22));
+        assert!(mknod(&dir.join("a\0b"), 0o010600, 0).is_err());
+
+        assert!(fs::remove_dir_all(&dir).is_ok());
+    }
+    #[test]
+    fn renameat2() {
+        let dir = env::temp_dir().join(format!("rsfs-disk-node-test-renameat2-{}", process::id()));
+        assert!(fs::create_dir(&dir).is_ok());
+        assert!(fs::write(dir.join("a"), b"a").is_ok());
+        assert!(fs::write(dir.join("b"), b"b").is_ok());
+
+        assert_eq!(rename(&dir.join("a"), &dir.join("b"), 1).unwrap_err().raw_os_error(),
+                   Some(17));
+        assert_eq!(rename(&dir.join("c"), &dir.join("d"), 1).unwrap_err().raw_os_error(),
+                   Some(2));
+        assert!(rename(&dir.join("a"), &dir.join("c"), 1).is_ok());
+        // Not every filesystem supports exchanging.
+        if rename(&dir.join("b"), &dir.join("c"), 2).is_ok() {
+            assert_eq!(fs::read(dir.join("b")).unwrap(), b"a");
+            assert_eq!(fs::read(dir.join("c")).unwrap(), b"b");
+        }
+
+        assert!(fs::remove_dir_all(&dir).is_ok());
+    }
+}
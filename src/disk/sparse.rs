@@ -0,0 +1,50 @@
+//! Seeking through the data and holes of sparse files on disk.
+//!
+//! std does not expose `SEEK_DATA` and `SEEK_HOLE`, so on Linux we call `lseek(2)` directly. The
+//! 64-bit offset version is `lseek64` in glibc and `lseek` in musl. Other Unix systems disagree
+//! on the values of the two whences, and seeking sparse files is reported as unsupported there.
+
+pub(crate) use self::sys::seek;
+
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+mod sys {
+    use std::io::{Error, Result};
+    use std::os::raw::c_int;
+
+    use unix_ext::SparseSeek;
+
+    const SEEK_DATA: c_int = 3;
+    const SEEK_HOLE: c_int = 4;
+
+    extern "C" {
+        #[cfg_attr(target_env = "gnu", link_name = "lseek64")]
+        fn lseek(fd: c_int, offset: i64, whence: c_int) -> i64;
+    }
+
+    pub(crate) fn seek(fd: c_int, pos: SparseSeek) -> Result<u64> {
+        let (offset, whence) = match pos {
+            SparseSeek::Data(offset) => (offset, SEEK_DATA),
+            SparseSeek::Hole(offset) => (offset, SEEK_HOLE),
+        };
+        if offset > i64::MAX as u64 {
+            return Err(::errors::ENXIO());
+        }
+        let ret = unsafe { lseek(fd, offset as i64, whence) };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(ret as u64)
+    }
+}
+
+#[cfg(not(all(target_os = "linux", any(target_env = "gnu", target_env = "musl"))))]
+mod sys {
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::raw::c_int;
+
+    use unix_ext::SparseSeek;
+
+    pub(crate) fn seek(_: c_int, _: SparseSeek) -> Result<u64> {
+        Err(Error::new(ErrorKind::Unsupported, "seeking sparse files is only supported on Linux"))
+    }
+}
@@ -0,0 +1,179 @@
+//! Extended attributes on disk.
+//!
+//! std does not expose extended attributes, so on Linux we call the `*xattr(2)` family directly.
+//! Other Unix systems have incompatible signatures, and extended attributes are reported as
+//! unsupported there.
+
+use std::path::Path;
+
+pub(crate) use self::sys::{get, list, remove, set};
+
+/// `Target` is what an extended attribute call operates on.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Target<'a> {
+    /// The file at a path, following a symlink at the end of the path.
+    Path(&'a Path),
+    /// The file at a path, not following a symlink at the end of the path.
+    SymlinkPath(&'a Path),
+    /// An open file.
+    Fd(i32),
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use std::ffi::{CString, OsStr, OsString};
+    use std::io::{Error, ErrorKind, Result};
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::ptr;
+
+    use super::Target;
+
+    extern "C" {
+        fn getxattr(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize)
+            -> isize;
+        fn lgetxattr(path: *const c_char, name: *const c_char, value: *mut c_void, size: usize)
+            -> isize;
+        fn fgetxattr(fd: c_int, name: *const c_char, value: *mut c_void, size: usize) -> isize;
+        fn setxattr(path: *const c_char, name: *const c_char, value: *const c_void, size: usize,
+                    flags: c_int) -> c_int;
+        fn lsetxattr(path: *const c_char, name: *const c_char, value: *const c_void, size: usize,
+                     flags: c_int) -> c_int;
+        fn fsetxattr(fd: c_int, name: *const c_char, value: *const c_void, size: usize,
+                     flags: c_int) -> c_int;
+        fn listxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+        fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+        fn flistxattr(fd: c_int, list: *mut c_char, size: usize) -> isize;
+        fn removexattr(path: *const c_char, name: *const c_char) -> c_int;
+        fn lremovexattr(path: *const c_char, name: *const c_char) -> c_int;
+        fn fremovexattr(fd: c_int, name: *const c_char) -> c_int;
+    }
+
+    /// `CTarget` is a `Target` with its path converted for C.
+    enum CTarget {
+        Path(CString),
+        SymlinkPath(CString),
+        Fd(c_int),
+    }
+
+    impl CTarget {
+        fn new(target: Target) -> Result<CTarget> {
+            Ok(match target {
+                Target::Path(path) => CTarget::Path(c_string(path.as_os_str())?),
+                Target::SymlinkPath(path) => CTarget::SymlinkPath(c_string(path.as_os_str())?),
+                Target::Fd(fd) => CTarget::Fd(fd),
+            })
+        }
+    }
+
+    fn c_string(s: &OsStr) -> Result<CString> {
+        CString::new(s.as_bytes()).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+    }
+
+    fn cvt(ret: isize) -> Result<usize> {
+        if ret < 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    // sized calls f with a buffer big enough for what it returns. f is first called without a
+    // buffer to learn the size; if what it returns grows before the second call, which fails
+    // with ERANGE, we start over.
+    fn sized<F: Fn(*mut c_void, usize) -> isize>(f: F) -> Result<Vec<u8>> {
+        loop {
+            let size = cvt(f(ptr::null_mut(), 0))?;
+            let mut buf = vec![0u8; size];
+            match cvt(f(buf.as_mut_ptr() as *mut c_void, size)) {
+                Ok(n) => {
+                    buf.truncate(n);
+                    return Ok(buf);
+                }
+                Err(ref e) if e.raw_os_error() == Some(34) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(crate) fn get(target: Target, name: &OsStr) -> Result<Vec<u8>> {
+        let (target, name) = (CTarget::new(target)?, c_string(name)?);
+        sized(|buf, size| unsafe {
+            match target {
+                CTarget::Path(ref p) => getxattr(p.as_ptr(), name.as_ptr(), buf, size),
+                CTarget::SymlinkPath(ref p) => lgetxattr(p.as_ptr(), name.as_ptr(), buf, size),
+                CTarget::Fd(fd) => fgetxattr(fd, name.as_ptr(), buf, size),
+            }
+        })
+    }
+
+    pub(crate) fn set(target: Target, name: &OsStr, value: &[u8]) -> Result<()> {
+        let (target, name) = (CTarget::new(target)?, c_string(name)?);
+        let (val, size) = (value.as_ptr() as *const c_void, value.len());
+        let ret = unsafe {
+            match target {
+                CTarget::Path(ref p) => setxattr(p.as_ptr(), name.as_ptr(), val, size, 0),
+                CTarget::SymlinkPath(ref p) => lsetxattr(p.as_ptr(), name.as_ptr(), val, size, 0),
+                CTarget::Fd(fd) => fsetxattr(fd, name.as_ptr(), val, size, 0),
+            }
+        };
+        cvt(ret as isize).map(|_| ())
+    }
+
+    pub(crate) fn list(target: Target) -> Result<Vec<OsString>> {
+        let target = CTarget::new(target)?;
+        let names = sized(|buf, size| unsafe {
+            let buf = buf as *mut c_char;
+            match target {
+                CTarget::Path(ref p) => listxattr(p.as_ptr(), buf, size),
+                CTarget::SymlinkPath(ref p) => llistxattr(p.as_ptr(), buf, size),
+                CTarget::Fd(fd) => flistxattr(fd, buf, size),
+            }
+        })?;
+        // Names are each terminated by a NUL.
+        Ok(names.split(|&b| b == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| OsString::from_vec(name.to_vec()))
+                .collect())
+    }
+
+    pub(crate) fn remove(target: Target, name: &OsStr) -> Result<()> {
+        let (target, name) = (CTarget::new(target)?, c_string(name)?);
+        let ret = unsafe {
+            match target {
+                CTarget::Path(ref p) => removexattr(p.as_ptr(), name.as_ptr()),
+                CTarget::SymlinkPath(ref p) => lremovexattr(p.as_ptr(), name.as_ptr()),
+                CTarget::Fd(fd) => fremovexattr(fd, name.as_ptr()),
+            }
+        };
+        cvt(ret as isize).map(|_| ())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod sys {
+    use std::ffi::{OsStr, OsString};
+    use std::io::{Error, ErrorKind, Result};
+
+    use super::Target;
+
+    fn unsupported() -> Error {
+        Error::new(ErrorKind::Unsupported, "extended attributes are only supported on Linux")
+    }
+
+    pub(crate) fn get(_: Target, _: &OsStr) -> Result<Vec<u8>> {
+        Err(unsupported())
+    }
+
+    pub(crate) fn set(_: Target, _: &OsStr, _: &[u8]) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub(crate) fn list(_: Target) -> Result<Vec<OsString>> {
+        Err(unsupported())
+    }
+
+    pub(crate) fn remove(_: Target, _: &OsStr) -> Result<()> {
+        Err(unsupported())
+    }
+}
@@ -4,8 +4,11 @@
 //! Notably, these errors are the only errors returned in the [`mem`] module if using implemented
 //! behavior (unimplemented behavior returns `Error::new::ErrorKind::Other`).
 //!
+//! These are primarily useful for injecting errors with [`mem::test`].
+//!
 //! [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
 //! [`mem`]: ../mem/index.html
+//! [`mem::test`]: ../mem/test/index.html
 
 use std::io::Error;
 
@@ -21,6 +24,24 @@ pub fn ENOENT() -> Error {
     Error::from_raw_os_error(2)
 }
 
+/// Used when a low level I/O error occurs.
+#[allow(non_snake_case)]
+pub fn EIO() -> Error {
+    Error::from_raw_os_error(5)
+}
+
+/// Used when the value of an extended attribute is too large.
+#[allow(non_snake_case)]
+pub fn E2BIG() -> Error {
+    Error::from_raw_os_error(7)
+}
+
+/// Used when seeking to data or a hole at or past the end of a file.
+#[allow(non_snake_case)]
+pub fn ENXIO() -> Error {
+    Error::from_raw_os_error(6)
+}
+
 /// Used when performing an operation with a file that was not opened in a way to allow that
 /// operation (read on a write only open, etc).
 #[allow(non_snake_case)]
@@ -46,6 +67,12 @@ pub fn EEXIST() -> Error {
     Error::from_raw_os_error(17)
 }
 
+/// Used when linking or renaming across filesystems.
+#[allow(non_snake_case)]
+pub fn EXDEV() -> Error {
+    Error::from_raw_os_error(18)
+}
+
 /// Used when attempting to perform a directory operation on a file.
 #[allow(non_snake_case)]
 pub fn ENOTDIR() -> Error {
@@ -64,10 +91,22 @@ pub fn EINVAL() -> Error {
     Error::from_raw_os_error(22)
 }
 
+/// Used when there is no space left on the device.
+#[allow(non_snake_case)]
+pub fn ENOSPC() -> Error {
+    Error::from_raw_os_error(28)
+}
+
+/// Used when the name of an extended attribute is empty or too long.
+#[allow(non_snake_case)]
+pub fn ERANGE() -> Error {
+    Error::from_raw_os_error(34)
+}
+
 /// Used when an operation needs an empty directory and is performed on a non-empty directory.
 #[allow(non_snake_case)]
 pub fn ENOTEMPTY() -> Error {
-    // TODO Windows is 41, other Unix / BSD distros differ from 39.
+    // TODO other Unix / BSD distros differ from 39. Windows is in the windows module.
     Error::from_raw_os_error(39)
 }
 
@@ -76,3 +115,49 @@ pub fn ENOTEMPTY() -> Error {
 pub fn ELOOP() -> Error {
     Error::from_raw_os_error(40)
 }
+
+/// Used when getting or removing an extended attribute that does not exist.
+#[allow(non_snake_case)]
+pub fn ENODATA() -> Error {
+    // TODO BSD distros differ from 61 and return ENOATTR (93) instead.
+    Error::from_raw_os_error(61)
+}
+
+/// Used when an operation is not supported, such as using an unknown extended attribute
+/// namespace.
+#[allow(non_snake_case)]
+pub fn EOPNOTSUPP() -> Error {
+    Error::from_raw_os_error(95)
+}
+
+/// Windows specific error codes.
+///
+/// Windows reports some errors with Win32 error codes that have no `errno` value shared with Linux;
+/// the functions in this module return those codes, which Windows hosts decode into the matching
+/// `io::ErrorKind`. These are the errors returned from the [`mem::windows`] module where it
+/// differs from Unix.
+///
+/// [`mem::windows`]: ../../mem/windows/index.html
+pub mod windows {
+    use std::io::Error;
+
+    /// Used when access is denied, such as writing a read-only file or through a handle opened
+    /// without write access (`ERROR_ACCESS_DENIED`).
+    #[allow(non_snake_case)]
+    pub fn EACCES() -> Error {
+        Error::from_raw_os_error(5)
+    }
+
+    /// Used when an operation needs an empty directory and is performed on a non-empty directory
+    /// (`ERROR_DIR_NOT_EMPTY`).
+    #[allow(non_snake_case)]
+    pub fn ENOTEMPTY() -> Error {
+        Error::from_raw_os_error(145)
+    }
+
+    /// Used when traversing too many symlinks (`ERROR_CANT_RESOLVE_FILENAME`).
+    #[allow(non_snake_case)]
+    pub fn ELOOP() -> Error {
+        Error::from_raw_os_error(1921)
+    }
+}
@@ -1,9 +1,10 @@
 //! This module provides basic generic types for a filesystem.
 
+use std::error;
 use std::ffi::OsString;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
-use std::io::{Read, Seek, Write};
+use std::io::{self, ErrorKind, Read, Seek, Write};
 use std::io::Result;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
@@ -335,6 +336,310 @@ pub trait File: Debug + Read + Seek + Write + Sized {
     /// # }
     /// ```
     fn set_permissions(&self, perm: Self::Permissions) -> Result<()>;
+    /// Changes the timestamps of the underlying file.
+    ///
+    /// Times that are not set in `times` are left unchanged. This is the equivalent of
+    /// [`std::fs::File::set_times`].
+    ///
+    /// The default implementation returns an error of kind `Unsupported`, for filesystems that
+    /// cannot change the timestamps of files.
+    ///
+    /// [`std::fs::File::set_times`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.set_times
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let file = fs.create_file("foo.txt")?;
+    /// let times = FileTimes::new()
+    ///     .set_accessed(UNIX_EPOCH)
+    ///     .set_modified(UNIX_EPOCH + Duration::from_secs(60));
+    /// file.set_times(times)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn set_times(&self, _times: FileTimes) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Changes the modification time of the underlying file.
+    ///
+    /// This is an alias for `set_times(FileTimes::new().set_modified(time))`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let file = fs.create_file("foo.txt")?;
+    /// file.set_modified(UNIX_EPOCH)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn set_modified(&self, time: SystemTime) -> Result<()> {
+        self.set_times(FileTimes::new().set_modified(time))
+    }
+    /// Acquires an exclusive advisory lock on the file, blocking until it can be acquired.
+    ///
+    /// This is the equivalent of [`std::fs::File::lock`]. Locks are held by the open file, shared
+    /// with every handle returned from [`try_clone`], and conflict with locks held through any
+    /// other open of the same file. At most one exclusive lock may be held on a file, and not
+    /// while any shared lock is held. Locks are advisory: they do not prevent reads or writes.
+    ///
+    /// If the open file already holds a lock, it is converted to an exclusive lock. Conversion is
+    /// not atomic; other locks may be acquired in between, as with `flock`. Locks are released by
+    /// [`unlock`] or when every handle to the open file is dropped.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`, for filesystems that
+    /// cannot lock files, as do the defaults of the other locking methods.
+    ///
+    /// [`std::fs::File::lock`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.lock
+    /// [`try_clone`]: trait.File.html#tymethod.try_clone
+    /// [`unlock`]: trait.File.html#method.unlock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let f = fs.create_file("foo.lock")?;
+    /// f.lock()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn lock(&self) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Acquires a shared advisory lock on the file, blocking until it can be acquired.
+    ///
+    /// This is the equivalent of [`std::fs::File::lock_shared`]. Any number of shared locks may be
+    /// held on a file at once. See [`lock`] for how locks are held and released.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`std::fs::File::lock_shared`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.lock_shared
+    /// [`lock`]: trait.File.html#method.lock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let f = fs.create_file("foo.lock")?;
+    /// f.lock_shared()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn lock_shared(&self) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Attempts to acquire an exclusive advisory lock on the file without blocking.
+    ///
+    /// This is the equivalent of [`std::fs::File::try_lock`]. If another open of the file holds a
+    /// lock, this returns [`TryLockError::WouldBlock`]. See [`lock`] for how locks are held and
+    /// released.
+    ///
+    /// The default implementation returns `TryLockError::Error` with an error of kind
+    /// `Unsupported`.
+    ///
+    /// [`std::fs::File::try_lock`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.try_lock
+    /// [`TryLockError::WouldBlock`]: enum.TryLockError.html#variant.WouldBlock
+    /// [`lock`]: trait.File.html#method.lock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let first = fs.create_file("foo.lock")?;
+    /// let second = fs.open_file("foo.lock")?;
+    /// assert!(first.try_lock().is_ok());
+    /// assert!(matches!(second.try_lock(), Err(TryLockError::WouldBlock)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn try_lock(&self) -> ::std::result::Result<(), TryLockError> {
+        Err(TryLockError::Error(io::Error::from(ErrorKind::Unsupported)))
+    }
+    /// Attempts to acquire a shared advisory lock on the file without blocking.
+    ///
+    /// This is the equivalent of [`std::fs::File::try_lock_shared`]. If another open of the file
+    /// holds an exclusive lock, this returns [`TryLockError::WouldBlock`]. See [`lock`] for how
+    /// locks are held and released.
+    ///
+    /// The default implementation returns `TryLockError::Error` with an error of kind
+    /// `Unsupported`.
+    ///
+    /// [`std::fs::File::try_lock_shared`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.try_lock_shared
+    /// [`TryLockError::WouldBlock`]: enum.TryLockError.html#variant.WouldBlock
+    /// [`lock`]: trait.File.html#method.lock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let first = fs.create_file("foo.lock")?;
+    /// let second = fs.open_file("foo.lock")?;
+    /// assert!(first.try_lock_shared().is_ok());
+    /// assert!(second.try_lock_shared().is_ok());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn try_lock_shared(&self) -> ::std::result::Result<(), TryLockError> {
+        Err(TryLockError::Error(io::Error::from(ErrorKind::Unsupported)))
+    }
+    /// Releases any lock held by the open file.
+    ///
+    /// This is the equivalent of [`std::fs::File::unlock`]. Unlocking a file that holds no lock
+    /// does nothing.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`std::fs::File::unlock`]: https://doc.rust-lang.org/std/fs/struct.File.html#method.unlock
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let f = fs.create_file("foo.lock")?;
+    /// f.lock()?;
+    /// f.unlock()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn unlock(&self) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+}
+
+/// Representation of the timestamps that can be set on a file.
+///
+/// This struct replaces [`std::fs::FileTimes`], which cannot be inspected and therefore cannot be
+/// implemented by other filesystems. It is used with [`File::set_times`] and
+/// [`GenFS::set_times`].
+///
+/// [`std::fs::FileTimes`]: https://doc.rust-lang.org/std/fs/struct.FileTimes.html
+/// [`File::set_times`]: trait.File.html#method.set_times
+/// [`GenFS::set_times`]: trait.GenFS.html#method.set_times
+///
+/// # Examples
+///
+/// ```
+/// use std::time::UNIX_EPOCH;
+///
+/// use rsfs::FileTimes;
+///
+/// let times = FileTimes::new().set_modified(UNIX_EPOCH);
+/// assert_eq!(times.modified(), Some(UNIX_EPOCH));
+/// assert_eq!(times.accessed(), None);
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileTimes {
+    accessed: Option<SystemTime>,
+    modified: Option<SystemTime>,
+}
+
+impl FileTimes {
+    /// Creates a new `FileTimes` with no times set.
+    pub fn new() -> FileTimes {
+        FileTimes::default()
+    }
+    /// Sets the last access time of a file.
+    pub fn set_accessed(mut self, t: SystemTime) -> FileTimes {
+        self.accessed = Some(t); self
+    }
+    /// Sets the last modified time of a file.
+    pub fn set_modified(mut self, t: SystemTime) -> FileTimes {
+        self.modified = Some(t); self
+    }
+    /// Returns the last access time to set, if any.
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.accessed
+    }
+    /// Returns the last modified time to set, if any.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+}
+
+/// An error returned from [`File::try_lock`] and [`File::try_lock_shared`].
+///
+/// This mirrors `std::fs::TryLockError`, which is only available since Rust 1.89.
+///
+/// [`File::try_lock`]: trait.File.html#method.try_lock
+/// [`File::try_lock_shared`]: trait.File.html#method.try_lock_shared
+///
+/// # Examples
+///
+/// ```
+/// use std::io;
+///
+/// use rsfs::TryLockError;
+///
+/// let err = io::Error::from(TryLockError::WouldBlock);
+/// assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+/// ```
+#[derive(Debug)]
+pub enum TryLockError {
+    /// The lock could not be acquired because another open of the file holds a conflicting lock.
+    WouldBlock,
+    /// The lock could not be acquired for another reason.
+    Error(io::Error),
+}
+
+impl fmt::Display for TryLockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TryLockError::WouldBlock => f.write_str("lock acquisition failed: would block"),
+            TryLockError::Error(ref err) => write!(f, "lock acquisition failed: {}", err),
+        }
+    }
+}
+
+impl error::Error for TryLockError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TryLockError::WouldBlock => None,
+            TryLockError::Error(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<TryLockError> for io::Error {
+    /// Converts to an I/O error of kind `WouldBlock`, or the underlying error.
+    fn from(err: TryLockError) -> io::Error {
+        match err {
+            TryLockError::WouldBlock => io::Error::from(ErrorKind::WouldBlock),
+            TryLockError::Error(err) => err,
+        }
+    }
 }
 
 /// Returned from [`Metadata::file_type`], this trait represents the type of a file.
@@ -758,7 +1063,7 @@ pub trait OpenOptions: Clone + Debug {
     /// * Attempting to open a file with access that the user lacks permissions for
     /// * Filesystem-level errors (full disk, etc)
     /// * Invalid combinations of open options (truncate without write access, no access mode set,
-    /// etc)
+    ///   etc)
     ///
     /// # Examples
     ///
@@ -977,6 +1282,39 @@ pub trait GenFS: Send + Sync {
     /// ```
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()>;
 
+    /// Returns the current working directory as an absolute path.
+    ///
+    /// Relative paths given to every other function are resolved from this directory. This is
+    /// the equivalent of [`std::env::current_dir`].
+    ///
+    /// The default implementation returns an error of kind `Unsupported`, for filesystems without
+    /// a current directory, as does the default of [`set_current_dir`].
+    ///
+    /// [`std::env::current_dir`]: https://doc.rust-lang.org/std/env/fn.current_dir.html
+    /// [`set_current_dir`]: #method.set_current_dir
+    ///
+    /// # Errors
+    ///
+    /// While there may be more error cases, this function will error in the following cases:
+    ///
+    /// * The current directory has been removed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// println!("The current directory is {}", fs.current_dir()?.display());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn current_dir(&self) -> Result<PathBuf> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+
     /// Creates a new hard link on the filesystem.
     ///
     /// The `dst` path will be a link pointing to the `src` path.
@@ -1182,6 +1520,40 @@ pub trait GenFS: Send + Sync {
     /// ```
     fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()>;
 
+    /// Changes the current working directory to `path`, following symlinks.
+    ///
+    /// This is the equivalent of [`std::env::set_current_dir`]. Note that for the disk
+    /// filesystem, this changes the current directory of the whole process.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`std::env::set_current_dir`]: https://doc.rust-lang.org/std/env/fn.set_current_dir.html
+    ///
+    /// # Errors
+    ///
+    /// While there may be more error cases, this function will error in the following cases:
+    ///
+    /// * `path` does not exist or is not a directory
+    /// * User lacks permissions to enter the directory at `path`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_dir_all("/some/dir")?;
+    /// fs.set_current_dir("/some")?;
+    /// assert!(fs.metadata("dir")?.is_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn set_current_dir<P: AsRef<Path>>(&self, _path: P) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+
     /// Changes the permissions of a file or directory.
     ///
     /// # Errors
@@ -1206,6 +1578,43 @@ pub trait GenFS: Send + Sync {
     /// ```
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()>;
 
+    /// Changes the timestamps of a file or directory, following symlinks.
+    ///
+    /// Times that are not set in `times` are left unchanged. This is the path based equivalent of
+    /// [`File::set_times`], similar to `utimensat(2)`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`File::set_times`]: trait.File.html#method.set_times
+    ///
+    /// # Errors
+    ///
+    /// While there may be more error cases, this function will error in the following cases:
+    ///
+    /// * `path` does not exist
+    /// * User lacks permissions to change the timestamps of the entry at `path`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_file("foo.txt")?;
+    ///
+    /// fs.set_times("foo.txt", FileTimes::new().set_modified(UNIX_EPOCH))?;
+    /// assert_eq!(fs.metadata("foo.txt")?.modified()?, UNIX_EPOCH);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    fn set_times<P: AsRef<Path>>(&self, _path: P, _times: FileTimes) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+
     /// Query the metadata about a file without following symlinks.
     ///
     /// # Errors
@@ -1325,4 +1734,150 @@ pub trait GenFS: Send + Sync {
     /// # }
     /// ```
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File>;
+
+    /// Reads the entire contents of a file into a bytes vector.
+    ///
+    /// This method replaces [`std::fs::read`]. The provided implementation opens the file with
+    /// [`open_file`] and reads it to the end; implementations may override it with something
+    /// faster.
+    ///
+    /// [`std::fs::read`]: https://doc.rust-lang.org/std/fs/fn.read.html
+    /// [`open_file`]: trait.GenFS.html#tymethod.open_file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `path` does not exist or cannot be read. Other errors
+    /// may be returned according to [`OpenOptions::open`].
+    ///
+    /// [`OpenOptions::open`]: trait.OpenOptions.html#tymethod.open
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.write("foo.txt", b"hello")?;
+    /// assert_eq!(fs.read("foo.txt")?, b"hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        let mut file = self.open_file(path)?;
+        let mut data = Vec::with_capacity(file.metadata().map_or(0, |m| m.len() as usize));
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+    /// Reads the entire contents of a file into a string.
+    ///
+    /// This method replaces [`std::fs::read_to_string`]. The provided implementation uses
+    /// [`read`].
+    ///
+    /// [`std::fs::read_to_string`]: https://doc.rust-lang.org/std/fs/fn.read_to_string.html
+    /// [`read`]: trait.GenFS.html#method.read
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `path` does not exist or cannot be read, and an error
+    /// of kind `InvalidData` if the contents are not valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.write("foo.txt", "hello")?;
+    /// assert_eq!(fs.read_to_string("foo.txt")?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.utf8_error()))
+    }
+    /// Writes a slice as the entire contents of a file.
+    ///
+    /// This method replaces [`std::fs::write`]. It creates the file if it does not exist and
+    /// truncates it if it does. The provided implementation uses [`create_file`]; implementations
+    /// may override it with something faster.
+    ///
+    /// [`std::fs::write`]: https://doc.rust-lang.org/std/fs/fn.write.html
+    /// [`create_file`]: trait.GenFS.html#tymethod.create_file
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.write("foo.txt", b"Lorem ipsum")?;
+    /// fs.write("bar.txt", "dolor sit")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<()> {
+        self.create_file(path)?.write_all(contents.as_ref())
+    }
+    /// Returns whether `path` points at an existing entity, traversing symbolic links.
+    ///
+    /// This method replaces [`Path::exists`]. Any error, including permission errors, is treated
+    /// as the path not existing; use [`try_exists`] to tell them apart.
+    ///
+    /// [`Path::exists`]: https://doc.rust-lang.org/std/path/struct.Path.html#method.exists
+    /// [`try_exists`]: trait.GenFS.html#method.try_exists
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// assert!(!fs.exists("foo.txt"));
+    /// fs.create_file("foo.txt")?;
+    /// assert!(fs.exists("foo.txt"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.metadata(path).is_ok()
+    }
+    /// Returns `Ok(true)` if `path` points at an existing entity, traversing symbolic links, and
+    /// `Ok(false)` if it does not exist.
+    ///
+    /// This method replaces [`std::fs::exists`]. Unlike [`exists`], errors other than the path not
+    /// being found are returned, so a path that cannot be checked is not mistaken for missing.
+    ///
+    /// [`std::fs::exists`]: https://doc.rust-lang.org/std/fs/fn.exists.html
+    /// [`exists`]: trait.GenFS.html#method.exists
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// assert!(!fs.try_exists("foo.txt")?);
+    /// fs.create_file("foo.txt")?;
+    /// assert!(fs.try_exists("foo.txt")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn try_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        match self.metadata(path) {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
 }
@@ -0,0 +1,471 @@
+//! Matching glob patterns against the paths of any [`GenFS`].
+//!
+//! [`glob`] lists every path on a filesystem that matches a shell-style pattern, using only
+//! `GenFS` methods, so that it behaves the same on the disk and in-memory filesystems.
+//!
+//! Patterns support the following syntax:
+//!
+//! * `?` matches any single character.
+//! * `*` matches any sequence of characters, including none.
+//! * `**` matches any sequence of directories, including none, as long as it is a whole path
+//!   component. It does not walk into symlinks to directories.
+//! * `[abc]` matches any one of the characters in the brackets, and `[a-z]` matches any character
+//!   in the range. `[!abc]` or `[^abc]` matches any character not in the brackets. A `]` directly
+//!   after the opening bracket (or its negation) is matched literally, which also makes `[*]`,
+//!   `[?]`, `[[]`, and `[{]` the way to match a special character literally.
+//! * `{a,b}` matches either alternative. Alternatives can hold any other syntax, including path
+//!   separators and further braces.
+//!
+//! Wildcards never match a path separator and, unlike in most shells, do match names that start
+//! with a `.`.
+//!
+//! [`GenFS`]: ../trait.GenFS.html
+//! [`glob`]: ../fn.glob.html
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::vec::IntoIter;
+
+use errors::ENOTDIR;
+use fs::{DirEntry, GenFS, Metadata};
+use walk::{self, walk_dir};
+
+/// Returns every path on `fs` that matches `pattern`, sorted.
+///
+/// Components of the pattern without any special characters are joined onto the path without
+/// listing their directory. Every other component is matched against the names listed by
+/// `read_dir`. Paths that cannot be listed or inspected, such as a directory without read
+/// permission, are yielded as errors in sorted position; a missing path is not an error and simply
+/// does not match.
+///
+/// # Errors
+///
+/// This function returns a [`PatternError`] if `pattern` has an unclosed `[` or `{`.
+///
+/// [`PatternError`]: glob/struct.PatternError.html
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+///
+/// use rsfs::*;
+/// use rsfs::mem::FS;
+/// # fn foo() -> Result<(), Box<dyn std::error::Error>> {
+/// let fs = FS::new();
+/// fs.create_dir_all("config/app/db")?;
+/// fs.create_file("config/base.toml")?;
+/// fs.create_file("config/app/db/pool.toml")?;
+/// fs.create_file("config/app/notes.txt")?;
+///
+/// let paths = glob(&fs, "config/**/*.{toml,yaml}")?.collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(paths, vec![PathBuf::from("config/app/db/pool.toml"),
+///                        PathBuf::from("config/base.toml")]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn glob<F: GenFS>(fs: &F, pattern: &str) -> Result<Paths, PatternError> {
+    let mut found = BTreeMap::new();
+    for alternative in expand(pattern)? {
+        let (base, segs) = parse(&alternative)?;
+        Matcher { fs, found: &mut found }.walk(base, 0, &segs);
+    }
+    Ok(Paths(found.into_iter().collect::<Vec<_>>().into_iter()))
+}
+
+/// An iterator over the paths matched by [`glob`], in sorted order.
+///
+/// [`glob`]: ../fn.glob.html
+#[derive(Debug)]
+pub struct Paths(IntoIter<(PathBuf, Option<io::Error>)>);
+
+impl Iterator for Paths {
+    type Item = Result<PathBuf, walk::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(path, err)| match err {
+            Some(err) => {
+                let depth = path.components().count();
+                Err(walk::Error::new(&path, depth, err))
+            }
+            None => Ok(path),
+        })
+    }
+}
+
+/// An error returned from [`glob`] for an invalid pattern.
+///
+/// [`glob`]: ../fn.glob.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError {
+    pos: usize,
+    msg: &'static str,
+}
+
+impl PatternError {
+    /// Returns the byte position in the pattern of the character that caused the error.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid glob pattern at byte {}: {}", self.pos, self.msg)
+    }
+}
+
+impl error::Error for PatternError {}
+
+impl From<PatternError> for io::Error {
+    fn from(err: PatternError) -> io::Error {
+        io::Error::new(ErrorKind::InvalidInput, err)
+    }
+}
+
+/// expand returns every alternative of a pattern with all braces expanded.
+fn expand(pattern: &str) -> Result<Vec<String>, PatternError> {
+    // Find the first top level brace, skipping brackets so that `[{]` stays literal.
+    let bytes = pattern.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'[' => i = class_end(pattern, i)?,
+            b'{' => break,
+            _ => i += 1,
+        }
+    }
+    if i == bytes.len() {
+        return Ok(vec![pattern.to_owned()]);
+    }
+
+    let open = i;
+    let (mut depth, mut starts) = (0, vec![open + 1]);
+    loop {
+        i += 1;
+        match bytes.get(i) {
+            None => return Err(PatternError { pos: open, msg: "unclosed '{'" }),
+            Some(b'[') => i = class_end(pattern, i)? - 1,
+            Some(b'{') => depth += 1,
+            Some(b'}') if depth > 0 => depth -= 1,
+            Some(b'}') => break,
+            Some(b',') if depth == 0 => starts.push(i + 1),
+            _ => (),
+        }
+    }
+    starts.push(i + 1);
+
+    let (head, tail) = (&pattern[..open], &pattern[i + 1..]);
+    let mut expanded = Vec::new();
+    for alt in starts.windows(2).map(|w| &pattern[w[0]..w[1] - 1]) {
+        expanded.extend(expand(&format!("{}{}{}", head, alt, tail))?);
+    }
+    Ok(expanded)
+}
+
+/// class_end returns the index just past the bracket expression opening at start.
+fn class_end(pattern: &str, start: usize) -> Result<usize, PatternError> {
+    let bytes = pattern.as_bytes();
+    let mut i = start + 1;
+    if bytes.get(i) == Some(&b'!') || bytes.get(i) == Some(&b'^') {
+        i += 1;
+    }
+    if bytes.get(i) == Some(&b']') {
+        i += 1;
+    }
+    match bytes[i..].iter().position(|&b| b == b']') {
+        Some(end) => Ok(i + end + 1),
+        None => Err(PatternError { pos: start, msg: "unclosed '['" }),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+#[derive(Debug, PartialEq)]
+enum Seg {
+    Literal(String),
+    Pattern(Vec<Token>),
+    Recursive,
+}
+
+/// parse splits a brace free pattern into the literal path that every match starts with and the
+/// segments that follow it.
+fn parse(pattern: &str) -> Result<(PathBuf, Vec<Seg>), PatternError> {
+    let mut base = PathBuf::new();
+    let mut segs = Vec::new();
+    for comp in Path::new(pattern).components() {
+        let name = match comp {
+            Component::Normal(name) => name.to_str().expect("pattern is a str"),
+            // Only the root and prefix, or leading . and .. components, can be literal.
+            _ if segs.is_empty() => {
+                base.push(comp.as_os_str());
+                continue;
+            }
+            _ => comp.as_os_str().to_str().expect("pattern is a str"),
+        };
+        let seg = if name == "**" {
+            Seg::Recursive
+        } else if name.contains(['*', '?', '[']) {
+            Seg::Pattern(tokens(name)?)
+        } else if segs.is_empty() {
+            base.push(name);
+            continue;
+        } else {
+            Seg::Literal(name.to_owned())
+        };
+        segs.push(seg);
+    }
+    Ok((base, segs))
+}
+
+/// tokens compiles a single path component.
+fn tokens(name: &str) -> Result<Vec<Token>, PatternError> {
+    let mut tokens = Vec::new();
+    let mut chars = name.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        tokens.push(match c {
+            '?' => Token::Any,
+            '*' if tokens.last() == Some(&Token::Star) => continue,
+            '*' => Token::Star,
+            '[' => {
+                let end = class_end(name, i)?;
+                let mut class = name[i + 1..end - 1].chars().peekable();
+                let negated = class.next_if(|&c| c == '!' || c == '^').is_some();
+                let mut ranges = Vec::new();
+                while let Some(lo) = class.next() {
+                    if class.next_if_eq(&'-').is_none() {
+                        ranges.push((lo, lo));
+                    } else if let Some(hi) = class.next() {
+                        ranges.push((lo, hi));
+                    } else {
+                        // A trailing '-' is literal.
+                        ranges.extend_from_slice(&[(lo, lo), ('-', '-')]);
+                    }
+                }
+                while chars.next_if(|&(j, _)| j < end).is_some() {}
+                Token::Class { negated, ranges }
+            }
+            c => Token::Char(c),
+        });
+    }
+    Ok(tokens)
+}
+
+/// matches returns whether name matches tokens.
+fn matches(tokens: &[Token], name: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let (mut t, mut n) = (0, 0);
+    // On a mismatch, the last star absorbs one more character and we try again from after it.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let matched = match tokens.get(t) {
+            Some(&Token::Star) => {
+                star = Some((t, n));
+                t += 1;
+                continue;
+            }
+            Some(&Token::Any) => true,
+            Some(&Token::Char(c)) => c == name[n],
+            Some(Token::Class { negated, ranges }) => {
+                ranges.iter().any(|&(lo, hi)| lo <= name[n] && name[n] <= hi) != *negated
+            }
+            None => false,
+        };
+        if matched {
+            t += 1;
+            n += 1;
+        } else if let Some((st, sn)) = star {
+            star = Some((st, sn + 1));
+            t = st + 1;
+            n = sn + 1;
+        } else {
+            return false;
+        }
+    }
+    tokens[t..].iter().all(|t| *t == Token::Star)
+}
+
+/// listable returns the path to list for dir, which is empty when matching relative paths.
+fn listable(dir: &Path) -> &Path {
+    if dir.as_os_str().is_empty() { Path::new(".") } else { dir }
+}
+
+struct Matcher<'a, F: GenFS + 'a> {
+    fs:    &'a F,
+    found: &'a mut BTreeMap<PathBuf, Option<io::Error>>,
+}
+
+impl<'a, F: GenFS + 'a> Matcher<'a, F> {
+    fn found(&mut self, path: PathBuf) {
+        self.found.entry(path).or_insert(None);
+    }
+
+    // failed records err at path unless the path simply does not exist. A parent that is not a
+    // directory is ENOTDIR, or ERROR_DIRECTORY on Windows disks.
+    fn failed(&mut self, path: PathBuf, err: io::Error) {
+        let not_dir = err.raw_os_error() == ENOTDIR().raw_os_error() ||
+            cfg!(windows) && err.raw_os_error() == Some(267);
+        if err.kind() != ErrorKind::NotFound && !not_dir {
+            self.found.insert(path, Some(err));
+        }
+    }
+
+    // walk matches segs below dir, which exists unless depth is 0.
+    fn walk(&mut self, dir: PathBuf, depth: usize, segs: &[Seg]) {
+        let (seg, rest) = match segs.split_first() {
+            Some(split) => split,
+            None => {
+                // A relative "**" matches its empty base, which is not a path to yield.
+                if depth > 0 && dir.as_os_str().is_empty() {
+                    return;
+                }
+                if depth > 0 {
+                    return self.found(dir);
+                }
+                return match self.fs.symlink_metadata(listable(&dir)) {
+                    Ok(_) => self.found(dir),
+                    Err(e) => self.failed(dir, e),
+                };
+            }
+        };
+        match *seg {
+            Seg::Literal(ref name) => {
+                let path = dir.join(name);
+                match self.fs.symlink_metadata(&path) {
+                    Ok(_) => self.walk(path, depth + 1, rest),
+                    Err(e) => self.failed(path, e),
+                }
+            }
+            Seg::Pattern(ref tokens) => {
+                let read_dir = match self.fs.read_dir(listable(&dir)) {
+                    Ok(read_dir) => read_dir,
+                    Err(e) => return self.failed(dir, e),
+                };
+                let mut names = Vec::new();
+                for ent in read_dir {
+                    match ent {
+                        Ok(ent) => names.push(ent.file_name()),
+                        Err(e) => return self.failed(dir, e),
+                    }
+                }
+                names.sort();
+                for name in names.into_iter().filter(|n| matches(tokens, &n.to_string_lossy())) {
+                    self.walk(dir.join(name), depth + 1, rest);
+                }
+            }
+            Seg::Recursive => {
+                // Walking "." yields paths starting with "./" that we want relative.
+                let relative = |path: &Path| match path.strip_prefix(listable(&dir)) {
+                    Ok(rel) if rel.as_os_str().is_empty() => dir.clone(),
+                    Ok(rel) => dir.join(rel),
+                    Err(_) => path.to_path_buf(),
+                };
+                let mut dirs = Vec::new();
+                for ent in walk_dir(self.fs, listable(&dir)).sort_by_file_name() {
+                    match ent {
+                        Ok(ref ent) if !rest.is_empty() && !ent.metadata().is_dir() => (),
+                        Ok(ent) => dirs.push(relative(ent.path())),
+                        Err(e) => self.failed(relative(e.path()), e.into_io_error()),
+                    }
+                }
+                for found in dirs {
+                    self.walk(found, depth + 1, rest);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use errors::EACCES;
+    use fs::GenFS;
+    use mem::unix::{FS, Permissions};
+    use unix_ext::{GenFSExt, PermissionsExt};
+
+    use super::{expand, glob, matches, tokens};
+
+    #[test]
+    fn braces() {
+        assert_eq!(expand("a").unwrap(), vec!["a"]);
+        assert_eq!(expand("{a,b}/{c,d}").unwrap(), vec!["a/c", "a/d", "b/c", "b/d"]);
+        assert_eq!(expand("x{a,{b,c}d,}y").unwrap(), vec!["xay", "xbdy", "xcdy", "xy"]);
+        assert_eq!(expand("[{]{a,b}").unwrap(), vec!["[{]a", "[{]b"]);
+        assert_eq!(expand("a{b,c").unwrap_err().pos(), 1);
+        assert_eq!(expand("a[b").unwrap_err().pos(), 1);
+    }
+
+    #[test]
+    fn match_names() {
+        let m = |pat, name| matches(&tokens(pat).unwrap(), name);
+        assert!(m("*", "") && m("*", ".hidden") && m("a*", "abc") && !m("a*", "ba"));
+        assert!(m("*.toml", "a.toml") && !m("*.toml", "a.toml.bak"));
+        assert!(m("a*b*c", "aXbYbZc") && !m("a*b*c", "aXbYbZ"));
+        assert!(m("?b", "ab") && !m("?b", "b"));
+        assert!(m("[a-c]x", "bx") && !m("[a-c]x", "dx"));
+        assert!(m("[!a-c]x", "dx") && !m("[^a-c]x", "ax"));
+        assert!(m("[]]", "]") && m("[!]]", "a") && m("[*]", "*") && !m("[*]", "a"));
+        assert!(m("[a-]", "-") && m("é?", "éa"));
+    }
+
+    #[test]
+    fn glob_fs() {
+        let fs = FS::new();
+        assert!(fs.create_dir_all("/config/app/db").is_ok());
+        assert!(fs.create_dir_all("/config/locked").is_ok());
+        assert!(fs.create_file("/config/a.toml").is_ok());
+        assert!(fs.create_file("/config/app/b.toml").is_ok());
+        assert!(fs.create_file("/config/app/db/c.toml").is_ok());
+        assert!(fs.create_file("/config/app/db/c.yaml").is_ok());
+        assert!(fs.create_file("/config/locked/d.toml").is_ok());
+        assert!(fs.symlink("/config/app", "/config/link").is_ok());
+
+        let matched = |pat| -> Vec<String> {
+            glob(&fs, pat).unwrap()
+                .map(|p| p.unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(matched("/config/**/*.toml"),
+                   vec!["/config/a.toml", "/config/app/b.toml", "/config/app/db/c.toml",
+                        "/config/locked/d.toml"]);
+        assert_eq!(matched("/config/*/db/c.{toml,yaml}"),
+                   vec!["/config/app/db/c.toml", "/config/app/db/c.yaml",
+                        "/config/link/db/c.toml", "/config/link/db/c.yaml"]);
+        assert_eq!(matched("/config/{app,missing}/b.toml"), vec!["/config/app/b.toml"]);
+        assert_eq!(matched("/config/a.toml/*"), Vec::<String>::new());
+        assert_eq!(matched("/config/app/**"),
+                   vec!["/config/app", "/config/app/b.toml", "/config/app/db",
+                        "/config/app/db/c.toml", "/config/app/db/c.yaml"]);
+
+        assert!(fs.set_current_dir("/config").is_ok());
+        assert_eq!(matched("*/b.toml"), vec!["app/b.toml", "link/b.toml"]);
+        assert_eq!(matched("**/c.yaml"), vec!["app/db/c.yaml"]);
+        assert_eq!(matched("**"),
+                   vec!["a.toml", "app", "app/b.toml", "app/db", "app/db/c.toml",
+                        "app/db/c.yaml", "link", "locked", "locked/d.toml"]);
+
+        // Unreadable directories are errors in sorted position; the rest still match.
+        assert!(fs.set_permissions("/config/locked", Permissions::from_mode(0o300)).is_ok());
+        let globbed: Vec<_> = glob(&fs, "/config/**/*.toml").unwrap().collect();
+        assert_eq!(globbed.len(), 4);
+        let err = globbed[3].as_ref().unwrap_err();
+        assert_eq!(err.path(), PathBuf::from("/config/locked"));
+        assert_eq!(err.io_error().raw_os_error(), EACCES().raw_os_error());
+
+        // A relative "**" never matches the current directory itself, even when it is empty.
+        assert!(fs.create_dir("/empty").is_ok());
+        assert!(fs.set_current_dir("/empty").is_ok());
+        assert!(matched("**").is_empty());
+    }
+}
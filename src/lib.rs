@@ -7,15 +7,14 @@
 //! handles errors properly because generally, you are not testing on an already broken machine.
 //! You could attempt to set up FUSE, which, although doable, is involved.
 //!
-//! This crate provides a generic filesystem with various implementations. At the moment, only
-//! normal (non-injectable) disk and in-memory implementations are provided. In the future, an
-//! error-injectable shim around the in-memory file system will be provided to help trigger
-//! filesystem errors in unit tests.
+//! This crate provides a generic filesystem with various implementations. Normal disk and
+//! in-memory implementations are provided, as well as an error-injectable shim around the
+//! in-memory filesystem to help trigger filesystem errors in unit tests.
 //!
 //! The intent of this crate is for you to use the generic [`rsfs::GenFS`] everywhere where you use
 //! `std::fs` in your code. Your `main.rs` can use [`rsfs::disk::FS`] to get default disk behavior
-//! while your tests use `rsfs::mem::test::FS` (once it exists) to get an in-memory filesystem that
-//! can have errors injected.
+//! while your tests use [`rsfs::mem::test::FS`] to get an in-memory filesystem that can have
+//! errors injected.
 //!
 //! # An in-memory filesystem
 //!
@@ -25,20 +24,21 @@
 //! `rsfs::mem` is a platform specific module that `pub use`s the proper module based off the
 //! builder's platform. To get a platform agnostic module, you need to use the in-memory platform
 //! you desire. Thus, if you use [`rsfs::mem::unix`], you will get an in-memory system that follows
-//! Unix semantics. If you use `rsfs::mem::windows`, you will get an in-memory system that follows
-//! Windows semantics (however, you would have to write that module first).
+//! Unix semantics. If you use [`rsfs::mem::windows`], you will get an in-memory system that
+//! follows Windows semantics.
 //!
 //! This means that `rsfs::mem` aims to essentially be an in-memory drop in for `std::fs` and
 //! forces you to structure your code in a cross-platform way. `rsfs::mem::unix` aims to be a Unix
-//! specific drop in that buys you Unix semantics on all platforms.
+//! specific drop in that buys you Unix semantics on all platforms, and `rsfs::mem::windows` does
+//! the same for Windows semantics. Platform specific functionality is available through the
+//! [`rsfs::unix_ext`] and [`rsfs::windows_ext`] traits.
 //!
 //! # Caveats
 //!
-//! The current in-memory filesystems are only implemented for Unix. This means that the only
-//! cross-platform in-memory filesystem is specifically `rsfs::mem::unix`. Window's users can help
-//! by implementing the in-memory analog for Windows.
+//! The in-memory error injecting filesystem, `rsfs::mem::test`, currently only wraps
+//! `rsfs::mem::unix`.
 //!
-//! The in-memory filesystem is implemented using some unsafe code. I deemed this necessary after
+//! The Unix in-memory filesystem is implemented using some unsafe code. I deemed this necessary after
 //! working with the recursive data structure that is a filesystem through an `Arc`/`RwLock` for
 //! too long. The code is pretty well tested; there should be no problems. The usage of unsafe, in
 //! my opinion, makes the code much clearer, but it did require special care in some functions.
@@ -53,15 +53,26 @@
 //! [`std::fs`]: https://doc.rust-lang.org/std/fs/
 //! [`rsfs::GenFS`]: trait.GenFS.html
 //! [`rsfs::disk::FS`]: disk/struct.FS.html
+//! [`rsfs::mem::test::FS`]: mem/test/struct.FS.html
 //! [`rsfs::mem`]: mem/index.html
 //! [`rsfs::mem::unix`]: mem/unix/index.html
+//! [`rsfs::mem::windows`]: mem/windows/index.html
+//! [`rsfs::unix_ext`]: unix_ext/index.html
+//! [`rsfs::windows_ext`]: windows_ext/index.html
 
 mod fs;
 pub use fs::*;
+pub use glob::glob;
+pub use walk::walk_dir;
 
+pub mod disk;
 pub mod mem;
 pub mod unix_ext;
+pub mod windows_ext;
 
-mod errors;
+pub mod glob;
+pub mod walk;
+
+pub mod errors;
 mod path_parts;
 mod ptr;
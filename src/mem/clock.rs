@@ -0,0 +1,140 @@
+//! Clocks used to timestamp entries in the in-memory filesystems.
+//!
+//! Every modified, accessed, and created time in [`mem::unix`] and [`mem::windows`] comes from the
+//! [`Clock`] the filesystem was created with. By default, that is the [`SystemClock`]. Tests that
+//! depend on timestamps can instead use a [`MockClock`], which only moves when told to, or a
+//! [`FrozenClock`], which never moves.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! use rsfs::*;
+//! use rsfs::mem::FS;
+//! use rsfs::mem::clock::{Clock, MockClock};
+//!
+//! let clock = MockClock::new(UNIX_EPOCH);
+//! let fs = FS::with_clock(clock.clone());
+//!
+//! fs.create_file("old").unwrap();
+//! clock.advance(Duration::from_secs(8 * 24 * 60 * 60));
+//! fs.create_file("new").unwrap();
+//!
+//! let age = clock.now().duration_since(fs.metadata("old").unwrap().modified().unwrap()).unwrap();
+//! assert!(age > Duration::from_secs(7 * 24 * 60 * 60));
+//! ```
+//!
+//! [`mem::unix`]: ../unix/index.html
+//! [`mem::windows`]: ../windows/index.html
+//! [`Clock`]: trait.Clock.html
+//! [`SystemClock`]: struct.SystemClock.html
+//! [`MockClock`]: struct.MockClock.html
+//! [`FrozenClock`]: struct.FrozenClock.html
+
+extern crate parking_lot;
+
+use self::parking_lot::Mutex;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time for an in-memory filesystem.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// A `Clock` that returns the real time, [`SystemTime::now`].
+///
+/// [`SystemTime::now`]: https://doc.rust-lang.org/std/time/struct.SystemTime.html#method.now
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `Clock` that only moves when it is advanced or set.
+///
+/// Clones of a `MockClock` share the same time, so a test can keep a clone to move the time of a
+/// filesystem it gave the clock to.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use rsfs::mem::clock::{Clock, MockClock};
+///
+/// let clock = MockClock::new(UNIX_EPOCH);
+/// let shared = clock.clone();
+/// shared.advance(Duration::from_secs(5));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(5));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<SystemTime>>);
+
+impl MockClock {
+    /// Creates a `MockClock` starting at `start`.
+    pub fn new(start: SystemTime) -> MockClock {
+        MockClock(Arc::new(Mutex::new(start)))
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock() += by;
+    }
+
+    /// Sets the clock to `to`, which may be before the current time.
+    pub fn set(&self, to: SystemTime) {
+        *self.0.lock() = to;
+    }
+}
+
+impl Default for MockClock {
+    /// Creates a `MockClock` starting at the Unix epoch.
+    fn default() -> Self {
+        MockClock::new(UNIX_EPOCH)
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock()
+    }
+}
+
+/// A `Clock` that always returns the same time.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::UNIX_EPOCH;
+///
+/// use rsfs::mem::clock::{Clock, FrozenClock};
+///
+/// let clock = FrozenClock::new(UNIX_EPOCH);
+/// assert_eq!(clock.now(), UNIX_EPOCH);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct FrozenClock(SystemTime);
+
+impl FrozenClock {
+    /// Creates a `FrozenClock` that is stuck at `at`.
+    pub fn new(at: SystemTime) -> FrozenClock {
+        FrozenClock(at)
+    }
+}
+
+impl Clock for FrozenClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// `SharedClock` is how the in-memory filesystems hold on to their clock.
+pub(crate) type SharedClock = Arc<dyn Clock>;
@@ -0,0 +1,260 @@
+//! Structured differences between two in-memory filesystems.
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use mem::host::{self, Node, Special};
+use mem::sparse::Data;
+
+/// The type of an entry in a filesystem, as reported by [`Change::Type`].
+///
+/// [`Change::Type`]: enum.Change.html#variant.Type
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Dir,
+    /// A symlink.
+    Symlink,
+    /// A FIFO.
+    Fifo,
+    /// A Unix domain socket.
+    Socket,
+    /// A block device.
+    BlockDevice,
+    /// A character device.
+    CharDevice,
+}
+
+/// A single difference between two filesystems, as returned by [`diff`].
+///
+/// Changes are relative to the first filesystem: `Added` paths exist only in the second and
+/// `Removed` paths exist only in the first. Every change is reported at the highest path it
+/// applies to: the contents of an added or removed directory, or of a directory that changed
+/// type, are not listed separately.
+///
+/// [`diff`]: fn.diff.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Change {
+    /// The path exists only in the second filesystem.
+    Added(PathBuf),
+    /// The path exists only in the first filesystem.
+    Removed(PathBuf),
+    /// The file at `path` has different contents. `ranges` are the byte ranges that differ,
+    /// including the range that only the longer of the two files has.
+    Content {
+        path:   PathBuf,
+        ranges: Vec<Range<u64>>,
+    },
+    /// The entry at `path` has different permissions. On Windows, modes only differ in whether
+    /// the write bits are set.
+    Mode {
+        path: PathBuf,
+        from: u32,
+        to:   u32,
+    },
+    /// The entry at `path` is of a different type.
+    Type {
+        path: PathBuf,
+        from: EntryKind,
+        to:   EntryKind,
+    },
+    /// The symlink at `path` points somewhere else.
+    Symlink {
+        path: PathBuf,
+        from: PathBuf,
+        to:   PathBuf,
+    },
+    /// The device file at `path` refers to a different device.
+    Device {
+        path: PathBuf,
+        from: u64,
+        to:   u64,
+    },
+    /// The file at `path` shares its contents with a different set of hard links. `from` and `to`
+    /// are the other names of the file, sorted.
+    Links {
+        path: PathBuf,
+        from: Vec<PathBuf>,
+        to:   Vec<PathBuf>,
+    },
+}
+
+fn kind(node: &Node) -> EntryKind {
+    match node.kind {
+        host::Kind::Dir => EntryKind::Dir,
+        host::Kind::File(_) => EntryKind::File,
+        host::Kind::Symlink { .. } => EntryKind::Symlink,
+        host::Kind::Special { ftyp, .. } => match ftyp {
+            Special::Fifo => EntryKind::Fifo,
+            Special::Socket => EntryKind::Socket,
+            Special::BlockDevice => EntryKind::BlockDevice,
+            Special::CharDevice => EntryKind::CharDevice,
+        },
+    }
+}
+
+/// names maps every file path to the other paths that share its link.
+fn names(nodes: &[Node]) -> HashMap<&Path, Vec<PathBuf>> {
+    let mut groups: HashMap<(u64, u64), Vec<&Path>> = HashMap::new();
+    for node in nodes {
+        if let Some(link) = node.link {
+            groups.entry(link).or_default().push(&node.path);
+        }
+    }
+    let mut names = HashMap::new();
+    for node in nodes {
+        if let host::Kind::File(_) = node.kind {
+            let mut others: Vec<PathBuf> = node.link
+                .and_then(|link| groups.get(&link))
+                .into_iter()
+                .flatten()
+                .filter(|other| **other != node.path)
+                .map(|other| other.to_path_buf())
+                .collect();
+            others.sort();
+            names.insert(node.path.as_path(), others);
+        }
+    }
+    names
+}
+
+/// ranges returns the byte ranges that differ between from and to. Holes in both files read the
+/// same, so only the bytes under an extent of either file are compared.
+fn ranges(from: &Data, to: &Data) -> Vec<Range<u64>> {
+    let mut ranges: Vec<Range<u64>> = Vec::new();
+    let common = cmp::min(from.len(), to.len());
+    let mut spans: Vec<Range<usize>> = from.extents()
+        .chain(to.extents())
+        .map(|(start, extent)| start..cmp::min(start + extent.len(), common))
+        .filter(|span| span.start < span.end)
+        .collect();
+    spans.sort_by_key(|span| span.start);
+
+    let (mut l, mut r) = (Vec::new(), Vec::new());
+    let mut next = 0; // the first byte not yet compared
+    for span in spans {
+        let start = cmp::max(span.start, next);
+        if start >= span.end {
+            continue;
+        }
+        l.resize(span.end - start, 0);
+        r.resize(span.end - start, 0);
+        from.read_at(start, &mut l);
+        to.read_at(start, &mut r);
+        for i in (0..l.len()).filter(|&i| l[i] != r[i]) {
+            let i = (start + i) as u64;
+            match ranges.last_mut() {
+                Some(last) if last.end == i => last.end += 1,
+                _ => ranges.push(i..i + 1),
+            }
+        }
+        next = span.end;
+    }
+    let (common, longest) = (common as u64, cmp::max(from.len(), to.len()) as u64);
+    if common < longest {
+        match ranges.last_mut() {
+            Some(last) if last.end == common => last.end = longest,
+            _ => ranges.push(common..longest),
+        }
+    }
+    ranges
+}
+
+/// changes compares two trees listed as nodes, reporting paths joined onto root.
+pub(crate) fn changes(from: &[Node], to: &[Node], root: &Path) -> Vec<Change> {
+    let mut all: BTreeMap<&Path, (Option<&Node>, Option<&Node>)> = BTreeMap::new();
+    for node in from {
+        all.entry(&node.path).or_default().0 = Some(node);
+    }
+    for node in to {
+        all.entry(&node.path).or_default().1 = Some(node);
+    }
+    let (from_names, to_names) = (names(from), names(to));
+
+    let mut changes = Vec::new();
+    let mut covered: Option<&Path> = None;
+    for (&path, &(l, r)) in &all {
+        if covered.is_some_and(|covered| path.starts_with(covered)) {
+            continue;
+        }
+        let full = root.join(path);
+        let (l, r) = match (l, r) {
+            (Some(l), Some(r)) => (l, r),
+            (l, _) => {
+                covered = Some(path);
+                changes.push(if l.is_some() { Change::Removed(full) } else { Change::Added(full) });
+                continue;
+            }
+        };
+        if kind(l) != kind(r) {
+            covered = Some(path);
+            changes.push(Change::Type { path: full, from: kind(l), to: kind(r) });
+            continue;
+        }
+        match (&l.kind, &r.kind) {
+            (host::Kind::File(ref ld), host::Kind::File(ref rd)) => {
+                if ld != rd {
+                    changes.push(Change::Content { path: full.clone(), ranges: ranges(ld, rd) });
+                }
+                if from_names[path] != to_names[path] {
+                    changes.push(Change::Links {
+                        path: full.clone(),
+                        from: from_names[path].iter().map(|p| root.join(p)).collect(),
+                        to:   to_names[path].iter().map(|p| root.join(p)).collect(),
+                    });
+                }
+            }
+            (host::Kind::Symlink { target: ref lt, .. },
+             host::Kind::Symlink { target: ref rt, .. }) if lt != rt => {
+                changes.push(Change::Symlink {
+                    path: full.clone(),
+                    from: lt.clone(),
+                    to:   rt.clone(),
+                });
+            }
+            (host::Kind::Special { rdev: lr, .. }, host::Kind::Special { rdev: rr, .. })
+                if lr != rr => {
+                changes.push(Change::Device { path: full.clone(), from: *lr, to: *rr });
+            }
+            _ => (),
+        }
+        if l.mode != r.mode {
+            changes.push(Change::Mode { path: full, from: l.mode, to: r.mode });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use mem::sparse::Data;
+
+    fn dense(from: &[u8], to: &[u8]) -> Vec<::std::ops::Range<u64>> {
+        super::ranges(&Data::from(from.to_vec()), &Data::from(to.to_vec()))
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(dense(b"abc", b"abc"), vec![]);
+        assert_eq!(dense(b"abcdef", b"aXYdeZ"), vec![1..3, 5..6]);
+        assert_eq!(dense(b"abc", b"abXYZ"), vec![2..5]);
+        assert_eq!(dense(b"abcde", b"ab"), vec![2..5]);
+        assert_eq!(dense(b"", b"ab"), vec![0..2]);
+
+        // Huge sparse files are compared by their extents, never filled in.
+        let (mut from, mut to) = (Data::default(), Data::default());
+        from.set_len(1 << 40);
+        from.write_at(1 << 30, b"abc");
+        to.write_at(1 << 30, b"aXc");
+        // Written zeros read the same as a hole.
+        to.write_at(1 << 39, b"\0Y");
+        to.set_len(1 << 41);
+        let at = 1 << 30;
+        let y = 1 << 39 | 1;
+        assert_eq!(super::ranges(&from, &to), vec![at + 1..at + 2, y..y + 1, 1 << 40..1 << 41]);
+    }
+}
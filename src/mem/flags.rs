@@ -0,0 +1,44 @@
+//! Open flags for the in-memory Unix filesystem.
+//!
+//! `OpenOptionsExt::custom_flags` passes raw `open(2)` flags, so we understand them by their Linux
+//! values for the target architecture, which `unix_ext` exports on Linux. Other hosts have their
+//! own values, but we understand the Linux values there too.
+
+use std::io::{Error, ErrorKind, Result};
+
+use unix_ext::flags::{O_APPEND, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_EXCL, O_LARGEFILE,
+                      O_NOATIME, O_NOCTTY, O_NOFOLLOW, O_NONBLOCK, O_SYNC, O_TMPFILE, O_TRUNC};
+
+/// The access mode bits, which the Rust options set instead.
+const O_ACCMODE: i32 = 0o3;
+
+/// The flags we understand besides the access mode.
+const KNOWN: i32 = O_CREAT | O_EXCL | O_NOCTTY | O_TRUNC | O_APPEND | O_NONBLOCK | O_SYNC
+    | O_NOATIME | O_CLOEXEC | O_TMPFILE | O_LARGEFILE | O_DIRECTORY | O_NOFOLLOW;
+
+/// check returns flags without their access mode, failing if any flag is not one we understand.
+pub(crate) fn check(flags: i32) -> Result<i32> {
+    let flags = flags & !O_ACCMODE;
+    let unknown = flags & !KNOWN;
+    if unknown != 0 {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("unsupported open flags: {:#o}", unknown)));
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn check() {
+        assert_eq!(super::check(O_CREAT | O_NOFOLLOW | 0o2).unwrap(), O_CREAT | O_NOFOLLOW);
+        assert_eq!(super::check(O_TMPFILE).unwrap(), O_TMPFILE);
+        let err = super::check(O_CLOEXEC | 1 << 30).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "unsupported open flags: 0o10000000000");
+    }
+}
@@ -0,0 +1,315 @@
+//! Copying trees between the host filesystem and the in-memory filesystems.
+//!
+//! Both in-memory filesystems import and export through a flat list of `Node`s: `read` lists a
+//! host tree as nodes, `import` recreates nodes through an in-memory filesystem's public API, and
+//! `write` recreates nodes that an in-memory filesystem listed from its own tree on the host. Tar
+//! archives go through the same nodes; see the `tar` module.
+
+use std::collections::HashMap;
+use std::fs as rs_fs;
+use std::io::{ErrorKind, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use disk;
+use fs::{self, File, GenFS};
+use mem::sparse::Data;
+
+/// `Node` is a single entry in a tree, in the order it must be created: every directory comes
+/// before its children.
+#[derive(Debug)]
+pub(crate) struct Node {
+    /// path is relative to the root of the tree, which is the empty path.
+    pub(crate) path:     PathBuf,
+    pub(crate) kind:     Kind,
+    /// mode holds the Unix permission bits of the node.
+    pub(crate) mode:     u32,
+    /// accessed and modified are None if unknown, in which case they are left as is.
+    pub(crate) accessed: Option<SystemTime>,
+    pub(crate) modified: Option<SystemTime>,
+    /// link is shared by every node that is a hard link to the same file, or None if the file has
+    /// a single name.
+    pub(crate) link:     Option<(u64, u64)>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Kind {
+    Dir,
+    /// File data keeps its holes, so that sparse files are never filled in.
+    File(Data),
+    /// dir is whether the symlink points to a directory, which only matters to Windows.
+    Symlink { target: PathBuf, dir: bool },
+    /// rdev is the device a device file refers to, and 0 for FIFOs and sockets.
+    Special { ftyp: Special, rdev: u64 },
+}
+
+/// `Special` is the type of a file that has nothing behind it in memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Special {
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+}
+
+impl Special {
+    /// bits returns the file type bits of a mode for the type.
+    pub(crate) fn bits(self) -> u32 {
+        match self {
+            Special::Fifo => 0o010000,
+            Special::Socket => 0o140000,
+            Special::BlockDevice => 0o060000,
+            Special::CharDevice => 0o020000,
+        }
+    }
+}
+
+/// major returns the major number of a device, encoded as glibc does.
+pub(crate) fn major(rdev: u64) -> u64 {
+    ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff)
+}
+
+/// minor returns the minor number of a device, encoded as glibc does.
+pub(crate) fn minor(rdev: u64) -> u64 {
+    (rdev & 0xff) | ((rdev >> 12) & !0xff)
+}
+
+/// makedev returns the device with the given major and minor numbers, encoded as glibc does.
+pub(crate) fn makedev(major: u64, minor: u64) -> u64 {
+    (major & 0xfff) << 8 | (major & !0xfff) << 32 | (minor & 0xff) | (minor & !0xff) << 12
+}
+
+impl Node {
+    /// times returns the node's timestamps to be set once the node and its children exist.
+    pub(crate) fn times(&self) -> fs::FileTimes {
+        let mut times = fs::FileTimes::new();
+        if let Some(accessed) = self.accessed {
+            times = times.set_accessed(accessed);
+        }
+        if let Some(modified) = self.modified {
+            times = times.set_modified(modified);
+        }
+        times
+    }
+}
+
+/// read lists the host tree at root as nodes. Host entries that cannot be represented, such as
+/// sockets, FIFOs, and devices, are not listed; their host paths are returned instead.
+pub(crate) fn read<P: AsRef<Path>>(root: P) -> Result<(Vec<Node>, Vec<PathBuf>)> {
+    let (mut nodes, mut skipped) = (Vec::new(), Vec::new());
+    let mut todo = vec![PathBuf::new()];
+    while let Some(rel) = todo.pop() {
+        let host = root.as_ref().join(&rel);
+        let meta = rs_fs::symlink_metadata(&host)?;
+        let ftyp = meta.file_type();
+        let kind = if ftyp.is_dir() {
+            let mut children = rs_fs::read_dir(&host)?
+                .map(|ent| ent.map(|ent| rel.join(ent.file_name())))
+                .collect::<Result<Vec<_>>>()?;
+            children.sort();
+            todo.extend(children.into_iter().rev());
+            Kind::Dir
+        } else if ftyp.is_file() {
+            Kind::File(Data::from(rs_fs::read(&host)?))
+        } else if ftyp.is_symlink() {
+            Kind::Symlink {
+                target: rs_fs::read_link(&host)?,
+                dir:    symlink_dir(&host, &meta),
+            }
+        } else {
+            skipped.push(host);
+            continue;
+        };
+        nodes.push(Node {
+            path:     rel,
+            kind,
+            mode:     mode(&meta),
+            accessed: Some(meta.accessed()?),
+            modified: Some(meta.modified()?),
+            link:     link(&meta),
+        });
+    }
+    Ok((nodes, skipped))
+}
+
+/// import recreates nodes in fs at into, creating symlinks with symlink and special files with
+/// mknod and converting modes with perms. Directories that already exist are reused and files that
+/// already exist are overwritten.
+pub(crate) fn import<F, P, S, N, M>(fs: &F, nodes: &[Node], into: P, symlink: S, mknod: N,
+                                    perms: M) -> Result<()>
+    where F: GenFS,
+          P: AsRef<Path>,
+          S: Fn(&F, &Path, &Path, bool) -> Result<()>,
+          N: Fn(&F, &Path, Special, u64) -> Result<()>,
+          M: Fn(u32) -> F::Permissions,
+{
+    let mut links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    for node in nodes {
+        let path = into.as_ref().join(&node.path);
+        match node.kind {
+            Kind::Dir => fs.create_dir_all(&path)?,
+            Kind::File(ref data) => {
+                if let Some(first) = node.link.and_then(|link| links.get(&link)) {
+                    fs.hard_link(first, &path)?;
+                    continue;
+                }
+                let mut file = fs.create_file(&path)?;
+                write_data(&mut file, data)?;
+                file.set_len(data.len() as u64)?;
+                if let Some(link) = node.link {
+                    links.insert(link, path);
+                }
+            }
+            Kind::Symlink { ref target, dir } => symlink(fs, target, &path, dir)?,
+            Kind::Special { ftyp, rdev } => mknod(fs, &path, ftyp, rdev)?,
+        }
+    }
+    for node in nodes.iter().rev() {
+        if let Kind::Symlink { .. } = node.kind {
+            continue;
+        }
+        let path = into.as_ref().join(&node.path);
+        fs.set_times(&path, node.times())?;
+        fs.set_permissions(&path, perms(node.mode))?;
+    }
+    Ok(())
+}
+
+/// write recreates nodes on the host at root. Directories that already exist are reused, and
+/// anything else already at a path is replaced rather than written through. Timestamps and
+/// permissions are set last, children first, so that neither creating children nor read-only
+/// permissions get in the way.
+///
+/// Special files are not written, as creating them may need privileges; their host paths are
+/// returned instead.
+pub(crate) fn write<P: AsRef<Path>>(nodes: &[Node], root: P) -> Result<Vec<PathBuf>> {
+    let mut skipped = Vec::new();
+    let mut links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    for node in nodes {
+        let host = root.as_ref().join(&node.path);
+        match node.kind {
+            Kind::Dir => {
+                // The root is left alone, even if it is a symlink to the directory to write to.
+                if !node.path.as_os_str().is_empty() {
+                    remove_existing(&host)?;
+                }
+                rs_fs::create_dir_all(&host)?
+            }
+            Kind::File(ref data) => {
+                remove_existing(&host)?;
+                if let Some(first) = node.link.and_then(|link| links.get(&link)) {
+                    rs_fs::hard_link(first, &host)?;
+                    continue;
+                }
+                let mut file = rs_fs::File::create(&host)?;
+                write_data(&mut file, data)?;
+                file.set_len(data.len() as u64)?;
+                if let Some(link) = node.link {
+                    links.insert(link, host);
+                }
+            }
+            Kind::Symlink { ref target, dir } => {
+                remove_existing(&host)?;
+                symlink(target, &host, dir)?
+            }
+            Kind::Special { .. } => skipped.push(host),
+        }
+    }
+    for node in nodes.iter().rev() {
+        if let Kind::Symlink { .. } | Kind::Special { .. } = node.kind {
+            continue;
+        }
+        let host = root.as_ref().join(&node.path);
+        disk::FS.set_times(&host, node.times())?;
+        set_mode(&host, node.mode)?;
+    }
+    Ok(skipped)
+}
+
+// remove_existing removes whatever is at host unless it is a directory, so that a file or symlink
+// there, even a symlink to a directory, is replaced instead of being followed.
+fn remove_existing(host: &Path) -> Result<()> {
+    match rs_fs::symlink_metadata(host) {
+        Ok(ref meta) if meta.is_dir() => Ok(()),
+        // Windows only removes symlinks to directories with remove_dir.
+        Ok(_) => rs_fs::remove_file(host).or_else(|e| rs_fs::remove_dir(host).map_err(|_| e)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// write_data writes the extents of data to a new, empty file, leaving holes unwritten. The file
+// must then be extended to the length of data.
+fn write_data<W: Seek + Write>(file: &mut W, data: &Data) -> Result<()> {
+    for (start, extent) in data.extents() {
+        file.seek(SeekFrom::Start(start as u64))?;
+        file.write_all(extent)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(meta: &rs_fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(meta: &rs_fs::Metadata) -> u32 {
+    let mode = if meta.is_dir() { 0o777 } else { 0o666 };
+    if meta.permissions().readonly() { mode & !0o222 } else { mode }
+}
+
+#[cfg(unix)]
+fn link(meta: &rs_fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    if meta.is_file() && meta.nlink() > 1 {
+        Some((meta.dev(), meta.ino()))
+    } else {
+        None
+    }
+}
+
+// Identifying hard links on Windows requires opening every file, so we treat them as copies.
+#[cfg(not(unix))]
+fn link(_: &rs_fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(windows)]
+fn symlink_dir(_: &Path, meta: &rs_fs::Metadata) -> bool {
+    use std::os::windows::fs::FileTypeExt;
+    meta.file_type().is_symlink_dir()
+}
+
+#[cfg(not(windows))]
+fn symlink_dir(host: &Path, _: &rs_fs::Metadata) -> bool {
+    host.is_dir()
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, host: &Path, _: bool) -> Result<()> {
+    ::std::os::unix::fs::symlink(target, host)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, host: &Path, dir: bool) -> Result<()> {
+    if dir {
+        ::std::os::windows::fs::symlink_dir(target, host)
+    } else {
+        ::std::os::windows::fs::symlink_file(target, host)
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(host: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    rs_fs::set_permissions(host, rs_fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(host: &Path, mode: u32) -> Result<()> {
+    let mut perms = rs_fs::metadata(host)?.permissions();
+    perms.set_readonly(mode & 0o222 == 0);
+    rs_fs::set_permissions(host, perms)
+}
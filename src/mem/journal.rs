@@ -0,0 +1,245 @@
+//! Durability of the namespace of the in-memory Unix filesystem.
+//!
+//! On a real disk, a new or removed name only survives a crash once its directory has been
+//! synced. The journal keeps the entries of every directory as of its last sync along with every
+//! change made since, in order. Syncing a directory commits the changes that touch it, so a rename
+//! between two directories is committed whole when either of them is synced. A crash keeps the
+//! synced entries and, depending on how it is simulated, some of the changes.
+//!
+//! Inodes are identified by a `Key` and described by a node of any type, which the filesystem
+//! uses to remember what of each inode's own metadata and data has been synced. Once no synced
+//! entry or pending change can name an inode, its node is dropped.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::mem;
+
+/// `Key` identifies an inode.
+pub(crate) type Key = usize;
+
+/// `Entries` are the names in a directory and the inodes they name.
+pub(crate) type Entries = BTreeMap<OsString, Key>;
+
+/// `Binding` binds `name` in the directory `dir` to the inode `key`, or unbinds it if `key` is
+/// `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Binding {
+    pub(crate) dir:  Key,
+    pub(crate) name: OsString,
+    pub(crate) key:  Option<Key>,
+}
+
+/// `Journal` tracks which directory entries would survive a crash.
+#[derive(Clone, Debug)]
+pub(crate) struct Journal<N> {
+    /// root is the root directory, which is never bound to a name.
+    root:    Key,
+    /// nodes describe every inode that may be named after a crash.
+    nodes:   HashMap<Key, N>,
+    /// synced are the entries of each directory as of its last sync.
+    synced:  HashMap<Key, Entries>,
+    /// pending are the changes that have not been synced, oldest first. Each change is made of
+    /// bindings that are committed together.
+    pending: Vec<Vec<Binding>>,
+}
+
+impl<N> Journal<N> {
+    /// new returns a journal for the filesystem rooted at root, which has no entries.
+    pub(crate) fn new(root: Key) -> Journal<N> {
+        Journal {
+            root,
+            nodes:   HashMap::new(),
+            synced:  HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// root returns the root directory.
+    pub(crate) fn root(&self) -> Key {
+        self.root
+    }
+
+    /// node returns the node for key, creating it with make if the journal does not know key.
+    pub(crate) fn node<F: FnOnce() -> N>(&mut self, key: Key, make: F) -> &mut N {
+        self.nodes.entry(key).or_insert_with(make)
+    }
+
+    /// len returns the number of nodes the journal knows.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// get returns the node for key, if the journal knows it.
+    pub(crate) fn get(&self, key: Key) -> Option<&N> {
+        self.nodes.get(&key)
+    }
+
+    /// get_mut is like get, but mutable.
+    pub(crate) fn get_mut(&mut self, key: Key) -> Option<&mut N> {
+        self.nodes.get_mut(&key)
+    }
+
+    /// commit sets the synced entries of dir, as when the filesystem is synced whole.
+    pub(crate) fn commit(&mut self, dir: Key, entries: Entries) {
+        self.synced.insert(dir, entries);
+    }
+
+    /// change records a change that has not been synced.
+    pub(crate) fn change(&mut self, bindings: Vec<Binding>) {
+        if !bindings.is_empty() {
+            self.pending.push(bindings);
+        }
+    }
+
+    /// sync_dir commits, in order, every pending change that touches dir.
+    pub(crate) fn sync_dir(&mut self, dir: Key) {
+        for change in mem::take(&mut self.pending) {
+            if change.iter().any(|binding| binding.dir == dir) {
+                apply(&mut self.synced, &change);
+            } else {
+                self.pending.push(change);
+            }
+        }
+    }
+
+    /// prune drops every node and synced directory that the root can no longer reach through
+    /// synced entries, and that no pending change names, unless keep returns true for its node.
+    pub(crate) fn prune<F: Fn(&N) -> bool>(&mut self, keep: F) {
+        let mut reached = HashSet::new();
+        let mut todo = vec![self.root];
+        for binding in self.pending.iter().flatten() {
+            todo.push(binding.dir);
+            todo.extend(binding.key);
+        }
+        while let Some(key) = todo.pop() {
+            if reached.insert(key) {
+                todo.extend(self.synced.get(&key).into_iter().flat_map(|dir| dir.values()));
+            }
+        }
+        let nodes = &mut self.nodes;
+        nodes.retain(|key, node| reached.contains(key) || keep(node));
+        self.synced.retain(|key, _| nodes.contains_key(key));
+    }
+
+    /// crashed returns the entries of every directory after a crash: the synced entries, with the
+    /// pending changes that keep returns true for applied in order.
+    pub(crate) fn crashed<F: FnMut() -> bool>(&self, mut keep: F) -> HashMap<Key, Entries> {
+        let mut entries = self.synced.clone();
+        for change in &self.pending {
+            if keep() {
+                apply(&mut entries, change);
+            }
+        }
+        entries
+    }
+}
+
+// apply applies each binding of change to entries.
+fn apply(entries: &mut HashMap<Key, Entries>, change: &[Binding]) {
+    for binding in change {
+        let dir = entries.entry(binding.dir).or_default();
+        match binding.key {
+            Some(key) => { dir.insert(binding.name.clone(), key); }
+            None => { dir.remove(&binding.name); }
+        }
+    }
+}
+
+/// `Rng` is a small xorshift generator, so that the same seed always crashes the same way.
+#[derive(Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    /// new returns a generator seeded with seed. Small seeds would start with small states, so
+    /// the seed is first scrambled with splitmix64; xorshift never leaves zero, so that is avoided.
+    pub(crate) fn new(seed: u64) -> Rng {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Rng(if z == 0 { 1 } else { z })
+    }
+
+    /// coin returns true or false with equal odds.
+    pub(crate) fn coin(&mut self) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 >> 63 == 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsString;
+
+    use super::*;
+
+    fn bind(dir: Key, name: &str, key: Option<Key>) -> Binding {
+        Binding { dir, name: OsString::from(name), key }
+    }
+
+    #[test]
+    fn sync_dir() {
+        let mut journal: Journal<()> = Journal::new(1);
+        journal.commit(1, Entries::new());
+        journal.change(vec![bind(1, "a", Some(2))]);
+        journal.change(vec![bind(2, "f", Some(3))]);
+        journal.change(vec![bind(2, "f", None), bind(1, "g", Some(3))]);
+        journal.change(vec![bind(1, "a", None)]);
+
+        // Nothing survives until it is synced, unless the crash keeps it.
+        let g = || vec![(OsString::from("g"), 3)].into_iter().collect::<Entries>();
+        assert_eq!(journal.crashed(|| false)[&1], Entries::new());
+        assert_eq!(journal.crashed(|| true)[&1], g());
+
+        // Syncing 2 commits the creation of f and its rename, but not the removal of a.
+        journal.sync_dir(2);
+        let crashed = journal.crashed(|| false);
+        assert_eq!(crashed[&1], g());
+        assert_eq!(crashed[&2], Entries::new());
+
+        // Syncing 1 commits the rest, in order.
+        journal.sync_dir(1);
+        let crashed = journal.crashed(|| true);
+        assert_eq!(crashed[&1], g());
+        assert!(journal.pending.is_empty());
+    }
+
+    #[test]
+    fn prune() {
+        let mut journal: Journal<bool> = Journal::new(1);
+        for (key, open) in [(1, false), (2, false), (3, false), (4, true), (5, false)] {
+            journal.node(key, || open);
+        }
+        journal.commit(1, vec![(OsString::from("a"), 2)].into_iter().collect());
+        journal.commit(2, vec![(OsString::from("f"), 3)].into_iter().collect());
+        journal.change(vec![bind(1, "g", Some(5))]);
+
+        // Nodes that can be named, or that are kept, survive pruning.
+        journal.prune(|&open| open);
+        assert_eq!(journal.nodes.len(), 5);
+
+        // Removing a committed directory drops it and what it named, unless named elsewhere.
+        journal.change(vec![bind(2, "f", None), bind(1, "a", None)]);
+        journal.sync_dir(1);
+        journal.prune(|&open| open);
+        let mut keys: Vec<_> = journal.nodes.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec![1, 4, 5]);
+        assert_eq!(journal.synced.len(), 1);
+    }
+
+    #[test]
+    fn rng() {
+        let flips = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..64).map(|_| rng.coin()).collect::<Vec<_>>()
+        };
+        assert_eq!(flips(7), flips(7));
+        assert_ne!(flips(7), flips(8));
+        let heads = flips(0).into_iter().filter(|&h| h).count();
+        assert!(heads > 16 && heads < 48);
+    }
+}
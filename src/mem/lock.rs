@@ -0,0 +1,116 @@
+//! Advisory whole-file locks for the in-memory filesystems.
+//!
+//! Locks belong to an open file description, which in both in-memory filesystems is the
+//! `FileCursor` that every `try_clone` of a `File` shares. Every file holds a `Locks` that is
+//! shared between all of its names and open descriptions; descriptions identify themselves by the
+//! address of their cursor and release their lock when the cursor drops.
+
+extern crate parking_lot;
+
+use self::parking_lot::{Condvar, Mutex};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// The kind of lock held by an open file description.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Shared,
+    Exclusive,
+}
+
+/// `Locks` tracks which open descriptions hold a lock on a file.
+#[derive(Default)]
+pub(crate) struct Locks {
+    holders:  Mutex<HashMap<usize, Kind>>,
+    released: Condvar,
+}
+
+impl fmt::Debug for Locks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Locks").field("holders", &*self.holders.lock()).finish()
+    }
+}
+
+impl Locks {
+    /// lock takes a lock of kind `want` for the description `id`, waiting for conflicting locks to
+    /// be released if `block` is true. It returns false if the lock would have to wait and `block`
+    /// is false.
+    ///
+    /// Like `flock`, converting a lock the description already holds is not atomic: the old lock
+    /// is released first and is lost if the new lock cannot be taken.
+    pub(crate) fn lock(&self, id: usize, want: Kind, block: bool) -> bool {
+        let mut holders = self.holders.lock();
+        match holders.remove(&id) {
+            Some(held) if held == want => {
+                holders.insert(id, held);
+                return true;
+            }
+            Some(_) => {
+                self.released.notify_all();
+            }
+            None => (),
+        }
+        loop {
+            let conflict = holders.values().any(|&held| {
+                held == Kind::Exclusive || want == Kind::Exclusive
+            });
+            if !conflict {
+                holders.insert(id, want);
+                return true;
+            }
+            if !block {
+                return false;
+            }
+            self.released.wait(&mut holders);
+        }
+    }
+
+    /// unlock releases any lock held by the description `id`.
+    pub(crate) fn unlock(&self, id: usize) {
+        if self.holders.lock().remove(&id).is_some() {
+            self.released.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{Kind, Locks};
+
+    #[test]
+    fn locks() {
+        let locks = Locks::default();
+        assert!(locks.lock(1, Kind::Shared, false));
+        assert!(locks.lock(2, Kind::Shared, false));
+        assert!(!locks.lock(3, Kind::Exclusive, false));
+
+        // A failed conversion loses the lock that was held.
+        assert!(!locks.lock(1, Kind::Exclusive, false));
+        locks.unlock(2);
+        assert!(locks.lock(3, Kind::Exclusive, false));
+        assert!(locks.lock(3, Kind::Exclusive, false));
+        assert!(!locks.lock(1, Kind::Shared, false));
+
+        // Blocked locks are taken once the conflicting lock is released.
+        let locks = Arc::new(locks);
+        let (tx, rx) = mpsc::channel();
+        let waiter = {
+            let locks = locks.clone();
+            thread::spawn(move || {
+                assert!(locks.lock(1, Kind::Shared, true));
+                tx.send(()).unwrap();
+            })
+        };
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        locks.unlock(3);
+        waiter.join().unwrap();
+        assert!(rx.recv().is_ok());
+        assert!(locks.lock(2, Kind::Shared, false));
+    }
+}
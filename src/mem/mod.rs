@@ -1,17 +1,35 @@
 //! An in-memory filesystem.
 //!
-//! The [`FS`] provides an in-memory file system. Only a Unix implementation is currently
-//! available. Errors returned attempt to mimic true operating sytsem error codes, but may not
-//! catch subtle differences between operating systems.
+//! The [`FS`] provides an in-memory file system. Errors returned attempt to mimic true operating
+//! sytsem error codes, but may not catch subtle differences between operating systems.
 //!
 //! This module is platform specific and uses the proper in-memory semantics via a `pub use`
-//! depending on the builder's operating system. As mentioned above, only Unix is currently
-//! supported, meaning _this_ module will not work on Windows. To get a platform agnostic in-memory
-//! filesystem, use the proper platform specific module. For example, if you use
-//! [`rsfs::mem::unix`], you will have a cross-platform in-memory filesystem that obeys Unix
-//! semantics. When `rsfs::mem::windows` is written, that can be used to get a cross-platform
-//! Windows specific in-memory filesystem (additionally, once it is written, _this_ module will
-//! work on Windows systems).
+//! depending on the builder's operating system. To get a platform agnostic in-memory filesystem,
+//! use the proper platform specific module. For example, if you use [`rsfs::mem::unix`], you will
+//! have a cross-platform in-memory filesystem that obeys Unix semantics, and if you use
+//! [`rsfs::mem::windows`], you will have a cross-platform in-memory filesystem that obeys Windows
+//! semantics.
+//!
+//! Timestamps in both in-memory filesystems come from a [`clock`], which can be replaced to make
+//! tests that depend on time deterministic. Both can also be created with byte and inode limits
+//! (see `FS::with_limits`) so that writes and creates fail with `ENOSPC` as they would on a full
+//! disk. Files in both are sparse: writing past the end of a file or extending it leaves a hole
+//! that takes no memory, and on Unix, no space or blocks either (see
+//! `unix_ext::FileExt::seek_sparse`). The Unix filesystem also stores extended attributes on its
+//! inodes, so hard links share them (see `unix_ext::GenFSExt::set_xattr`), and can hold FIFOs,
+//! sockets, and devices (see `unix_ext::GenFSExt::mknod`). Nothing is behind those in memory, so
+//! opening them fails with `ENXIO`, and they are left out of exports and renders. Its files can
+//! be opened with the common Linux `open(2)` flags, such as `O_NOFOLLOW` and `O_TMPFILE` (see
+//! `unix_ext::OpenOptionsExt::custom_flags`). It can also track what has been synced (see
+//! `FS::with_crash_tracking`), so that `FS::crash` can show what would be left of a tree after a
+//! power loss.
+//!
+//! Both can also be copied with `FS::snapshot` and later rolled back with `FS::restore`, so that
+//! many tests can start from the same tree without rebuilding it. Trees on the host can be copied
+//! in with `FS::import_from` and copied back out with `FS::export_to`, and tar archives can be read
+//! with `FS::from_tar` and written with `FS::write_tar`. When two trees should match but do not,
+//! `diff` lists each [`Change`] between them, and `FS::render` prints a tree as text for failure
+//! messages or comparing against expected output (see [`RenderOptions`]).
 //!
 //! This module should provide a decent alternative to FUSE if there is no need to use your in
 //! memory filesystem outside of your process.
@@ -36,17 +54,36 @@
 //! assert_eq!(rf.read(&mut output).unwrap(), 5);
 //! assert_eq!(&output, b"hello");
 //! ```
-//! ```
 //!
 //! [`FS`]: struct.FS.html
 //! [`rsfs::mem::unix`]: unix/index.html
+//! [`rsfs::mem::windows`]: windows/index.html
+//! [`clock`]: clock/index.html
+//! [`Change`]: enum.Change.html
+//! [`RenderOptions`]: struct.RenderOptions.html
 //! [`errors`]: ../errors/index.html
 
-#[path = "unix.rs"]
-mod fs;
-
-pub use self::fs::*;
+#[cfg(unix)]
+pub use self::unix::*;
+#[cfg(windows)]
+pub use self::windows::*;
 
 pub mod unix;
+pub mod windows;
+
+pub use self::diff::{Change, EntryKind};
+pub use self::render::RenderOptions;
+
+pub mod clock;
+mod diff;
+mod flags;
+mod host;
+mod journal;
+mod lock;
+mod render;
+mod space;
+mod sparse;
+mod tar;
+mod xattr;
 
-// TODO mod test;
+pub mod test;
@@ -0,0 +1,228 @@
+//! Rendering the tree of an in-memory filesystem as text.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str;
+use std::time::UNIX_EPOCH;
+
+use mem::host::{self, Kind, Node, Special};
+
+/// Options for rendering the tree of an `FS` as text with [`FS::render`].
+///
+/// Every entry is rendered on its own line, indented four spaces per level below the root and
+/// sorted by name. Directories end with the path separator, `/` or `\` on Windows, and symlinks
+/// are followed by `->` and their target. Names that are not valid UTF-8, control characters,
+/// and backslashes are escaped as in Rust string literals, except that backslashes separating
+/// the components of Windows paths are not. Options add details in parentheses after each name;
+/// by default, only names are rendered, except that FIFOs, sockets, and devices always have
+/// their type as the first detail, with the major and minor numbers of devices, as `chr 1:3`.
+///
+/// [`FS::render`]: struct.FS.html#method.render
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::{FS, RenderOptions};
+/// use std::io::Write;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// fs.create_dir("d")?;
+/// fs.create_file("d/f")?.write_all(b"hello")?;
+///
+/// assert_eq!(fs.render(RenderOptions::new().sizes(true)), "\
+/// /
+///     d/
+///         f (len 5)
+/// ");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    modes:     bool,
+    sizes:     bool,
+    times:     bool,
+    inodes:    bool,
+    max_depth: Option<usize>,
+}
+
+impl RenderOptions {
+    /// Creates a blank set of options that renders only names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to render the permission bits of each entry in octal, as `mode 0644`.
+    pub fn modes(&mut self, modes: bool) -> &mut Self {
+        self.modes = modes; self
+    }
+
+    /// Sets whether to render the length of each file, as `len 5`.
+    pub fn sizes(&mut self, sizes: bool) -> &mut Self {
+        self.sizes = sizes; self
+    }
+
+    /// Sets whether to render the modification time of each entry as seconds and nanoseconds
+    /// since the Unix epoch, as `mtime 1000000.000000000`. Timestamps are only deterministic with
+    /// a fixed [`clock`].
+    ///
+    /// [`clock`]: clock/index.html
+    pub fn times(&mut self, times: bool) -> &mut Self {
+        self.times = times; self
+    }
+
+    /// Sets whether to render which files are hard links to the same inode. Every set of linked
+    /// names is numbered in the order it is first rendered, as `link 1`.
+    pub fn inodes(&mut self, inodes: bool) -> &mut Self {
+        self.inodes = inodes; self
+    }
+
+    /// Sets the deepest level to render, where the root is level 0. Directories at the deepest
+    /// level that have entries are followed by an indented `...`.
+    pub fn max_depth(&mut self, max_depth: usize) -> &mut Self {
+        self.max_depth = Some(max_depth); self
+    }
+}
+
+/// render renders nodes with the root named root, in a tree whose paths are separated by sep.
+pub(crate) fn render(nodes: &[Node], root: &str, sep: char, opts: &RenderOptions) -> String {
+    let mut sizes: HashMap<(u64, u64), usize> = HashMap::new();
+    for link in nodes.iter().filter_map(|node| node.link) {
+        *sizes.entry(link).or_insert(0) += 1;
+    }
+    let mut links: HashMap<(u64, u64), usize> = HashMap::new();
+
+    let mut out = String::new();
+    let mut truncated = None;
+    for node in nodes {
+        let depth = node.path.components().count();
+        if let Some(max) = opts.max_depth.filter(|&max| depth > max) {
+            // Only the first child of a truncated directory gets a marker.
+            let parent = node.path.parent();
+            if depth == max + 1 && truncated != parent {
+                truncated = parent;
+                let _ = writeln!(out, "{:1$}...", "", 4 * depth);
+            }
+            continue;
+        }
+
+        out.push_str(&" ".repeat(4 * depth));
+        match node.path.file_name() {
+            Some(name) => out.push_str(&escape(Path::new(name), sep)),
+            None => out.push_str(root),
+        }
+        match node.kind {
+            Kind::Dir if !node.path.as_os_str().is_empty() => out.push(sep),
+            Kind::Symlink { ref target, .. } => {
+                out.push_str(" -> ");
+                out.push_str(&escape(target, sep));
+            }
+            _ => (),
+        }
+
+        let mut details = Vec::new();
+        if let Kind::Special { ftyp, rdev } = node.kind {
+            details.push(match ftyp {
+                Special::Fifo => "fifo".to_string(),
+                Special::Socket => "socket".to_string(),
+                Special::BlockDevice => format!("blk {}:{}", host::major(rdev), host::minor(rdev)),
+                Special::CharDevice => format!("chr {}:{}", host::major(rdev), host::minor(rdev)),
+            });
+        }
+        if opts.modes {
+            details.push(format!("mode {:04o}", node.mode));
+        }
+        if opts.sizes {
+            if let Kind::File(ref data) = node.kind {
+                details.push(format!("len {}", data.len()));
+            }
+        }
+        if opts.times {
+            if let Some(modified) = node.modified {
+                details.push(match modified.duration_since(UNIX_EPOCH) {
+                    Ok(since) => format!("mtime {}.{:09}", since.as_secs(), since.subsec_nanos()),
+                    Err(e) => {
+                        let before = e.duration();
+                        format!("mtime -{}.{:09}", before.as_secs(), before.subsec_nanos())
+                    }
+                });
+            }
+        }
+        if opts.inodes {
+            if let Some(link) = node.link.filter(|link| sizes[link] > 1) {
+                let next = links.len() + 1;
+                details.push(format!("link {}", links.entry(link).or_insert(next)));
+            }
+        }
+        if !details.is_empty() {
+            let _ = write!(out, " ({})", details.join(", "));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// escape renders a name or path, escaping bytes that are not valid UTF-8 as `\xNN` and control
+/// characters and backslashes other than sep as Rust string literals would.
+fn escape(path: &Path, sep: char) -> String {
+    let mut escaped = String::new();
+    let bytes = bytes(path);
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let (valid, invalid) = match str::from_utf8(rest) {
+            Ok(valid) => (valid, 0),
+            Err(e) => {
+                let valid = str::from_utf8(&rest[..e.valid_up_to()]).unwrap_or_default();
+                (valid, e.error_len().unwrap_or(rest.len() - e.valid_up_to()))
+            }
+        };
+        for c in valid.chars() {
+            if c.is_control() || (c == '\\' && c != sep) {
+                escaped.extend(c.escape_default());
+            } else {
+                escaped.push(c);
+            }
+        }
+        for b in &rest[valid.len()..valid.len() + invalid] {
+            let _ = write!(escaped, "\\x{:02x}", b);
+        }
+        rest = &rest[valid.len() + invalid..];
+    }
+    escaped
+}
+
+#[cfg(unix)]
+fn bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escape() {
+        // Backslashes are only left alone as the separators of Windows paths.
+        let name = Path::new("a\\b\tc\u{e9}");
+        assert_eq!(super::escape(name, '/'), "a\\\\b\\tc\u{e9}");
+        assert_eq!(super::escape(name, '\\'), "a\\b\\tc\u{e9}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_invalid() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = Path::new(OsStr::from_bytes(b"a\xff\xe9b\xe2\x82"));
+        assert_eq!(super::escape(name, '/'), r"a\xff\xe9b\xe2\x82");
+    }
+}
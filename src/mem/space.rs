@@ -0,0 +1,156 @@
+//! Byte and inode accounting for the in-memory filesystems.
+//!
+//! Every inode in a filesystem holds a `Charge` against the filesystem's `Space`. The charge
+//! reserves one inode and tracks how many bytes of file data the inode holds. Charges are shared
+//! between every name and open handle of an inode and are released when the last of them drops,
+//! which mirrors how a real filesystem only frees an unlinked file once it is closed.
+//!
+//! Spaces also number what they hold: every space has a device number unique to the process and
+//! every charge gets an inode number unique to its space, which together identify a file.
+
+extern crate parking_lot;
+
+use self::parking_lot::Mutex;
+
+use std::io::Result;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use errors::ENOSPC;
+
+/// `Space` tracks the bytes and inodes used by a filesystem against its optional limits.
+#[derive(Debug)]
+pub(crate) struct Space(Mutex<Usage>);
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Usage {
+    bytes:      u64,
+    inodes:     u64,
+    max_bytes:  Option<u64>,
+    max_inodes: Option<u64>,
+    dev:        u64,
+    last_ino:   u64,
+}
+
+impl Usage {
+    /// next_ino returns an inode number that has not been handed out by this space before.
+    fn next_ino(&mut self) -> u64 {
+        self.last_ino += 1;
+        self.last_ino
+    }
+}
+
+impl Default for Space {
+    fn default() -> Space {
+        static LAST_DEV: AtomicU64 = AtomicU64::new(0);
+        Space(Mutex::new(Usage {
+            dev: LAST_DEV.fetch_add(1, Ordering::Relaxed) + 1,
+            ..Usage::default()
+        }))
+    }
+}
+
+impl Space {
+    /// Sets the byte and inode limits, where `None` is unlimited. Limits may be set below what is
+    /// already used, in which case only operations that free space succeed until usage drops.
+    pub(crate) fn set_limits(&self, bytes: Option<u64>, inodes: Option<u64>) {
+        let mut usage = self.0.lock();
+        usage.max_bytes = bytes;
+        usage.max_inodes = inodes;
+    }
+
+    pub(crate) fn bytes_used(&self) -> u64 {
+        self.0.lock().bytes
+    }
+
+    pub(crate) fn inodes_used(&self) -> u64 {
+        self.0.lock().inodes
+    }
+
+    /// charge reserves an inode in `space`, failing with ENOSPC if no inodes are left.
+    pub(crate) fn charge(space: &Arc<Space>) -> Result<Charge> {
+        let ino = {
+            let mut usage = space.0.lock();
+            if usage.max_inodes.is_some_and(|max| usage.inodes >= max) {
+                return Err(ENOSPC());
+            }
+            usage.inodes += 1;
+            usage.next_ino()
+        };
+        Ok(Charge {
+            space: space.clone(),
+            bytes: Mutex::new(0),
+            ino,
+        })
+    }
+
+    /// charge_over reserves an inode holding `bytes` bytes in `space` regardless of its limits.
+    /// Restoring a snapshot may go over limits in the same way that limits may be set below usage.
+    pub(crate) fn charge_over(space: &Arc<Space>, bytes: usize) -> Charge {
+        let bytes = bytes as u64;
+        let ino = {
+            let mut usage = space.0.lock();
+            usage.inodes += 1;
+            usage.bytes += bytes;
+            usage.next_ino()
+        };
+        Charge {
+            space: space.clone(),
+            bytes: Mutex::new(bytes),
+            ino,
+        }
+    }
+}
+
+/// `Charge` is the inode, and the bytes of data, that a single inode holds against its `Space`.
+#[derive(Debug)]
+pub(crate) struct Charge {
+    space: Arc<Space>,
+    bytes: Mutex<u64>,
+    ino:   u64,
+}
+
+impl Charge {
+    /// resize changes the number of bytes this charge holds to `len`, failing with ENOSPC if
+    /// growing would go over the byte limit. Shrinking always succeeds.
+    pub(crate) fn resize(&self, len: usize) -> Result<()> {
+        let len = len as u64;
+        let mut bytes = self.bytes.lock();
+        let mut usage = self.space.0.lock();
+        if len > *bytes {
+            let grown = usage.bytes + (len - *bytes);
+            if usage.max_bytes.is_some_and(|max| grown > max) {
+                return Err(ENOSPC());
+            }
+            usage.bytes = grown;
+        } else {
+            usage.bytes -= *bytes - len;
+        }
+        *bytes = len;
+        Ok(())
+    }
+
+    pub(crate) fn space(&self) -> &Arc<Space> {
+        &self.space
+    }
+
+    /// ino returns the inode number of this charge, which is unique within its space.
+    pub(crate) fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// dev returns the device number of this charge's space.
+    pub(crate) fn dev(&self) -> u64 {
+        self.space.0.lock().dev
+    }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        let bytes = *self.bytes.get_mut();
+        let mut usage = self.space.0.lock();
+        usage.inodes -= 1;
+        usage.bytes -= bytes;
+    }
+}
+
@@ -0,0 +1,296 @@
+//! Sparse file data for the in-memory filesystems.
+//!
+//! File data is stored as extents of written bytes. Everything between extents, and everything
+//! between the last extent and the length of the file, is a hole that reads as zeros but takes no
+//! memory. Writing past the end of a file or extending it with `set_len` only creates a hole, so
+//! a file can be gigabytes long while holding a handful of bytes.
+//!
+//! Cloning data shares its extents rather than copying them, and writing to shared data copies
+//! only the extents it touches. No extent crosses a multiple of `CHUNK`, so that a write never
+//! copies more than a chunk of bytes around it.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Included};
+use std::sync::Arc;
+
+/// CHUNK is the most bytes an extent holds.
+const CHUNK: usize = 1 << 16;
+
+/// `Data` is the contents of a file: its length and the extents of bytes that have been written.
+///
+/// Extents never overlap, never cross a multiple of `CHUNK`, never touch unless they meet at one,
+/// and never reach past the length of the file; writes that touch an extent within its chunk are
+/// merged into it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Data {
+    len:     usize,
+    extents: BTreeMap<usize, Arc<Vec<u8>>>,
+}
+
+impl From<Vec<u8>> for Data {
+    fn from(bytes: Vec<u8>) -> Data {
+        let mut data = Data::default();
+        data.write_at(0, &bytes);
+        data
+    }
+}
+
+impl PartialEq for Data {
+    /// Files are equal if they read the same, no matter where their holes are.
+    fn eq(&self, other: &Data) -> bool {
+        self.len == other.len &&
+            self.extents.iter().all(|(&start, extent)| other.reads_as(start, extent)) &&
+            other.extents.iter().all(|(&start, extent)| self.reads_as(start, extent))
+    }
+}
+
+impl Eq for Data {}
+
+impl Data {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// allocated returns the number of bytes held in extents.
+    pub(crate) fn allocated(&self) -> usize {
+        self.extents.values().map(|extent| extent.len()).sum()
+    }
+
+    /// allocated_blocks returns the number of `size` aligned blocks that extents touch.
+    pub(crate) fn allocated_blocks(&self, size: usize) -> usize {
+        let mut blocks = 0;
+        let mut next = 0; // the first block not yet counted
+        for (&start, extent) in &self.extents {
+            let (first, last) = (start / size, (start + extent.len() - 1) / size);
+            blocks += last + 1 - cmp::max(first, next);
+            next = last + 1;
+        }
+        blocks
+    }
+
+    /// allocated_after_write returns what allocated would return after writing n bytes at at.
+    pub(crate) fn allocated_after_write(&self, at: usize, n: usize) -> usize {
+        let end = at + n;
+        let mut covered = 0;
+        for (&start, extent) in self.extents.range(..end) {
+            let (lo, hi) = (cmp::max(start, at), cmp::min(start + extent.len(), end));
+            covered += hi.saturating_sub(lo);
+        }
+        self.allocated() + n - covered
+    }
+
+    /// read_at reads into dst from at, returning how many bytes were read. Holes read as zeros.
+    pub(crate) fn read_at(&self, at: usize, dst: &mut [u8]) -> usize {
+        if at >= self.len {
+            return 0;
+        }
+        let n = cmp::min(dst.len(), self.len - at);
+        let dst = &mut dst[..n];
+        for b in dst.iter_mut() {
+            *b = 0;
+        }
+        let end = at + n;
+        // The extent starting at or before at may reach into the read.
+        let first = self.extents.range(..=at).next_back().map(|(&start, _)| start).unwrap_or(at);
+        for (&start, extent) in self.extents.range(first..end) {
+            let (lo, hi) = (cmp::max(start, at), cmp::min(start + extent.len(), end));
+            if lo < hi {
+                dst[lo - at..hi - at].copy_from_slice(&extent[lo - start..hi - start]);
+            }
+        }
+        n
+    }
+
+    /// write_at writes src at at, extending the file with a hole if at is past its end.
+    pub(crate) fn write_at(&mut self, mut at: usize, mut src: &[u8]) {
+        while !src.is_empty() {
+            let n = cmp::min(src.len(), CHUNK - at % CHUNK);
+            self.write_chunk(at, &src[..n]);
+            at += n;
+            src = &src[n..];
+        }
+    }
+
+    // write_chunk writes src, which must not cross a multiple of CHUNK, at at.
+    fn write_chunk(&mut self, at: usize, src: &[u8]) {
+        let end = at + src.len();
+        let chunk = at / CHUNK;
+
+        // The write extends the extent it starts in or right after, if there is one in its chunk.
+        let (start, mut extent) = match self.extents.range(..=at).next_back() {
+            Some((&start, extent)) if start / CHUNK == chunk && start + extent.len() >= at => {
+                let extent = self.extents.remove(&start).expect("extent was just found");
+                (start, Arc::try_unwrap(extent).unwrap_or_else(|shared| (*shared).clone()))
+            }
+            _ => (at, Vec::new()),
+        };
+        let tail = if extent.len() > end - start {
+            extent.split_off(end - start)
+        } else {
+            Vec::new()
+        };
+        extent.truncate(at - start);
+        extent.extend_from_slice(src);
+        extent.extend_from_slice(&tail);
+
+        // Later extents in the chunk that the write overlaps or touches are merged in.
+        let later: Vec<usize> = self.extents
+                                    .range((Excluded(at), Included(end)))
+                                    .map(|(&start, _)| start)
+                                    .filter(|&start| start / CHUNK == chunk)
+                                    .collect();
+        for later in later {
+            let merged = self.extents.remove(&later).expect("extent was just found");
+            if later + merged.len() > end {
+                extent.extend_from_slice(&merged[end - later..]);
+            }
+        }
+
+        self.extents.insert(start, Arc::new(extent));
+        self.len = cmp::max(self.len, end);
+    }
+
+    /// set_len truncates the file to len or extends it with a hole.
+    pub(crate) fn set_len(&mut self, len: usize) {
+        if len < self.len {
+            self.extents.split_off(&len);
+            if let Some((&start, extent)) = self.extents.iter_mut().next_back() {
+                if start + extent.len() > len {
+                    Arc::make_mut(extent).truncate(len - start);
+                }
+            }
+        }
+        self.len = len;
+    }
+
+    /// seek_data returns the start of the first data at or after at, or None if there is no
+    /// data after at.
+    pub(crate) fn seek_data(&self, at: usize) -> Option<usize> {
+        if at >= self.len {
+            return None;
+        }
+        match self.extents.range(..=at).next_back() {
+            Some((&start, extent)) if start + extent.len() > at => Some(at),
+            _ => self.extents.range(at..).next().map(|(&start, _)| start),
+        }
+    }
+
+    /// seek_hole returns the start of the first hole at or after at, or None if at is past the
+    /// end of the file. Every file ends in a hole at its length.
+    pub(crate) fn seek_hole(&self, at: usize) -> Option<usize> {
+        if at >= self.len {
+            return None;
+        }
+        let mut end = match self.extents.range(..=at).next_back() {
+            Some((&start, extent)) if start + extent.len() > at => start + extent.len(),
+            _ => return Some(at),
+        };
+        // Extents only touch where they meet a chunk, and the data goes on there.
+        while let Some(extent) = self.extents.get(&end) {
+            end += extent.len();
+        }
+        Some(end)
+    }
+
+    /// extents returns the start and bytes of each extent, in order. Everything else is a hole.
+    /// Extents may touch where they meet a multiple of `CHUNK`.
+    pub(crate) fn extents(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.extents.iter().map(|(&start, extent)| (start, &extent[..]))
+    }
+
+    /// to_vec returns the contents of the file with its holes filled in.
+    #[cfg(test)]
+    pub(crate) fn to_vec(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.len];
+        for (&start, extent) in &self.extents {
+            bytes[start..start + extent.len()].copy_from_slice(extent);
+        }
+        bytes
+    }
+
+    // reads_as returns whether reading bytes.len() bytes at at would read bytes.
+    fn reads_as(&self, at: usize, bytes: &[u8]) -> bool {
+        let mut read = vec![0; bytes.len()];
+        self.read_at(at, &mut read) == bytes.len() && read == bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{CHUNK, Data};
+
+    #[test]
+    fn sparse() {
+        let mut data = Data::from(b"hello".to_vec());
+        data.write_at(10, b"world");
+        assert_eq!(data.len(), 15);
+        assert_eq!(data.to_vec(), b"hello\0\0\0\0\0world");
+        assert_eq!((data.allocated(), data.extents.len()), (10, 2));
+
+        // Writes merge the extents they overlap or touch.
+        data.write_at(5, b"--");
+        assert_eq!(data.extents.len(), 2);
+        data.write_at(7, b"***");
+        assert_eq!(data.extents.len(), 1);
+        assert_eq!(data.to_vec(), b"hello--***world");
+        data.write_at(1, b"EL");
+        assert_eq!(data.to_vec(), b"hELlo--***world");
+
+        // Reads fill holes with zeros and stop at the end of the file.
+        let mut data = Data::default();
+        data.write_at(4, b"ab");
+        data.write_at(10, b"cd");
+        let mut buf = [0xff; 16];
+        assert_eq!(data.read_at(3, &mut buf), 9);
+        assert_eq!(&buf[..9], b"\0ab\0\0\0\0cd");
+        assert_eq!(data.read_at(12, &mut buf), 0);
+
+        assert_eq!(data.seek_data(0), Some(4));
+        assert_eq!(data.seek_data(5), Some(5));
+        assert_eq!(data.seek_data(6), Some(10));
+        assert_eq!(data.seek_data(12), None);
+        assert_eq!(data.seek_hole(0), Some(0));
+        assert_eq!(data.seek_hole(4), Some(6));
+        assert_eq!(data.seek_hole(11), Some(12));
+        assert_eq!(data.seek_hole(12), None);
+
+        // Extending makes a hole at the end; truncating cuts extents.
+        data.set_len(1 << 40);
+        assert_eq!(data.seek_data(11), Some(11));
+        assert_eq!(data.seek_hole(11), Some(12));
+        assert_eq!(data.seek_data(12), None);
+        assert_eq!(data.allocated_blocks(4096), 1);
+        data.write_at(1 << 39, b"x");
+        assert_eq!(data.allocated_blocks(4096), 2);
+        assert_eq!(data.allocated_after_write(3, 10), 5 + 10 - 4);
+        data.set_len(11);
+        assert_eq!((data.allocated(), data.to_vec()), (3, b"\0\0\0\0ab\0\0\0\0c".to_vec()));
+
+        // Holes and zeros read the same.
+        let mut zeros = Data::default();
+        zeros.write_at(0, &[0; 11]);
+        zeros.write_at(4, b"ab");
+        zeros.write_at(10, b"c");
+        assert_eq!(zeros, data);
+        zeros.set_len(12);
+        assert!(zeros != data);
+
+        // Extents stop at chunks, and clones share them until they are written.
+        let mut data = Data::default();
+        data.write_at(CHUNK - 2, b"abcd");
+        assert_eq!(data.extents.len(), 2);
+        assert_eq!(data.seek_hole(CHUNK - 1), Some(CHUNK + 2));
+        let copy = data.clone();
+        data.write_at(CHUNK, b"CD");
+        assert!(Arc::ptr_eq(&data.extents[&(CHUNK - 2)], &copy.extents[&(CHUNK - 2)]));
+        assert!(!Arc::ptr_eq(&data.extents[&CHUNK], &copy.extents[&CHUNK]));
+        assert_eq!((&data.to_vec()[CHUNK - 2..], &copy.to_vec()[CHUNK - 2..]),
+                   (&b"abCD"[..], &b"abcd"[..]));
+        data.set_len(CHUNK - 1);
+        assert_eq!(copy.len(), CHUNK + 2);
+        assert_eq!(copy.extents[&(CHUNK - 2)].len(), 2);
+    }
+}
@@ -0,0 +1,586 @@
+//! Reading and writing tar archives for the in-memory filesystems.
+//!
+//! Archives are converted to and from the same `Node`s used for copying trees to and from the
+//! host. We write ustar headers, falling back to pax extended headers for paths that do not fit,
+//! and read ustar, pax, and the GNU long name extensions. Files with holes are written and read in
+//! the pax sparse format 1.0 of GNU tar, so that only their data is stored.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use mem::host::{self, Kind, Node, Special};
+use mem::sparse::Data;
+
+const BLOCK: usize = 512;
+
+/// Offsets and lengths of the ustar header fields we use.
+const NAME:     (usize, usize) = (0, 100);
+const MODE:     (usize, usize) = (100, 8);
+const UID:      (usize, usize) = (108, 8);
+const GID:      (usize, usize) = (116, 8);
+const SIZE:     (usize, usize) = (124, 12);
+const MTIME:    (usize, usize) = (136, 12);
+const CHKSUM:   (usize, usize) = (148, 8);
+const TYPE:     usize = 156;
+const LINKNAME: (usize, usize) = (157, 100);
+const MAGIC:    (usize, usize) = (257, 8);
+const DEVMAJOR: (usize, usize) = (329, 8);
+const DEVMINOR: (usize, usize) = (337, 8);
+const PREFIX:   (usize, usize) = (345, 155);
+
+/// Mode given to parent directories that an archive does not list.
+const IMPLIED_DIR_MODE: u32 = 0o755;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid tar archive: {}", msg))
+}
+
+/// read parses a tar archive into nodes. Hard links share a link with the node they link to, and
+/// parent directories missing from the archive are added before their first child.
+pub(crate) fn read<R: Read>(mut r: R) -> Result<Vec<Node>> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    let mut long_name = None;
+    let mut long_link = None;
+    let mut pax: HashMap<String, Vec<u8>> = HashMap::new();
+
+    let mut header = [0u8; BLOCK];
+    loop {
+        if !read_block(&mut r, &mut header)? || header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if checksum(&header) != number(field(&header, CHKSUM))? {
+            return Err(invalid("bad header checksum"));
+        }
+        let size = match pax.remove("size") {
+            Some(size) => decimal(&size)?,
+            None => number(field(&header, SIZE))?,
+        };
+        let mut data = Vec::new();
+        if r.by_ref().take(size).read_to_end(&mut data)? as u64 != size {
+            return Err(invalid("truncated entry"));
+        }
+        let mut pad = [0u8; BLOCK];
+        r.read_exact(&mut pad[..(BLOCK - data.len() % BLOCK) % BLOCK])?;
+
+        let typ = header[TYPE];
+        match typ {
+            b'x' => {
+                pax.extend(records(&data)?);
+                continue;
+            }
+            b'g' => continue,
+            b'L' => {
+                long_name = Some(cstr(&data).to_vec());
+                continue;
+            }
+            b'K' => {
+                long_link = Some(cstr(&data).to_vec());
+                continue;
+            }
+            _ => (),
+        }
+
+        let realsize = match pax.remove("GNU.sparse.major") {
+            Some(ref major) if major == b"1" => match pax.remove("GNU.sparse.realsize") {
+                Some(realsize) => Some(decimal(&realsize)?),
+                None => return Err(invalid("sparse file without a size")),
+            },
+            None if !pax.keys().any(|key| key.starts_with("GNU.sparse.")) => None,
+            _ => return Err(invalid("unsupported sparse format")),
+        };
+        let name = pax.remove("GNU.sparse.name").filter(|_| realsize.is_some())
+            .or_else(|| pax.remove("path"))
+            .or_else(|| long_name.take())
+            .unwrap_or_else(|| {
+            let name = cstr(field(&header, NAME));
+            let prefix = cstr(field(&header, PREFIX));
+            if field(&header, MAGIC).starts_with(b"ustar") && !prefix.is_empty() {
+                [prefix, b"/", name].concat()
+            } else {
+                name.to_vec()
+            }
+        });
+        let link = pax.remove("linkpath").or_else(|| long_link.take())
+            .unwrap_or_else(|| cstr(field(&header, LINKNAME)).to_vec());
+        let modified = match pax.remove("mtime") {
+            Some(mtime) => pax_time(&mtime)?,
+            None => Duration::from_secs(number(field(&header, MTIME))?),
+        };
+        pax.clear();
+
+        let path = relative(&name)?;
+        let kind = match typ {
+            b'0' | b'\0' | b'7' => match realsize {
+                Some(realsize) => Kind::File(sparse_data(&data, realsize)?),
+                None => Kind::File(Data::from(data)),
+            },
+            b'5' => Kind::Dir,
+            b'3' | b'4' | b'6' => Kind::Special {
+                ftyp: match typ {
+                    b'3' => Special::CharDevice,
+                    b'4' => Special::BlockDevice,
+                    _ => Special::Fifo,
+                },
+                rdev: match typ {
+                    b'6' => 0,
+                    _ => host::makedev(number(field(&header, DEVMAJOR))?,
+                                       number(field(&header, DEVMINOR))?),
+                },
+            },
+            b'2' => Kind::Symlink { target: from_bytes(&link), dir: false },
+            b'1' => {
+                let target = relative(&link)?;
+                let first = match index.get(&target) {
+                    Some(&i) if matches!(nodes[i].kind, Kind::File(_)) => i,
+                    _ => return Err(invalid("hard link to a file not earlier in the archive")),
+                };
+                let link = Some((0, first as u64));
+                nodes[first].link = link;
+                Node {
+                    path,
+                    kind:     Kind::File(Data::default()),
+                    mode:     nodes[first].mode,
+                    accessed: None,
+                    modified: nodes[first].modified,
+                    link,
+                }.push(&mut nodes, &mut index);
+                continue;
+            }
+            _ => return Err(invalid(&format!("unsupported entry type {:?}", typ as char))),
+        };
+        Node {
+            path,
+            kind,
+            mode:     number(field(&header, MODE))? as u32 & 0o7777,
+            accessed: None,
+            modified: Some(UNIX_EPOCH + modified),
+            link:     None,
+        }.push(&mut nodes, &mut index);
+    }
+    Ok(nodes)
+}
+
+impl Node {
+    /// push adds a node read from an archive, first adding any parent directories that have not
+    /// been seen. Later entries for the same path replace earlier ones, as they do when extracting.
+    fn push(self, nodes: &mut Vec<Node>, index: &mut HashMap<PathBuf, usize>) {
+        let mut missing: Vec<PathBuf> = self.path.ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !index.contains_key(*dir))
+            .map(Path::to_path_buf)
+            .collect();
+        while let Some(dir) = missing.pop() {
+            index.insert(dir.clone(), nodes.len());
+            nodes.push(Node {
+                path:     dir,
+                kind:     Kind::Dir,
+                mode:     IMPLIED_DIR_MODE,
+                accessed: None,
+                modified: None,
+                link:     None,
+            });
+        }
+        index.insert(self.path.clone(), nodes.len());
+        nodes.push(self);
+    }
+}
+
+/// write writes nodes as a tar archive. The root of the tree is written as `./`, and a file whose
+/// link was already written is written as a hard link to the first name it was written with.
+pub(crate) fn write<W: Write>(nodes: &[Node], mut w: W) -> Result<()> {
+    let mut links: HashMap<(u64, u64), Vec<u8>> = HashMap::new();
+    for node in nodes {
+        let mut name = tar_name(&node.path);
+        let mtime = node.modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        let (typ, link, data): (u8, Vec<u8>, Option<&Data>) = match node.kind {
+            Kind::Dir => {
+                name.push(b'/');
+                (b'5', Vec::new(), None)
+            }
+            Kind::File(ref data) => match node.link.and_then(|link| links.get(&link)) {
+                Some(first) => (b'1', first.clone(), None),
+                None => {
+                    if let Some(link) = node.link {
+                        links.insert(link, name.clone());
+                    }
+                    (b'0', Vec::new(), Some(data))
+                }
+            },
+            Kind::Symlink { ref target, .. } => (b'2', to_bytes(target), None),
+            Kind::Special { ftyp: Special::Socket, .. } => continue,
+            Kind::Special { ftyp, .. } => (match ftyp {
+                Special::CharDevice => b'3',
+                Special::BlockDevice => b'4',
+                _ => b'6',
+            }, Vec::new(), None),
+        };
+
+        let mut header = [0u8; BLOCK];
+        if let Kind::Special { rdev, .. } = node.kind {
+            put_number(&mut header, DEVMAJOR, host::major(rdev));
+            put_number(&mut header, DEVMINOR, host::minor(rdev));
+        }
+        let mut records = Vec::new();
+        let mut map = Vec::new();
+        if let Some(data) = data.filter(|data| data.allocated() < data.len()) {
+            records.extend(record("GNU.sparse.major", b"1"));
+            records.extend(record("GNU.sparse.minor", b"0"));
+            records.extend(record("GNU.sparse.name", &name));
+            records.extend(record("GNU.sparse.realsize", data.len().to_string().as_bytes()));
+            name = sparse_name(&name);
+            map = sparse_map(data);
+        }
+        if !split_name(&mut header, &name) {
+            records.extend(record("path", &name));
+            put(&mut header, NAME, &name[..NAME.1]);
+        }
+        if link.len() > LINKNAME.1 {
+            records.extend(record("linkpath", &link));
+        } else {
+            put(&mut header, LINKNAME, &link);
+        }
+        if !records.is_empty() {
+            let mut pax = [0u8; BLOCK];
+            put(&mut pax, NAME, b"././@PaxHeader");
+            write_header(&mut w, &mut pax, b'x', 0o644, records.len() as u64, 0)?;
+            write_data(&mut w, &records)?;
+        }
+        let allocated = data.map_or(0, Data::allocated);
+        let size = (map.len() + allocated) as u64;
+        write_header(&mut w, &mut header, typ, node.mode & 0o7777, size, mtime)?;
+        w.write_all(&map)?;
+        for (_, extent) in data.into_iter().flat_map(Data::extents) {
+            w.write_all(extent)?;
+        }
+        w.write_all(&[0; BLOCK][..(BLOCK - allocated % BLOCK) % BLOCK])?;
+    }
+    w.write_all(&[0; 2 * BLOCK])
+}
+
+/// write_header fills in the numeric fields, magic, and checksum of header and writes it.
+fn write_header<W: Write>(w: &mut W, header: &mut [u8; BLOCK], typ: u8, mode: u32, size: u64,
+                          mtime: u64) -> Result<()> {
+    put_number(header, MODE, u64::from(mode));
+    put_number(header, UID, 0);
+    put_number(header, GID, 0);
+    put_number(header, SIZE, size);
+    put_number(header, MTIME, mtime);
+    header[TYPE] = typ;
+    put(header, MAGIC, b"ustar\x0000");
+    let sum = checksum(header);
+    put(header, CHKSUM, format!("{:06o}\0 ", sum).as_bytes());
+    w.write_all(&header[..])
+}
+
+/// write_data writes data padded to a whole number of blocks.
+fn write_data<W: Write>(w: &mut W, data: &[u8]) -> Result<()> {
+    w.write_all(data)?;
+    w.write_all(&[0; BLOCK][..(BLOCK - data.len() % BLOCK) % BLOCK])
+}
+
+/// sparse_name returns the name that a sparse file is stored under, in a `GNUSparseFile.0`
+/// directory beside it, for tools that cannot read sparse files to extract its data to.
+fn sparse_name(name: &[u8]) -> Vec<u8> {
+    let base = name.iter().rposition(|&b| b == b'/').map_or(0, |slash| slash + 1);
+    [&name[..base], b"GNUSparseFile.0/", &name[base..]].concat()
+}
+
+/// sparse_map returns the map of the extents of data that precedes them in a sparse entry, padded
+/// to a whole number of blocks. Extents that touch are mapped as one. The map always ends with an
+/// empty extent at the end of the file.
+fn sparse_map(data: &Data) -> Vec<u8> {
+    let mut entries: Vec<(usize, usize)> = Vec::new();
+    for (start, extent) in data.extents() {
+        match entries.last_mut() {
+            Some(&mut (at, ref mut len)) if at + *len == start => *len += extent.len(),
+            _ => entries.push((start, extent.len())),
+        }
+    }
+    entries.push((data.len(), 0));
+    let mut map = format!("{}\n", entries.len()).into_bytes();
+    for (start, len) in entries {
+        map.extend(format!("{}\n{}\n", start, len).into_bytes());
+    }
+    map.resize(map.len() + (BLOCK - map.len() % BLOCK) % BLOCK, 0);
+    map
+}
+
+/// sparse_data parses the map and extents of a sparse entry into a file of length realsize.
+fn sparse_data(entry: &[u8], realsize: u64) -> Result<Data> {
+    let mut at = 0;
+    let mut next = || {
+        let len = entry[at..].iter().position(|&b| b == b'\n')
+            .ok_or_else(|| invalid("truncated sparse map"))?;
+        at += len + 1;
+        decimal(&entry[at - len - 1..at - 1])
+    };
+    let count = next()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        entries.push((next()?, next()?));
+    }
+    at += (BLOCK - at % BLOCK) % BLOCK;
+
+    let mut data = Data::default();
+    for (start, n) in entries {
+        let n = n as usize;
+        if start.checked_add(n as u64).map_or(true, |end| end > realsize) || at + n > entry.len() {
+            return Err(invalid("bad sparse map"));
+        }
+        data.write_at(start as usize, &entry[at..at + n]);
+        at += n;
+    }
+    data.set_len(realsize as usize);
+    Ok(data)
+}
+
+/// split_name stores name in the header's name field, or across its prefix and name fields at a
+/// slash, returning false if name does not fit either way.
+fn split_name(header: &mut [u8; BLOCK], name: &[u8]) -> bool {
+    if name.len() <= NAME.1 {
+        put(header, NAME, name);
+        return true;
+    }
+    // The trailing slash of a directory cannot be the split point.
+    let split = name[..name.len() - 1].iter().enumerate().rev()
+        .filter(|&(_, &b)| b == b'/')
+        .map(|(i, _)| i)
+        .find(|&i| i <= PREFIX.1 && name.len() - i - 1 <= NAME.1);
+    match split {
+        Some(i) => {
+            put(header, PREFIX, &name[..i]);
+            put(header, NAME, &name[i + 1..]);
+            true
+        }
+        None => false,
+    }
+}
+
+/// record formats a pax record, whose leading length counts its own digits.
+fn record(key: &str, value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3; // space, equals, newline
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+    let mut record = format!("{} {}=", len, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+/// records parses the pax records in data.
+fn records(mut data: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut parsed = Vec::new();
+    while !data.is_empty() && data[0] != 0 {
+        let space = data.iter().position(|&b| b == b' ').ok_or_else(|| invalid("bad pax record"))?;
+        let len = decimal(&data[..space])? as usize;
+        if len <= space + 1 || len > data.len() || data[len - 1] != b'\n' {
+            return Err(invalid("bad pax record"));
+        }
+        let record = &data[space + 1..len - 1];
+        let eq = record.iter().position(|&b| b == b'=').ok_or_else(|| invalid("bad pax record"))?;
+        parsed.push((String::from_utf8_lossy(&record[..eq]).into_owned(), record[eq + 1..].to_vec()));
+        data = &data[len..];
+    }
+    Ok(parsed)
+}
+
+/// read_block fills block, returning false if the reader was already at its end.
+fn read_block<R: Read>(r: &mut R, block: &mut [u8; BLOCK]) -> Result<bool> {
+    let mut read = 0;
+    while read < BLOCK {
+        match r.read(&mut block[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(invalid("truncated header")),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// checksum sums the header's bytes, counting the checksum field as spaces.
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    header.iter().enumerate().map(|(i, &b)| {
+        if i >= CHKSUM.0 && i < CHKSUM.0 + CHKSUM.1 { u64::from(b' ') } else { u64::from(b) }
+    }).sum()
+}
+
+fn field(header: &[u8; BLOCK], (at, len): (usize, usize)) -> &[u8] {
+    &header[at..at + len]
+}
+
+fn put(header: &mut [u8; BLOCK], (at, len): (usize, usize), value: &[u8]) {
+    header[at..at + value.len().min(len)].copy_from_slice(&value[..value.len().min(len)]);
+}
+
+/// put_number stores n as NUL terminated octal, or in GNU base-256 if it does not fit.
+fn put_number(header: &mut [u8; BLOCK], (at, len): (usize, usize), n: u64) {
+    let octal = format!("{:01$o}\0", n, len - 1);
+    if octal.len() <= len {
+        put(header, (at, len), octal.as_bytes());
+        return;
+    }
+    let field = &mut header[at..at + len];
+    for (i, b) in field.iter_mut().rev().enumerate() {
+        *b = if i < 8 { (n >> (8 * i)) as u8 } else { 0 };
+    }
+    field[0] |= 0x80;
+}
+
+/// number parses an octal or GNU base-256 numeric field.
+fn number(field: &[u8]) -> Result<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        return Ok(field[1..].iter().fold(u64::from(field[0] & 0x7f), |n, &b| n << 8 | u64::from(b)));
+    }
+    let digits = cstr(field);
+    let digits = String::from_utf8_lossy(digits);
+    let digits = digits.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid("bad numeric field"))
+}
+
+fn decimal(digits: &[u8]) -> Result<u64> {
+    String::from_utf8_lossy(digits).parse().map_err(|_| invalid("bad pax number"))
+}
+
+/// pax_time parses a pax timestamp, which may have a fractional part.
+fn pax_time(value: &[u8]) -> Result<Duration> {
+    let value = String::from_utf8_lossy(value);
+    let mut parts = value.splitn(2, '.');
+    let secs = parts.next().unwrap_or("").parse().map_err(|_| invalid("bad pax mtime"))?;
+    let nanos = match parts.next() {
+        Some(frac) => format!("{:0<9.9}", frac).parse().map_err(|_| invalid("bad pax mtime"))?,
+        None => 0,
+    };
+    Ok(Duration::new(secs, nanos))
+}
+
+/// cstr returns the bytes of a field up to its first NUL.
+fn cstr(field: &[u8]) -> &[u8] {
+    match field.iter().position(|&b| b == 0) {
+        Some(end) => &field[..end],
+        None => field,
+    }
+}
+
+/// relative converts an archive path to a path relative to the root, which is the empty path.
+fn relative(name: &[u8]) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in from_bytes(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir | Component::RootDir => (),
+            _ => return Err(invalid("path leaves the archive root")),
+        }
+    }
+    Ok(path)
+}
+
+/// tar_name joins the components of a relative path with slashes, naming the root `.`.
+fn tar_name(path: &Path) -> Vec<u8> {
+    let mut name = b".".to_vec();
+    for component in path.components() {
+        name.push(b'/');
+        name.extend(to_bytes(Path::new(component.as_os_str())));
+    }
+    if name.len() > 1 {
+        name.drain(..2);
+    }
+    name
+}
+
+#[cfg(unix)]
+fn to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().replace('\\', "/").into_bytes()
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record() {
+        assert_eq!(super::record("path", b"a"), b"9 path=a\n");
+        // Adding a digit to the length must count that digit.
+        let value = vec![b'x'; 91];
+        let rec = super::record("path", &value);
+        assert_eq!(rec.len(), 101);
+        assert!(rec.starts_with(b"101 path="));
+        assert_eq!(records(&rec).unwrap(), vec![(String::from("path"), value)]);
+        assert!(records(b"5 path=a\n").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        let mut header = [0u8; BLOCK];
+        put_number(&mut header, MODE, 0o755);
+        assert_eq!(field(&header, MODE), b"0000755\0");
+        assert_eq!(number(field(&header, MODE)).unwrap(), 0o755);
+
+        // Sizes past 8GiB do not fit in octal and use base-256.
+        put_number(&mut header, SIZE, 1 << 40);
+        assert_eq!(header[SIZE.0], 0x80);
+        assert_eq!(number(field(&header, SIZE)).unwrap(), 1 << 40);
+
+        assert_eq!(number(b"  644 \0\0").unwrap(), 0o644);
+        assert!(number(b"9").is_err());
+        assert_eq!(pax_time(b"12.5").unwrap(), Duration::new(12, 500_000_000));
+    }
+
+    #[test]
+    fn names() {
+        let mut header = [0u8; BLOCK];
+        let long = [vec![b'a'; 120], b"/".to_vec(), vec![b'b'; 90]].concat();
+        assert!(split_name(&mut header, &long));
+        assert_eq!(cstr(field(&header, PREFIX)), &long[..120]);
+        assert_eq!(cstr(field(&header, NAME)), &long[121..]);
+        assert!(!split_name(&mut header, &[b'a'; 101]));
+
+        assert_eq!(tar_name(Path::new("")), b".");
+        assert_eq!(tar_name(Path::new("a/b")), b"a/b");
+        assert_eq!(relative(b"./a//b/").unwrap(), PathBuf::from("a/b"));
+        assert_eq!(relative(b"/a").unwrap(), PathBuf::from("a"));
+        assert!(relative(b"a/../../b").is_err());
+    }
+    #[test]
+    fn sparse() {
+        // Extents that touch across chunks are mapped as one.
+        let mut data = Data::default();
+        data.write_at(1 << 16, &[1; 1 << 17]);
+        data.write_at(1 << 20, b"x");
+        data.set_len(1 << 21);
+        let map = sparse_map(&data);
+        assert_eq!(map.len(), BLOCK);
+        assert!(map.starts_with(b"3\n65536\n131072\n1048576\n1\n2097152\n0\n\0"));
+        let entry = [&map[..], &[1; 1 << 17][..], b"x"].concat();
+        assert!(sparse_data(&entry, 1 << 21).unwrap() == data);
+    }
+}
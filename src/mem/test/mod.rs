@@ -4,16 +4,63 @@
 //! on top of the [`mem`] module provided by this crate; read that module documentation first to
 //! understand how the in-memory aspects of this filesystem will behave.
 //!
-//! Injected errors are consumed via a FIFO queue. The calling API passes a sequence of closures
-//! that will be passed which function a filesystem call is in if the function can error. The
-//! sequence of closures is drained as they return `Some(Result)`; if a closure returns `None`, the
-//! closure is not drained from the sequence.
+//! Every method that can fail is a call site, named by a variant of [`Call`] (or [`FileCall`] for
+//! reads and writes). Before a call site runs, the `FS` consults, in order:
 //!
-#[path = "unix.rs"]
-mod fs;
+//! 1. a FIFO queue of results for that call site, filled by [`FS::inject`] (or
+//!    [`FS::inject_file`]). Queued results are drained one per call. A queued `Ok` lets the call
+//!    through, which makes it easy to fail, say, only the third `sync_all`.
+//! 2. an optional closure set by [`FS::set_inject_fn`] (or [`FS::set_inject_file_fn`]). The
+//!    closure is given an [`In`] (or [`InFile`]) describing the call and its arguments, so it can
+//!    decide to fail based on paths or buffer sizes.
+//!
+//! If the result is an error, the error is returned and the underlying in-memory filesystem is
+//! not touched. For reads and writes, an `Ok(n)` limits the read or write to at most `n` bytes,
+//! allowing short reads and writes to be triggered.
+//!
+//! Injections are shared by every clone of an `FS` and every type created from it.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::Write;
+//!
+//! use rsfs::*;
+//! use rsfs::errors::*;
+//! use rsfs::mem::test::{Call, FileCall, In, FS};
+//!
+//! let fs = FS::new();
+//! let mut f = fs.create_file("f").unwrap();
+//!
+//! // The next sync_all fails; the one after it succeeds.
+//! fs.inject(Call::FileSyncAll, Err(EIO()));
+//! assert!(f.sync_all().is_err());
+//! assert!(f.sync_all().is_ok());
+//!
+//! // The next write is short.
+//! fs.inject_file(FileCall::Write, Ok(2));
+//! assert_eq!(f.write(b"hello").unwrap(), 2);
+//!
+//! // Every rename into "dst" fails.
+//! fs.set_inject_fn(Some(Box::new(|call: In| match call {
+//!     In::FSRename(_, to) if to.starts_with("dst") => Err(EACCES()),
+//!     _ => Ok(()),
+//! })));
+//! assert!(fs.rename("f", "dst").is_err());
+//! assert!(fs.rename("f", "g").is_ok());
+//! ```
+//!
+//! [`FS`]: struct.FS.html
+//! [`mem`]: ../index.html
+//! [`Call`]: enum.Call.html
+//! [`FileCall`]: enum.FileCall.html
+//! [`In`]: enum.In.html
+//! [`InFile`]: enum.InFile.html
+//! [`FS::inject`]: struct.FS.html#method.inject
+//! [`FS::inject_file`]: struct.FS.html#method.inject_file
+//! [`FS::set_inject_fn`]: struct.FS.html#method.set_inject_fn
+//! [`FS::set_inject_file_fn`]: struct.FS.html#method.set_inject_file_fn
 
-pub use self::fs::*;
+pub use self::unix::*;
 
 mod unix;
-
-// TODO redo this documentation when eventually making this module public.
@@ -1,14 +1,13 @@
 //! This module provides an in-memory filesystem, error injectable filesystem. It is `pub use`d in
 //! `rsfs::mem::test`.
 
-// TODO redo this entire API.
-
 extern crate parking_lot;
 
 use std::cmp;
-use std::ffi::OsString;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
 use std::fmt;
-use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -19,16 +18,25 @@ use fs;
 use unix_ext;
 use mem::unix as mem;
 
-/// The `testfs` is about to call a method on a file that can error.
+/// The test `FS` is about to read or write through a file.
+///
+/// This is passed to the closure set with [`FS::set_inject_file_fn`].
+///
+/// [`FS::set_inject_file_fn`]: struct.FS.html#method.set_inject_file_fn
 pub enum InFile<'p> {
+    /// A `read` through `Read`.
     Read(&'p File, &'p [u8]),
+    /// A `write` through `Write`.
     Write(&'p File, &'p [u8]),
+    /// A `read_at` through `unix_ext::FileExt`.
     ReadAt(&'p File, &'p [u8], u64),
+    /// A `write_at` through `unix_ext::FileExt`.
     WriteAt(&'p File, &'p [u8], u64),
 }
 
-impl<'a> InFile<'a> {
-    pub fn buf_len(&'a self) -> usize {
+impl<'p> InFile<'p> {
+    /// Returns the length of the buffer being read into or written from.
+    pub fn buf_len(&self) -> usize {
         match *self {
             InFile::Read(_, b) => b.len(),
             InFile::Write(_, b) => b.len(),
@@ -36,9 +44,36 @@ impl<'a> InFile<'a> {
             InFile::WriteAt(_, b, _) => b.len(),
         }
     }
+
+    /// Returns the call site this read or write is occuring at.
+    pub fn call(&self) -> FileCall {
+        match *self {
+            InFile::Read(..) => FileCall::Read,
+            InFile::Write(..) => FileCall::Write,
+            InFile::ReadAt(..) => FileCall::ReadAt,
+            InFile::WriteAt(..) => FileCall::WriteAt,
+        }
+    }
 }
 
-/// The `testfs` is about to call a method that can error.
+/// The call sites of [`InFile`], used to queue injected results with [`FS::inject_file`].
+///
+/// [`InFile`]: enum.InFile.html
+/// [`FS::inject_file`]: struct.FS.html#method.inject_file
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FileCall {
+    Read,
+    Write,
+    ReadAt,
+    WriteAt,
+}
+
+/// The test `FS` is about to call a method that can error.
+///
+/// This is passed to the closure set with [`FS::set_inject_fn`]. Each variant is named after the
+/// type and method being called and carries the arguments to that call.
+///
+/// [`FS::set_inject_fn`]: struct.FS.html#method.set_inject_fn
 pub enum In<'p> {
     DirBuilderCreate(&'p DirBuilder, &'p PathBuf),
     DirEntryMetadata(&'p DirEntry),
@@ -49,8 +84,19 @@ pub enum In<'p> {
     FileMetadata(&'p File),
     FileTryClone(&'p File),
     FileSetPermissions(&'p File, mem::Permissions),
+    FileSetTimes(&'p File, fs::FileTimes),
+    FileLock(&'p File),
+    FileLockShared(&'p File),
+    FileTryLock(&'p File),
+    FileTryLockShared(&'p File),
+    FileUnlock(&'p File),
     FileFlush(&'p File),
     FileSeek(&'p File, &'p SeekFrom),
+    FileSeekSparse(&'p File, unix_ext::SparseSeek),
+    FileGetXattr(&'p File, &'p OsStr),
+    FileSetXattr(&'p File, &'p OsStr, &'p [u8]),
+    FileListXattr(&'p File),
+    FileRemoveXattr(&'p File, &'p OsStr),
     MetadataModified(&'p Metadata),
     MetadataAccessed(&'p Metadata),
     MetadataCreated(&'p Metadata),
@@ -60,6 +106,7 @@ pub enum In<'p> {
     FSCopy(&'p PathBuf, &'p PathBuf),
     FSCreateDir(&'p PathBuf),
     FSCreateDirAll(&'p PathBuf),
+    FSCurrentDir,
     FSHardLink(&'p PathBuf, &'p PathBuf),
     FSMetadata(&'p PathBuf),
     FSReadDir(&'p PathBuf),
@@ -68,24 +115,257 @@ pub enum In<'p> {
     FSRemoveDirAll(&'p PathBuf),
     FSRemoveFile(&'p PathBuf),
     FSRename(&'p PathBuf, &'p PathBuf),
+    FSSetCurrentDir(&'p PathBuf),
     FSSetPermissions(&'p PathBuf, &'p mem::Permissions),
+    FSSetTimes(&'p PathBuf, fs::FileTimes),
     FSSymlinkMetadata(&'p PathBuf),
     FSOpenFile(&'p PathBuf),
     FSCreateFile(&'p PathBuf),
-    FSSymlink(&'p PathBuf, &'p PathBuf)
+    FSSymlink(&'p PathBuf, &'p PathBuf),
+    FSChown(&'p PathBuf, Option<u32>, Option<u32>),
+    FSMkfifo(&'p PathBuf, u32),
+    FSMknod(&'p PathBuf, u32, u64),
+    FSLinkFile(&'p PathBuf),
+    FSRenameWithFlags(&'p PathBuf, &'p PathBuf, u32),
+    FSGetXattr(&'p PathBuf, &'p OsStr),
+    FSSetXattr(&'p PathBuf, &'p OsStr, &'p [u8]),
+    FSListXattr(&'p PathBuf),
+    FSRemoveXattr(&'p PathBuf, &'p OsStr),
+    FSSymlinkGetXattr(&'p PathBuf, &'p OsStr),
+    FSSymlinkSetXattr(&'p PathBuf, &'p OsStr, &'p [u8]),
+    FSSymlinkListXattr(&'p PathBuf),
+    FSSymlinkRemoveXattr(&'p PathBuf, &'p OsStr),
+}
+
+impl<'p> In<'p> {
+    /// Returns the call site this `In` is occuring at.
+    pub fn call(&self) -> Call {
+        match *self {
+            In::DirBuilderCreate(..) => Call::DirBuilderCreate,
+            In::DirEntryMetadata(..) => Call::DirEntryMetadata,
+            In::DirEntryFileType(..) => Call::DirEntryFileType,
+            In::FileSyncAll(..) => Call::FileSyncAll,
+            In::FileSyncData(..) => Call::FileSyncData,
+            In::FileSetLen(..) => Call::FileSetLen,
+            In::FileMetadata(..) => Call::FileMetadata,
+            In::FileTryClone(..) => Call::FileTryClone,
+            In::FileSetPermissions(..) => Call::FileSetPermissions,
+            In::FileSetTimes(..) => Call::FileSetTimes,
+            In::FileLock(..) => Call::FileLock,
+            In::FileLockShared(..) => Call::FileLockShared,
+            In::FileTryLock(..) => Call::FileTryLock,
+            In::FileTryLockShared(..) => Call::FileTryLockShared,
+            In::FileUnlock(..) => Call::FileUnlock,
+            In::FileFlush(..) => Call::FileFlush,
+            In::FileSeek(..) => Call::FileSeek,
+            In::FileSeekSparse(..) => Call::FileSeekSparse,
+            In::FileGetXattr(..) => Call::FileGetXattr,
+            In::FileSetXattr(..) => Call::FileSetXattr,
+            In::FileListXattr(..) => Call::FileListXattr,
+            In::FileRemoveXattr(..) => Call::FileRemoveXattr,
+            In::MetadataModified(..) => Call::MetadataModified,
+            In::MetadataAccessed(..) => Call::MetadataAccessed,
+            In::MetadataCreated(..) => Call::MetadataCreated,
+            In::OpenOptionsOpen(..) => Call::OpenOptionsOpen,
+            In::ReadDirNext(..) => Call::ReadDirNext,
+            In::FSCanonicalize(..) => Call::FSCanonicalize,
+            In::FSCopy(..) => Call::FSCopy,
+            In::FSCreateDir(..) => Call::FSCreateDir,
+            In::FSCreateDirAll(..) => Call::FSCreateDirAll,
+            In::FSCurrentDir => Call::FSCurrentDir,
+            In::FSHardLink(..) => Call::FSHardLink,
+            In::FSMetadata(..) => Call::FSMetadata,
+            In::FSReadDir(..) => Call::FSReadDir,
+            In::FSReadLink(..) => Call::FSReadLink,
+            In::FSRemoveDir(..) => Call::FSRemoveDir,
+            In::FSRemoveDirAll(..) => Call::FSRemoveDirAll,
+            In::FSRemoveFile(..) => Call::FSRemoveFile,
+            In::FSRename(..) => Call::FSRename,
+            In::FSSetCurrentDir(..) => Call::FSSetCurrentDir,
+            In::FSSetPermissions(..) => Call::FSSetPermissions,
+            In::FSSetTimes(..) => Call::FSSetTimes,
+            In::FSSymlinkMetadata(..) => Call::FSSymlinkMetadata,
+            In::FSOpenFile(..) => Call::FSOpenFile,
+            In::FSCreateFile(..) => Call::FSCreateFile,
+            In::FSSymlink(..) => Call::FSSymlink,
+            In::FSChown(..) => Call::FSChown,
+            In::FSMkfifo(..) => Call::FSMkfifo,
+            In::FSMknod(..) => Call::FSMknod,
+            In::FSLinkFile(..) => Call::FSLinkFile,
+            In::FSRenameWithFlags(..) => Call::FSRenameWithFlags,
+            In::FSGetXattr(..) => Call::FSGetXattr,
+            In::FSSetXattr(..) => Call::FSSetXattr,
+            In::FSListXattr(..) => Call::FSListXattr,
+            In::FSRemoveXattr(..) => Call::FSRemoveXattr,
+            In::FSSymlinkGetXattr(..) => Call::FSSymlinkGetXattr,
+            In::FSSymlinkSetXattr(..) => Call::FSSymlinkSetXattr,
+            In::FSSymlinkListXattr(..) => Call::FSSymlinkListXattr,
+            In::FSSymlinkRemoveXattr(..) => Call::FSSymlinkRemoveXattr,
+        }
+    }
+}
+
+/// The call sites of [`In`], used to queue injected results with [`FS::inject`].
+///
+/// [`In`]: enum.In.html
+/// [`FS::inject`]: struct.FS.html#method.inject
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Call {
+    DirBuilderCreate,
+    DirEntryMetadata,
+    DirEntryFileType,
+    FileSyncAll,
+    FileSyncData,
+    FileSetLen,
+    FileMetadata,
+    FileTryClone,
+    FileSetPermissions,
+    FileSetTimes,
+    FileLock,
+    FileLockShared,
+    FileTryLock,
+    FileTryLockShared,
+    FileUnlock,
+    FileFlush,
+    FileSeek,
+    FileSeekSparse,
+    FileGetXattr,
+    FileSetXattr,
+    FileListXattr,
+    FileRemoveXattr,
+    MetadataModified,
+    MetadataAccessed,
+    MetadataCreated,
+    OpenOptionsOpen,
+    ReadDirNext,
+    FSCanonicalize,
+    FSCopy,
+    FSCreateDir,
+    FSCreateDirAll,
+    FSCurrentDir,
+    FSHardLink,
+    FSMetadata,
+    FSReadDir,
+    FSReadLink,
+    FSRemoveDir,
+    FSRemoveDirAll,
+    FSRemoveFile,
+    FSRename,
+    FSSetCurrentDir,
+    FSSetPermissions,
+    FSSetTimes,
+    FSSymlinkMetadata,
+    FSOpenFile,
+    FSCreateFile,
+    FSSymlink,
+    FSChown,
+    FSMkfifo,
+    FSMknod,
+    FSLinkFile,
+    FSRenameWithFlags,
+    FSGetXattr,
+    FSSetXattr,
+    FSListXattr,
+    FSRemoveXattr,
+    FSSymlinkGetXattr,
+    FSSymlinkSetXattr,
+    FSSymlinkListXattr,
+    FSSymlinkRemoveXattr,
+}
+
+/// A closure deciding whether an [`In`] call fails. See [`FS::set_inject_fn`].
+///
+/// [`In`]: enum.In.html
+/// [`FS::set_inject_fn`]: struct.FS.html#method.set_inject_fn
+pub type MaybeInjectFn = Option<Box<dyn FnMut(In) -> Result<()> + Send>>;
+/// A closure deciding whether an [`InFile`] read or write fails or is short. See
+/// [`FS::set_inject_file_fn`].
+///
+/// [`InFile`]: enum.InFile.html
+/// [`FS::set_inject_file_fn`]: struct.FS.html#method.set_inject_file_fn
+pub type MaybeInjectFileFn = Option<Box<dyn FnMut(InFile) -> Result<usize> + Send>>;
+
+/// `Injector` holds everything that decides whether a call site fails. Every type in this module
+/// shares the `Injector` of the `FS` it came from.
+struct Injector {
+    queued:         HashMap<Call, VecDeque<Result<()>>>,
+    queued_file:    HashMap<FileCall, VecDeque<Result<usize>>>,
+    inject_fn:      MaybeInjectFn,
+    inject_file_fn: MaybeInjectFileFn,
+    /// generation counts the closures set, so that a closure taken out to be called is only put
+    /// back if it was not replaced while it ran.
+    generation:     u64,
+}
+
+type SharedInjector = Arc<Mutex<Injector>>;
+
+impl Injector {
+    fn new() -> SharedInjector {
+        Arc::new(Mutex::new(Injector {
+            queued:         HashMap::new(),
+            queued_file:    HashMap::new(),
+            inject_fn:      None,
+            inject_file_fn: None,
+            generation:     0,
+        }))
+    }
+}
+
+/// `check` returns the result for a call site, preferring a queued result over the closure.
+///
+/// The closure is called without the injector locked, so that it may call back into the
+/// filesystem; calls it makes are not checked against the closure itself.
+fn check(injector: &SharedInjector, at: In) -> Result<()> {
+    let (mut f, generation) = {
+        let mut injector = injector.lock();
+        if let Some(res) = injector.queued.get_mut(&at.call()).and_then(VecDeque::pop_front) {
+            return res;
+        }
+        match injector.inject_fn.take() {
+            Some(f) => (f, injector.generation),
+            None => return Ok(()),
+        }
+    };
+    let res = f(at);
+    let mut injector = injector.lock();
+    if injector.generation == generation {
+        injector.inject_fn = Some(f);
+    }
+    res
+}
+
+/// `check_file` returns how many bytes a read or write may use, preferring a queued result over
+/// the closure, which is called as in `check`. The returned size is never larger than the buffer.
+fn check_file(injector: &SharedInjector, at: InFile) -> Result<usize> {
+    let len = at.buf_len();
+    let (mut f, generation) = {
+        let mut injector = injector.lock();
+        if let Some(res) = injector.queued_file.get_mut(&at.call()).and_then(VecDeque::pop_front) {
+            return res.map(|n| cmp::min(n, len));
+        }
+        match injector.inject_file_fn.take() {
+            Some(f) => (f, injector.generation),
+            None => return Ok(len),
+        }
+    };
+    let res = f(at);
+    let mut injector = injector.lock();
+    if injector.generation == generation {
+        injector.inject_file_fn = Some(f);
+    }
+    res.map(|n| cmp::min(n, len))
 }
 
 /// A builder used to create directories in various manners.
 ///
 /// This struct wraps [`mem::DirBuilder`] with the addition that `.create()` can potentially
-/// consume a [`testfs`] injected error. See the module [documentation] for an example.
+/// consume an injected error. See the module [documentation] for an example.
 ///
-/// [`mem::DirBuilder`]: ../mem/struct.DirBuilder.html
-/// [`testfs`]: index.html
+/// [`mem::DirBuilder`]: ../struct.DirBuilder.html
 /// [documentation]: index.html
 pub struct DirBuilder {
-    injector: InjectFn,
-    pub inner: mem::DirBuilder,
+    injector: SharedInjector,
+    inner:    mem::DirBuilder,
 }
 
 impl fmt::Debug for DirBuilder {
@@ -99,13 +379,7 @@ impl fs::DirBuilder for DirBuilder {
         self.inner.recursive(recursive); self
     }
     fn create<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::DirBuilderCreate(self, &path.as_ref().to_owned()))) {
-            r?;
-        }
+        check(&self.injector, In::DirBuilderCreate(self, &path.as_ref().to_owned()))?;
         self.inner.create(path)
     }
 }
@@ -118,16 +392,15 @@ impl unix_ext::DirBuilderExt for DirBuilder {
 
 /// Entries returned by the [`ReadDir`] iterator.
 ///
-/// This struct wraps [`mem::DirEntry`] with the addition that `.metadata()` can potentially
-/// consume a [`testfs`] injected error. See the module [documentation] for an example.
+/// This struct wraps [`mem::DirEntry`] with the addition that `.metadata()` and `.file_type()`
+/// can potentially consume an injected error. See the module [documentation] for an example.
 ///
 /// [`ReadDir`]: struct.ReadDir.html
-/// [`mem::DirEntry`]: ../mem/struct.DirEntry.html
-/// [`testfs`]: index.html
+/// [`mem::DirEntry`]: ../struct.DirEntry.html
 /// [documentation]: index.html
 pub struct DirEntry {
-    injector: InjectFn,
-    pub inner: mem::DirEntry,
+    injector: SharedInjector,
+    inner:    mem::DirEntry,
 }
 
 impl fmt::Debug for DirEntry {
@@ -144,26 +417,14 @@ impl fs::DirEntry for DirEntry {
         self.inner.path()
     }
     fn metadata(&self) -> Result<Self::Metadata> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::DirEntryMetadata(self))) {
-            r?;
-        }
+        check(&self.injector, In::DirEntryMetadata(self))?;
         Ok(Metadata {
             injector: self.injector.clone(),
-            inner: self.inner.metadata()?,
+            inner:    self.inner.metadata()?,
         })
     }
     fn file_type(&self) -> Result<Self::FileType> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::DirEntryFileType(self))) {
-            r?;
-        }
+        check(&self.injector, In::DirEntryFileType(self))?;
         self.inner.file_type()
     }
     fn file_name(&self) -> OsString {
@@ -171,18 +432,23 @@ impl fs::DirEntry for DirEntry {
     }
 }
 
+impl unix_ext::DirEntryExt for DirEntry {
+    fn ino(&self) -> u64 {
+        unix_ext::DirEntryExt::ino(&self.inner)
+    }
+}
+
 /// A view into a file on the filesystem.
 ///
 /// This struct wraps [`mem::File`] with the addition that file methods can potentially consume
-/// [`testfs`] injected errors. See the module [documentation] for an example.
+/// injected errors and that reads and writes can be made short. See the module [documentation]
+/// for an example.
 ///
-/// [`mem::File`]: ../mem/struct.File.html
-/// [`testfs`]: index.html
+/// [`mem::File`]: ../struct.File.html
 /// [documentation]: index.html
 pub struct File {
-    injector: InjectFn,
-    file_injector: InjectFilePartialFn,
-    inner: mem::File,
+    injector: SharedInjector,
+    inner:    mem::File,
 }
 
 impl fmt::Debug for File {
@@ -192,101 +458,102 @@ impl fmt::Debug for File {
 }
 
 impl fs::File for File {
-    type Metadata = Metadata;
+    type Metadata    = Metadata;
     type Permissions = mem::Permissions;
 
     fn sync_all(&self) -> Result<()> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FileSyncAll(self))) {
-            r?;
-        }
+        check(&self.injector, In::FileSyncAll(self))?;
         self.inner.sync_all()
     }
     fn sync_data(&self) -> Result<()> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FileSyncData(self))) {
-            r?;
-        }
+        check(&self.injector, In::FileSyncData(self))?;
         self.inner.sync_data()
     }
     fn set_len(&self, size: u64) -> Result<()> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FileSetLen(self, size))) {
-            r?;
-        }
+        check(&self.injector, In::FileSetLen(self, size))?;
         self.inner.set_len(size)
     }
     fn metadata(&self) -> Result<Self::Metadata> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FileMetadata(self))) {
-            r?;
-        }
+        check(&self.injector, In::FileMetadata(self))?;
         Ok(Metadata {
             injector: self.injector.clone(),
-            inner: self.inner.metadata()?,
+            inner:    self.inner.metadata()?,
         })
     }
     fn try_clone(&self) -> Result<Self> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FileTryClone(self))) {
-            r?;
-        }
-        let inner = self.inner.try_clone()?;
+        check(&self.injector, In::FileTryClone(self))?;
         Ok(File {
             injector: self.injector.clone(),
-            file_injector: self.file_injector.clone(),
-            inner: inner,
+            inner:    self.inner.try_clone()?,
         })
     }
     fn set_permissions(&self, perm: Self::Permissions) -> Result<()> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FileSetPermissions(self, perm))) {
-            r?;
-        }
+        check(&self.injector, In::FileSetPermissions(self, perm))?;
         self.inner.set_permissions(perm)
     }
+    fn set_times(&self, times: fs::FileTimes) -> Result<()> {
+        check(&self.injector, In::FileSetTimes(self, times))?;
+        self.inner.set_times(times)
+    }
+    fn lock(&self) -> Result<()> {
+        check(&self.injector, In::FileLock(self))?;
+        self.inner.lock()
+    }
+    fn lock_shared(&self) -> Result<()> {
+        check(&self.injector, In::FileLockShared(self))?;
+        self.inner.lock_shared()
+    }
+    fn try_lock(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        check(&self.injector, In::FileTryLock(self)).map_err(try_lock_error)?;
+        self.inner.try_lock()
+    }
+    fn try_lock_shared(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        check(&self.injector, In::FileTryLockShared(self)).map_err(try_lock_error)?;
+        self.inner.try_lock_shared()
+    }
+    fn unlock(&self) -> Result<()> {
+        check(&self.injector, In::FileUnlock(self))?;
+        self.inner.unlock()
+    }
+}
+
+// try_lock_error turns an injected error of kind WouldBlock into TryLockError::WouldBlock.
+fn try_lock_error(e: Error) -> fs::TryLockError {
+    if e.kind() == ErrorKind::WouldBlock {
+        fs::TryLockError::WouldBlock
+    } else {
+        fs::TryLockError::Error(e)
+    }
 }
 
 impl unix_ext::FileExt for File {
     fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
-        let mut injector = self.file_injector.lock(); // always hold for the entire fn
-        let mut sz = buf.len();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(InFile::ReadAt(self, buf, offset))) {
-            sz = cmp::min(r?, buf.len());
-        }
-        (&self.inner).read_at(&mut buf[..sz], offset)
+        let sz = check_file(&self.injector, InFile::ReadAt(self, buf, offset))?;
+        self.inner.read_at(&mut buf[..sz], offset)
     }
     fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
-        let mut injector = self.file_injector.lock(); // always hold for the entire fn
-        let mut sz = buf.len();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(InFile::WriteAt(self, buf, offset))) {
-            sz = cmp::min(r?, buf.len());
-        }
-        (&self.inner).write_at(&buf[..sz], offset)
+        let sz = check_file(&self.injector, InFile::WriteAt(self, buf, offset))?;
+        self.inner.write_at(&buf[..sz], offset)
+    }
+    fn seek_sparse(&self, pos: unix_ext::SparseSeek) -> Result<u64> {
+        check(&self.injector, In::FileSeekSparse(self, pos))?;
+        self.inner.seek_sparse(pos)
+    }
+    fn get_xattr<N: AsRef<OsStr>>(&self, name: N) -> Result<Vec<u8>> {
+        check(&self.injector, In::FileGetXattr(self, name.as_ref()))?;
+        self.inner.get_xattr(name)
+    }
+    fn set_xattr<N: AsRef<OsStr>>(&self, name: N, value: &[u8]) -> Result<()> {
+        check(&self.injector, In::FileSetXattr(self, name.as_ref(), value))?;
+        self.inner.set_xattr(name, value)
+    }
+    fn list_xattr(&self) -> Result<Vec<OsString>> {
+        check(&self.injector, In::FileListXattr(self))?;
+        self.inner.list_xattr()
+    }
+    fn remove_xattr<N: AsRef<OsStr>>(&self, name: N) -> Result<()> {
+        check(&self.injector, In::FileRemoveXattr(self, name.as_ref()))?;
+        self.inner.remove_xattr(name)
     }
 }
 
@@ -309,56 +576,42 @@ impl Seek for File {
     }
 }
 
-impl<'a> Read for &'a File {
+impl Read for &File {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let mut injector = self.file_injector.lock(); // always hold for the entire fn
         // The injector may return Ok, but that we should read a small number of bytes.
-        let mut sz = buf.len();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(InFile::Read(self, buf))) {
-            sz = cmp::min(r?, buf.len());
-        }
+        let sz = check_file(&self.injector, InFile::Read(self, buf))?;
         (&self.inner).read(&mut buf[..sz])
     }
 }
-
-impl<'a> Write for &'a File {
+impl Write for &File {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let mut injector = self.file_injector.lock(); // always hold for the entire fn
         // The injector may return Ok, but that we should write a small number of bytes.
-        let mut sz = buf.len();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(InFile::Write(self, buf))) {
-            sz = cmp::min(r?, buf.len());
-        }
+        let sz = check_file(&self.injector, InFile::Write(self, buf))?;
         (&self.inner).write(&buf[..sz])
     }
     fn flush(&mut self) -> Result<()> {
+        check(&self.injector, In::FileFlush(self))?;
         (&self.inner).flush()
     }
 }
-
-impl<'a> Seek for &'a File {
+impl Seek for &File {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FileSeek(self, &pos))) {
-            r?;
-        }
+        check(&self.injector, In::FileSeek(self, &pos))?;
         (&self.inner).seek(pos)
     }
 }
 
+/// Metadata information about a file.
+///
+/// This struct wraps [`mem::Metadata`] with the addition that the time methods can potentially
+/// consume injected errors. See the module [documentation] for an example.
+///
+/// [`mem::Metadata`]: ../struct.Metadata.html
+/// [documentation]: index.html
 #[derive(Clone)]
 pub struct Metadata {
-    injector: InjectFn,
-    inner: mem::Metadata,
+    injector: SharedInjector,
+    inner:    mem::Metadata,
 }
 
 impl fs::Metadata for Metadata {
@@ -381,34 +634,67 @@ impl fs::Metadata for Metadata {
         self.inner.permissions()
     }
     fn modified(&self) -> Result<SystemTime> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::MetadataModified(self))) {
-            r?;
-        }
-        Ok(self.inner.modified()?)
+        check(&self.injector, In::MetadataModified(self))?;
+        self.inner.modified()
     }
     fn accessed(&self) -> Result<SystemTime> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::MetadataAccessed(self))) {
-            r?;
-        }
-        Ok(self.inner.accessed()?)
+        check(&self.injector, In::MetadataAccessed(self))?;
+        self.inner.accessed()
     }
     fn created(&self) -> Result<SystemTime> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::MetadataCreated(self))) {
-            r?;
-        }
-        Ok(self.inner.created()?)
+        check(&self.injector, In::MetadataCreated(self))?;
+        self.inner.created()
+    }
+}
+
+impl unix_ext::MetadataExt for Metadata {
+    fn dev(&self) -> u64 {
+        unix_ext::MetadataExt::dev(&self.inner)
+    }
+    fn ino(&self) -> u64 {
+        unix_ext::MetadataExt::ino(&self.inner)
+    }
+    fn mode(&self) -> u32 {
+        unix_ext::MetadataExt::mode(&self.inner)
+    }
+    fn nlink(&self) -> u64 {
+        unix_ext::MetadataExt::nlink(&self.inner)
+    }
+    fn uid(&self) -> u32 {
+        unix_ext::MetadataExt::uid(&self.inner)
+    }
+    fn gid(&self) -> u32 {
+        unix_ext::MetadataExt::gid(&self.inner)
+    }
+    fn rdev(&self) -> u64 {
+        unix_ext::MetadataExt::rdev(&self.inner)
+    }
+    fn size(&self) -> u64 {
+        unix_ext::MetadataExt::size(&self.inner)
+    }
+    fn atime(&self) -> i64 {
+        unix_ext::MetadataExt::atime(&self.inner)
+    }
+    fn atime_nsec(&self) -> i64 {
+        unix_ext::MetadataExt::atime_nsec(&self.inner)
+    }
+    fn mtime(&self) -> i64 {
+        unix_ext::MetadataExt::mtime(&self.inner)
+    }
+    fn mtime_nsec(&self) -> i64 {
+        unix_ext::MetadataExt::mtime_nsec(&self.inner)
+    }
+    fn ctime(&self) -> i64 {
+        unix_ext::MetadataExt::ctime(&self.inner)
+    }
+    fn ctime_nsec(&self) -> i64 {
+        unix_ext::MetadataExt::ctime_nsec(&self.inner)
+    }
+    fn blksize(&self) -> u64 {
+        unix_ext::MetadataExt::blksize(&self.inner)
+    }
+    fn blocks(&self) -> u64 {
+        unix_ext::MetadataExt::blocks(&self.inner)
     }
 }
 
@@ -421,16 +707,14 @@ impl fmt::Debug for Metadata {
 /// Options and flags which can be used to configure how a file is opened.
 ///
 /// This struct wraps [`mem::OpenOptions`] with the addition that `.open()` can potentially consume
-/// a [`testfs`] injected error. See the module [documentation] for an example.
+/// an injected error. See the module [documentation] for an example.
 ///
-/// [`mem::OpenOptions`]: ../mem/struct.OpenOptions.html
-/// [`testfs`]: index.html
+/// [`mem::OpenOptions`]: ../struct.OpenOptions.html
 /// [documentation]: index.html
 #[derive(Clone)]
 pub struct OpenOptions {
-    injector: InjectFn,
-    file_injector: InjectFilePartialFn,
-    inner: mem::OpenOptions,
+    injector: SharedInjector,
+    inner:    mem::OpenOptions,
 }
 
 impl fmt::Debug for OpenOptions {
@@ -461,18 +745,10 @@ impl fs::OpenOptions for OpenOptions {
         self.inner.create_new(create_new); self
     }
     fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::OpenOptionsOpen(self, &path.as_ref().to_owned()))) {
-            r?;
-        }
-
+        check(&self.injector, In::OpenOptionsOpen(self, &path.as_ref().to_owned()))?;
         Ok(File {
             injector: self.injector.clone(),
-            file_injector: self.file_injector.clone(),
-            inner: self.inner.open(path)?,
+            inner:    self.inner.open(path)?,
         })
     }
 }
@@ -489,14 +765,14 @@ impl unix_ext::OpenOptionsExt for OpenOptions {
 /// Iterator over entries in a directory.
 ///
 /// This struct wraps [`mem::ReadDir`] with the addition that `.next()` can potentially consume
-/// [`testfs`] injected errors. See the module [documentation] for an example.
+/// injected errors. An injected error is yielded in place of the next entry; the entry itself is
+/// not skipped. See the module [documentation] for an example.
 ///
-/// [`mem::ReadDir`]: ../mem/struct.ReadDir.html
-/// [`testfs`]: index.html
+/// [`mem::ReadDir`]: ../struct.ReadDir.html
 /// [documentation]: index.html
 pub struct ReadDir {
-    injector: InjectFn,
-    inner: mem::ReadDir,
+    injector: SharedInjector,
+    inner:    mem::ReadDir,
 }
 
 impl fmt::Debug for ReadDir {
@@ -509,31 +785,19 @@ impl Iterator for ReadDir {
     type Item = Result<DirEntry>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut injector = self.injector.lock(); // always hold for the entire fn
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::ReadDirNext(self))) {
-            if r.is_err() {
-                return Some(Err(r.expect_err("is_err failed!")));
-            }
+        if let Err(e) = check(&self.injector, In::ReadDirNext(self)) {
+            return Some(Err(e));
         }
         self.inner
             .next()
             .map(|res| Ok(DirEntry {
                 injector: self.injector.clone(),
-                inner: res?,
+                inner:    res?,
             }))
     }
 }
 
-type InjectFn = Arc<Mutex<MaybeInjectFn>>;
-type InjectFilePartialFn = Arc<Mutex<MaybeInjectFilePartialFn>>;
-
-pub type MaybeInjectFn = Option<Box<FnMut(In) -> Result<()> + Send>>;
-pub type MaybeInjectFilePartialFn = Option<Box<FnMut(InFile) -> Result<usize> + Send>>;
-
-/// An in-memory struct that satisfies [`rsfs::FS`] and allows for injectable errors.
+/// An in-memory struct that satisfies [`rsfs::GenFS`] and allows for injectable errors.
 ///
 /// `FS` is thread safe and copyable. It wraps [`mem::FS`] with an ability to inject errors. This
 /// should be generally useful for triggering error conditions in your code that generally are
@@ -541,14 +805,13 @@ pub type MaybeInjectFilePartialFn = Option<Box<FnMut(InFile) -> Result<usize> +
 ///
 /// See the module [documentation] for an example.
 ///
-/// [`rsfs::FS`]: ../trait.FS.html
-/// [`mem::FS`]: ../mem/struct.FS.html
+/// [`rsfs::GenFS`]: ../../trait.GenFS.html
+/// [`mem::FS`]: ../struct.FS.html
 /// [documentation]: index.html
 #[derive(Clone)]
 pub struct FS {
-    injector: InjectFn,
-    file_injector: InjectFilePartialFn,
-    inner: mem::FS,
+    injector: SharedInjector,
+    inner:    mem::FS,
 }
 
 impl fmt::Debug for FS {
@@ -559,264 +822,361 @@ impl fmt::Debug for FS {
 
 impl FS {
     /// Creates an empty `FS` with mode `0o777`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::test::FS;
+    /// let fs = FS::new();
+    /// ```
     pub fn new() -> FS {
         Self::with_mode(0o777)
     }
 
     /// Creates an empty `FS` with the given mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::test::FS;
+    /// let fs = FS::with_mode(0o300);
+    /// ```
     pub fn with_mode(mode: u32) -> FS {
+        Self::from_mem(mem::FS::with_mode(mode))
+    }
+
+    /// Wraps an existing in-memory filesystem. Operations through the returned `FS` are visible
+    /// through `inner` and vice versa, but only calls through the returned `FS` can fail with
+    /// injected errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem;
+    /// let inner = mem::FS::new();
+    /// let fs = mem::test::FS::from_mem(inner.clone());
+    /// fs.create_dir("a").unwrap();
+    /// assert!(inner.metadata("a").unwrap().is_dir());
+    /// ```
+    pub fn from_mem(inner: mem::FS) -> FS {
         FS {
-            injector: Arc::new(Mutex::new(None)),
-            file_injector: Arc::new(Mutex::new(None)),
-            inner: mem::FS::with_mode(mode),
+            injector: Injector::new(),
+            inner,
         }
     }
 
-    pub fn set_inject_fn(&mut self, f: MaybeInjectFn) {
-        *self.injector.lock() = f
+    /// Queues `res` to be returned from the next call at `call`.
+    ///
+    /// Results queued for the same call site are consumed in the order they were queued. A queued
+    /// `Ok(())` lets the call run against the in-memory filesystem as normal, while a queued `Err`
+    /// is returned without touching the filesystem. Queued results take precedence over the
+    /// closure set by [`set_inject_fn`].
+    ///
+    /// [`set_inject_fn`]: struct.FS.html#method.set_inject_fn
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::errors::*;
+    /// # use rsfs::mem::test::{Call, FS};
+    /// let fs = FS::new();
+    ///
+    /// // Fail only the second create_dir.
+    /// fs.inject(Call::FSCreateDir, Ok(()));
+    /// fs.inject(Call::FSCreateDir, Err(ENOSPC()));
+    ///
+    /// assert!(fs.create_dir("a").is_ok());
+    /// assert!(fs.create_dir("b").is_err());
+    /// assert!(fs.create_dir("b").is_ok());
+    /// ```
+    pub fn inject(&self, call: Call, res: Result<()>) {
+        self.injector.lock().queued.entry(call).or_default().push_back(res)
+    }
+
+    /// Queues `res` to be returned from the next read or write at `call`.
+    ///
+    /// An `Ok(n)` limits the read or write to at most `n` bytes, while an `Err` is returned
+    /// without reading or writing. Queued results take precedence over the closure set by
+    /// [`set_inject_file_fn`].
+    ///
+    /// [`set_inject_file_fn`]: struct.FS.html#method.set_inject_file_fn
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Write;
+    /// # use rsfs::*;
+    /// # use rsfs::mem::test::{FileCall, FS};
+    /// let fs = FS::new();
+    /// let mut f = fs.create_file("f").unwrap();
+    ///
+    /// fs.inject_file(FileCall::Write, Ok(1));
+    /// assert_eq!(f.write(b"abc").unwrap(), 1);
+    /// assert_eq!(f.write(b"bc").unwrap(), 2);
+    /// ```
+    pub fn inject_file(&self, call: FileCall, res: Result<usize>) {
+        self.injector.lock().queued_file.entry(call).or_default().push_back(res)
+    }
+
+    /// Sets a closure that is consulted for every call site that has nothing queued. Passing
+    /// `None` removes any existing closure.
+    ///
+    /// The closure may call back into the filesystem, such as into the file it is passed; calls
+    /// made while it runs are not checked against it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::errors::*;
+    /// # use rsfs::mem::test::{In, FS};
+    /// let fs = FS::new();
+    /// fs.set_inject_fn(Some(Box::new(|call: In| match call {
+    ///     In::FSCreateDir(path) if path.ends_with("bad") => Err(EACCES()),
+    ///     _ => Ok(()),
+    /// })));
+    ///
+    /// assert!(fs.create_dir("bad").is_err());
+    /// assert!(fs.create_dir("good").is_ok());
+    /// ```
+    pub fn set_inject_fn(&self, f: MaybeInjectFn) {
+        let mut injector = self.injector.lock();
+        injector.inject_fn = f;
+        injector.generation += 1;
     }
 
-    pub fn set_inject_file_partial_fn(&mut self, f: MaybeInjectFilePartialFn) {
-        *self.file_injector.lock() = f
+    /// Sets a closure that is consulted for every read or write that has nothing queued. The
+    /// closure returns the maximum number of bytes the read or write may use, or an error.
+    /// Passing `None` removes any existing closure.
+    pub fn set_inject_file_fn(&self, f: MaybeInjectFileFn) {
+        let mut injector = self.injector.lock();
+        injector.inject_file_fn = f;
+        injector.generation += 1;
+    }
+
+    /// Removes all queued results and closures.
+    pub fn clear_injections(&self) {
+        let mut injector = self.injector.lock();
+        injector.queued.clear();
+        injector.queued_file.clear();
+        injector.inject_fn = None;
+        injector.inject_file_fn = None;
+        injector.generation += 1;
+    }
+
+    /// Returns the in-memory filesystem this `FS` wraps. Calls through the returned filesystem
+    /// never have errors injected.
+    pub fn inner(&self) -> &mem::FS {
+        &self.inner
+    }
+}
+
+impl Default for FS {
+    fn default() -> Self {
+        FS::new()
     }
 }
 
 impl fs::GenFS for FS {
-    type DirBuilder = DirBuilder;
-    type DirEntry = DirEntry;
-    type Metadata = Metadata;
+    type DirBuilder  = DirBuilder;
+    type DirEntry    = DirEntry;
+    type File        = File;
+    type Metadata    = Metadata;
     type OpenOptions = OpenOptions;
     type Permissions = mem::Permissions;
-    type ReadDir = ReadDir;
-    type File = File;
+    type ReadDir     = ReadDir;
 
     fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSCanonicalize(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSCanonicalize(&path.as_ref().to_owned()))?;
         self.inner.canonicalize(path)
     }
-
     fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSCopy(&from.as_ref().to_owned(), &to.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSCopy(&from.as_ref().to_owned(), &to.as_ref().to_owned()))?;
         self.inner.copy(from, to)
     }
-
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSCreateDir(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSCreateDir(&path.as_ref().to_owned()))?;
         self.inner.create_dir(path)
     }
-
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSCreateDirAll(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSCreateDirAll(&path.as_ref().to_owned()))?;
         self.inner.create_dir_all(path)
     }
-
+    fn current_dir(&self) -> Result<PathBuf> {
+        check(&self.injector, In::FSCurrentDir)?;
+        self.inner.current_dir()
+    }
     fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSHardLink(&src.as_ref().to_owned(), &dst.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSHardLink(&src.as_ref().to_owned(), &dst.as_ref().to_owned()))?;
         self.inner.hard_link(src, dst)
     }
-
-    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSMetadata(&path.as_ref().to_owned()))) {
-            r?
-        }
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        check(&self.injector, In::FSMetadata(&path.as_ref().to_owned()))?;
         Ok(Metadata {
             injector: self.injector.clone(),
-            inner: self.inner.metadata(path)?,
+            inner:    self.inner.metadata(path)?,
         })
     }
-
-    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<ReadDir> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSReadDir(&path.as_ref().to_owned()))) {
-            r?
-        }
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir> {
+        check(&self.injector, In::FSReadDir(&path.as_ref().to_owned()))?;
         Ok(ReadDir {
             injector: self.injector.clone(),
-            inner: self.inner.read_dir(path)?,
+            inner:    self.inner.read_dir(path)?,
         })
     }
-
     fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSReadLink(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSReadLink(&path.as_ref().to_owned()))?;
         self.inner.read_link(path)
     }
-
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSRemoveDir(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSRemoveDir(&path.as_ref().to_owned()))?;
         self.inner.remove_dir(path)
     }
-
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FSRemoveDirAll(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSRemoveDirAll(&path.as_ref().to_owned()))?;
         self.inner.remove_dir_all(path)
     }
-
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSRemoveFile(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSRemoveFile(&path.as_ref().to_owned()))?;
         self.inner.remove_file(path)
     }
-
     fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSRename(&from.as_ref().to_owned(), &to.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSRename(&from.as_ref().to_owned(), &to.as_ref().to_owned()))?;
         self.inner.rename(from, to)
     }
-
+    fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        check(&self.injector, In::FSSetCurrentDir(&path.as_ref().to_owned()))?;
+        self.inner.set_current_dir(path)
+    }
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Self::Permissions) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FSSetPermissions(&path.as_ref().to_owned(), &perm))) {
-            r?
-        }
+        check(&self.injector, In::FSSetPermissions(&path.as_ref().to_owned(), &perm))?;
         self.inner.set_permissions(path, perm)
     }
-
-    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-               .as_mut()
-               .map(|f| f(In::FSSymlinkMetadata(&path.as_ref().to_owned()))) {
-            r?
-        }
+    fn set_times<P: AsRef<Path>>(&self, path: P, times: fs::FileTimes) -> Result<()> {
+        check(&self.injector, In::FSSetTimes(&path.as_ref().to_owned(), times))?;
+        self.inner.set_times(path, times)
+    }
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        check(&self.injector, In::FSSymlinkMetadata(&path.as_ref().to_owned()))?;
         Ok(Metadata {
             injector: self.injector.clone(),
-            inner: self.inner.symlink_metadata(path)?,
+            inner:    self.inner.symlink_metadata(path)?,
         })
     }
 
-    fn new_openopts(&self) -> OpenOptions {
+    fn new_openopts(&self) -> Self::OpenOptions {
         OpenOptions {
             injector: self.injector.clone(),
-            file_injector: self.file_injector.clone(),
-            inner: self.inner.new_openopts(),
+            inner:    self.inner.new_openopts(),
         }
     }
-
-    fn new_dirbuilder(&self) -> DirBuilder {
+    fn new_dirbuilder(&self) -> Self::DirBuilder {
         DirBuilder {
             injector: self.injector.clone(),
-            inner: self.inner.new_dirbuilder(),
+            inner:    self.inner.new_dirbuilder(),
         }
     }
 
     fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FSOpenFile(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSOpenFile(&path.as_ref().to_owned()))?;
         Ok(File {
             injector: self.injector.clone(),
-            file_injector: self.file_injector.clone(),
-            inner: self.inner.open_file(path)?,
+            inner:    self.inner.open_file(path)?,
         })
     }
-
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FSCreateFile(&path.as_ref().to_owned()))) {
-            r?
-        }
+        check(&self.injector, In::FSCreateFile(&path.as_ref().to_owned()))?;
         Ok(File {
             injector: self.injector.clone(),
-            file_injector: self.file_injector.clone(),
-            inner: self.inner.create_file(path)?,
+            inner:    self.inner.create_file(path)?,
         })
     }
 }
 
 impl unix_ext::GenFSExt for FS {
     fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
-        let mut injector = self.injector.lock();
-        if let Some(r) =
-            injector
-                .as_mut()
-                .map(|f| f(In::FSSymlink(&src.as_ref().to_owned(), &dst.as_ref().to_owned()))) {
-            r?
-        }
-        Ok(self.inner.symlink(src, dst)?)
+        check(&self.injector, In::FSSymlink(&src.as_ref().to_owned(), &dst.as_ref().to_owned()))?;
+        self.inner.symlink(src, dst)
+    }
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        check(&self.injector, In::FSChown(&path.as_ref().to_owned(), uid, gid))?;
+        self.inner.chown(path, uid, gid)
+    }
+    fn mkfifo<P: AsRef<Path>>(&self, path: P, mode: u32) -> Result<()> {
+        check(&self.injector, In::FSMkfifo(&path.as_ref().to_owned(), mode))?;
+        self.inner.mkfifo(path, mode)
+    }
+    fn mknod<P: AsRef<Path>>(&self, path: P, mode: u32, dev: u64) -> Result<()> {
+        check(&self.injector, In::FSMknod(&path.as_ref().to_owned(), mode, dev))?;
+        self.inner.mknod(path, mode, dev)
+    }
+    fn link_file<P: AsRef<Path>>(&self, file: &File, dst: P) -> Result<()> {
+        check(&self.injector, In::FSLinkFile(&dst.as_ref().to_owned()))?;
+        self.inner.link_file(&file.inner, dst)
+    }
+    fn rename_with_flags<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q, flags: u32)
+        -> Result<()>
+    {
+        check(&self.injector, In::FSRenameWithFlags(&from.as_ref().to_owned(),
+                                                         &to.as_ref().to_owned(),
+                                                         flags))?;
+        self.inner.rename_with_flags(from, to, flags)
+    }
+    fn get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N) -> Result<Vec<u8>> {
+        check(&self.injector, In::FSGetXattr(&path.as_ref().to_owned(), name.as_ref()))?;
+        self.inner.get_xattr(path, name)
+    }
+    fn set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N, value: &[u8])
+        -> Result<()>
+    {
+        check(&self.injector, In::FSSetXattr(&path.as_ref().to_owned(), name.as_ref(), value))?;
+        self.inner.set_xattr(path, name, value)
+    }
+    fn list_xattr<P: AsRef<Path>>(&self, path: P) -> Result<Vec<OsString>> {
+        check(&self.injector, In::FSListXattr(&path.as_ref().to_owned()))?;
+        self.inner.list_xattr(path)
+    }
+    fn remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N) -> Result<()> {
+        check(&self.injector, In::FSRemoveXattr(&path.as_ref().to_owned(), name.as_ref()))?;
+        self.inner.remove_xattr(path, name)
+    }
+    fn symlink_get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N)
+        -> Result<Vec<u8>>
+    {
+        check(&self.injector, In::FSSymlinkGetXattr(&path.as_ref().to_owned(), name.as_ref()))?;
+        self.inner.symlink_get_xattr(path, name)
+    }
+    fn symlink_set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N, value: &[u8])
+        -> Result<()>
+    {
+        let path_buf = path.as_ref().to_owned();
+        check(&self.injector, In::FSSymlinkSetXattr(&path_buf, name.as_ref(), value))?;
+        self.inner.symlink_set_xattr(path, name, value)
+    }
+    fn symlink_list_xattr<P: AsRef<Path>>(&self, path: P) -> Result<Vec<OsString>> {
+        check(&self.injector, In::FSSymlinkListXattr(&path.as_ref().to_owned()))?;
+        self.inner.symlink_list_xattr(path)
+    }
+    fn symlink_remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N)
+        -> Result<()>
+    {
+        let path_buf = path.as_ref().to_owned();
+        check(&self.injector, In::FSSymlinkRemoveXattr(&path_buf, name.as_ref()))?;
+        self.inner.symlink_remove_xattr(path, name)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use fs::{DirBuilder, GenFS, OpenOptions};
     use std::io::{Error, Read, Result, Seek, SeekFrom, Write};
-    use std::collections::VecDeque;
+
+    use fs::{DirBuilder as DirBuilderTrait, DirEntry as DirEntryTrait, File as FileTrait,
+             FileType as FileTypeTrait, GenFS, Metadata as MetadataTrait,
+             OpenOptions as OpenOptionsTrait};
     use super::*;
     use errors::*;
     use unix_ext::*;
@@ -827,10 +1187,10 @@ mod test {
 
     #[test]
     fn basic() {
-        let mut fs = FS::with_mode(0o300);
+        let fs = FS::with_mode(0o300);
         assert!(fs.create_dir_all("a/b/c").is_ok());
         let mut wf =
-        fs.new_openopts().mode(0o600).write(true).create_new(true).open("a/f").unwrap();
+            fs.new_openopts().mode(0o600).write(true).create_new(true).open("a/f").unwrap();
         assert_eq!(wf.write(b"hello").unwrap(), 5);
 
         let mut rf = fs.open_file("a/f").unwrap();
@@ -840,50 +1200,201 @@ mod test {
         assert_eq!(rf.read(&mut output).unwrap(), 4);
         assert_eq!(&output, b"ello");
 
-        let cascade_file = Arc::new(Mutex::new(vec![]));
-        let cf_clone = cascade_file.clone();
-        let mut cascade_fs: VecDeque<Box<FnMut(In) -> Result<()> + Send>> = VecDeque::new();
-        cascade_fs.push_back(Box::new(|_in: In| -> Result<()> {
-                if let In::DirBuilderCreate(_, pb) = _in {
-                    if pb.to_str().unwrap() == "a/d" {
-                        return Err(ENOENT())
-                    }
-                }
-                Ok(())
-            }));
-        cascade_fs.push_back(Box::new(move |_in: In| -> Result<()> {
-                cf_clone.lock().push(Box::new(|_in: InFile| -> Result<usize> {
-                    if let InFile::Write(_, buf) = _in {
-                        return Ok((buf.len() / 2))
-                    }
-                    Ok(_in.buf_len())
-                }));
-                Ok(())
-            }));
-
+        // The closure fails creating "a/d" once and, on its second call, starts halving writes.
+        let mut calls = 0;
         fs.set_inject_fn(Some(Box::new(move |_in: In| -> Result<()> {
-            match cascade_fs.pop_front() {
-                Some(mut f) => f(_in),
-                None => Ok(()),
+            calls += 1;
+            if let In::DirBuilderCreate(_, pb) = _in {
+                if calls == 1 && pb.to_str().unwrap() == "a/d" {
+                    return Err(ENOENT());
+                }
             }
+            Ok(())
         })));
-        fs.set_inject_file_partial_fn(Some(Box::new(move |_in: InFile| -> Result<usize> {
-            match cascade_file.lock().pop() {
-                Some(f) => f(_in),
-                None => Ok(_in.buf_len()),
+        fs.set_inject_file_fn(Some(Box::new(|_in: InFile| -> Result<usize> {
+            if let InFile::Write(_, buf) = _in {
+                return Ok(buf.len() / 2);
             }
+            Ok(_in.buf_len())
         })));
 
-        assert!(errs_eq(fs.new_dirbuilder().create("a/d").unwrap_err(), ENOENT())); // error 1
+        assert!(errs_eq(fs.new_dirbuilder().create("a/d").unwrap_err(), ENOENT()));
         assert!(fs.new_dirbuilder().create("a/d").is_ok());
-        // When redoing this entire API, it'll actually be worth it to have more tests.
-        //assert_eq!(rf.seek(SeekFrom::Start(1)).unwrap(), 1);
-        //assert_eq!(rf.read(&mut output).unwrap(), 4); // ok 2
-        //assert_eq!(&output, &[1, 2, 3, 4]);
-        //assert_eq!(rf.seek(SeekFrom::Start(2)).unwrap(), 2);
-        //assert!(errs_eq(rf.read(&mut output).unwrap_err(), EACCES())); // error 3
-        //assert_eq!(rf.read(&mut output).unwrap(), 4);
-        //assert_eq!(&output, &[2, 3, 4, 5]);
+        assert_eq!(wf.write(b"hello").unwrap(), 2);
+        assert_eq!(rf.seek(SeekFrom::Start(1)).unwrap(), 1);
+        assert_eq!(rf.read(&mut output).unwrap(), 4);
+        assert_eq!(&output, b"ello");
+
+        fs.clear_injections();
+        assert_eq!(wf.write(b"hello").unwrap(), 5);
     }
-}
 
+    #[test]
+    fn queued() {
+        let fs = FS::new();
+        let f = fs.create_file("f").unwrap();
+
+        // Queued results are per call site and drain in order.
+        fs.inject(Call::FileSyncAll, Ok(()));
+        fs.inject(Call::FileSyncAll, Err(EIO()));
+        fs.inject(Call::FileSyncData, Err(EBADF()));
+        assert!(f.sync_all().is_ok());
+        assert!(errs_eq(f.sync_all().unwrap_err(), EIO()));
+        assert!(f.sync_all().is_ok());
+        assert!(errs_eq(f.sync_data().unwrap_err(), EBADF()));
+        assert!(f.sync_data().is_ok());
+
+        // Injected errors do not touch the filesystem.
+        fs.inject(Call::FSRename, Err(EXDEV()));
+        assert!(errs_eq(fs.rename("f", "g").unwrap_err(), EXDEV()));
+        assert!(fs.metadata("f").is_ok());
+        assert!(fs.metadata("g").is_err());
+        assert!(fs.rename("f", "g").is_ok());
+
+        // Queued results take precedence over the closure, which is used once the queue is empty.
+        fs.set_inject_fn(Some(Box::new(|_in: In| -> Result<()> {
+            match _in {
+                In::FSMetadata(_) => Err(EACCES()),
+                _ => Ok(()),
+            }
+        })));
+        fs.inject(Call::FSMetadata, Ok(()));
+        assert!(fs.metadata("g").is_ok());
+        assert!(errs_eq(fs.metadata("g").unwrap_err(), EACCES()));
+        assert!(fs.symlink_metadata("g").is_ok());
+        fs.set_inject_fn(None);
+        assert!(fs.metadata("g").is_ok());
+
+        // Injections are shared with clones and with the inner filesystem untouched.
+        let clone = fs.clone();
+        fs.inject(Call::FSCreateDir, Err(ENOSPC()));
+        assert!(fs.inner().create_dir("d").is_ok());
+        assert!(errs_eq(clone.create_dir("e").unwrap_err(), ENOSPC()));
+        assert!(fs.create_dir("e").is_ok());
+    }
+
+    #[test]
+    fn reentrant() {
+        // Closures may call back into the file they are passed, without the closure itself
+        // being consulted again.
+        let fs = FS::new();
+        let mut f = fs.new_openopts().read(true).write(true).create(true).open("f").unwrap();
+        assert!(f.write_all(b"hello").is_ok());
+        fs.set_inject_fn(Some(Box::new(|call: In| match call {
+            In::FileSyncAll(f) if MetadataTrait::len(&f.metadata()?) == 5 => Err(EIO()),
+            _ => Ok(()),
+        })));
+        assert!(errs_eq(f.sync_all().unwrap_err(), EIO()));
+        assert!(errs_eq(f.sync_all().unwrap_err(), EIO()));
+        assert!(f.metadata().is_ok());
+
+        fs.set_inject_file_fn(Some(Box::new(|call: InFile| match call {
+            InFile::ReadAt(f, ..) => Ok(MetadataTrait::len(&f.metadata()?) as usize - 3),
+            _ => Ok(usize::MAX),
+        })));
+        let mut buf = [0; 5];
+        assert_eq!(f.read_at(&mut buf, 0).unwrap(), 2);
+        assert_eq!(&buf[..2], b"he");
+        fs.clear_injections();
+        assert!(f.sync_all().is_ok());
+    }
+
+    #[test]
+    fn read_dir() {
+        let fs = FS::new();
+        assert!(fs.create_dir("a").is_ok());
+        assert!(fs.create_dir("b").is_ok());
+
+        fs.inject(Call::FSReadDir, Err(EACCES()));
+        assert!(errs_eq(fs.read_dir("/").unwrap_err(), EACCES()));
+
+        // An error from next does not skip the entry.
+        fs.inject(Call::ReadDirNext, Ok(()));
+        fs.inject(Call::ReadDirNext, Err(EIO()));
+        let mut reader = fs.read_dir("/").unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().file_name(), "a");
+        assert!(errs_eq(reader.next().unwrap().unwrap_err(), EIO()));
+        let entry = reader.next().unwrap().unwrap();
+        assert_eq!(entry.file_name(), "b");
+        assert!(reader.next().is_none());
+
+        fs.inject(Call::DirEntryFileType, Err(EIO()));
+        assert!(entry.file_type().is_err());
+        assert!(entry.file_type().unwrap().is_dir());
+    }
+
+    #[test]
+    fn file_io() {
+        let fs = FS::new();
+        let mut f = fs.new_openopts().read(true).write(true).create(true).open("f").unwrap();
+
+        fs.inject_file(FileCall::Write, Ok(3));
+        fs.inject_file(FileCall::Write, Err(ENOSPC()));
+        assert_eq!(f.write(b"hello").unwrap(), 3);
+        assert!(errs_eq(f.write(b"lo").unwrap_err(), ENOSPC()));
+        assert_eq!(f.write(b"lo").unwrap(), 2);
+
+        // Short results larger than the buffer are clamped to the buffer.
+        fs.inject_file(FileCall::WriteAt, Ok(100));
+        assert_eq!(f.write_at(b"!", 5).unwrap(), 1);
+
+        fs.inject(Call::FileSeek, Err(EINVAL()));
+        assert!(f.seek(SeekFrom::Start(0)).is_err());
+        assert_eq!(f.seek(SeekFrom::Start(0)).unwrap(), 0);
+
+        let mut output = [0u8; 6];
+        fs.inject_file(FileCall::Read, Ok(1));
+        assert_eq!(f.read(&mut output).unwrap(), 1);
+        assert_eq!(f.read(&mut output[1..]).unwrap(), 5);
+        assert_eq!(&output, b"hello!");
+
+        fs.inject_file(FileCall::ReadAt, Err(EIO()));
+        assert!(errs_eq(f.read_at(&mut output, 0).unwrap_err(), EIO()));
+
+        fs.inject(Call::FileFlush, Err(EIO()));
+        assert!(f.flush().is_err());
+        assert!(f.flush().is_ok());
+
+        fs.inject(Call::FileTryClone, Err(EBADF()));
+        assert!(f.try_clone().is_err());
+        assert!(f.try_clone().is_ok());
+    }
+
+    #[test]
+    fn std_helpers() {
+        // The provided helpers go through the injectable calls.
+        let fs = FS::new();
+        assert!(fs.write("f", b"hello").is_ok());
+        fs.inject(Call::FSCreateFile, Err(ENOSPC()));
+        assert!(errs_eq(fs.write("f", b"bye").unwrap_err(), ENOSPC()));
+        fs.inject(Call::FSOpenFile, Err(EIO()));
+        assert!(errs_eq(fs.read("f").unwrap_err(), EIO()));
+        assert_eq!(fs.read_to_string("f").unwrap(), "hello");
+
+        fs.inject(Call::FSMetadata, Err(EACCES()));
+        assert!(errs_eq(fs.try_exists("f").unwrap_err(), EACCES()));
+        fs.inject(Call::FSMetadata, Err(EACCES()));
+        assert!(!fs.exists("f"));
+        assert!(fs.try_exists("f").unwrap());
+        assert!(!fs.try_exists("g").unwrap());
+    }
+
+    #[test]
+    fn locks() {
+        use fs::TryLockError;
+
+        let fs = FS::new();
+        let f = fs.create_file("f").unwrap();
+        fs.inject(Call::FileTryLock, Err(Error::from(ErrorKind::WouldBlock)));
+        assert!(matches!(f.try_lock(), Err(TryLockError::WouldBlock)));
+        fs.inject(Call::FileTryLockShared, Err(EIO()));
+        match f.try_lock_shared() {
+            Err(TryLockError::Error(e)) => assert!(errs_eq(e, EIO())),
+            r => panic!("unexpected {:?}", r),
+        }
+        fs.inject(Call::FileLock, Err(EBADF()));
+        assert!(errs_eq(f.lock().unwrap_err(), EBADF()));
+        assert!(f.lock().is_ok());
+        assert!(matches!(fs.open_file("f").unwrap().try_lock(), Err(TryLockError::WouldBlock)));
+    }
+}
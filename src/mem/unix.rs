@@ -35,22 +35,35 @@ extern crate parking_lot;
 
 use self::parking_lot::{Mutex, RwLock};
 
-use std::cmp::{self, Ordering};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::{Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::vec::IntoIter;
 
 use fs::{self, DirBuilder as _DirBuilder, FileType as _FileType, Metadata as _Metadata};
 use unix_ext;
+use unix_ext::flags::{O_APPEND, O_CREAT, O_DIRECTORY, O_EXCL, O_NOATIME, O_NOFOLLOW, O_SYNC,
+                      O_TMPFILE, O_TRUNC};
 
 use errors::*;
+use mem::clock::{Clock, SharedClock, SystemClock};
+use mem::diff::{self, Change};
+use mem::flags;
+use mem::host;
+use mem::journal::{self, Binding, Journal, Rng};
+use mem::lock::{self, Locks};
+use mem::render::{self, RenderOptions};
+use mem::tar;
+use mem::space::{Charge, Space};
+use mem::sparse::Data;
+use mem::xattr::{self, Xattrs};
 use path_parts::{normalize, IteratorExt, Part, Parts};
 use ptr::Raw;
 
@@ -59,6 +72,10 @@ use ptr::Raw;
 /// to return a larger number if the directory contains many children with long names.
 const DIRLEN: usize = 4096;
 
+/// `BLKSIZE` is the block size reported by `MetadataExt`. Directories and the data of files are
+/// allocated in whole blocks, while holes in files and symlinks take no blocks.
+const BLKSIZE: u64 = 4096;
+
 /// A builder used to create directories in various manners.
 ///
 /// This builder implements [`rsfs::DirBuilder`] and supports [unix extensions].
@@ -110,9 +127,10 @@ impl unix_ext::DirBuilderExt for DirBuilder {
 /// Entries returned by the [`ReadDir`] iterator.
 ///
 /// An instance of `DirEntry` implements [`rsfs::DirEntry`] and represents an entry inside a
-/// directory on the in-memory filesystem.
+/// directory on the in-memory filesystem. It also has [unix extensions].
 ///
 /// [`rsfs::DirEntry`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.DirEntry.html
+/// [unix extensions]: https://docs.rs/rsfs/0.4.1/rsfs/unix_ext/trait.DirEntryExt.html
 /// [`ReadDir`]: struct.ReadDir.html
 ///
 /// # Examples
@@ -159,59 +177,52 @@ impl fs::DirEntry for DirEntry {
     }
 }
 
+impl unix_ext::DirEntryExt for DirEntry {
+    fn ino(&self) -> u64 {
+        self.inode.read().ino
+    }
+}
+
 /// `RawFile` is the underlying contents of a file in our filesystem. `OpenOption`s .open() call
 /// returns a view of a file. If a file is removed from the filesystem, currently open files can
 /// still be read from or written to, but the file will not be openable anymore.
 #[derive(Debug)]
 struct RawFile {
-    /// data is the backing file data.
-    data: Vec<u8>,
+    /// data is the backing file data, which may have holes.
+    data: Data,
     /// inode allows us to read and write the most up to date metadata.
     inode: Inode,
+    /// linkable is whether the file can be linked in while it has no links, which only files
+    /// opened with `O_TMPFILE` and without `O_EXCL` can, and only until they are first linked.
+    linkable: bool,
 }
 
 impl RawFile {
     /// read_at reads contents of the file into dst from a given index in the file.
     fn read_at(&self, at: usize, dst: &mut [u8]) -> Result<usize> {
-        self.inode.write().times.update(ACCESSED);
-
-        let data = &self.data;
-        if at > data.len() {
-            return Ok(0);
-        }
-
-        let unread = &data[at..];
-        let copy_size = cmp::min(dst.len(), unread.len());
-
-        dst[..copy_size].copy_from_slice(&unread[..copy_size]);
-        Ok(copy_size)
+        self.inode.touch(ACCESSED);
+        Ok(self.data.read_at(at, dst))
     }
 
-    /// write_at writes to the RawFile at a given index, zero extending the existing data if
-    /// necessary.
+    /// write_at writes to the RawFile at a given index, leaving a hole between the existing data
+    /// and the index if necessary. Only the bytes that were not yet allocated take space.
     fn write_at(&mut self, at: usize, src: &[u8]) -> Result<usize> {
         if src.is_empty() {
             return Ok(0)
         }
+        self.inode.charge.resize(self.data.allocated_after_write(at, src.len()))?;
+        self.data.write_at(at, src);
+        self.inode.touch(MODIFIED);
+        self.resized();
+        Ok(src.len())
+    }
 
-        let mut dst = &mut self.data;
-        if at > dst.len() {
-            let mut new = vec![0; at + src.len()];
-            new[..dst.len()].copy_from_slice(dst);
-            *dst = new;
-        }
-
-        let new_end = src.len() + at;
-        if dst.len() >= new_end {
-            dst[at..new_end].copy_from_slice(src);
-        } else {
-            dst.truncate(at);
-            dst.extend_from_slice(src);
-        }
+    /// resized updates the length and blocks of our inode after our data changes.
+    fn resized(&self) {
+        let blocks = self.data.allocated_blocks(BLKSIZE as usize) as u64 * (BLKSIZE / 512);
         let mut inode = self.inode.write();
-        inode.times.update(MODIFIED);
-        inode.length = dst.len();
-        Ok(src.len())
+        inode.length = self.data.len();
+        inode.blocks = blocks;
     }
 }
 
@@ -220,6 +231,9 @@ impl RawFile {
 /// An instance of `File` can be read or written to depending on the options it was opened with.
 /// Files also implement `Seek` to alter the logical cursor position of the internal file.
 ///
+/// Directories can be opened to read, which allows syncing them or using their metadata and
+/// permissions, but reading from or writing to them fails with `EISDIR`.
+///
 /// This struct implements [`rsfs::File`] and has [unix extensions].
 ///
 /// [`rsfs::File`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.File.html
@@ -247,21 +261,36 @@ pub struct File {
     /// read indicates this file view will append to the current end of the underlying file on
     /// every write.
     append: bool,
+    /// dir indicates this file view is of a directory, which cannot be read from or written to.
+    dir:    bool,
 
     /// cursor is wrapped in an `Arc<Mutex<_>>` solely to support `File`s probably-never-used
     /// `try_clone` function.
     cursor: Arc<Mutex<FileCursor>>,
 }
 
+impl File {
+    // locks returns the locks on our file along with the id of our open file description, which
+    // is the address of our cursor. Locks are released when the cursor drops.
+    fn locks(&self) -> (usize, Arc<Locks>) {
+        let cursor = self.cursor.lock();
+        let id = &*cursor as *const FileCursor as usize;
+        let locks = cursor.file.read().inode.locks.clone();
+        (id, locks)
+    }
+}
+
 impl fs::File for File {
     type Metadata    = Metadata;
     type Permissions = Permissions;
 
     fn sync_all(&self) -> Result<()> {
+        self.cursor.lock().sync(self.dir, true);
         Ok(())
     }
 
     fn sync_data(&self) -> Result<()> {
+        self.cursor.lock().sync(self.dir, false);
         Ok(())
     }
 
@@ -269,7 +298,7 @@ impl fs::File for File {
         if !self.write {
             return Err(EINVAL());
         }
-        Ok(self.cursor.lock().set_len(size)?)
+        self.cursor.lock().set_len(size)
     }
 
     fn metadata(&self) -> Result<Self::Metadata> {
@@ -284,6 +313,7 @@ impl fs::File for File {
             read:   self.read,
             write:  self.write,
             append: self.append,
+            dir:    self.dir,
             cursor: self.cursor.clone(),
         })
     }
@@ -291,8 +321,57 @@ impl fs::File for File {
     fn set_permissions(&self, perms: Self::Permissions) -> Result<()> {
         let cursor = self.cursor.lock();
         let file = cursor.file.write();
-        let mut inode = file.inode.write();
-        inode.perms = perms;
+        if !file.inode.owned_by(cursor.ids) {
+            return Err(EPERM());
+        }
+        file.inode.set_perms(perms);
+        Ok(())
+    }
+
+    fn set_times(&self, times: fs::FileTimes) -> Result<()> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.write();
+        if !file.inode.owned_by(cursor.ids) {
+            return Err(EPERM());
+        }
+        let now = file.inode.clock.now();
+        file.inode.write().times.set(times, now);
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<()> {
+        let (id, locks) = self.locks();
+        locks.lock(id, lock::Kind::Exclusive, true);
+        Ok(())
+    }
+
+    fn lock_shared(&self) -> Result<()> {
+        let (id, locks) = self.locks();
+        locks.lock(id, lock::Kind::Shared, true);
+        Ok(())
+    }
+
+    fn try_lock(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        let (id, locks) = self.locks();
+        if locks.lock(id, lock::Kind::Exclusive, false) {
+            Ok(())
+        } else {
+            Err(fs::TryLockError::WouldBlock)
+        }
+    }
+
+    fn try_lock_shared(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        let (id, locks) = self.locks();
+        if locks.lock(id, lock::Kind::Shared, false) {
+            Ok(())
+        } else {
+            Err(fs::TryLockError::WouldBlock)
+        }
+    }
+
+    fn unlock(&self) -> Result<()> {
+        let (id, locks) = self.locks();
+        locks.unlock(id);
         Ok(())
     }
 }
@@ -307,81 +386,132 @@ struct FileCursor {
     file: Arc<RwLock<RawFile>>,
     /// at tracks this cursor's position in the underlying file.
     at:   usize,
+    /// ids are the ids the file was opened with, which extended attribute permission checks use.
+    ids:     Ids,
+    /// noatime is whether the file was opened with `O_NOATIME`, so reads do not touch its atime.
+    noatime: bool,
+    /// sync is `O_SYNC` or `O_DSYNC` if the file was opened with either, so writes sync the file.
+    sync:    i32,
+    /// journal is the journal of the filesystem the file was opened in, which syncing updates.
+    journal: SharedJournal,
+}
+
+impl Drop for FileCursor {
+    fn drop(&mut self) {
+        let id = self as *const FileCursor as usize;
+        self.file.read().inode.locks.unlock(id);
+    }
 }
 
 impl FileCursor {
-    /// The backing function for `File`s `set_len`, this can function runcate or zero extend the
-    /// underlying file.
+    /// The backing function for `File`s `set_len`, this can function truncate or extend the
+    /// underlying file with a hole, which takes no space.
     fn set_len(&mut self, size: u64) -> Result<()> {
         let mut file = self.file.write();
-        file.inode.write().times.update(MODIFIED);
-
-        let size = size as usize;
-        match file.data.len().cmp(&size) {
-            Ordering::Less => {
-                // If data is smaller, create a longer Vec and copy the original contents over.
-                // This borrows from RawFile's write_at.
-                let mut new = vec![0; size];
-                new[..file.data.len()].copy_from_slice(&file.data);
-                file.data = new;
-            }
-            // If data is longer, simply truncate it.
-            Ordering::Greater => file.data.truncate(size),
-            // If equal, we get to do nothing.
-            _ => (),
-        }
+        file.data.set_len(size as usize);
+        file.inode.charge.resize(file.data.allocated())?;
+        file.inode.touch(MODIFIED);
+        file.resized();
         Ok(())
     }
 
     /// The backing function for `File`s `read`.
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let n = self.file.read().read_at(self.at, buf)?;
+        let n = self.read_at(self.at, buf)?;
         self.at += n;
         Ok(n)
     }
 
+    /// The backing function for `File`s `read_at`, which does not touch atime for a file opened
+    /// with `O_NOATIME`.
+    fn read_at(&self, at: usize, buf: &mut [u8]) -> Result<usize> {
+        let file = self.file.read();
+        if self.noatime {
+            return Ok(file.data.read_at(at, buf));
+        }
+        file.read_at(at, buf)
+    }
+
     /// The backing function for `File`s `write`.
     fn write(&mut self, buf: &[u8], append: bool) -> Result<usize> {
-        let mut file = self.file.write();
-        if append {
-            self.at = file.data.len();
-        }
-        let n = file.write_at(self.at, buf)?;
+        let n = {
+            let mut file = self.file.write();
+            if append {
+                self.at = file.data.len();
+            }
+            file.write_at(self.at, buf)?
+        };
         self.at += n;
+        self.wrote();
         Ok(n)
     }
 
+    /// wrote syncs the file after a write if it was opened with `O_SYNC` or `O_DSYNC`.
+    fn wrote(&self) {
+        if self.sync != 0 {
+            self.sync(false, self.sync == O_SYNC);
+        }
+    }
+
+    /// The backing function for `File`s `sync_all` and `sync_data`, which makes the file's data
+    /// survive a crash, along with its metadata if all is true. Syncing a directory instead makes
+    /// the changes to its entries survive a crash.
+    fn sync(&self, dir: bool, all: bool) {
+        let file = self.file.read();
+        let key = file.inode.key();
+        let mut journal = self.journal.lock();
+        let journal = match *journal {
+            Some(ref mut journal) => journal,
+            None => return,
+        };
+        if dir {
+            journal.sync_dir(key);
+            journal.prune(Durable::open_and_unnamed);
+        }
+        // Files that were never in the filesystem's tree, such as files still open from before it
+        // was restored, are unknown to the journal and have nothing to sync.
+        if let Some(durable) = journal.get_mut(key) {
+            if !dir {
+                durable.data = file.data.clone();
+            }
+            if all {
+                durable.meta = file.inode.view();
+                durable.xattrs = file.inode.xattrs.read().clone();
+            }
+        }
+    }
+
     /// The backing function for `File`s `seek`. While it is undefined to seek past the end of the
     /// file, we attempt to emulate what appears to be Rust's/Unix's behavior.
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         let file = self.file.write();
-        file.inode.write().times.update(ACCESSED);
-
-        let len = file.data.len();
-        // seek seemingly returns (if successful) the sum of the position we are seeking from and
-        // the offset even if we seek past the end of the file.
-        match pos {
-            SeekFrom::Start(offset) => {
-                self.at = cmp::min(len, offset as usize);
-                Ok(offset)
-            }
-            SeekFrom::Current(offset) => {
-                let at_end = (self.at as i64).saturating_add(offset);
-                if at_end < 0 {
-                    return Err(EINVAL());
-                }
-                self.at = cmp::min(len, at_end as usize);
-                Ok(at_end as u64)
-            }
-            SeekFrom::End(offset) => {
-                let at_end = (len as i64).saturating_add(offset);
-                if at_end < 0 {
-                    return Err(EINVAL());
-                }
-                self.at = cmp::min(len, at_end as usize);
-                Ok(at_end as u64)
-            }
+        if !self.noatime {
+            file.inode.touch(ACCESSED);
+        }
+
+        // Like lseek, we can seek past the end of the file. Reads there return nothing, and
+        // writes leave a hole between the end of the file and where they start.
+        let at = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => (self.at as i64).saturating_add(offset),
+            SeekFrom::End(offset) => (file.data.len() as i64).saturating_add(offset),
+        };
+        if at < 0 {
+            return Err(EINVAL());
         }
+        self.at = at as usize;
+        Ok(at as u64)
+    }
+
+    /// The backing function for `File`s `seek_sparse`.
+    fn seek_sparse(&mut self, pos: unix_ext::SparseSeek) -> Result<u64> {
+        let file = self.file.read();
+        let at = match pos {
+            unix_ext::SparseSeek::Data(offset) => file.data.seek_data(offset as usize),
+            unix_ext::SparseSeek::Hole(offset) => file.data.seek_hole(offset as usize),
+        }.ok_or_else(ENXIO)?;
+        self.at = at;
+        Ok(at as u64)
     }
 }
 
@@ -400,26 +530,32 @@ impl Write for File {
 }
 impl Seek for File {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-        Ok(self.cursor.lock().seek(pos)?)
+        self.cursor.lock().seek(pos)
     }
 }
 
 // Now we actually do the impls for &'a File.
 
-impl<'a> Read for &'a File {
+impl Read for &File {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.dir {
+            return Err(EISDIR());
+        }
         if !self.read {
             return Err(EBADF());
         }
-        Ok(self.cursor.lock().read(buf)?)
+        self.cursor.lock().read(buf)
     }
 }
-impl<'a> Write for &'a File {
+impl Write for &File {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.dir {
+            return Err(EISDIR());
+        }
         if !self.write {
             return Err(EBADF());
         }
-        Ok(self.cursor.lock().write(buf, self.append)?)
+        self.cursor.lock().write(buf, self.append)
     }
     fn flush(&mut self) -> Result<()> {
         if !self.write {
@@ -428,28 +564,56 @@ impl<'a> Write for &'a File {
         Ok(())
     }
 }
-impl<'a> Seek for &'a File {
+impl Seek for &File {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
-        Ok(self.cursor.lock().seek(pos)?)
+        self.cursor.lock().seek(pos)
     }
 }
 
 impl unix_ext::FileExt for File {
     fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        if self.dir {
+            return Err(EISDIR());
+        }
         if !self.read {
             return Err(EBADF());
         }
-        let cursor = self.cursor.lock();
-        let file = cursor.file.read();
-        Ok(file.read_at(offset as usize, buf)?)
+        self.cursor.lock().read_at(offset as usize, buf)
     }
     fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        if self.dir {
+            return Err(EISDIR());
+        }
         if !self.write {
             return Err(EBADF());
         }
         let cursor = self.cursor.lock();
-        let mut file = cursor.file.write();
-        Ok(file.write_at(offset as usize, buf)?)
+        let n = cursor.file.write().write_at(offset as usize, buf)?;
+        cursor.wrote();
+        Ok(n)
+    }
+    fn seek_sparse(&self, pos: unix_ext::SparseSeek) -> Result<u64> {
+        self.cursor.lock().seek_sparse(pos)
+    }
+    fn get_xattr<N: AsRef<OsStr>>(&self, name: N) -> Result<Vec<u8>> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.read();
+        file.inode.get_xattr(name.as_ref(), cursor.ids)
+    }
+    fn set_xattr<N: AsRef<OsStr>>(&self, name: N, value: &[u8]) -> Result<()> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.read();
+        file.inode.set_xattr(name.as_ref(), value, cursor.ids)
+    }
+    fn list_xattr(&self) -> Result<Vec<OsString>> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.read();
+        Ok(file.inode.list_xattr(cursor.ids))
+    }
+    fn remove_xattr<N: AsRef<OsStr>>(&self, name: N) -> Result<()> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.read();
+        file.inode.remove_xattr(name.as_ref(), cursor.ids)
     }
 }
 
@@ -459,6 +623,10 @@ enum Ftyp {
     File,
     Dir,
     Symlink,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
 }
 
 /// Returned from [`Metadata::file_type`], this structure represents the type of a file.
@@ -495,13 +663,29 @@ impl fs::FileType for FileType {
     }
 }
 
+impl unix_ext::FileTypeExt for FileType {
+    fn is_block_device(&self) -> bool {
+        self.0 == Ftyp::BlockDevice
+    }
+    fn is_char_device(&self) -> bool {
+        self.0 == Ftyp::CharDevice
+    }
+    fn is_fifo(&self) -> bool {
+        self.0 == Ftyp::Fifo
+    }
+    fn is_socket(&self) -> bool {
+        self.0 == Ftyp::Socket
+    }
+}
+
 /// Metadata information about a file.
 ///
 /// This structure, which implements [`rsfs::Metadata`], is returned from the [`metadata`] or
 /// [`symlink_metadata`] methods and represents known metadata information about a file at the
-/// instant in time this structure is instantiated.
+/// instant in time this structure is instantiated. It also has [unix extensions].
 ///
 /// [`rsfs::Metadata`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.Metadata.html
+/// [unix extensions]: https://docs.rs/rsfs/0.4.1/rsfs/unix_ext/trait.MetadataExt.html
 /// [`metadata`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.metadata
 /// [`symlink_metadata`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.symlink_metadata
 ///
@@ -549,6 +733,86 @@ impl fs::Metadata for Metadata {
     }
 }
 
+impl unix_ext::MetadataExt for Metadata {
+    fn dev(&self) -> u64 {
+        self.0.dev
+    }
+    fn ino(&self) -> u64 {
+        self.0.ino
+    }
+    fn mode(&self) -> u32 {
+        let ftyp = match self.0.ftyp.0 {
+            Ftyp::File        => 0o100000,
+            Ftyp::Dir         => 0o040000,
+            Ftyp::Symlink     => 0o120000,
+            Ftyp::Fifo        => 0o010000,
+            Ftyp::Socket      => 0o140000,
+            Ftyp::BlockDevice => 0o060000,
+            Ftyp::CharDevice  => 0o020000,
+        };
+        ftyp | self.0.perms.0
+    }
+    fn nlink(&self) -> u64 {
+        self.0.nlink
+    }
+    fn uid(&self) -> u32 {
+        self.0.uid
+    }
+    fn gid(&self) -> u32 {
+        self.0.gid
+    }
+    fn rdev(&self) -> u64 {
+        self.0.rdev
+    }
+    fn size(&self) -> u64 {
+        self.0.length as u64
+    }
+    fn atime(&self) -> i64 {
+        unix_time(self.0.times.accessed).0
+    }
+    fn atime_nsec(&self) -> i64 {
+        unix_time(self.0.times.accessed).1
+    }
+    fn mtime(&self) -> i64 {
+        unix_time(self.0.times.modified).0
+    }
+    fn mtime_nsec(&self) -> i64 {
+        unix_time(self.0.times.modified).1
+    }
+    fn ctime(&self) -> i64 {
+        unix_time(self.0.times.changed).0
+    }
+    fn ctime_nsec(&self) -> i64 {
+        unix_time(self.0.times.changed).1
+    }
+    fn blksize(&self) -> u64 {
+        BLKSIZE
+    }
+    fn blocks(&self) -> u64 {
+        self.0.blocks
+    }
+}
+
+// blocks returns the number of 512 byte blocks needed to hold len bytes in whole BLKSIZE blocks.
+fn blocks(len: usize) -> u64 {
+    (len as u64).div_ceil(BLKSIZE) * (BLKSIZE / 512)
+}
+
+// unix_time splits time into seconds and nanoseconds since the Unix epoch like a timespec, where
+// the nanoseconds are never negative even for times before the epoch.
+fn unix_time(time: SystemTime) -> (i64, i64) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos() as i64),
+        Err(e) => {
+            let before = e.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos as i64),
+            }
+        }
+    }
+}
+
 /// Options and flags which can be used to configure how a file is opened.
 ///
 /// This builder, created from `GenFS`s [`new_openopts`], exposes the ability to configure how a
@@ -604,6 +868,7 @@ pub struct OpenOptions {
     create: bool,
     excl:   bool,
     mode:   u32,
+    flags:  i32,
 }
 
 impl fs::OpenOptions for OpenOptions {
@@ -636,8 +901,8 @@ impl unix_ext::OpenOptionsExt for OpenOptions {
     fn mode(&mut self, mode: u32) -> &mut Self {
         self.mode = mode; self
     }
-    fn custom_flags(&mut self, _: i32) -> &mut Self {
-        self // we do nothing with these flags yet.
+    fn custom_flags(&mut self, flags: i32) -> &mut Self {
+        self.flags = flags; self
     }
 }
 
@@ -706,8 +971,8 @@ pub struct ReadDir {
 }
 
 impl ReadDir {
-    fn new<P: AsRef<Path>>(path: P, dir: &Dirent) -> Result<ReadDir> {
-        if !dir.readable() {
+    fn new<P: AsRef<Path>>(path: P, dir: &Dirent, ids: Ids) -> Result<ReadDir> {
+        if !dir.readable(ids) {
             return Err(EACCES());
         }
         let children = match dir.kind {
@@ -718,7 +983,7 @@ impl ReadDir {
         // Most linux systems actually dont update atime on every read because that'd be pretty
         // expensive (note "relatime" when checking the output of `mount`). We do because it is
         // cheap to update an in-memory timestamp.
-        dir.inode.write().times.update(ACCESSED);
+        dir.inode.touch(ACCESSED);
 
         // Iterate over the children and create a bunch of DirEntrys as appropriate.
         let mut dirents = Vec::new();
@@ -771,46 +1036,14 @@ impl Iterator for ReadDir {
 #[derive(Clone, Debug)]
 pub struct FS(Arc<Mutex<FileSystem>>);
 
-/* The following is meant for debugging purposes when developing this library only
+/// Renders the tree with the default [`RenderOptions`], which include only names.
+///
+/// [`RenderOptions`]: struct.RenderOptions.html
 impl fmt::Display for FS {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let fs = self.0.lock();
-
-        write!(f, "\nfilesystem\n----------\n")?;
-        if !fs.pwd.alive {
-            if Raw::ptr_eq(&fs.pwd.inner, &fs.root) {
-                return write!(f, "deleted");
-            }
-        }
-
-        let root = fs.root;
-
-        fn wr(f: &mut fmt::Formatter, d: Raw<Dirent>, level: u32) -> fmt::Result {
-            for _ in 0..level {
-                write!(f, "    ")?;
-            }
-            let name = d.name.as_os_str().to_str().expect("expected utf8 dirent name");
-            let inode = d.inode.view();
-            match d.kind {
-                DeKind::Dir(ref children) => {
-                    write!(f, "{}/ -- {:o} (len: {})\n",
-                        name, inode.perms.0, inode.length)?;
-                    for child in children.values() {
-                        wr(f, *child, level+1)?;
-                    }
-                    write!(f, "")
-                }
-                DeKind::File(_) => write!(f, "{} -- {:o} (len: {})\n",
-                        name, inode.perms.0, inode.length),
-                DeKind::Symlink(ref sl) => write!(f, "{}@->[{}] -- {:o} (len:{})\n",
-                        name, sl.clone().to_str().expect("expected utf8 symlink name"),
-                        inode.perms.0, inode.length),
-            }
-        }
-        wr(f, root, 0)
+        f.write_str(&self.render(&RenderOptions::new()))
     }
 }
-*/
 
 impl FS {
     /// Creates an empty `FS` with mode `0o777`.
@@ -836,75 +1069,619 @@ impl FS {
     /// let fs = FS::with_mode(0o300);
     /// ```
     pub fn with_mode(mode: u32) -> FS {
+        Self::with_mode_and_clock(mode, SystemClock)
+    }
+
+    /// Creates an empty `FS` with mode `0o777` that takes all of its timestamps from `clock`.
+    ///
+    /// See the [`clock`] module for the available clocks.
+    ///
+    /// [`clock`]: ../clock/index.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// use std::time::UNIX_EPOCH;
+    /// use rsfs::mem::clock::FrozenClock;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::with_clock(FrozenClock::new(UNIX_EPOCH));
+    /// fs.create_file("f")?;
+    /// assert_eq!(fs.metadata("f")?.modified()?, UNIX_EPOCH);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_clock<C: Clock + 'static>(clock: C) -> FS {
+        Self::with_mode_and_clock(0o777, clock)
+    }
+
+    /// Creates an empty `FS` with the given mode that takes all of its timestamps from `clock`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// use rsfs::mem::clock::MockClock;
+    /// let fs = FS::with_mode_and_clock(0o300, MockClock::default());
+    /// ```
+    pub fn with_mode_and_clock<C: Clock + 'static>(mode: u32, clock: C) -> FS {
+        let root_charge = Space::charge(&Arc::new(Space::default())).expect("unlimited space is never full");
         let pwd = Raw::from(Dirent {
             parent: None,
             kind:   DeKind::Dir(HashMap::new()),
             name:   OsString::from(""),
-            inode:  Inode::new(mode, Ftyp::Dir, DIRLEN),
+            inode:  Inode::create(mode, Ftyp::Dir, DIRLEN, Arc::new(clock), root_charge),
         });
         FS(Arc::new(Mutex::new(FileSystem {
-            root: pwd,
-            pwd:  Pwd::from(pwd),
+            pwd: Pwd::from(pwd),
         })))
     }
-}
 
-impl Default for FS {
-    fn default() -> Self {
-        FS::new()
+    /// Creates an empty `FS` with mode `0o777` that can hold at most `bytes` bytes of file data
+    /// and `inodes` files, directories, and symlinks, including the root directory.
+    ///
+    /// Creating an entry when no inodes are left, or writing file data past the byte limit, fails
+    /// with `ENOSPC`. Only data takes space: holes left by writing past the end of a file or by
+    /// extending it do not. Writes are all or nothing: a write that does not entirely fit writes
+    /// nothing. Space is freed when a file is truncated, or when its last name is removed and its
+    /// last open handle is dropped. See [`set_limits`] to change the limits later.
+    ///
+    /// [`set_limits`]: #method.set_limits
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// use std::io::Write;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::with_limits(4, 100);
+    ///
+    /// let mut f = fs.create_file("f")?;
+    /// assert!(f.write(b"hello").is_err());
+    /// assert_eq!(f.write(b"hell")?, 4);
+    ///
+    /// drop(f);
+    /// fs.remove_file("f")?;
+    /// assert_eq!(fs.bytes_used(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_limits(bytes: u64, inodes: u64) -> FS {
+        let fs = Self::new();
+        fs.set_limits(Some(bytes), Some(inodes));
+        fs
     }
-}
 
-impl fs::GenFS for FS {
-    type DirBuilder  = DirBuilder;
-    type DirEntry    = DirEntry;
-    type File        = File;
-    type Metadata    = Metadata;
-    type OpenOptions = OpenOptions;
-    type Permissions = Permissions;
-    type ReadDir     = ReadDir;
+    /// Sets the maximum bytes of file data and the maximum number of inodes this `FS` can hold,
+    /// where `None` is unlimited.
+    ///
+    /// Limits can be set below what is already in use. Operations that need more space then fail
+    /// with `ENOSPC` until enough is freed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir("a")?;
+    ///
+    /// fs.set_limits(None, Some(fs.inodes_used()));
+    /// assert!(fs.create_dir("b").is_err());
+    /// fs.remove_dir("a")?;
+    /// assert!(fs.create_dir("b").is_ok());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_limits(&self, bytes: Option<u64>, inodes: Option<u64>) {
+        self.0.lock().space.set_limits(bytes, inodes)
+    }
 
-    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
-        self.0.lock().canonicalize(path, &mut 0)
+    /// Returns the number of bytes of file data currently held by this `FS`.
+    pub fn bytes_used(&self) -> u64 {
+        self.0.lock().space.bytes_used()
     }
-    fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> {
-        // std::fs's copy actually uses std::fs functions to implement copy, which is nice. We will
-        // repeat that pattern here, which requires us to use rsfs traits
-        use fs::OpenOptions;
-        use fs::File;
-        // First, though, we have to validate that `from` is actually a file.
-        fn not_file() -> Error {
-            Error::new(ErrorKind::InvalidInput, "the source path is not an existing regular file")
-        }
 
-        let (from, to) = (from.as_ref(), to.as_ref());
+    /// Returns the number of inodes currently held by this `FS`, including the root directory.
+    pub fn inodes_used(&self) -> u64 {
+        self.0.lock().space.inodes_used()
+    }
 
-        // Now we do our "is file" checking, scoping the lock, because most other functions below
-        // this scope each use a lock.
-        {
-            let fs = &*self.0.lock();
-            let (fs, may_base) = fs.traverse(normalize(&from), &mut 0)?;
-            let base = may_base.ok_or_else(not_file)?;
+    /// Returns an immutable copy of this `FS` that can later be passed to [`restore`] or
+    /// [`from_snapshot`].
+    ///
+    /// A snapshot copies the whole tree: file contents, permissions, owners, timestamps, symlinks,
+    /// which names are hard links to the same inode, and the current directory. A snapshot does
+    /// not count against this `FS`'s limits.
+    ///
+    /// [`restore`]: #method.restore
+    /// [`from_snapshot`]: #method.from_snapshot
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir("a")?;
+    /// let snapshot = fs.snapshot();
+    ///
+    /// fs.remove_dir("a")?;
+    /// fs.restore(&snapshot);
+    /// assert!(fs.metadata("a")?.is_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        let fs = self.0.lock();
+        let copy = fs.copy(fs.clock.clone(), Arc::new(Space::default()));
+        Snapshot(FS(Arc::new(Mutex::new(copy))))
+    }
 
-            if !fs.executable() {
-                return Err(EACCES());
-            }
-            match fs.kind.dir_ref().get(&base) {
-                Some(child) => match child.kind {
-                    DeKind::File(_) => (),
-                    _ => return Err(not_file()),
-                },
-                None => return Err(not_file()),
-            }
-        }
+    /// Rolls this `FS` back to `snapshot`, which may have been taken from any `FS`.
+    ///
+    /// The restored tree keeps this `FS`'s clock, limits, user and group ids, and whether it tracks
+    /// crashes, starting out synced if it does. Restored files
+    /// count against the limits but are never refused for going over them; see [`set_limits`].
+    /// Files that are open when restoring keep referring to the files they were opened from, which
+    /// are no longer in the tree.
+    ///
+    /// [`set_limits`]: #method.set_limits
+    pub fn restore(&self, snapshot: &Snapshot) {
+        let mut fs = self.0.lock();
+        let mut copy = snapshot.0 .0.lock().copy(fs.clock.clone(), fs.space.clone());
+        copy.ids = fs.ids;
+        copy.track(fs.journal.lock().is_some());
+        *fs = copy;
+    }
 
-        // Now we do what std::fs does. Note that the open on from is technically a race: a second
-        // process could come in, remove from, and create a symlink with the same name. That would
-        // be the only potential problem. The end result would be that the symlink's target is
-        // copied, which really isn't a huge deal. If the race changed from to a directory, the
-        // io::copy would fail, as reads on directories fail immediately.
-        // (also see https://github.com/rust-lang/rust/issues/37885)
-        let mut reader = self.new_openopts().read(true).open(from)?;
+    /// Creates a new `FS` from `snapshot` with no limits, acting as uid 0 and gid 0, and using the
+    /// clock of the `FS` the snapshot was taken from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir_all("a/b")?;
+    /// let snapshot = fs.snapshot();
+    ///
+    /// let (left, right) = (FS::from_snapshot(&snapshot), FS::from_snapshot(&snapshot));
+    /// left.remove_dir("a/b")?;
+    /// assert!(right.metadata("a/b")?.is_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_snapshot(snapshot: &Snapshot) -> FS {
+        let snap = snapshot.0 .0.lock();
+        let mut copy = snap.copy(snap.clock.clone(), Arc::new(Space::default()));
+        copy.ids = Ids::default();
+        FS(Arc::new(Mutex::new(copy)))
+    }
+
+    /// Creates an empty `FS` with mode `0o777` that tracks what has been synced, so that [`crash`]
+    /// can show what would be left of it after a crash.
+    ///
+    /// Tracking keeps what a crash would leave of each inode, along with every change to a
+    /// directory until the directory is synced. Unsynced data is shared with the live files rather
+    /// than copied, but a tree that changes a lot without syncing its directories grows the
+    /// tracked changes. Other `FS`s do not track crashes.
+    ///
+    /// [`crash`]: #method.crash
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::{Crash, FS};
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::with_crash_tracking();
+    /// fs.create_dir("a")?;
+    /// assert!(fs.crash(Crash::DropUnsynced).metadata("a").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_crash_tracking() -> FS {
+        let fs = Self::new();
+        fs.0.lock().track(true);
+        fs
+    }
+
+    /// Makes everything in this `FS` survive a crash, like `sync(2)`.
+    ///
+    /// See [`crash`] for what survives otherwise. This does nothing if this `FS` does not track
+    /// crashes.
+    ///
+    /// [`crash`]: #method.crash
+    pub fn sync(&self) {
+        let fs = self.0.lock();
+        let mut journal = fs.journal.lock();
+        if let (Some(root), Some(_)) = (fs.root, journal.as_ref()) {
+            *journal = Some(synced(root));
+        }
+    }
+
+    /// Returns a new `FS` holding what this `FS` would hold after a crash, such as a power loss.
+    ///
+    /// Like a real disk, a file's data survives once the file is synced with `sync_data` or
+    /// `sync_all`, and its metadata and extended attributes once it is synced with `sync_all`.
+    /// Files opened with `O_SYNC` or `O_DSYNC` (see `unix_ext::OpenOptionsExt::custom_flags`) are
+    /// synced by every write. Created, removed, and renamed names survive once their directory is
+    /// opened and synced; syncing either directory of a rename makes the whole rename survive.
+    /// An `FS` created with [`with_crash_tracking`] starts out synced, as do ones restored from a
+    /// snapshot into it, and [`sync`] syncs everything.
+    ///
+    /// `model` chooses what happens to what was not synced. The new `FS` has no limits, acts as
+    /// uid 0 and gid 0, uses the clock of this `FS`, and tracks crashes, starting out synced.
+    /// This `FS` is left as it was.
+    ///
+    /// [`with_crash_tracking`]: #method.with_crash_tracking
+    /// [`sync`]: #method.sync
+    ///
+    /// # Panics
+    ///
+    /// Panics if this `FS` does not track crashes. To crash a tree read from elsewhere, restore a
+    /// snapshot of it into an `FS` that does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::{Crash, FS};
+    /// use std::io::Write;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::with_crash_tracking();
+    /// let mut f = fs.create_file("f")?;
+    /// f.write_all(b"hello")?;
+    /// f.sync_all()?;
+    /// fs.create_file("g")?;
+    ///
+    /// // f is synced, but neither name is until the root directory is.
+    /// let crashed = fs.crash(Crash::DropUnsynced);
+    /// assert!(crashed.metadata("f").is_err());
+    ///
+    /// fs.open_file("/")?.sync_all()?;
+    /// let crashed = fs.crash(Crash::DropUnsynced);
+    /// assert_eq!(crashed.metadata("f")?.len(), 5);
+    /// assert_eq!(crashed.metadata("g")?.len(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn crash(&self, model: Crash) -> FS {
+        FS(Arc::new(Mutex::new(self.0.lock().crashed(model))))
+    }
+
+    /// Copies the host file or directory tree at `host` into this `FS` at `into`.
+    ///
+    /// File contents, modes, symlinks, access and modification times, and hard links between
+    /// files in the tree are kept; symlink timestamps and ownership are not. Directories that
+    /// already exist in this `FS` are reused and files are overwritten. Entries are created as the
+    /// current uid and gid, subject to the usual permission checks and limits.
+    ///
+    /// Host entries that cannot be represented, such as sockets, FIFOs, and devices, are not
+    /// copied. Their host paths are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// let skipped = fs.import_from("tests/fixtures", "/fixtures")?;
+    /// assert!(skipped.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn import_from<P: AsRef<Path>, Q: AsRef<Path>>(&self, host: P, into: Q)
+        -> Result<Vec<PathBuf>>
+    {
+        let (nodes, skipped) = host::read(host)?;
+        self.import(&nodes, into)?;
+        Ok(skipped)
+    }
+
+    /// Copies this entire `FS` to the host directory `host`, creating it if necessary.
+    ///
+    /// File contents, modes, symlinks, access and modification times, and hard links are kept;
+    /// symlink timestamps and ownership are not. Directories that already exist on the host are
+    /// reused; files and symlinks already there are replaced, never written through.
+    ///
+    /// FIFOs, sockets, and devices are not copied, as creating them on the host may need
+    /// privileges. Their host paths are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir_all("a/b")?;
+    /// let skipped = fs.export_to("/tmp/exported")?;
+    /// assert!(skipped.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn export_to<P: AsRef<Path>>(&self, host: P) -> Result<Vec<PathBuf>> {
+        let nodes = self.0.lock().nodes()?;
+        host::write(&nodes, host)
+    }
+
+    /// Creates a new `FS` from the tar archive read from `reader`.
+    ///
+    /// Regular files, directories, symlinks, hard links, FIFOs, and devices are recreated with the
+    /// mode and modification time from their headers; ownership is not. Parent directories
+    /// missing from the archive are created with mode `0o755`. The archive may be in the ustar,
+    /// GNU, or pax format.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error of kind `InvalidData` if the archive is malformed, contains
+    /// a path that leaves the root with `..`, or contains an entry that cannot be represented,
+    /// such as a GNU volume header.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::from_tar(std::fs::File::open("tests/fixtures.tar")?)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_tar<R: Read>(reader: R) -> Result<FS> {
+        let fs = FS::new();
+        fs.import(&tar::read(reader)?, "/")?;
+        Ok(fs)
+    }
+
+    /// Writes this entire `FS` to `writer` as a tar archive.
+    ///
+    /// Entries are written in sorted order starting with the root directory as `./`. Hard links
+    /// are written as tar hard links to the first name of the file. Paths that do not fit in a
+    /// ustar header are written with pax extended headers. FIFOs and devices are written with
+    /// their device numbers; sockets, which tar archives cannot hold, are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir_all("a/b")?;
+    ///
+    /// let mut tar = Vec::new();
+    /// fs.write_tar(&mut tar)?;
+    /// assert!(FS::from_tar(&tar[..])?.metadata("a/b")?.is_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_tar<W: Write>(&self, writer: W) -> Result<()> {
+        let nodes = self.0.lock().nodes()?;
+        tar::write(&nodes, writer)
+    }
+
+    /// Renders the tree of this `FS` as text, one entry per line, with details chosen by `opts`.
+    ///
+    /// If the root directory has been removed, the rendering is just `/ (deleted)`. See
+    /// [`RenderOptions`] for the format.
+    ///
+    /// [`RenderOptions`]: struct.RenderOptions.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::unix_ext::*;
+    /// # use rsfs::mem::{FS, RenderOptions};
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.new_dirbuilder().mode(0o750).create("d")?;
+    /// fs.symlink("d", "l")?;
+    ///
+    /// assert_eq!(fs.render(RenderOptions::new().modes(true)), "\
+    /// / (mode 0777)
+    ///     d/ (mode 0750)
+    ///     l -> d (mode 0777)
+    /// ");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn render(&self, opts: &RenderOptions) -> String {
+        match self.0.lock().nodes() {
+            Ok(nodes) => render::render(&nodes, "/", '/', opts),
+            Err(_) => String::from("/ (deleted)\n"),
+        }
+    }
+
+    // import recreates nodes at into as the current ids.
+    fn import<P: AsRef<Path>>(&self, nodes: &[host::Node], into: P) -> Result<()> {
+        use unix_ext::GenFSExt;
+        host::import(self,
+                     nodes,
+                     into,
+                     |fs, target, path, _| fs.symlink(target, path),
+                     |fs, path, ftyp, rdev| fs.mknod(path, ftyp.bits() | 0o600, rdev),
+                     Permissions)
+    }
+
+    /// Sets the user id that future operations are performed as.
+    ///
+    /// Every `FS` starts as uid 0 and gid 0, which also own the root directory. Permission checks
+    /// use the owner, group, or other bits of a mode depending on whether the current ids own or
+    /// share a group with a file. Only the one group id is a member of a group; supplementary
+    /// groups are not modeled.
+    ///
+    /// Unlike a real system, uid 0 never bypasses the read, write, and execute bits of a mode, so
+    /// tests can deny access to files without switching users. Instead, uid 0 only bypasses
+    /// ownership: it counts as the owner of every file, so it may change the permissions and
+    /// times of any file, open any file with `O_NOATIME`, and change the attributes of files in
+    /// sticky directories. Besides that, only uid 0 may give files away with [`chown`], create
+    /// devices, and use the `trusted.` and `security.` extended attribute namespaces.
+    ///
+    /// [`chown`]: https://docs.rs/rsfs/0.4.1/rsfs/unix_ext/trait.GenFSExt.html#method.chown
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::FS;
+    /// use rsfs::unix_ext::*;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.new_openopts().write(true).create(true).mode(0o640).open("f")?;
+    ///
+    /// // Another user in the same group can read, but not write, the file.
+    /// fs.set_uid(1000);
+    /// assert!(fs.open_file("f").is_ok());
+    /// assert!(fs.new_openopts().write(true).open("f").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_uid(&self, uid: u32) {
+        self.0.lock().pwd.ids.uid = uid;
+    }
+
+    /// Sets the group id that future operations are performed as. See [`set_uid`].
+    ///
+    /// [`set_uid`]: struct.FS.html#method.set_uid
+    pub fn set_gid(&self, gid: u32) {
+        self.0.lock().pwd.ids.gid = gid;
+    }
+
+    /// Returns the user id that operations are performed as.
+    pub fn uid(&self) -> u32 {
+        self.0.lock().pwd.ids.uid
+    }
+
+    /// Returns the group id that operations are performed as.
+    pub fn gid(&self) -> u32 {
+        self.0.lock().pwd.ids.gid
+    }
+}
+
+impl Default for FS {
+    fn default() -> Self {
+        FS::new()
+    }
+}
+
+/// An immutable copy of an [`FS`] at a point in time, created with [`FS::snapshot`].
+///
+/// Cloning a `Snapshot` is cheap; clones share the same copy.
+///
+/// [`FS`]: struct.FS.html
+/// [`FS::snapshot`]: struct.FS.html#method.snapshot
+#[derive(Clone, Debug)]
+pub struct Snapshot(FS);
+
+/// What a crash simulated with [`FS::crash`] does to what was not synced.
+///
+/// [`FS::crash`]: struct.FS.html#method.crash
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Crash {
+    /// Nothing that was not synced survives.
+    DropUnsynced,
+    /// Each unsynced change to a directory and the unsynced metadata and data of each file may
+    /// or may not survive, as chosen by a generator seeded with the given seed. Directory changes
+    /// that survive are applied in the order they were made, and the same seed always crashes the
+    /// same way.
+    KeepRandom(u64),
+}
+
+/// Returns the differences between the trees of `a` and `b`, as changes that would turn `a` into
+/// `b`.
+///
+/// Contents, modes, types, symlink targets, and which names are hard links to the same file are
+/// compared; timestamps and ownership are not. Changes are sorted by path.
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::{self, Change, FS};
+/// use std::io::Write;
+/// use std::path::PathBuf;
+/// # fn foo() -> std::io::Result<()> {
+/// let (a, b) = (FS::new(), FS::new());
+/// a.create_file("f")?.write_all(b"hello")?;
+/// b.create_file("f")?.write_all(b"help")?;
+/// b.create_dir("d")?;
+///
+/// assert_eq!(mem::diff(&a, &b)?, vec![
+///     Change::Added(PathBuf::from("/d")),
+///     Change::Content { path: PathBuf::from("/f"), ranges: vec![3..5] },
+/// ]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn diff(a: &FS, b: &FS) -> Result<Vec<Change>> {
+    let from = a.0.lock().nodes()?;
+    let to = b.0.lock().nodes()?;
+    Ok(diff::changes(&from, &to, Path::new("/")))
+}
+
+impl fs::GenFS for FS {
+    type DirBuilder  = DirBuilder;
+    type DirEntry    = DirEntry;
+    type File        = File;
+    type Metadata    = Metadata;
+    type OpenOptions = OpenOptions;
+    type Permissions = Permissions;
+    type ReadDir     = ReadDir;
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.0.lock().canonicalize(path, &mut 0)
+    }
+    fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> {
+        // std::fs's copy actually uses std::fs functions to implement copy, which is nice. We will
+        // repeat that pattern here, which requires us to use rsfs traits
+        use fs::OpenOptions;
+        use fs::File;
+        // First, though, we have to validate that `from` is actually a file.
+        fn not_file() -> Error {
+            Error::new(ErrorKind::InvalidInput, "the source path is not an existing regular file")
+        }
+
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        // Now we do our "is file" checking, scoping the lock, because most other functions below
+        // this scope each use a lock.
+        {
+            let fs = &*self.0.lock();
+            let ids = fs.ids;
+            let (fs, may_base) = fs.traverse(normalize(&from), &mut 0)?;
+            let base = may_base.ok_or_else(not_file)?;
+
+            if !fs.executable(ids) {
+                return Err(EACCES());
+            }
+            match fs.kind.dir_ref().get(&base) {
+                Some(child) => match child.kind {
+                    DeKind::File(_) => (),
+                    _ => return Err(not_file()),
+                },
+                None => return Err(not_file()),
+            }
+        }
+
+        // Now we do what std::fs does. Note that the open on from is technically a race: a second
+        // process could come in, remove from, and create a symlink with the same name. That would
+        // be the only potential problem. The end result would be that the symlink's target is
+        // copied, which really isn't a huge deal. If the race changed from to a directory, the
+        // io::copy would fail, as reads on directories fail immediately.
+        // (also see https://github.com/rust-lang/rust/issues/37885)
+        let mut reader = self.new_openopts().read(true).open(from)?;
         let mut writer = self.new_openopts().write(true).truncate(true).create(true).open(to)?;
         let perm = reader.metadata()?.permissions();
         let ret = io::copy(&mut reader, &mut writer)?;
@@ -917,6 +1694,9 @@ impl fs::GenFS for FS {
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         self.new_dirbuilder().recursive(true).create(path)
     }
+    fn current_dir(&self) -> Result<PathBuf> {
+        self.0.lock().current_dir()
+    }
     fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
         self.0.lock().hard_link(src, dst)
     }
@@ -939,11 +1719,17 @@ impl fs::GenFS for FS {
         self.0.lock().remove_file(path)
     }
     fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
-        self.0.lock().rename(from, to)
+        self.0.lock().rename(from, to, 0)
+    }
+    fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.0.lock().set_current_dir(path)
     }
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perms: Self::Permissions) -> Result<()> {
         self.0.lock().set_permissions(path, perms, &mut 0)
     }
+    fn set_times<P: AsRef<Path>>(&self, path: P, times: fs::FileTimes) -> Result<()> {
+        self.0.lock().set_times(path, times, &mut 0)
+    }
     fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
         self.0.lock().symlink_metadata(path)
     }
@@ -958,6 +1744,7 @@ impl fs::GenFS for FS {
             create: false,
             excl:   false,
             mode:   0o666, // default per unix_ext
+            flags:  0,
         }
     }
     fn new_dirbuilder(&self) -> Self::DirBuilder {
@@ -977,20 +1764,88 @@ impl fs::GenFS for FS {
         use fs::OpenOptions;
         self.new_openopts().write(true).create(true).truncate(true).open(path.as_ref())
     }
+
+    // read and write hold our lock across opening and using the file.
+    fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        use fs::OpenOptions;
+        let mut opts = self.new_openopts();
+        opts.read(true);
+        let fs = self.0.lock();
+        let mut data = Vec::new();
+        (&fs.open(path, &opts, &mut 0)?).read_to_end(&mut data)?;
+        Ok(data)
+    }
+    fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<()> {
+        use fs::OpenOptions;
+        let mut opts = self.new_openopts();
+        opts.write(true).create(true).truncate(true);
+        let fs = self.0.lock();
+        (&fs.open(path, &opts, &mut 0)?).write_all(contents.as_ref())
+    }
 }
 
 impl unix_ext::GenFSExt for FS {
     fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
         self.0.lock().symlink(src, dst)
     }
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.0.lock().chown(path, uid, gid, &mut 0)
+    }
+    fn mkfifo<P: AsRef<Path>>(&self, path: P, mode: u32) -> Result<()> {
+        self.0.lock().mknod(path, 0o010000 | (mode & 0o7777), 0)
+    }
+    fn mknod<P: AsRef<Path>>(&self, path: P, mode: u32, dev: u64) -> Result<()> {
+        self.0.lock().mknod(path, mode, dev)
+    }
+    fn link_file<P: AsRef<Path>>(&self, file: &File, dst: P) -> Result<()> {
+        self.0.lock().link_file(file, dst)
+    }
+    fn rename_with_flags<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q, flags: u32)
+        -> Result<()>
+    {
+        self.0.lock().rename(from, to, flags)
+    }
+    fn get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N) -> Result<Vec<u8>> {
+        self.0.lock().get_xattr(path.as_ref(), name.as_ref(), true)
+    }
+    fn set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N, value: &[u8])
+        -> Result<()>
+    {
+        self.0.lock().set_xattr(path.as_ref(), name.as_ref(), value, true)
+    }
+    fn list_xattr<P: AsRef<Path>>(&self, path: P) -> Result<Vec<OsString>> {
+        self.0.lock().list_xattr(path.as_ref(), true)
+    }
+    fn remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N) -> Result<()> {
+        self.0.lock().remove_xattr(path.as_ref(), name.as_ref(), true)
+    }
+    fn symlink_get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N)
+        -> Result<Vec<u8>>
+    {
+        self.0.lock().get_xattr(path.as_ref(), name.as_ref(), false)
+    }
+    fn symlink_set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N, value: &[u8])
+        -> Result<()>
+    {
+        self.0.lock().set_xattr(path.as_ref(), name.as_ref(), value, false)
+    }
+    fn symlink_list_xattr<P: AsRef<Path>>(&self, path: P) -> Result<Vec<OsString>> {
+        self.0.lock().list_xattr(path.as_ref(), false)
+    }
+    fn symlink_remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, path: P, name: N)
+        -> Result<()>
+    {
+        self.0.lock().remove_xattr(path.as_ref(), name.as_ref(), false)
+    }
 }
 
-/// Times tracks the modified, accessed, and created time for a Dirent.
+/// Times tracks the modified, accessed, created, and status changed time for a Dirent.
 #[derive(Copy, Clone, Debug)]
 struct Times {
     modified: SystemTime,
     accessed: SystemTime,
     created:  SystemTime,
+    changed:  SystemTime,
 }
 
 /// Bitflag indicating a Dirent was modified.
@@ -999,25 +1854,27 @@ const MODIFIED: u8 = 1; // modified time
 const ACCESSED: u8 = 2; // accessed time
 /// Bitflag indicating a Dirent was created.
 const CREATED:  u8 = 4; // created time
+/// Bitflag indicating the status of a Dirent changed. Modifying a Dirent also changes its status.
+const CHANGED:  u8 = 8; // status changed time
 
 impl Times {
-    fn new() -> Times {
-        let now = SystemTime::now();
+    fn new(now: SystemTime) -> Times {
         Times {
             modified: now,
             accessed: now,
             created:  now,
+            changed:  now,
         }
     }
 
-    fn update(&mut self, fields: u8) {
-        const MASK: u8 = !(MODIFIED | ACCESSED | CREATED);
+    fn update(&mut self, fields: u8, now: SystemTime) {
+        const MASK: u8 = !(MODIFIED | ACCESSED | CREATED | CHANGED);
         if fields & MASK != 0 {
             panic!("incorrect times update usage!")
         }
-        let now = SystemTime::now();
         if fields & MODIFIED != 0 {
             self.modified = now;
+            self.changed = now;
         }
         if fields & ACCESSED != 0 {
             self.accessed = now;
@@ -1025,9 +1882,31 @@ impl Times {
         if fields & CREATED != 0 {
             self.created = now;
         }
+        if fields & CHANGED != 0 {
+            self.changed = now;
+        }
+    }
+
+    /// set sets the times that are set in times. Setting times changes the status at now.
+    fn set(&mut self, times: fs::FileTimes, now: SystemTime) {
+        if let Some(accessed) = times.accessed() {
+            self.accessed = accessed;
+        }
+        if let Some(modified) = times.modified() {
+            self.modified = modified;
+        }
+        self.changed = now;
     }
 }
 
+/// `Ids` are the user and group ids that filesystem operations are performed as. Permission checks
+/// compare them against the uid and gid of an inode.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Ids {
+    uid: u32,
+    gid: u32,
+}
+
 /// `InodeData` is the backing shared data of an "inode". Unix systems can have multiple files
 /// pointing to the same inode. We minimally mimic that in this code. We don't recreate a full unix
 /// filesystem, but the following data is shared behind a mutex when creating hard links or raw
@@ -1038,41 +1917,174 @@ struct InodeData {
     perms:  Permissions,
     ftyp:   FileType,
     length: usize,
+    uid:    u32,
+    gid:    u32,
+    dev:    u64,
+    ino:    u64,
+    nlink:  u64,
+    blocks: u64,
+    rdev:   u64,
 }
 
+// Like times, the device, inode number, and link count depend on how and where an inode was
+// created rather than on what it holds, so they are not compared.
 impl PartialEq for InodeData {
     fn eq(&self, other: &Self) -> bool {
         self.perms == other.perms &&
             self.ftyp == other.ftyp &&
-            self.length == other.length
+            self.length == other.length &&
+            self.uid == other.uid &&
+            self.gid == other.gid &&
+            self.rdev == other.rdev
     }
 }
 
 /// `Inode` is what makes sharing `InodeData` between hard links / dirents / raw files possible.
+/// Each inode also holds the filesystem's clock, which all of its time updates go through, and its
+/// charge against the filesystem's space, which is released when the last clone drops. Extended
+/// attributes are not `Copy`, so they are shared beside the data rather than in it.
 #[derive(Clone, Debug)]
-struct Inode(Arc<RwLock<InodeData>>);
+struct Inode {
+    data:   Arc<RwLock<InodeData>>,
+    xattrs: Arc<RwLock<Xattrs>>,
+    locks:  Arc<Locks>,
+    clock:  SharedClock,
+    charge: Arc<Charge>,
+}
 
 impl PartialEq for Inode {
     fn eq(&self, other: &Self) -> bool {
-        *self.read() == *other.read()
+        *self.read() == *other.read() && *self.xattrs.read() == *other.xattrs.read()
     }
 }
 
 impl Inode {
+    /// Creates an inode owned by root using the system clock and unlimited space.
+    #[cfg(test)]
     fn new(mode: u32, ftyp: Ftyp, len: usize) -> Inode {
-        Inode(Arc::new(RwLock::new(InodeData {
-            times:  Times::new(),
-            perms:  Permissions(mode),
-            ftyp:   FileType(ftyp),
-            length: len,
-        })))
+        let charge = Space::charge(&Arc::new(Space::default())).expect("unlimited space is never full");
+        Inode::create(mode, ftyp, len, Arc::new(SystemClock), charge)
+    }
+
+    /// Creates an inode owned by root; see `Pwd::new_inode` to create an inode owned by the
+    /// filesystem's current ids.
+    ///
+    /// Directories start with two links, one from their parent and one from their own `.`, while
+    /// everything else starts with the link from its parent.
+    fn create(mode: u32, ftyp: Ftyp, len: usize, clock: SharedClock, charge: Charge) -> Inode {
+        Inode {
+            data: Arc::new(RwLock::new(InodeData {
+                times:  Times::new(clock.now()),
+                perms:  Permissions(mode),
+                ftyp:   FileType(ftyp),
+                length: len,
+                uid:    0,
+                gid:    0,
+                dev:    charge.dev(),
+                ino:    charge.ino(),
+                nlink:  if ftyp == Ftyp::Dir { 2 } else { 1 },
+                blocks: if ftyp == Ftyp::Symlink { 0 } else { blocks(len) },
+                rdev:   0,
+            })),
+            xattrs: Arc::default(),
+            locks:  Arc::default(),
+            clock,
+            charge: Arc::new(charge),
+        }
+    }
+
+    /// touch updates the given times fields to the clock's current time.
+    fn touch(&self, fields: u8) {
+        let now = self.clock.now();
+        self.write().times.update(fields, now);
     }
 
     fn view(&self) -> InodeData {
         *self.read()
     }
-    fn perms(&self) -> Permissions {
-        self.read().perms
+
+    /// key identifies the inode in a journal, which keeps a weak reference to our data so that
+    /// the key is not reused while the journal knows it.
+    fn key(&self) -> journal::Key {
+        Arc::as_ptr(&self.data) as journal::Key
+    }
+
+    /// owned_by returns whether ids own the inode, which root owns all of. This is the only way
+    /// root is privileged in permission checks; see `FS::set_uid`.
+    fn owned_by(&self, ids: Ids) -> bool {
+        ids.uid == 0 || self.read().uid == ids.uid
+    }
+
+    /// set_perms sets the permissions of the inode, which changes its status.
+    fn set_perms(&self, perms: Permissions) {
+        let now = self.clock.now();
+        let mut data = self.write();
+        data.perms = perms;
+        data.times.update(CHANGED, now);
+    }
+
+    /// link adds a link to the inode.
+    fn link(&self) {
+        let now = self.clock.now();
+        let mut data = self.write();
+        data.nlink += 1;
+        data.times.update(CHANGED, now);
+    }
+
+    /// unlink removes a link from the inode.
+    fn unlink(&self) {
+        let now = self.clock.now();
+        let mut data = self.write();
+        data.nlink -= 1;
+        data.times.update(CHANGED, now);
+    }
+
+    /// access returns the rwx bits that apply to ids; see `Dirent::access`.
+    fn access(&self, ids: Ids) -> u32 {
+        let inode = self.read();
+        let mode = inode.perms.0;
+        if inode.uid == ids.uid {
+            (mode >> 6) & 0o7
+        } else if inode.gid == ids.gid {
+            (mode >> 3) & 0o7
+        } else {
+            mode & 0o7
+        }
+    }
+
+    /// xattr_caller describes ids to the inode's extended attributes.
+    fn xattr_caller(&self, ids: Ids) -> xattr::Caller {
+        let access = self.access(ids);
+        let data = self.read();
+        xattr::Caller {
+            root:   ids.uid == 0,
+            owner:  data.uid == ids.uid,
+            access,
+            user:   data.ftyp.is_file() || data.ftyp.is_dir(),
+            sticky: data.ftyp.is_dir() && data.perms.0 & 0o1000 != 0,
+        }
+    }
+
+    fn get_xattr(&self, name: &OsStr, ids: Ids) -> Result<Vec<u8>> {
+        self.xattrs.read().get(name, self.xattr_caller(ids))
+    }
+
+    /// set_xattr sets an extended attribute, which changes the inode's status.
+    fn set_xattr(&self, name: &OsStr, value: &[u8], ids: Ids) -> Result<()> {
+        self.xattrs.write().set(name, value, self.xattr_caller(ids))?;
+        self.touch(CHANGED);
+        Ok(())
+    }
+
+    fn list_xattr(&self, ids: Ids) -> Vec<OsString> {
+        self.xattrs.read().list(self.xattr_caller(ids))
+    }
+
+    /// remove_xattr removes an extended attribute, which changes the inode's status.
+    fn remove_xattr(&self, name: &OsStr, ids: Ids) -> Result<()> {
+        self.xattrs.write().remove(name, self.xattr_caller(ids))?;
+        self.touch(CHANGED);
+        Ok(())
     }
 }
 
@@ -1081,17 +2093,99 @@ impl Deref for Inode {
 
     #[inline]
     fn deref(&self) -> &Arc<RwLock<InodeData>> {
-        &self.0
+        &self.data
+    }
+}
+
+/// `Durable` is what a crash leaves of an inode: its metadata and extended attributes as of its
+/// creation or last `sync_all`, and for a file, its data as of its last sync. It only refers
+/// weakly to the live inode, whose unsynced state a crash may also keep, so that a removed inode
+/// is still freed.
+#[derive(Clone, Debug)]
+struct Durable {
+    kind:        DurableKind,
+    meta:        InodeData,
+    xattrs:      Xattrs,
+    data:        Data,
+    live:        Weak<RwLock<InodeData>>,
+    live_xattrs: Weak<RwLock<Xattrs>>,
+}
+
+/// `DurableKind` is the `DeKind` of a durable inode.
+#[derive(Clone, Debug)]
+enum DurableKind {
+    File(Weak<RwLock<RawFile>>),
+    Dir,
+    Symlink(PathBuf),
+    Special,
+}
+
+/// `SharedJournal` is the journal of a filesystem, which its open files also sync to, if the
+/// filesystem tracks crashes.
+type SharedJournal = Arc<Mutex<Option<Journal<Durable>>>>;
+
+impl Durable {
+    // new describes inode as it is now, with no data.
+    fn new(kind: DurableKind, inode: &Inode) -> Durable {
+        Durable {
+            kind,
+            meta:        inode.view(),
+            xattrs:      inode.xattrs.read().clone(),
+            data:        Data::default(),
+            live:        Arc::downgrade(&inode.data),
+            live_xattrs: Arc::downgrade(&inode.xattrs),
+        }
+    }
+
+    // of describes the inode of d as it is now, with no data.
+    fn of(d: &Dirent) -> Durable {
+        let kind = match d.kind {
+            DeKind::File(ref file) => DurableKind::File(Arc::downgrade(file)),
+            DeKind::Dir(_) => DurableKind::Dir,
+            DeKind::Symlink(ref sl) => DurableKind::Symlink(sl.clone()),
+            DeKind::Special => DurableKind::Special,
+        };
+        Durable::new(kind, &d.inode)
+    }
+
+    // open_and_unnamed returns whether the inode is still open somewhere without a name, such as
+    // a file opened with O_TMPFILE, which may yet be given a name and keep what was synced.
+    fn open_and_unnamed(&self) -> bool {
+        self.live.upgrade().is_some_and(|meta| meta.read().nlink == 0)
+    }
+}
+
+// synced returns a journal in which the whole tree at root has been synced.
+fn synced(root: Raw<Dirent>) -> Journal<Durable> {
+    let mut journal = Journal::new(root.inode.key());
+    let mut todo = vec![root];
+    while let Some(d) = todo.pop() {
+        let key = d.inode.key();
+        let durable = journal.node(key, || Durable::of(&d));
+        match d.kind {
+            DeKind::File(ref file) => durable.data = file.read().data.clone(),
+            DeKind::Dir(ref children) => {
+                journal.commit(key, children.iter()
+                                            .map(|(name, child)| (name.clone(), child.inode.key()))
+                                            .collect());
+                todo.extend(children.values().cloned());
+            }
+            _ => (),
+        }
     }
+    journal
 }
 
-/// `DeKind` differentiates between files, directories, and symlinks. It mildly duplicates
-/// information that is available in `InodeData`s ftyp.
+/// `DeKind` differentiates between files, directories, symlinks, and special files. It mildly
+/// duplicates information that is available in `InodeData`s ftyp, which tells special files apart.
+/// Special files (FIFOs, sockets, and devices) have nothing behind them in memory, so they hold
+/// nothing but their inode.
 #[derive(Debug)]
 enum DeKind {
     File(Arc<RwLock<RawFile>>),
     Dir(HashMap<OsString, Raw<Dirent>>),
     Symlink(PathBuf),
+    Special,
 }
 
 impl DeKind {
@@ -1143,28 +2237,58 @@ struct Dirent {
 // Functions implemented for Dirent basically all are helpers on reading the underlying inode data.
 impl Dirent {
     fn is_dir(&self) -> bool {
-        match self.kind {
-            DeKind::Dir(_) => true,
-            _ => false,
-        }
+        matches!(self.kind, DeKind::Dir(_))
     }
-    fn readable(&self) -> bool {
-        self.inode.perms().0 & 0o400 == 0o400
+    /// access returns the rwx bits that apply to ids: the owner bits if ids is the owner, the
+    /// group bits if ids is in the group, and the other bits otherwise. Like POSIX, only one set of
+    /// bits applies; an owner without permissions does not fall back to the group or other bits.
+    fn access(&self, ids: Ids) -> u32 {
+        self.inode.access(ids)
     }
-    fn writable(&self) -> bool {
-        self.inode.perms().0 & 0o200 == 0o200
+    fn readable(&self, ids: Ids) -> bool {
+        self.access(ids) & 0o4 == 0o4
     }
-    fn executable(&self) -> bool {
-        self.inode.perms().0 & 0o100 == 0o100
+    fn writable(&self, ids: Ids) -> bool {
+        self.access(ids) & 0o2 == 0o2
+    }
+    fn executable(&self, ids: Ids) -> bool {
+        self.access(ids) & 0o1 == 0o1
     }
     /// changeable implies executable and writable. Executable is always needed when attempting to
     /// write to a directory.
-    fn changeable(&self) -> bool {
-        self.inode.perms().0 & 0o300 == 0o300
+    fn changeable(&self, ids: Ids) -> bool {
+        self.access(ids) & 0o3 == 0o3
+    }
+    /// descends_from returns whether the dirent is ancestor or is under it.
+    fn descends_from(&self, ancestor: Raw<Dirent>) -> bool {
+        if ::std::ptr::eq(self, ancestor.ptr()) {
+            return true;
+        }
+        let mut at = self.parent;
+        while let Some(dirent) = at {
+            if Raw::ptr_eq(&dirent, &ancestor) {
+                return true;
+            }
+            at = dirent.parent;
+        }
+        false
     }
     /// Only recursive removes need completely open permissions.
-    fn rremovable(&self) -> bool {
-        self.inode.perms().0 & 0o700 == 0o700
+    fn rremovable(&self, ids: Ids) -> bool {
+        self.access(ids) & 0o7 == 0o7
+    }
+    /// unlinked drops the links of a dirent that was just removed from its parent: a file or
+    /// symlink loses its one link, while a directory loses all of its links and the parent loses
+    /// the link from the directory's `..`. The root directory has no parent to update.
+    fn unlinked(&self, parent: Option<Raw<Dirent>>) {
+        if !self.is_dir() {
+            return self.inode.unlink();
+        }
+        self.inode.write().nlink = 0;
+        self.inode.touch(CHANGED);
+        if let Some(parent) = parent {
+            parent.inode.unlink();
+        }
     }
 }
 
@@ -1186,38 +2310,54 @@ impl fmt::Debug for Dirent {
 /// `FileSystem` because we occasionally create ephemeral `Pwd`s on the fly, and we don't want a
 /// `Pwd`s `Drop` to invalidate our entire filesystem.
 ///
-/// Recursive removes can delete the filesystem from underneath us - we can recursively remove a
-/// parent directory. We need to invalidate the filesystem when that happens. Additionally, when a
-/// filesystem drops, we need to delete everything under root. What happens if we recursively
-/// removed root already? `Pwd`s `alive` covers both cases.
+/// Removes can delete the current directory from underneath us - we can remove it directly, remove
+/// a parent directory recursively, or rename an empty directory over it. We need to invalidate the
+/// current directory when that happens. Additionally, when a filesystem drops, we need to delete
+/// everything under root. What happens if we recursively removed root already? `Pwd`s `alive` and
+/// `root` cover both cases.
+///
+/// If our Pwd was removed, alive is false and relative paths fail with ENOENT, but absolute paths
+/// still work from root. If root itself was removed, root is None and every operation fails with
+/// EINVAL.
 ///
-/// If our Pwd was removed, alive is false, and all operations fail. Any time we use root, we check
-/// if Pwd is alive and, if it is not, we pointer compare root and `Pwd`s inner. If they are equal,
-/// root is unusable (because alive being false means `Pwd`s inner has been dropped).
+/// `Pwd` also carries the user and group ids that operations are performed as, the clock and
+/// space new inodes are created with, and the journal changes to the tree are recorded in;
+/// ephemeral `Pwd`s must be created with `at` so that they keep all four.
 #[derive(Debug)]
 struct Pwd {
-    inner: Raw<Dirent>,
-    alive: bool,
+    inner:   Raw<Dirent>,
+    root:    Option<Raw<Dirent>>,
+    alive:   bool,
+    ids:     Ids,
+    clock:   SharedClock,
+    space:   Arc<Space>,
+    journal: SharedJournal,
 }
 
+// From creates a Pwd at the root directory d that does not track crashes.
 impl From<Raw<Dirent>> for Pwd {
     fn from(d: Raw<Dirent>) -> Pwd {
+        let clock = d.inode.clock.clone();
+        let space = d.inode.charge.space().clone();
         Pwd {
-            inner: d,
-            alive: true,
+            inner:   d,
+            root:    Some(d),
+            alive:   true,
+            ids:     Ids::default(),
+            clock,
+            space,
+            journal: Arc::default(),
         }
     }
 }
 
 /// `FileSystem` is a single in-memory filesystem that can be cloned and passed around safely. A
 /// single `FileSystem` must be unique. On drop, the entire filesystem is deleted.
+///
+/// The root directory is held in `pwd`, which is the current working directory.
 #[derive(Debug)]
 struct FileSystem {
-    // root exists for preemptive support for changing the current working directory. `cd` used to
-    // exist, but was removed after adding symlink support. `root` held on to the original root
-    // directory as needed.
-    root: Raw<Dirent>,
-    pwd:  Pwd,
+    pwd: Pwd,
 }
 
 impl Deref for FileSystem {
@@ -1236,13 +2376,13 @@ impl DerefMut for FileSystem {
 
 impl Drop for FileSystem {
     fn drop(&mut self) {
-        if !self.pwd.alive && Raw::ptr_eq(&self.root, &self.pwd.inner) {
-            // It appears we have dropped ourself already.
-            return;
-        }
+        let root = match self.pwd.root {
+            Some(root) => root,
+            None => return, // It appears we have dropped ourself already.
+        };
 
         let mut todo = Vec::new();
-        todo.push(self.root);
+        todo.push(root);
         while let Some(elem) = todo.pop() {
             let rs = unsafe { Box::from_raw(elem.ptr()) };
             if let DeKind::Dir(ref d) = rs.kind {
@@ -1258,18 +2398,33 @@ fn path_empty<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().as_os_str().is_empty()
 }
 
-// We claim that two filesystems are equal if they have the same structure, contents, and modes.
-// This should only be used for testing.
+// path_of returns the absolute path to fs by walking up to the root directory.
+fn path_of(mut fs: Raw<Dirent>) -> PathBuf {
+    let mut rev = Vec::new();
+    while !fs.name.is_empty() {
+        rev.push(fs.name.clone());
+        fs = fs.parent.expect("a non-root directory should have a parent, only root has no name");
+    }
+    rev.reverse();
+    let mut pb = PathBuf::from("/");
+    for p in rev {
+        pb.push(p)
+    }
+    pb
+}
+
+// We claim that two filesystems are equal if they have the same structure, contents, modes, and
+// extended attributes. This should only be used for testing.
 impl PartialEq for FileSystem {
     fn eq(&self, other: &Self) -> bool {
         fn eq_at(l: Raw<Dirent>, r: Raw<Dirent>) -> bool {
-            if l.inode.view() != r.inode.view() ||
+            if l.inode != r.inode ||
                 l.name != r.name {
                 return false;
             }
             match (&l.kind, &r.kind) {
-                (&DeKind::File(ref fl), &DeKind::File(ref fr)) => fl.read().data == fr.read().data,
-                (&DeKind::Dir(ref dl), &DeKind::Dir(ref dr)) => {
+                (DeKind::File(fl), DeKind::File(fr)) => fl.read().data == fr.read().data,
+                (DeKind::Dir(dl), DeKind::Dir(dr)) => {
                     if dl.len() != dr.len() {
                         return false;
                     }
@@ -1283,20 +2438,389 @@ impl PartialEq for FileSystem {
                     }
 					true
                 },
-                (&DeKind::Symlink(ref sl), &DeKind::Symlink(ref sr)) => sl == sr,
+                (DeKind::Symlink(sl), DeKind::Symlink(sr)) => sl == sr,
+                (DeKind::Special, DeKind::Special) => true,
                 _ => false,
             }
         }
 
-        if !self.pwd.alive || !other.pwd.alive {
-            panic!("invalid FileSystem PartialEq - both FileSystem's pwd must be alive!")
+        match (self.pwd.root, other.pwd.root) {
+            (Some(l), Some(r)) => eq_at(l, r),
+            _ => panic!("invalid FileSystem PartialEq - both FileSystem's root must exist!"),
+        }
+    }
+}
+
+impl FileSystem {
+    // copy deep copies our tree into a new FileSystem whose inodes use clock and are charged
+    // against space. Names that share an inode in our tree share the copied inode, and the copy's
+    // current directory is the copy of ours. The copy tracks crashes if we do, starting out
+    // synced.
+    fn copy(&self, clock: SharedClock, space: Arc<Space>) -> FileSystem {
+        struct Copier {
+            clock:  SharedClock,
+            space:  Arc<Space>,
+            inodes: HashMap<*const RwLock<InodeData>, Inode>,
+            files:  HashMap<*const RwLock<RawFile>, Arc<RwLock<RawFile>>>,
+            pwd:    Option<Raw<Dirent>>, // our current directory, if alive
+            copied: Option<Raw<Dirent>>, // the copy of pwd
+        }
+
+        impl Copier {
+            fn inode(&mut self, inode: &Inode, len: usize) -> Inode {
+                let (clock, space) = (&self.clock, &self.space);
+                self.inodes.entry(Arc::as_ptr(&inode.data)).or_insert_with(|| {
+                    // The copy is a new inode in space, but keeps the times and links of ours.
+                    let charge = Space::charge_over(space, len);
+                    let mut data = inode.view();
+                    data.dev = charge.dev();
+                    data.ino = charge.ino();
+                    Inode {
+                        data:   Arc::new(RwLock::new(data)),
+                        xattrs: Arc::new(RwLock::new(inode.xattrs.read().clone())),
+                        locks:  Arc::default(),
+                        clock:  clock.clone(),
+                        charge: Arc::new(charge),
+                    }
+                }).clone()
+            }
+
+            fn dirent(&mut self, d: Raw<Dirent>, parent: Option<Raw<Dirent>>) -> Raw<Dirent> {
+                let kind = match d.kind {
+                    DeKind::File(ref file) => {
+                        let ptr = Arc::as_ptr(file);
+                        if !self.files.contains_key(&ptr) {
+                            let raw = file.read();
+                            let copied = RawFile {
+                                data:     raw.data.clone(),
+                                inode:    self.inode(&raw.inode, raw.data.allocated()),
+                                linkable: raw.linkable,
+                            };
+                            self.files.insert(ptr, Arc::new(RwLock::new(copied)));
+                        }
+                        DeKind::File(self.files[&ptr].clone())
+                    }
+                    DeKind::Dir(_) => DeKind::Dir(HashMap::new()),
+                    DeKind::Symlink(ref sl) => DeKind::Symlink(sl.clone()),
+                    DeKind::Special => DeKind::Special,
+                };
+                let inode = match kind {
+                    DeKind::File(ref file) => file.read().inode.clone(),
+                    _ => self.inode(&d.inode, 0),
+                };
+                let mut copy = Raw::from(Dirent {
+                    parent,
+                    kind,
+                    name: d.name.clone(),
+                    inode,
+                });
+                if self.pwd.is_some_and(|pwd| Raw::ptr_eq(&pwd, &d)) {
+                    self.copied = Some(copy);
+                }
+                if let DeKind::Dir(ref children) = d.kind {
+                    for (name, child) in children {
+                        let child = self.dirent(*child, Some(copy));
+                        copy.kind.dir_mut().insert(name.clone(), child);
+                    }
+                }
+                copy
+            }
+        }
+
+        let root = match self.pwd.root {
+            Some(root) => root,
+            // With our root removed, the copy is just as unusable as we are, and crashes the same.
+            None => return FileSystem {
+                pwd: Pwd {
+                    inner:   self.pwd.inner,
+                    root:    None,
+                    alive:   false,
+                    ids:     self.pwd.ids,
+                    clock,
+                    space,
+                    journal: Arc::new(Mutex::new(self.journal.lock().clone())),
+                },
+            },
+        };
+        let mut copier = Copier {
+            clock:  clock.clone(),
+            space:  space.clone(),
+            inodes: HashMap::new(),
+            files:  HashMap::new(),
+            pwd:    if self.pwd.alive { Some(self.pwd.inner) } else { None },
+            copied: None,
+        };
+        let copy = copier.dirent(root, None);
+        let inner = copier.copied.unwrap_or(copy);
+        let mut pwd = Pwd {
+            inner,
+            root:    Some(copy),
+            alive:   self.pwd.alive,
+            ids:     self.pwd.ids,
+            clock,
+            space,
+            journal: Arc::default(),
+        };
+        pwd.track(self.journal.lock().is_some());
+        FileSystem { pwd }
+    }
+
+    // crashed builds what our tree would be after a crash of the given model, using our clock and
+    // unlimited space. Names that share an inode after the crash share the built inode.
+    fn crashed(&self, model: Crash) -> FileSystem {
+        // Built is a built inode, along with its file if it is one.
+        type Built = (Inode, Option<Arc<RwLock<RawFile>>>);
+
+        struct Builder<'a> {
+            rng:     Option<Rng>,
+            clock:   SharedClock,
+            space:   Arc<Space>,
+            entries: &'a HashMap<journal::Key, journal::Entries>,
+            nodes:   &'a HashMap<journal::Key, Durable>,
+            inodes:  HashMap<journal::Key, Built>,
+            dirs:    HashSet<journal::Key>, // the directories already built
+        }
+
+        impl<'a> Builder<'a> {
+            // keep returns whether to keep something that was not synced.
+            fn keep(&mut self) -> bool {
+                self.rng.as_mut().is_some_and(Rng::coin)
+            }
+
+            fn inode(&mut self, durable: &Durable) -> Built {
+                let live = self.keep();
+                let (mut meta, xattrs) = match (durable.live.upgrade(),
+                                                durable.live_xattrs.upgrade()) {
+                    (Some(meta), Some(xattrs)) if live => (*meta.read(), xattrs.read().clone()),
+                    _ => (durable.meta, durable.xattrs.clone()),
+                };
+                let data = match durable.kind {
+                    DurableKind::File(ref file) => {
+                        let live = self.keep();
+                        match file.upgrade() {
+                            Some(ref file) if live => file.read().data.clone(),
+                            _ => durable.data.clone(),
+                        }
+                    }
+                    _ => Data::default(),
+                };
+                // Links are counted as the inode is named.
+                let charge = Space::charge_over(&self.space, data.allocated());
+                meta.dev = charge.dev();
+                meta.ino = charge.ino();
+                meta.nlink = 0;
+                let inode = Inode {
+                    data:   Arc::new(RwLock::new(meta)),
+                    xattrs: Arc::new(RwLock::new(xattrs)),
+                    locks:  Arc::default(),
+                    clock:  self.clock.clone(),
+                    charge: Arc::new(charge),
+                };
+                match durable.kind {
+                    DurableKind::File(_) => {
+                        let file = RawFile { data, inode: inode.clone(), linkable: false };
+                        file.resized();
+                        (inode, Some(Arc::new(RwLock::new(file))))
+                    }
+                    _ => (inode, None),
+                }
+            }
+
+            fn dirent(&mut self, key: journal::Key, name: OsString, parent: Option<Raw<Dirent>>)
+                -> Option<Raw<Dirent>>
+            {
+                let nodes = self.nodes;
+                let durable = nodes.get(&key)?;
+                let is_dir = matches!(durable.kind, DurableKind::Dir);
+                // A directory can only have one name; a crash could otherwise leave a cycle.
+                if is_dir && !self.dirs.insert(key) {
+                    return None;
+                }
+                if !self.inodes.contains_key(&key) {
+                    let built = self.inode(durable);
+                    self.inodes.insert(key, built);
+                }
+                let (inode, file) = self.inodes[&key].clone();
+                let kind = match durable.kind {
+                    DurableKind::File(_) => DeKind::File(file.expect("files are built with data")),
+                    DurableKind::Dir => DeKind::Dir(HashMap::new()),
+                    DurableKind::Symlink(ref sl) => DeKind::Symlink(sl.clone()),
+                    DurableKind::Special => DeKind::Special,
+                };
+                let mut d = Raw::from(Dirent { parent, kind, name, inode });
+                if !is_dir {
+                    d.inode.write().nlink += 1;
+                    return Some(d);
+                }
+                let entries = self.entries;
+                let mut subdirs = 0;
+                for (name, child) in entries.get(&key).into_iter().flatten() {
+                    if let Some(child) = self.dirent(*child, name.clone(), Some(d)) {
+                        if child.is_dir() {
+                            subdirs += 1;
+                        }
+                        d.kind.dir_mut().insert(name.clone(), child);
+                    }
+                }
+                d.inode.write().nlink = 2 + subdirs;
+                Some(d)
+            }
         }
 
-        eq_at(self.root, other.root)
+        let mut rng = match model {
+            Crash::DropUnsynced => None,
+            Crash::KeepRandom(seed) => Some(Rng::new(seed)),
+        };
+        // We clone what can be reached after the crash so that live files are read, for a crash
+        // that may keep their unsynced data, without the journal locked.
+        let (root, entries, nodes) = {
+            let journal = self.journal.lock();
+            let journal = journal.as_ref()
+                                 .expect("crashes are only tracked by FS::with_crash_tracking");
+            let entries = journal.crashed(|| rng.as_mut().is_some_and(Rng::coin));
+            let mut nodes = HashMap::new();
+            let mut todo = vec![journal.root()];
+            while let Some(key) = todo.pop() {
+                if nodes.contains_key(&key) {
+                    continue;
+                }
+                if let Some(durable) = journal.get(key) {
+                    if let DurableKind::Dir = durable.kind {
+                        todo.extend(entries.get(&key).into_iter().flat_map(|dir| dir.values()));
+                    }
+                    nodes.insert(key, durable.clone());
+                }
+            }
+            (journal.root(), entries, nodes)
+        };
+        let mut builder = Builder {
+            rng,
+            clock:   self.clock.clone(),
+            space:   Arc::new(Space::default()),
+            entries: &entries,
+            nodes:   &nodes,
+            inodes:  HashMap::new(),
+            dirs:    HashSet::new(),
+        };
+        let root = builder.dirent(root, OsString::from(""), None)
+                          .expect("the journal always knows our root");
+        let mut pwd = Pwd::from(root);
+        pwd.track(true);
+        FileSystem { pwd }
+    }
+
+    // nodes lists our tree for exporting, ignoring permissions. Every file is given a link so
+    // that hard links stay hard links.
+    fn nodes(&self) -> Result<Vec<host::Node>> {
+        let root = self.pwd.root.ok_or_else(EINVAL)?;
+        let mut nodes = Vec::new();
+        let mut todo = vec![(PathBuf::new(), root)];
+        while let Some((path, d)) = todo.pop() {
+            let kind = match d.kind {
+                DeKind::Dir(ref children) => {
+                    let mut children: Vec<_> = children.iter().collect();
+                    children.sort_by(|l, r| l.0.cmp(r.0));
+                    todo.extend(children.into_iter()
+                                        .rev()
+                                        .map(|(name, child)| (path.join(name), *child)));
+                    host::Kind::Dir
+                }
+                DeKind::File(ref file) => host::Kind::File(file.read().data.clone()),
+                DeKind::Symlink(ref sl) => host::Kind::Symlink {
+                    target: sl.clone(),
+                    dir:    self.pwd.metadata(path_of(d), &mut 0).is_ok_and(|m| m.is_dir()),
+                },
+                DeKind::Special => {
+                    let inode = d.inode.view();
+                    host::Kind::Special {
+                        ftyp: match inode.ftyp.0 {
+                            Ftyp::Fifo => host::Special::Fifo,
+                            Ftyp::Socket => host::Special::Socket,
+                            Ftyp::BlockDevice => host::Special::BlockDevice,
+                            _ => host::Special::CharDevice,
+                        },
+                        rdev: inode.rdev,
+                    }
+                }
+            };
+            let inode = d.inode.view();
+            nodes.push(host::Node {
+                link:     match kind {
+                    host::Kind::File(_) => Some((0, Arc::as_ptr(&d.inode.data) as u64)),
+                    _ => None,
+                },
+                path,
+                kind,
+                mode:     inode.perms.0,
+                accessed: Some(inode.times.accessed),
+                modified: Some(inode.times.modified),
+            });
+        }
+        Ok(nodes)
     }
 }
 
 impl Pwd {
+    // at returns an ephemeral Pwd at d with our ids, clock, and space.
+    fn at(&self, d: Raw<Dirent>) -> Pwd {
+        Pwd {
+            inner:   d,
+            root:    self.root,
+            alive:   true,
+            ids:     self.ids,
+            clock:   self.clock.clone(),
+            space:   self.space.clone(),
+            journal: self.journal.clone(),
+        }
+    }
+
+    // track starts tracking crashes with the whole tree synced if on, or stops tracking them if
+    // not. Files that are already open keep syncing to the journal they were opened with.
+    fn track(&mut self, on: bool) {
+        let journal = match self.root {
+            Some(root) if on => Some(synced(root)),
+            _ => None,
+        };
+        self.journal = Arc::new(Mutex::new(journal));
+    }
+
+    // kill_if_pwd invalidates us if removed, which is about to be freed, is our current directory.
+    fn kill_if_pwd(&mut self, removed: &Raw<Dirent>) {
+        if self.alive && Raw::ptr_eq(removed, &self.inner) {
+            self.alive = false;
+        }
+    }
+
+    // new_inode returns an inode owned by our ids and using our clock, failing with ENOSPC if our
+    // space has no inodes left.
+    fn new_inode(&self, mode: u32, ftyp: Ftyp, len: usize) -> Result<Inode> {
+        let inode = Inode::create(mode, ftyp, len, self.clock.clone(), Space::charge(&self.space)?);
+        {
+            let mut data = inode.write();
+            data.uid = self.ids.uid;
+            data.gid = self.ids.gid;
+        }
+        Ok(inode)
+    }
+
+    // log records that the names in each directory changed together and have not been synced,
+    // describing any inode they now name that the journal does not know yet.
+    fn log(&self, names: &[(Raw<Dirent>, &OsString)]) {
+        let mut journal = self.journal.lock();
+        let journal = match *journal {
+            Some(ref mut journal) => journal,
+            None => return,
+        };
+        let bindings = names.iter().map(|&(dir, name)| {
+            let key = dir.kind.dir_ref().get(name).map(|child| {
+                journal.node(child.inode.key(), || Durable::of(child));
+                child.inode.key()
+            });
+            Binding { dir: dir.inode.key(), name: name.clone(), key }
+        }).collect();
+        journal.change(bindings);
+    }
+
     // up_path traverses up parent directories in a normalized path, erroring if we cannot cd into
     // (exec) the parent directory. Filesystem operations work relative to their canonicalized
     // path, even if we are inside a symlink. Changing directories does not change their atime.
@@ -1304,22 +2828,19 @@ impl Pwd {
                parts: Parts)
                -> Result<(Raw<Dirent>, Vec<Part>)> {
         // up_path is the point of entry for every function in pwd. Conveniently, this also means
-        // this is the only location we need to check if our filesystem has been removed from under
-        // us via a remove_dir_all.
+        // this is the only location we need to check if our filesystem or our current directory
+        // has been removed from under us.
+        let root = self.root.ok_or_else(EINVAL)?;
+        // If (normalized) parts begins at root, there are no ParentDirs. We do not need executable
+        // privileges to return the root directory.
+        if parts.at_root {
+            return Ok((root, parts.inner));
+        }
         if !self.alive {
-            return Err(EINVAL());
+            return Err(ENOENT());
         }
         // `up` is what we return: the dirent after traversing up all ParentDirs in `parts`.
         let mut up = self.inner;
-        // If (normalized) parts begins at root, there are no ParentDirs. We do not need executable
-        // privileges to return the root directory, even though in this code, we have to traverse
-        // up to it.
-        if parts.at_root {
-            while let Some(parent) = up.parent {
-                up = parent;
-            }
-            return Ok((up, parts.inner));
-        }
 
         let mut parts_iter = parts.inner.into_iter().peekable();
         while parts_iter.peek()
@@ -1327,7 +2848,7 @@ impl Pwd {
                         .is_some() {
             parts_iter.next();
             if let Some(parent) = up.parent {
-                if !parent.executable() {
+                if !parent.executable(self.ids) {
                     return Err(EACCES());
                 }
                 up = parent;
@@ -1360,14 +2881,14 @@ impl Pwd {
             match on.kind {
                 DeKind::Dir(ref children) => {
                     // If there are not two more entries, we are just before the base. Return.
-                    if !parts_iter.peek2().is_some() {
+                    if parts_iter.peek2().is_none() {
                         match parts_iter.next() {
                             Some(Part::Normal(base)) => return Ok((fs, Some(base))),
                             _ => return Ok((fs, None)),
                         }
                     }
                     // Change into the directory if we can and it exists.
-                    if !fs.executable() {
+                    if !fs.executable(self.ids) {
                         return Err(EACCES());
                     }
                     fs = children.get(parts_iter
@@ -1383,7 +2904,7 @@ impl Pwd {
                     // We traverse the symlink before we can continue with parts_iter. We traverse
                     // _from_ its parent, and we must set fs to the traversed fs when it is done.
                     fs = fs.parent.expect("symlinks should always have a parent");
-                    let (new_fs, may_base) = Pwd::from(fs).traverse(normalize(sl), level)?;
+                    let (new_fs, may_base) = self.at(fs).traverse(normalize(sl), level)?;
                     fs = new_fs;
                     match may_base {
                         Some(base) => {
@@ -1427,19 +2948,6 @@ impl Pwd {
     //
     // This function is the _only_ reason Dirent's have `name`.
     fn canonicalize<P: AsRef<Path>>(&self, path: P, level: &mut u8) -> Result<PathBuf> {
-        fn build_from_fs(mut fs: Raw<Dirent>) -> Result<PathBuf> {
-            let mut rev = Vec::new();
-            while !fs.name.is_empty() {
-                rev.push(fs.name.clone());
-                fs = fs.parent.expect("a non-root directory should have a parent, only root has no name");
-            }
-            rev.reverse();
-            let mut pb = PathBuf::from("/");
-            for p in rev {
-                pb.push(p)
-            }
-            Ok(pb)
-        }
         let (fs, may_base) = self.traverse(normalize(&path), level)?;
         let base = match may_base {
             Some(base) => base,
@@ -1447,14 +2955,66 @@ impl Pwd {
                 if path_empty(&path) {
                     return Err(ENOENT());
                 }
-                return build_from_fs(fs);
+                return Ok(path_of(fs));
             }
         };
 
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
+            return Err(EACCES());
+        }
+
+        let parent = fs;
+        match fs.kind.dir_ref().get(&base) {
+            Some(child) => {
+                if let DeKind::Symlink(ref sl) = child.kind {
+                    if {*level += 1; *level} == 40 {
+                        return Err(ELOOP());
+                    }
+                    return self.at(parent).canonicalize(sl, level);
+                }
+                Ok(path_of(*child))
+            }
+            None => Err(ENOENT()),
+        }
+    }
+
+    // current_dir returns the path to our current directory, which fails if it has been removed.
+    fn current_dir(&self) -> Result<PathBuf> {
+        if self.root.is_none() {
+            return Err(EINVAL());
+        }
+        if !self.alive {
+            return Err(ENOENT());
+        }
+        Ok(path_of(self.inner))
+    }
+
+    // set_current_dir implements chdir, traversing symlinks as necessary. Like canonicalize, our
+    // new current directory is where symlinks lead, not the symlinks themselves.
+    fn set_current_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let dir = self.dir_at(path, &mut 0)?;
+        if !dir.executable(self.ids) {
             return Err(EACCES());
         }
+        self.inner = dir;
+        self.alive = true;
+        Ok(())
+    }
 
+    // dir_at returns the directory at path, following symlinks.
+    fn dir_at<P: AsRef<Path>>(&self, path: P, level: &mut u8) -> Result<Raw<Dirent>> {
+        let (fs, may_base) = self.traverse(normalize(&path), level)?;
+        let base = match may_base {
+            Some(base) => base,
+            None => if path_empty(&path) {
+                return Err(ENOENT());
+            } else {
+                return Ok(fs); // either the root dir or a parent dir
+            }
+        };
+        if !fs.executable(self.ids) {
+            return Err(EACCES());
+        }
         let parent = fs;
         match fs.kind.dir_ref().get(&base) {
             Some(child) => {
@@ -1462,9 +3022,12 @@ impl Pwd {
                     if {*level += 1; *level} == 40 {
                         return Err(ELOOP());
                     }
-                    return Pwd::from(parent).canonicalize(sl, level);
+                    return self.at(parent).dir_at(sl, level);
                 }
-                build_from_fs(*child)
+                if !child.is_dir() {
+                    return Err(ENOTDIR());
+                }
+                Ok(*child)
             }
             None => Err(ENOENT()),
         }
@@ -1485,7 +3048,7 @@ impl Pwd {
             }
         };
 
-        if !fs.changeable() {
+        if !fs.changeable(self.ids) {
             return Err(EACCES());
         }
 
@@ -1502,9 +3065,11 @@ impl Pwd {
                 v.insert(Raw::from(Dirent {
                     parent: Some(parent),
                     kind:   DeKind::Dir(HashMap::new()),
-                    name:   base,
-                    inode:  Inode::new(mode, Ftyp::Dir, DIRLEN),
+                    name:   base.clone(),
+                    inode:  self.new_inode(mode, Ftyp::Dir, DIRLEN)?,
                 }));
+                parent.inode.link(); // the new directory's ..
+                self.log(&[(parent, &base)]);
                 Ok(())
             }
         }
@@ -1532,14 +3097,14 @@ impl Pwd {
         // - dst file must not exist
         // - dst must be writable
         // - src must be file
-        if !src_fs.executable() {
+        if !src_fs.executable(self.ids) {
             return Err(EACCES());
         }
 
         let (mut dst_fs, dst_may_base) = self.traverse(normalize(&dst), &mut 0)?;
         let dst_base = dst_may_base.ok_or_else(EEXIST)?;
 
-        if !dst_fs.executable() {
+        if !dst_fs.executable(self.ids) {
             return Err(EACCES());
         }
         let src_child = match src_fs.kind.dir_ref().get(&src_base) {
@@ -1549,38 +3114,74 @@ impl Pwd {
         if dst_fs.kind.dir_ref().get(&dst_base).is_some() {
             return Err(EEXIST());
         }
-        if !dst_fs.writable() {
+        if !dst_fs.writable(self.ids) {
             return Err(EACCES());
         }
 
+        let kind = match src_child.kind {
+            DeKind::Dir(_) => return Err(EPERM()),
+            DeKind::Special => DeKind::Special,
+            DeKind::Symlink(ref sl) => DeKind::Symlink(sl.clone()),
+            DeKind::File(ref f) => DeKind::File(f.clone()),
+        };
         let parent = dst_fs;
-        match src_child.kind {
-            DeKind::Dir(_) => Err(EPERM()),
-            DeKind::Symlink(ref sl) => {
-                dst_fs.kind
-                      .dir_mut()
-                      .insert(dst_base.clone(),
-                              Raw::from(Dirent {
-                                  parent: Some(parent),
-                                  kind:   DeKind::Symlink(sl.clone()),
-                                  name:   dst_base,
-                                  inode:  src_child.inode.clone(),
-                              }));
-                Ok(())
-            }
-            DeKind::File(ref f) => {
-                dst_fs.kind
-                      .dir_mut()
-                      .insert(dst_base.clone(),
-                              Raw::from(Dirent {
-                                  parent: Some(parent),
-                                  kind:   DeKind::File(f.clone()),
-                                  name:   dst_base,
-                                  inode:  src_child.inode.clone(),
-                              }));
-                Ok(())
-            }
+        dst_fs.kind
+              .dir_mut()
+              .insert(dst_base.clone(),
+                      Raw::from(Dirent {
+                          parent: Some(parent),
+                          kind,
+                          name:   dst_base.clone(),
+                          inode:  src_child.inode.clone(),
+                      }));
+        src_child.inode.link();
+        self.log(&[(parent, &dst_base)]);
+        Ok(())
+    }
+
+    // link_file names an open file like hard_link names the file at a path. A file with no links
+    // can only be named if it was opened with O_TMPFILE and has never been named.
+    fn link_file<P: AsRef<Path>>(&self, file: &File, dst: P) -> Result<()> {
+        // Like hard_link, directories cannot be linked.
+        if file.dir {
+            return Err(EPERM());
+        }
+        let file = file.cursor.lock().file.clone();
+        if !Arc::ptr_eq(file.read().inode.charge.space(), &self.space) {
+            return Err(EXDEV());
         }
+
+        let (mut dst_fs, dst_may_base) = self.traverse(normalize(&dst), &mut 0)?;
+        let dst_base = dst_may_base.ok_or_else(EEXIST)?;
+
+        if !dst_fs.executable(self.ids) {
+            return Err(EACCES());
+        }
+        if dst_fs.kind.dir_ref().get(&dst_base).is_some() {
+            return Err(EEXIST());
+        }
+        if !dst_fs.writable(self.ids) {
+            return Err(EACCES());
+        }
+
+        let mut raw = file.write();
+        if raw.inode.read().nlink == 0 && !raw.linkable {
+            return Err(ENOENT());
+        }
+        raw.linkable = false;
+        let parent = dst_fs;
+        dst_fs.kind
+              .dir_mut()
+              .insert(dst_base.clone(),
+                      Raw::from(Dirent {
+                          parent: Some(parent),
+                          kind:   DeKind::File(file.clone()),
+                          name:   dst_base.clone(),
+                          inode:  raw.inode.clone(),
+                      }));
+        raw.inode.link();
+        self.log(&[(parent, &dst_base)]);
+        Ok(())
     }
 
     // symlink itself is an incredibly easy function to implement, so long as all the scaffolding
@@ -1589,7 +3190,7 @@ impl Pwd {
         let (mut dst_fs, dst_may_base) = self.traverse(normalize(&dst), &mut 0)?;
         let dst_base = dst_may_base.ok_or_else(EEXIST)?;
 
-        if !dst_fs.changeable() {
+        if !dst_fs.changeable(self.ids) {
             return Err(EACCES());
         }
 
@@ -1606,9 +3207,63 @@ impl Pwd {
                       Raw::from(Dirent {
                           parent: Some(parent),
                           kind:   DeKind::Symlink(sl),
-                          name:   dst_base,
-                          inode:  Inode::new(0o777, Ftyp::Symlink, len),
+                          name:   dst_base.clone(),
+                          inode:  self.new_inode(0o777, Ftyp::Symlink, len)?,
+                      }));
+        self.log(&[(parent, &dst_base)]);
+        Ok(())
+    }
+
+    // mknod creates a regular or special file like symlink creates a symlink. The file type bits
+    // of mode are checked before anything else, and only root can create devices.
+    fn mknod<P: AsRef<Path>>(&self, path: P, mode: u32, dev: u64) -> Result<()> {
+        let ftyp = match mode & 0o170000 {
+            0 | 0o100000 => Ftyp::File,
+            0o010000 => Ftyp::Fifo,
+            0o140000 => Ftyp::Socket,
+            0o060000 => Ftyp::BlockDevice,
+            0o020000 => Ftyp::CharDevice,
+            _ => return Err(EINVAL()),
+        };
+
+        let (mut dst_fs, dst_may_base) = self.traverse(normalize(&path), &mut 0)?;
+        let dst_base = dst_may_base.ok_or_else(EEXIST)?;
+
+        if !dst_fs.changeable(self.ids) {
+            return Err(EACCES());
+        }
+        if dst_fs.kind.dir_ref().get(&dst_base).is_some() {
+            return Err(EEXIST());
+        }
+        let device = ftyp == Ftyp::BlockDevice || ftyp == Ftyp::CharDevice;
+        if device && self.ids.uid != 0 {
+            return Err(EPERM());
+        }
+
+        let inode = self.new_inode(mode & 0o7777, ftyp, 0)?;
+        let kind = if ftyp == Ftyp::File {
+            DeKind::File(Arc::new(RwLock::new(RawFile {
+                data:     Data::default(),
+                inode:    inode.clone(),
+                linkable: false,
+            })))
+        } else {
+            if device {
+                inode.write().rdev = dev;
+            }
+            DeKind::Special
+        };
+        let parent = dst_fs;
+        dst_fs.kind
+              .dir_mut()
+              .insert(dst_base.clone(),
+                      Raw::from(Dirent {
+                          parent: Some(parent),
+                          kind,
+                          name:   dst_base.clone(),
+                          inode,
                       }));
+        self.log(&[(parent, &dst_base)]);
         Ok(())
     }
 
@@ -1635,7 +3290,7 @@ impl Pwd {
             }
         };
 
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
             return Err(EACCES());
         }
 
@@ -1647,7 +3302,7 @@ impl Pwd {
                     if {*level += 1; *level} == 40 {
                         return Err(ELOOP());
                     }
-                    return Pwd::from(parent).metadata(child.kind.symlink_ref(), level);
+                    return self.at(parent).metadata(child.kind.symlink_ref(), level);
                 }
                 Ok(Metadata(meta))
             }
@@ -1667,11 +3322,11 @@ impl Pwd {
             None => if path_empty(&path) {
                 return Err(ENOENT());
             } else { // path resolved to root or parent paths
-                return ReadDir::new(&og_path, &*fs);
+                return ReadDir::new(&og_path, &fs, self.ids);
             }
         };
 
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
             return Err(EACCES());
         }
 
@@ -1683,10 +3338,10 @@ impl Pwd {
                     if {*level += 1; *level} == 40 {
                         return Err(ELOOP());
                     }
-                    return Pwd::from(parent).read_dir(og_path, sl, level);
+                    return self.at(parent).read_dir(og_path, sl, level);
                 }
                 // Otherwise we ReadDir whatever this is - ReadDir::new handles ENOTDIR.
-                ReadDir::new(&og_path, &*child)
+                ReadDir::new(&og_path, child, self.ids)
             },
             None => Err(ENOENT()),
         }
@@ -1701,7 +3356,7 @@ impl Pwd {
                 EINVAL()
             })?;
 
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
             return Err(EACCES());
         }
         match fs.kind.dir_ref().get(&base) {
@@ -1713,7 +3368,7 @@ impl Pwd {
         }
     }
 
-    fn remove<P: AsRef<Path>>(&self, path: P, kind: FileType) -> Result<()> {
+    fn remove<P: AsRef<Path>>(&mut self, path: P, kind: FileType) -> Result<()> {
         let (mut fs, may_base) = self.traverse(normalize(&path), &mut 0)?;
         let base = may_base.ok_or_else(||
             if path_empty(&path) {
@@ -1726,7 +3381,7 @@ impl Pwd {
         // We need to make sure that the FileType being requested for removal matches the FileType
         // of the directory. Scope this check so child drops before we mutate it.
         {
-            if !fs.changeable() {
+            if !fs.changeable(self.ids) {
                 return Err(EACCES());
             }
             let child = fs.kind
@@ -1735,8 +3390,7 @@ impl Pwd {
                           .ok_or_else(ENOENT)?;
 
             match kind.0 {
-                // Symlinks and files are just simply removed, but directories must be empty.
-                Ftyp::File | Ftyp::Symlink => if child.is_dir() { return Err(EISDIR()); },
+                // Directories must be empty, but everything else is just simply removed.
                 Ftyp::Dir => {
                     if !child.is_dir() {
                         return Err(ENOTDIR());
@@ -1745,6 +3399,7 @@ impl Pwd {
                         return Err(ENOTEMPTY());
                     }
                 },
+                _ => if child.is_dir() { return Err(EISDIR()); },
             }
         }
 
@@ -1753,14 +3408,17 @@ impl Pwd {
                         .dir_mut()
                         .remove(&base)
                         .expect("remove logic checking existence is wrong");
-        // We cannot remove a directory underneath pwd, so we are fine.
-        unsafe { Box::from_raw(removed.ptr()); }
+        removed.unlinked(Some(fs));
+        // The removed directory may be our (necessarily empty) current directory.
+        self.kill_if_pwd(&removed);
+        unsafe { drop(Box::from_raw(removed.ptr())); }
+        self.log(&[(fs, &base)]);
         Ok(())
     }
-    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         self.remove(path, FileType(Ftyp::File))
     }
-    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    fn remove_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         self.remove(path, FileType(Ftyp::Dir))
     }
 
@@ -1771,16 +3429,12 @@ impl Pwd {
         //
         // We will do this by comparing every deleted dirent to our pwd, and, if one is our pwd, we
         // will invalidate ourself.
-        fn maybe_kill_self(pwd: &mut Pwd, removed: &Raw<Dirent>) {
-            if pwd.alive && Raw::ptr_eq(removed, &pwd.inner) {
-                pwd.alive = false;
-            }
-        }
         // Rust's remove_dir_all is actually very weak (weaker than rm -r). Rust relies on read_dir
         // to recurse, which requires `ls`. Standard linux is able to remove empty directories with
         // only write and execute privileges. This code attempts to mimic what Rust will do.
         fn recursive_remove(pwd: &mut Pwd, mut fs: Raw<Dirent>) -> Result<()> {
-            let accessible = fs.rremovable();
+            let accessible = fs.rremovable(pwd.ids);
+            let parent = fs;
             if let DeKind::Dir(ref mut children) = fs.kind { // symlinks & files are simply removed
                 if !accessible {
                     return Err(EACCES());
@@ -1810,8 +3464,9 @@ impl Pwd {
                 for child_name in deleted {
                     let removed = children.remove(&child_name)
                                           .expect("deleted has child_name not in child map");
-                    maybe_kill_self(pwd, &removed);
-                    unsafe { Box::from_raw(removed.ptr()); } // free the memory
+                    removed.unlinked(Some(parent));
+                    pwd.kill_if_pwd(&removed);
+                    unsafe { drop(Box::from_raw(removed.ptr())); } // free the memory
                 }
                 res?
             }
@@ -1822,37 +3477,61 @@ impl Pwd {
         let (mut fs, may_base) = self.traverse(normalize(&path), &mut 0)?;
         match may_base {
             Some(base) => { // removing a non-root path
-                if !fs.changeable() {
+                if !fs.changeable(self.ids) {
                     return Err(EACCES());
                 }
-                if let Entry::Occupied(child) = fs.kind.dir_mut().entry(base) {
+                // Only the removal of the top entry is logged: whatever survives a crash under it
+                // is unreachable unless that removal is lost too.
+                let parent = fs;
+                if let Entry::Occupied(child) = fs.kind.dir_mut().entry(base.clone()) {
                     recursive_remove(self, *child.get())?;
-                    maybe_kill_self(self, child.get());
-                    unsafe { Box::from_raw(child.remove().ptr()); }
+                    child.get().unlinked(Some(parent));
+                    self.kill_if_pwd(child.get());
+                    unsafe { drop(Box::from_raw(child.remove().ptr())); }
+                    self.log(&[(parent, &base)]);
                 }
             }
             None => { // removing either a direct parent directory or everything under root.
                 if path_empty(&path) {
                     return Err(ENOENT());
                 }
+                if fs.parent.is_some_and(|parent| !parent.changeable(self.ids)) {
+                    return Err(EACCES());
+                }
                 recursive_remove(self, fs)?;
-                maybe_kill_self(self, &fs);
-                unsafe { Box::from_raw(fs.ptr()); }
+                fs.unlinked(fs.parent);
+                self.kill_if_pwd(&fs);
+                match fs.parent {
+                    Some(mut parent) => {
+                        parent.kind.dir_mut().remove(&fs.name);
+                        self.log(&[(parent, &fs.name)]);
+                    }
+                    None => self.root = None,
+                }
+                unsafe { drop(Box::from_raw(fs.ptr())); }
             }
         }
         Ok(())
     }
 
-    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+    // rename implements both plain renames and renames with flags, which either refuse to replace
+    // an existing entry or exchange two entries.
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q, flags: u32)
+        -> Result<()>
+    {
+        let (noreplace, exchange) = match flags {
+            0 => (false, false),
+            unix_ext::RENAME_NOREPLACE => (true, false),
+            unix_ext::RENAME_EXCHANGE => (false, true),
+            _ => return Err(EINVAL()),
+        };
+
         let (mut old_fs, old_may_base) = self.traverse(normalize(&from), &mut 0)?;
         let old_base = old_may_base.ok_or_else(||
             if path_empty(&from) {
                 ENOENT()
-            } else if old_fs.parent.is_some() {
-                EBUSY() // renaming through parent directories returns EBUSY
             } else {
-                // I really don't want to support this, nor manually test what can happen.
-                Error::new(ErrorKind::Other, "rename of root unimplemented")
+                EBUSY() // renaming root or through parent directories returns EBUSY
             })?;
         let (mut new_fs, new_may_base) = self.traverse(normalize(&to), &mut 0)?;
         let new_base = new_may_base.ok_or_else(||
@@ -1864,38 +3543,70 @@ impl Pwd {
                 EEXIST()
             })?;
 
-        if Raw::ptr_eq(&old_fs, &new_fs) && old_base == new_base {
+        if Raw::ptr_eq(&old_fs, &new_fs) && old_base == new_base && !noreplace {
             return Ok(());
         }
 
         // The error order appears to be:
         // - both dirs must be executable
         // - file must exist
+        // - with RENAME_NOREPLACE, the new file must not exist, and with RENAME_EXCHANGE, it must
+        // - a directory cannot move into itself
         // - both dirs must be writable
-        // - files must be the same type (and for directories, empty)
+        // - files must be the same type (and for directories, empty), unless exchanged
 
-        if !old_fs.executable() || !new_fs.executable() {
+        if !old_fs.executable(self.ids) || !new_fs.executable(self.ids) {
             return Err(EACCES());
         }
 
         // Rust's rename is strong, but also annoying, in that it can rename a directory to a
         // directory if that directory is empty. We could make the code elegant, but this will do.
-        let (old_exist, old_is_dir) =
-            match old_fs.kind.dir_ref().get(&old_base) {
-                Some(child) => (true, child.is_dir()),
-                None => (false, false),
-            };
+        let old_child = match old_fs.kind.dir_ref().get(&old_base) {
+            Some(child) => *child,
+            None => return Err(ENOENT()),
+        };
+        let old_is_dir = old_child.is_dir();
 
-        if !old_exist {
+        let new_child = new_fs.kind.dir_ref().get(&new_base).cloned();
+        if noreplace && new_child.is_some() {
+            return Err(EEXIST());
+        }
+        if exchange && new_child.is_none() {
             return Err(ENOENT());
         }
+        if new_fs.descends_from(old_child) ||
+            exchange && new_child.is_some_and(|new_child| old_fs.descends_from(new_child)) {
+            return Err(EINVAL());
+        }
 
-        if !old_fs.writable() || !new_fs.writable() {
+        if !old_fs.writable(self.ids) || !new_fs.writable(self.ids) {
             return Err(EACCES());
         }
 
+        if exchange {
+            let (mut renamed, mut replaced) =
+                (old_child, new_child.expect("logic verifying dirent existence is wrong"));
+            if old_is_dir != replaced.is_dir() && !Raw::ptr_eq(&old_fs, &new_fs) {
+                // Only one directory's .. moves, from its old parent to the other.
+                let (from_fs, to_fs) =
+                    if old_is_dir { (old_fs, new_fs) } else { (new_fs, old_fs) };
+                from_fs.inode.unlink();
+                to_fs.inode.link();
+            }
+            renamed.name = new_base.clone();
+            renamed.parent = Some(new_fs);
+            renamed.inode.touch(MODIFIED|ACCESSED|CREATED);
+            replaced.name = old_base.clone();
+            replaced.parent = Some(old_fs);
+            replaced.inode.touch(MODIFIED|ACCESSED|CREATED);
+            old_fs.kind.dir_mut().insert(old_base.clone(), replaced);
+            new_fs.kind.dir_mut().insert(new_base.clone(), renamed);
+            self.log(&[(old_fs, &old_base), (new_fs, &new_base)]);
+            return Ok(());
+        }
+
         let (new_exist, new_is_dir, new_is_empty) =
-            match new_fs.kind.dir_ref().get(&new_base) {
+            match new_child {
                 Some(child) => match child.kind {
                     DeKind::Dir(ref children) => (true, true, children.is_empty()),
                     _ => (true, false, false),
@@ -1920,9 +3631,21 @@ impl Pwd {
         let mut renamed = old_fs.kind.dir_mut().remove(&old_base)
                             .expect("logic verifying dirent existence is wrong");
         renamed.name = new_base.clone();
-        let mut inode = renamed.inode.write();
-        inode.times.update(MODIFIED|ACCESSED|CREATED);
-        new_fs.kind.dir_mut().insert(new_base, renamed);
+        renamed.parent = Some(new_fs);
+        renamed.inode.touch(MODIFIED|ACCESSED|CREATED);
+        if old_is_dir && !Raw::ptr_eq(&old_fs, &new_fs) {
+            // The renamed directory's .. moves from its old parent to its new one.
+            old_fs.inode.unlink();
+            new_fs.inode.link();
+        }
+        if let Some(replaced) = new_fs.kind.dir_mut().insert(new_base.clone(), renamed) {
+            // The replaced dirent is either a file, a symlink, or an empty directory, which may be
+            // our current directory.
+            replaced.unlinked(Some(new_fs));
+            self.kill_if_pwd(&replaced);
+            unsafe { drop(Box::from_raw(replaced.ptr())); }
+        }
+        self.log(&[(old_fs, &old_base), (new_fs, &new_base)]);
         Ok(())
     }
 
@@ -1930,7 +3653,7 @@ impl Pwd {
     //
     // This function takes a recursion level as it may be recursive if the end of path is a symlink.
     fn set_permissions<P: AsRef<Path>>(&self, path: P, perms: Permissions, level: &mut u8) -> Result<()> {
-        let (mut fs, may_base) = self.traverse(normalize(&path), level)?;
+        let (fs, may_base) = self.traverse(normalize(&path), level)?;
         let base = match may_base {
             Some(base) => base,
             None => if path_empty(&path) {
@@ -1938,11 +3661,10 @@ impl Pwd {
             } else {
                 // Symlinks are always 0o777. If traverse returns no base, path resolved to
                 // either the root directory or a parent directory - we can set perms.
-                fs.inode.write().perms = perms;
-                return Ok(());
+                return self.set_inode_perms(&fs.inode, perms);
             }
         };
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
             return Err(EACCES());
         }
         let parent = fs;
@@ -1953,16 +3675,115 @@ impl Pwd {
                     if {*level += 1; *level} == 40 {
                         return Err(ELOOP());
                     }
-                    return Pwd::from(parent).set_permissions(sl, perms, level);
+                    return self.at(parent).set_permissions(sl, perms, level);
                 }
-                let mut child = *child; // copy out of the borrow
-                child.inode.write().perms = perms;
-                Ok(())
+                self.set_inode_perms(&child.inode, perms)
+            }
+            None => Err(ENOENT()),
+        }
+    }
+
+    // set_inode_perms sets the permissions of an inode if we own it or are root.
+    fn set_inode_perms(&self, inode: &Inode, perms: Permissions) -> Result<()> {
+        if !inode.owned_by(self.ids) {
+            return Err(EPERM());
+        }
+        inode.set_perms(perms);
+        Ok(())
+    }
+
+    // chown implements chown, traversing symlinks as necessary, exactly like set_permissions.
+    fn chown<P: AsRef<Path>>(&self, path: P, uid: Option<u32>, gid: Option<u32>, level: &mut u8)
+        -> Result<()>
+    {
+        let (fs, may_base) = self.traverse(normalize(&path), level)?;
+        let base = match may_base {
+            Some(base) => base,
+            None => if path_empty(&path) {
+                return Err(ENOENT());
+            } else {
+                return self.chown_inode(&fs.inode, uid, gid);
+            }
+        };
+        if !fs.executable(self.ids) {
+            return Err(EACCES());
+        }
+        let parent = fs;
+        match fs.kind.dir_ref().get(&base) {
+            Some(child) => {
+                if let DeKind::Symlink(ref sl) = child.kind {
+                    if {*level += 1; *level} == 40 {
+                        return Err(ELOOP());
+                    }
+                    return self.at(parent).chown(sl, uid, gid, level);
+                }
+                self.chown_inode(&child.inode, uid, gid)
+            }
+            None => Err(ENOENT()),
+        }
+    }
+
+    // chown_inode changes the owner and group of an inode if our ids are allowed to: root can do
+    // anything, while an owner can only change the group to its own group.
+    fn chown_inode(&self, inode: &Inode, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let now = inode.clock.now();
+        let mut inode = inode.write();
+        let owner_only = inode.uid == self.ids.uid &&
+            uid.map_or(true, |uid| uid == inode.uid) &&
+            gid.map_or(true, |gid| gid == inode.gid || gid == self.ids.gid);
+        if self.ids.uid != 0 && !owner_only {
+            return Err(EPERM());
+        }
+        if let Some(uid) = uid {
+            inode.uid = uid;
+        }
+        if let Some(gid) = gid {
+            inode.gid = gid;
+        }
+        inode.times.update(CHANGED, now);
+        Ok(())
+    }
+
+    // set_times implements utimensat, traversing symlinks as necessary, exactly like
+    // set_permissions.
+    fn set_times<P: AsRef<Path>>(&self, path: P, times: fs::FileTimes, level: &mut u8) -> Result<()> {
+        let (fs, may_base) = self.traverse(normalize(&path), level)?;
+        let base = match may_base {
+            Some(base) => base,
+            None => if path_empty(&path) {
+                return Err(ENOENT());
+            } else {
+                return self.set_inode_times(&fs.inode, times);
+            }
+        };
+        if !fs.executable(self.ids) {
+            return Err(EACCES());
+        }
+        let parent = fs;
+        match fs.kind.dir_ref().get(&base) {
+            Some(child) => {
+                if let DeKind::Symlink(ref sl) = child.kind {
+                    if {*level += 1; *level} == 40 {
+                        return Err(ELOOP());
+                    }
+                    return self.at(parent).set_times(sl, times, level);
+                }
+                self.set_inode_times(&child.inode, times)
             }
             None => Err(ENOENT()),
         }
     }
 
+    // set_inode_times sets the times of an inode if we own it or are root.
+    fn set_inode_times(&self, inode: &Inode, times: fs::FileTimes) -> Result<()> {
+        if !inode.owned_by(self.ids) {
+            return Err(EPERM());
+        }
+        let now = inode.clock.now();
+        inode.write().times.set(times, now);
+        Ok(())
+    }
+
     fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
         let (fs, may_base) = self.traverse(normalize(&path), &mut 0)?;
         let base = match may_base {
@@ -1974,7 +3795,7 @@ impl Pwd {
             }
         };
 
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
             return Err(EACCES());
         }
 
@@ -1984,6 +3805,54 @@ impl Pwd {
         }
     }
 
+    // lookup returns the dirent at path, following a symlink at the end of path if follow is true
+    // like metadata does, or not like symlink_metadata does.
+    fn lookup<P: AsRef<Path>>(&self, path: P, follow: bool, level: &mut u8) -> Result<Raw<Dirent>> {
+        let (fs, may_base) = self.traverse(normalize(&path), level)?;
+        let base = match may_base {
+            Some(base) => base,
+            None => if path_empty(&path) {
+                return Err(ENOENT());
+            } else {
+                return Ok(fs); // either the root dir or a parent dir
+            }
+        };
+        if !fs.executable(self.ids) {
+            return Err(EACCES());
+        }
+        let parent = fs;
+        match fs.kind.dir_ref().get(&base) {
+            Some(child) => {
+                if let DeKind::Symlink(ref sl) = child.kind {
+                    if follow {
+                        if {*level += 1; *level} == 40 {
+                            return Err(ELOOP());
+                        }
+                        return self.at(parent).lookup(sl, follow, level);
+                    }
+                }
+                Ok(*child)
+            }
+            None => Err(ENOENT()),
+        }
+    }
+
+    fn get_xattr(&self, path: &Path, name: &OsStr, follow: bool) -> Result<Vec<u8>> {
+        self.lookup(path, follow, &mut 0)?.inode.get_xattr(name, self.ids)
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8], follow: bool) -> Result<()> {
+        self.lookup(path, follow, &mut 0)?.inode.set_xattr(name, value, self.ids)
+    }
+
+    fn list_xattr(&self, path: &Path, follow: bool) -> Result<Vec<OsString>> {
+        Ok(self.lookup(path, follow, &mut 0)?.inode.list_xattr(self.ids))
+    }
+
+    fn remove_xattr(&self, path: &Path, name: &OsStr, follow: bool) -> Result<()> {
+        self.lookup(path, follow, &mut 0)?.inode.remove_xattr(name, self.ids)
+    }
+
     // open has to take the master filesystem (FS) because File itself can call set_permissions.
     // There is probably an avenue to clean this up.
     fn open<P: AsRef<Path>>(&self,
@@ -1996,8 +3865,15 @@ impl Pwd {
         //   - create_new (excl) implies create
         //   - if !write, read is incompatible with any of create, create_new, and trunc
         //   - trunc and append are incompatible
+        // Custom flags can set any of these, and we first fold them into our options.
+        //
         // First, let us validate the combinations and set implied fields.
         let mut options = options.clone();
+        let flags = flags::check(options.flags)?;
+        options.append |= flags & O_APPEND != 0;
+        options.trunc |= flags & O_TRUNC != 0;
+        options.create |= flags & O_CREAT != 0;
+        options.excl |= flags & (O_CREAT | O_EXCL) == O_CREAT | O_EXCL;
         if options.append {
             options.write = true;
         }
@@ -2010,6 +3886,13 @@ impl Pwd {
         if options.trunc && options.append {
             return Err(EINVAL());
         }
+        // Linux refuses to create a directory with open, and O_TMPFILE creates its own file.
+        if options.create && flags & O_DIRECTORY != 0 {
+            return Err(EINVAL());
+        }
+        if flags & O_TMPFILE == O_TMPFILE {
+            return self.open_tmpfile(path.as_ref(), &options, level);
+        }
 
         // Now, on with (potentially) opening.
         let (mut fs, may_base) = self.traverse(normalize(&path), level)?;
@@ -2019,36 +3902,37 @@ impl Pwd {
                 return Err(ENOENT());
             } else { // root or parent directories only (both of which,
                      // being dirs, fail immediately in open_existing)
-                return Self::open_existing(&fs, &options);
+                return self.open_existing(&fs, &options);
             }
         };
 
-        if !fs.executable() {
+        if !fs.executable(self.ids) {
             return Err(EACCES());
         }
         // If the file exists, open it.
         let parent = fs;
         if let Some(child) = fs.kind.dir_ref().get(&base) {
             if let DeKind::Symlink(ref sl) = child.kind {
-                if {*level += 1; *level} == 40 {
+                if {*level += 1; *level} == 40 || flags & O_NOFOLLOW != 0 {
                     return Err(ELOOP());
                 }
-                return Pwd::from(parent).open(sl, &options, level);
+                return self.at(parent).open(sl, &options, level);
             }
-            return Self::open_existing(child, &options);
+            return self.open_existing(child, &options);
         }
 
         // From here down we worry about creating a new file.
         if !options.create {
             return Err(ENOENT());
         }
-        if !fs.writable() {
+        if !fs.writable(self.ids) {
             return Err(EACCES());
         }
 
         let file = Arc::new(RwLock::new(RawFile { // backing "inode" file
-            data:  Vec::new(),
-            inode: Inode::new(options.mode, Ftyp::File, 0),
+            data:     Data::default(),
+            inode:    self.new_inode(options.mode, Ftyp::File, 0)?,
+            linkable: false,
         }));
         let child = Raw::from(Dirent {
             parent: Some(fs),
@@ -2056,66 +3940,141 @@ impl Pwd {
             name:   base.clone(),
             inode:  file.write().inode.clone(),
         });
-        fs.kind.dir_mut().insert(base, child);
+        fs.kind.dir_mut().insert(base.clone(), child);
+        self.log(&[(fs, &base)]);
         Ok(File { // file view
             read:   options.read,
             write:  options.write,
             append: options.append,
+            dir:    false,
+
+            cursor: Arc::new(Mutex::new(FileCursor {
+                file,
+                at:      0,
+                ids:     self.ids,
+                noatime: flags & O_NOATIME != 0,
+                sync:    flags & O_SYNC,
+                journal: self.journal.clone(),
+            })),
+        })
+    }
+
+    // open_tmpfile opens a new file with no name in the directory at path, like O_TMPFILE. The
+    // file can be given a name with link_file unless it was opened with O_EXCL.
+    fn open_tmpfile(&self, path: &Path, options: &OpenOptions, level: &mut u8) -> Result<File> {
+        if !options.write || options.create {
+            return Err(EINVAL());
+        }
+        let dir = self.lookup(path, true, level)?;
+        if !dir.is_dir() {
+            return Err(ENOTDIR());
+        }
+        if !dir.changeable(self.ids) {
+            return Err(EACCES());
+        }
+
+        let inode = self.new_inode(options.mode, Ftyp::File, 0)?;
+        inode.write().nlink = 0;
+        let file = Arc::new(RwLock::new(RawFile {
+            data:     Data::default(),
+            inode:    inode.clone(),
+            linkable: options.flags & O_EXCL == 0,
+        }));
+        // The file has no name to log, but its data can be synced before it is given one.
+        if let Some(ref mut journal) = *self.journal.lock() {
+            journal.node(inode.key(), || {
+                Durable::new(DurableKind::File(Arc::downgrade(&file)), &inode)
+            });
+        }
+        Ok(File {
+            read:   options.read,
+            write:  true,
+            append: options.append,
+            dir:    false,
 
             cursor: Arc::new(Mutex::new(FileCursor {
-                file: file,
-                at:   0,
+                file,
+                at:      0,
+                ids:     self.ids,
+                noatime: options.flags & O_NOATIME != 0,
+                sync:    options.flags & O_SYNC,
+                journal: self.journal.clone(),
             })),
         })
     }
 
     // `open_existing` opens known existing file with the given options, returning an error if the
     // file cannot be opened with those options.
-    fn open_existing(fs: &Raw<Dirent>, options: &OpenOptions) -> Result<File> {
+    fn open_existing(&self, fs: &Raw<Dirent>, options: &OpenOptions) -> Result<File> {
+        let ids = self.ids;
         if options.excl {
             return Err(EEXIST());
         }
+        if options.flags & O_DIRECTORY != 0 && !fs.is_dir() {
+            return Err(ENOTDIR());
+        }
 
-        // we panic below if fs is a symlink
-        if fs.is_dir() {
-            if options.write {
-                return Err(EISDIR());
-            }
-            // TODO we could return Ok here so that users can stat the File, but that is more
-            // complicated than seems worth it. Reads fail with "Is a directory", and right now
-            // there is no hook into RawFile with how to fail reads.
-            return Err(Error::new(ErrorKind::Other, "open on directory unimplemented"));
+        // Directories can only be opened to read.
+        if fs.is_dir() && options.write {
+            return Err(EISDIR());
         }
 
         let (mut read, mut write) = (false, false);
         if options.read {
-            if !fs.readable() {
+            if !fs.readable(ids) {
                 return Err(EACCES());
             }
             read = true;
         }
         if options.write {
-            if !fs.writable() {
+            if !fs.writable(ids) {
                 return Err(EACCES());
             }
             write = true;
         }
-
-        let file = fs.kind.file_ref().clone();
+        // Only the owner of a file may keep from updating its atime.
+        let noatime = options.flags & O_NOATIME != 0;
+        if noatime && !fs.inode.owned_by(ids) {
+            return Err(EPERM());
+        }
+        // Special files have nothing behind them in memory: no pipe, no socket, and no driver.
+        // Linux fails to open a socket or a device without a driver with ENXIO, as it does a FIFO
+        // opened for writing without blocking when nothing reads it. We never block.
+        //
+        // A directory has nothing to read either, but we back it with an empty file sharing its
+        // inode so that its metadata, permissions, and locks can be used through the handle.
+        let file = match fs.kind {
+            DeKind::Special => return Err(ENXIO()),
+            DeKind::Dir(_) => Arc::new(RwLock::new(RawFile {
+                data:     Data::default(),
+                inode:    fs.inode.clone(),
+                linkable: false,
+            })),
+            _ => fs.kind.file_ref().clone(), // we panic here if fs is a symlink
+        };
         {
             let mut raw_file = file.write();
             if options.trunc {
-                raw_file.data = Vec::new();
+                raw_file.inode.charge.resize(0)?;
+                raw_file.data = Data::default();
+                raw_file.resized();
+            }
+            if !noatime {
+                raw_file.inode.touch(ACCESSED);
             }
-            raw_file.inode.write().times.update(ACCESSED);
         }
         Ok(File {
-            read:   read,
-            write:  write,
+            read,
+            write,
             append: options.append,
+            dir:    fs.is_dir(),
             cursor: Arc::new(Mutex::new(FileCursor {
-                file: file,
-                at:   0,
+                file,
+                at:      0,
+                ids,
+                noatime,
+                sync:    options.flags & O_SYNC,
+                journal: self.journal.clone(),
             })),
         })
     }
@@ -2156,12 +4115,11 @@ mod test {
         });
 
         let exp = FS(Arc::new(Mutex::new(FileSystem {
-                root: exp_root,
-                pwd:  Pwd::from(exp_root),
+                pwd: Pwd::from(exp_root),
             })));
         {
             let parent = exp_root;
-            let ref mut dir = exp_root.kind.dir_mut();
+            let dir = &mut exp_root.kind.dir_mut();
             dir.insert(OsString::from("lolz"), Raw::from(Dirent {
                 parent: Some(parent),
                 kind:   DeKind::Dir(HashMap::new()),
@@ -2172,8 +4130,9 @@ mod test {
             dir.insert(OsString::from("f"), Raw::from(Dirent {
                 parent: Some(parent),
                 kind:   DeKind::File(Arc::new(RwLock::new(RawFile{
-                    data:  vec![1, 2, 3],
-                    inode: file_inode.clone(),
+                    data:     Data::from(vec![1, 2, 3]),
+                    inode:    file_inode.clone(),
+                    linkable: false,
                 }))),
                 name:   OsString::from("f"),
                 inode:  file_inode,
@@ -2193,9 +4152,8 @@ mod test {
         assert!(fs.symlink(".././zzz", "sl").is_ok());
         assert!(fs.set_permissions("/", Permissions::from_mode(0)).is_ok());
         assert!(fs == exp);
-        // TODO this test proves that equality works, but does not prove that inequality is
-        // correct. While I have verified it, every piece of the above fs could be changed
-        // to prove it via a test.
+        assert!(super::diff(&fs, &exp).unwrap().is_empty());
+        // The diff test proves that inequality is caught, piece by piece.
     }
 
     #[test]
@@ -2209,8 +4167,7 @@ mod test {
             inode:  Inode::new(0o300, Ftyp::Dir, DIRLEN),
         });
         let exp = FS(Arc::new(Mutex::new(FileSystem {
-                root: exp_root,
-                pwd:  Pwd::from(exp_root),
+                pwd: Pwd::from(exp_root),
             })));
 
         {
@@ -2308,6 +4265,7 @@ mod test {
         assert!(fs.set_permissions("unexec", Permissions::from_mode(0o200)).is_ok());
         assert!(errs_eq(fs.new_openopts().write(true).open("").unwrap_err(), ENOENT()));
 
+        #[allow(clippy::too_many_arguments)]
         fn test_open<P: AsRef<Path>>(on: i32,
                                      fs: &FS,
                                      read: bool,
@@ -2329,11 +4287,11 @@ mod test {
                         .mode(mode)
                         .open(&path);
 
-            if err.is_some() {
-                if res.is_ok() {
-                    panic!("#{}: expected an error", on);
-                }
-                let (res_err, exp_err) = (res.unwrap_err(), err.unwrap());
+            if let Some(exp_err) = err {
+                let res_err = match res {
+                    Ok(_) => panic!("#{}: expected an error", on),
+                    Err(e) => e,
+                };
                 if res_err.kind() == ErrorKind::Other && exp_err.kind() == ErrorKind::Other {
                     return;
                 }
@@ -2343,10 +4301,10 @@ mod test {
                 return;
             }
 
-            if res.is_err() {
-                panic!("#{}: not ok: {:?}", on, res.unwrap_err());
-            }
-            let file = res.unwrap();
+            let file = match res {
+                Ok(file) => file,
+                Err(e) => panic!("#{}: not ok: {:?}", on, e),
+            };
             assert!(read == file.read);
             assert!((write || append) == file.write);
             assert!(append == file.append);
@@ -2371,9 +4329,8 @@ mod test {
         // Open on a directory with write is bad...
         test_open(on(), &fs, f, t, f, f, f, f, 0o700, "/", Some(EISDIR())); // w
 
-        // Open on a directory is invalid in this code.
-        test_open(on(), &fs, t, f, f, f, f, f, 0o700, "/", Some(Error::new(ErrorKind::Other, "")));
-        test_open(on(), &fs, t, f, f, f, f, f, 0o700, "okdir", Some(Error::new(ErrorKind::Other, "")));
+        // ...as is reading one without permission (see open_dir for reading one with it).
+        test_open(on(), &fs, t, f, f, f, f, f, 0o700, "okdir", Some(EACCES()));
 
         // New files in unreachable directories...
         test_open(on(), &fs, f, t, f, f, t, f, 0o200, "unexec/a", Some(EACCES()));
@@ -2510,7 +4467,7 @@ mod test {
         assert!(errs_eq(fs.rename("a/b/c/d", "").unwrap_err(), EACCES()));
         assert!(errs_eq(fs.rename("a", "a/b/c/d").unwrap_err(), EACCES()));
         assert!(errs_eq(fs.rename("", "d/e/f").unwrap_err(), ENOENT()));
-        assert!(fs.rename("/", "d/e/f").unwrap_err().kind() == ErrorKind::Other);
+        assert!(errs_eq(fs.rename("/", "d/e/f").unwrap_err(), EBUSY()));
         assert!(errs_eq(fs.rename("a/b/c", "").unwrap_err(), ENOENT()));
         assert!(errs_eq(fs.rename("d", "/").unwrap_err(), EEXIST()));
         assert!(fs.rename("a", "a").is_ok());
@@ -2532,6 +4489,54 @@ mod test {
         assert!(fs == exp);
     }
 
+    #[test]
+    fn rename_with_flags() {
+        let fs = FS::new();
+        let errno = |r: Result<()>| r.unwrap_err().raw_os_error().unwrap();
+        assert!(fs.create_dir_all("/a/b").is_ok());
+        assert!(fs.create_dir("/c").is_ok());
+        assert!(fs.write("/a/f", b"f").is_ok());
+        assert!(fs.write("/g", b"g").is_ok());
+
+        // Directories cannot move into themselves, and root cannot move at all.
+        assert_eq!(errno(fs.rename("/a", "/a/b/a")), 22);
+        assert_eq!(errno(fs.rename("/a", "/a/z")), 22);
+        assert_eq!(errno(fs.rename_with_flags("/", "/z", RENAME_NOREPLACE)), 16);
+        assert_eq!(errno(fs.rename_with_flags("/a", "/z", 4)), 22);
+        assert_eq!(errno(fs.rename_with_flags("/a", "/z", RENAME_NOREPLACE | RENAME_EXCHANGE)),
+                   22);
+
+        // RENAME_NOREPLACE only renames to missing names...
+        assert_eq!(errno(fs.rename_with_flags("/g", "/a/f", RENAME_NOREPLACE)), 17);
+        assert_eq!(errno(fs.rename_with_flags("/g", "/g", RENAME_NOREPLACE)), 17);
+        assert!(fs.rename_with_flags("/g", "/a/g", RENAME_NOREPLACE).is_ok());
+        assert_eq!(fs.read("/a/g").unwrap(), b"g");
+
+        // ...while RENAME_EXCHANGE only renames to existing names, of any type.
+        assert_eq!(errno(fs.rename_with_flags("/a/g", "/z", RENAME_EXCHANGE)), 2);
+        assert_eq!(errno(fs.rename_with_flags("/a/b", "/a", RENAME_EXCHANGE)), 22);
+        assert!(fs.rename_with_flags("/a/f", "/a/g", RENAME_EXCHANGE).is_ok());
+        assert_eq!(fs.read("/a/f").unwrap(), b"g");
+        assert_eq!(fs.read("/a/g").unwrap(), b"f");
+        assert!(fs.rename_with_flags("/a/f", "/a/f", RENAME_EXCHANGE).is_ok());
+
+        // Exchanging a directory with a file across directories moves one .. link, while
+        // exchanging two directories moves none.
+        let nlink = |p| fs.metadata(p).unwrap().nlink();
+        assert!(fs.write("/h", b"h").is_ok());
+        assert_eq!((nlink("/"), nlink("/a")), (4, 3));
+        assert!(fs.rename_with_flags("/a/b", "/h", RENAME_EXCHANGE).is_ok());
+        assert!(fs.metadata("/h").unwrap().is_dir() && fs.metadata("/a/b").unwrap().is_file());
+        assert_eq!((nlink("/"), nlink("/a")), (5, 2));
+        assert!(fs.rename_with_flags("/h", "/a/b", RENAME_EXCHANGE).is_ok());
+        assert_eq!((nlink("/"), nlink("/a")), (4, 3));
+        assert!(fs.write("/a/b/x", b"x").is_ok());
+        assert!(fs.rename_with_flags("/a/b", "/c", RENAME_EXCHANGE).is_ok());
+        assert_eq!((nlink("/"), nlink("/a")), (4, 3));
+        assert_eq!(fs.read("/c/x").unwrap(), b"x");
+        assert!(fs.read_dir("/a/b").unwrap().next().is_none());
+    }
+
     #[test]
     fn read_dir() {
         // Rote test to ensure ReadDir iteration works and is alphabetical.
@@ -2573,13 +4578,14 @@ mod test {
     #[test]
     fn raw_file() {
         let mut raw_file = RawFile {
-            data: Vec::new(),
+            data: Data::default(),
             inode: Inode::new(0, Ftyp::File, 0),
+            linkable: false,
         };
 
         let slice = &[1, 2, 3, 4, 5];
         assert_eq!(raw_file.write_at(0, &slice[..3]).unwrap(), 3);
-        assert_eq!(raw_file.data.as_slice(), &[1, 2, 3]);
+        assert_eq!(raw_file.data.to_vec(), &[1, 2, 3]);
 
         let mut output = [0u8; 5];
         assert_eq!(raw_file.read_at(0, &mut output).unwrap(), 3);
@@ -2595,10 +4601,10 @@ mod test {
         assert_eq!(&output, &[3, 3, 3, 0, 0]);
 
         assert_eq!(raw_file.write_at(1, &slice[..4]).unwrap(), 4);
-        assert_eq!(raw_file.data.as_slice(), &[1, 1, 2, 3, 4]);
+        assert_eq!(raw_file.data.to_vec(), &[1, 1, 2, 3, 4]);
 
         assert_eq!(raw_file.write_at(1, slice).unwrap(), 5);
-        assert_eq!(raw_file.data.as_slice(), &[1, 1, 2, 3, 4, 5]);
+        assert_eq!(raw_file.data.to_vec(), &[1, 1, 2, 3, 4, 5]);
 
         assert_eq!(raw_file.read_at(1, &mut output).unwrap(), 5);
         assert_eq!(&output, &[1, 2, 3, 4, 5]);
@@ -2609,7 +4615,7 @@ mod test {
         assert_eq!(raw_file.inode.read().length, 6);
 
         assert_eq!(raw_file.write_at(10, &slice[..1]).unwrap(), 1);
-        assert_eq!(raw_file.data.as_slice(), &[1, 1, 2, 3, 4, 5, 0, 0, 0, 0, 1]);
+        assert_eq!(raw_file.data.to_vec(), &[1, 1, 2, 3, 4, 5, 0, 0, 0, 0, 1]);
 
         let mut output = [0u8; 2];
         assert_eq!(raw_file.read_at(9, &mut output).unwrap(), 2);
@@ -2710,7 +4716,7 @@ mod test {
         {
             let cursor = z.cursor.lock();
             let file = cursor.file.read();
-            assert_eq!(file.data.as_slice(), &[1, 2, 3]);
+            assert_eq!(file.data.to_vec(), &[1, 2, 3]);
         }
         assert_eq!(z.write(vec![1, 2, 3].as_slice()).unwrap(), 3);
 
@@ -2719,20 +4725,20 @@ mod test {
             let f = fs.open_file("ls").unwrap(); // through linked (copied) symlink to f
             let cursor = f.cursor.lock();
             let file = cursor.file.read();
-            assert_eq!(file.data.as_slice(), &[1, 2, 3, 1, 2, 3]);
+            assert_eq!(file.data.to_vec(), &[1, 2, 3, 1, 2, 3]);
         }
 
         {
             let f = fs.open_file("cpy").unwrap(); // ensure our copied file is still normal
             let cursor = f.cursor.lock();
             let file = cursor.file.read();
-            assert_eq!(file.data.as_slice(), &[1, 2, 3]);
+            assert_eq!(file.data.to_vec(), &[1, 2, 3]);
         }
 
         {
             let cursor = f.cursor.lock(); // re-check the initial f
             let file = cursor.file.read();
-            assert_eq!(file.data.as_slice(), &[1, 2, 3, 1, 2, 3]);
+            assert_eq!(file.data.to_vec(), &[1, 2, 3, 1, 2, 3]);
         }
     }
 
@@ -2838,4 +4844,1209 @@ mod test {
 
         // Open is tested in hard_link
     }
+
+    #[test]
+    fn ownership() {
+        use fs::FileTimes;
+
+        let fs = FS::new();
+        assert_eq!((fs.uid(), fs.gid()), (0, 0));
+
+        // Files created are owned by the current ids.
+        fs.set_uid(1000);
+        fs.set_gid(100);
+        assert!(fs.new_openopts().write(true).create(true).mode(0o604).open("f").is_ok());
+        assert!(fs.create_dir("d").is_ok());
+        let inode = fs.metadata("f").unwrap().0;
+        assert_eq!((inode.uid, inode.gid), (1000, 100));
+
+        // The owner only gets the owner bits...
+        assert!(fs.open_file("f").is_ok());
+        assert!(fs.set_permissions("f", Permissions::from_mode(0o044)).is_ok());
+        assert!(errs_eq(fs.open_file("f").unwrap_err(), EACCES()));
+        assert!(fs.set_permissions("f", Permissions::from_mode(0o640)).is_ok());
+
+        // ...the group gets the group bits...
+        fs.set_uid(1001);
+        assert!(fs.open_file("f").is_ok());
+        assert!(errs_eq(fs.new_openopts().write(true).open("f").unwrap_err(), EACCES()));
+
+        // ...and everybody else gets the other bits. Only the owner can change them.
+        fs.set_gid(101);
+        assert!(errs_eq(fs.open_file("f").unwrap_err(), EACCES()));
+        let chmod = |path, mode| fs.set_permissions(path, Permissions::from_mode(mode));
+        assert!(errs_eq(chmod("f", 0o777).unwrap_err(), EPERM()));
+        fs.set_uid(1000);
+        assert!(chmod("f", 0o604).is_ok());
+        fs.set_uid(1001);
+        let f = fs.open_file("f").unwrap();
+        assert!(errs_eq(f.set_permissions(Permissions::from_mode(0o700)).unwrap_err(), EPERM()));
+
+        // Directories work the same way: d is 0o777 & owned by 1000:100.
+        assert!(errs_eq(chmod("d", 0o700).unwrap_err(), EPERM()));
+        fs.set_uid(1000);
+        assert!(chmod("d", 0o750).is_ok());
+        fs.set_uid(1001);
+        assert!(errs_eq(fs.create_file("d/f").unwrap_err(), EACCES()));
+        fs.set_gid(100);
+        assert!(errs_eq(fs.create_file("d/f").unwrap_err(), EACCES()));
+        assert!(fs.read_dir("d").is_ok());
+        fs.set_uid(1000);
+        assert!(fs.create_file("d/f").is_ok());
+
+        // Only root can give files away; owners can only change groups to their own.
+        assert!(errs_eq(fs.chown("f", Some(0), None).unwrap_err(), EPERM()));
+        assert!(errs_eq(fs.chown("f", None, Some(5)).unwrap_err(), EPERM()));
+        fs.set_gid(200);
+        assert!(fs.chown("f", Some(1000), Some(200)).is_ok());
+        fs.set_uid(1001);
+        assert!(errs_eq(fs.chown("f", None, Some(200)).unwrap_err(), EPERM()));
+        fs.set_uid(0);
+        assert!(fs.symlink("f", "sl").is_ok());
+        assert!(fs.chown("sl", Some(5), Some(6)).is_ok());
+        let inode = fs.metadata("f").unwrap().0;
+        assert_eq!((inode.uid, inode.gid), (5, 6));
+        assert!(errs_eq(fs.chown("missing", Some(5), None).unwrap_err(), ENOENT()));
+
+        // Root counts as the owner of every file, but is still held to the rwx bits.
+        assert!(chmod("f", 0o000).is_ok());
+        assert!(errs_eq(fs.open_file("f").unwrap_err(), EACCES()));
+        assert!(fs.set_times("f", FileTimes::new()).is_ok());
+        assert!(chmod("f", 0o400).is_ok());
+        assert!(errs_eq(fs.open_file("f").unwrap_err(), EACCES()));
+        assert!(fs.chown("f", Some(0), Some(0)).is_ok());
+        assert!(fs.open_file("f").is_ok());
+    }
+
+    #[test]
+    fn clock() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use fs::Metadata;
+        use mem::clock::{FrozenClock, MockClock};
+
+        let secs = Duration::from_secs;
+        let clock = MockClock::new(UNIX_EPOCH);
+        let fs = FS::with_clock(clock.clone());
+
+        let mut f = fs.create_file("f").unwrap();
+        let meta = fs.metadata("f").unwrap();
+        assert_eq!(meta.modified().unwrap(), UNIX_EPOCH);
+        assert_eq!(meta.accessed().unwrap(), UNIX_EPOCH);
+        assert_eq!(meta.created().unwrap(), UNIX_EPOCH);
+
+        // Writes only change the modified time...
+        clock.advance(secs(10));
+        assert!(f.write(b"hello").is_ok());
+        let meta = fs.metadata("f").unwrap();
+        assert_eq!(meta.modified().unwrap(), UNIX_EPOCH + secs(10));
+        assert_eq!(meta.accessed().unwrap(), UNIX_EPOCH);
+
+        // ...reads only change the accessed time...
+        clock.advance(secs(10));
+        assert!(fs.open_file("f").unwrap().read(&mut [0; 5]).is_ok());
+        let meta = fs.metadata("f").unwrap();
+        assert_eq!(meta.modified().unwrap(), UNIX_EPOCH + secs(10));
+        assert_eq!(meta.accessed().unwrap(), UNIX_EPOCH + secs(20));
+
+        // ...and new entries are created at the current time, even through symlinks.
+        clock.set(UNIX_EPOCH + secs(100));
+        assert!(fs.create_dir("d").is_ok());
+        assert!(fs.symlink("d", "sl").is_ok());
+        assert!(fs.create_file("sl/f").is_ok());
+        assert_eq!(fs.metadata("d").unwrap().created().unwrap(), UNIX_EPOCH + secs(100));
+        assert_eq!(fs.metadata("d/f").unwrap().created().unwrap(), UNIX_EPOCH + secs(100));
+
+        let fs = FS::with_mode_and_clock(0o700, FrozenClock::new(UNIX_EPOCH));
+        let mut f = fs.create_file("f").unwrap();
+        assert!(f.write(b"hello").is_ok());
+        assert_eq!(fs.metadata("f").unwrap().modified().unwrap(), UNIX_EPOCH);
+    }
+
+    #[test]
+    fn set_times() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use fs::{FileTimes, Metadata};
+        use mem::clock::FrozenClock;
+
+        let secs = Duration::from_secs;
+        let fs = FS::with_clock(FrozenClock::new(UNIX_EPOCH));
+
+        let f = fs.create_file("f").unwrap();
+        assert!(f.set_times(FileTimes::new().set_accessed(UNIX_EPOCH + secs(5))).is_ok());
+        let meta = fs.metadata("f").unwrap();
+        assert_eq!(meta.accessed().unwrap(), UNIX_EPOCH + secs(5));
+        assert_eq!(meta.modified().unwrap(), UNIX_EPOCH);
+        assert!(f.set_modified(UNIX_EPOCH + secs(6)).is_ok());
+        assert_eq!(fs.metadata("f").unwrap().modified().unwrap(), UNIX_EPOCH + secs(6));
+
+        // Path based set_times follows symlinks and works on directories.
+        assert!(fs.create_dir("d").is_ok());
+        assert!(fs.symlink("d", "sl").is_ok());
+        let times = FileTimes::new().set_accessed(UNIX_EPOCH + secs(7)).set_modified(UNIX_EPOCH + secs(8));
+        assert!(fs.set_times("sl", times).is_ok());
+        let meta = fs.metadata("d").unwrap();
+        assert_eq!(meta.accessed().unwrap(), UNIX_EPOCH + secs(7));
+        assert_eq!(meta.modified().unwrap(), UNIX_EPOCH + secs(8));
+        assert_eq!(fs.symlink_metadata("sl").unwrap().modified().unwrap(), UNIX_EPOCH);
+        assert!(fs.set_times("/", times).is_ok());
+        assert_eq!(fs.metadata("/").unwrap().modified().unwrap(), UNIX_EPOCH + secs(8));
+
+        assert!(errs_eq(fs.set_times("", times).unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.set_times("nonexistent", times).unwrap_err(), ENOENT()));
+
+        // Only the owner or root can set times through a path.
+        fs.set_uid(1000);
+        assert!(errs_eq(fs.set_times("f", times).unwrap_err(), EPERM()));
+        fs.set_uid(0);
+        assert!(fs.chown("f", Some(1000), None).is_ok());
+        fs.set_uid(1000);
+        assert!(fs.set_times("f", times).is_ok());
+
+        // Through an open file, the ids that opened it must own the file or be root.
+        fs.set_uid(0);
+        let g = fs.create_file("g").unwrap();
+        fs.set_uid(1000);
+        let opened = fs.open_file("g").unwrap();
+        assert!(errs_eq(opened.set_times(times).unwrap_err(), EPERM()));
+        assert!(errs_eq(opened.set_modified(UNIX_EPOCH).unwrap_err(), EPERM()));
+        assert!(g.set_times(times).is_ok());
+        assert!(f.set_times(times).is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        use fs::Metadata;
+
+        let fs = FS::with_limits(10, 4); // root, plus three more inodes
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (0, 1));
+
+        let mut f = fs.create_file("f").unwrap();
+        assert_eq!(f.write(b"0123456").unwrap(), 7);
+        assert!(errs_eq(f.write(b"7890").unwrap_err(), ENOSPC()));
+        assert_eq!(fs.metadata("f").unwrap().len(), 7);
+        assert!(f.write_at(b"abc", 2).is_ok()); // overwriting takes no space
+        assert!(f.set_len(1 << 40).is_ok()); // neither do holes
+        assert!(errs_eq(f.write_at(b"7890", 20).unwrap_err(), ENOSPC()));
+        assert!(f.write_at(b"789", 20).is_ok());
+        assert!(f.set_len(10).is_ok());
+        assert_eq!(fs.metadata("f").unwrap().len(), 10);
+        assert_eq!(fs.bytes_used(), 7);
+        assert!(f.write_at(b"789", 7).is_ok());
+        assert_eq!(fs.bytes_used(), 10);
+
+        // Hard links share an inode; symlinks and directories do not.
+        assert!(fs.hard_link("f", "hl").is_ok());
+        assert!(fs.symlink("f", "sl").is_ok());
+        assert!(fs.create_dir("d").is_ok());
+        assert_eq!(fs.inodes_used(), 4);
+        assert!(errs_eq(fs.create_dir("d2").unwrap_err(), ENOSPC()));
+        assert!(errs_eq(fs.create_file("f2").unwrap_err(), ENOSPC()));
+        assert!(errs_eq(fs.symlink("f", "sl2").unwrap_err(), ENOSPC()));
+
+        // An unlinked file keeps its space until its last name and last handle are gone.
+        assert!(fs.remove_file("f").is_ok());
+        assert!(fs.remove_file("hl").is_ok());
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (10, 4));
+        drop(f);
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (0, 3));
+
+        // Truncating frees space, as does replacing a file with a rename.
+        assert!(fs.create_file("f").unwrap().write_all(b"0123456789").is_ok());
+        let mut f = fs.create_file("f").unwrap();
+        assert_eq!(fs.bytes_used(), 0);
+        assert!(f.write_all(b"01234").is_ok());
+        drop(f);
+        assert!(fs.remove_file("sl").is_ok());
+        assert!(fs.create_file("g").is_ok());
+        assert!(fs.rename("g", "f").is_ok());
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (0, 3));
+
+        // Limits can be lifted or lowered below usage.
+        fs.set_limits(None, None);
+        assert!(fs.create_file("h").unwrap().write_all(&[0; 100]).is_ok());
+        fs.set_limits(Some(50), None);
+        let h = fs.new_openopts().write(true).open("h").unwrap();
+        assert!(errs_eq(h.write_at(b"x", 100).unwrap_err(), ENOSPC()));
+        assert!(h.set_len(40).is_ok());
+        assert_eq!(fs.bytes_used(), 40);
+
+        // Recursive removes free space too.
+        assert!(fs.remove_dir_all("d").is_ok());
+        assert_eq!(fs.inodes_used(), 3);
+    }
+
+    #[test]
+    fn current_dir() {
+        let fs = FS::new();
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from("/"));
+        assert!(fs.create_dir_all("a/b").is_ok());
+        assert!(fs.create_file("a/f").is_ok());
+        assert!(fs.symlink("a/b", "sl").is_ok());
+
+        // Relative paths resolve from the current directory, which is where symlinks lead.
+        assert!(fs.set_current_dir("a").is_ok());
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from("/a"));
+        assert!(fs.metadata("f").unwrap().is_file());
+        assert!(fs.set_current_dir("/sl").is_ok());
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from("/a/b"));
+        assert!(fs.metadata("../f").unwrap().is_file());
+        assert_eq!(fs.canonicalize(".").unwrap(), PathBuf::from("/a/b"));
+        assert!(fs.create_file("g").is_ok());
+        assert!(fs.metadata("/a/b/g").unwrap().is_file());
+
+        assert!(errs_eq(fs.set_current_dir("missing").unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.set_current_dir("../f").unwrap_err(), ENOTDIR()));
+        assert!(errs_eq(fs.set_current_dir("").unwrap_err(), ENOENT()));
+        assert!(fs.new_dirbuilder().mode(0o600).create("noexec").is_ok());
+        assert!(errs_eq(fs.set_current_dir("noexec").unwrap_err(), EACCES()));
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from("/a/b"));
+
+        // Removing the current directory leaves relative paths failing with ENOENT, while
+        // absolute paths still work.
+        assert!(fs.remove_file("g").is_ok());
+        assert!(fs.remove_dir("noexec").is_ok());
+        assert!(fs.remove_dir("/a/b").is_ok());
+        assert!(errs_eq(fs.current_dir().unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.metadata("g").unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.create_dir("g").unwrap_err(), ENOENT()));
+        assert!(fs.metadata("/a/f").unwrap().is_file());
+        assert!(fs.set_current_dir("/a").is_ok());
+        assert!(fs.metadata("f").unwrap().is_file());
+
+        // So do renaming over it and recursively removing it from above.
+        assert!(fs.create_dir("/e").is_ok());
+        assert!(fs.create_dir("/a/c").is_ok());
+        assert!(fs.set_current_dir("/a/c").is_ok());
+        assert!(fs.rename("/e", "/a/c").is_ok());
+        assert!(errs_eq(fs.current_dir().unwrap_err(), ENOENT()));
+        assert!(fs.set_current_dir("/a/c").is_ok());
+        assert!(fs.remove_dir_all("..").is_ok());
+        assert!(errs_eq(fs.current_dir().unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.metadata("/a").unwrap_err(), ENOENT()));
+        assert!(fs.symlink_metadata("/sl").is_ok());
+        assert!(fs.set_current_dir("/").is_ok());
+        assert_eq!(fs.inodes_used(), 2); // root and the dangling symlink
+    }
+
+    #[test]
+    fn snapshot() {
+        use std::time::Duration;
+
+        use fs::Metadata;
+        use mem::clock::MockClock;
+
+        let clock = MockClock::default();
+        let fs = FS::with_clock(clock.clone());
+        assert!(fs.create_dir_all("a/b").is_ok());
+        assert!(fs.create_file("a/f").unwrap().write_all(b"hello").is_ok());
+        assert!(fs.hard_link("a/f", "hl").is_ok());
+        assert!(fs.symlink("a/f", "sl").is_ok());
+        assert!(fs.set_permissions("a/b", Permissions::from_mode(0o700)).is_ok());
+        assert!(fs.chown("a/f", Some(1000), Some(1000)).is_ok());
+        assert!(fs.set_current_dir("a").is_ok());
+        let modified = fs.metadata("f").unwrap().modified().unwrap();
+        let (bytes, inodes) = (fs.bytes_used(), fs.inodes_used());
+
+        let snapshot = fs.snapshot();
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (bytes, inodes));
+        assert!(*snapshot.0 .0.lock() == *fs.0.lock());
+
+        clock.advance(Duration::from_secs(10));
+        assert!(fs.new_openopts().write(true).open("f").unwrap().write_all(b"HE").is_ok());
+        assert!(fs.remove_dir_all("/a").is_ok());
+        assert!(fs.create_file("/g").is_ok());
+        fs.set_uid(1000);
+
+        fs.restore(&snapshot);
+        assert!(*snapshot.0 .0.lock() == *fs.0.lock());
+        assert_eq!(fs.uid(), 1000);
+        fs.set_uid(0);
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (bytes, inodes));
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from("/a"));
+        assert_eq!(fs.metadata("f").unwrap().modified().unwrap(), modified);
+        assert!(errs_eq(fs.metadata("/g").unwrap_err(), ENOENT()));
+        assert_eq!(fs.read_link("/sl").unwrap(), PathBuf::from("a/f"));
+
+        // Hard links are still one inode, and the snapshot is untouched by changes after restore.
+        assert!(fs.new_openopts().append(true).open("/hl").unwrap().write_all(b"!").is_ok());
+        let mut contents = String::new();
+        assert!(fs.open_file("f").unwrap().read_to_string(&mut contents).is_ok());
+        assert_eq!(contents, "hello!");
+        let copy = FS::from_snapshot(&snapshot);
+        assert_eq!(copy.uid(), 0);
+        assert_eq!(copy.metadata("/hl").unwrap().len(), 5);
+
+        // Restoring counts against limits, but is not refused by them.
+        fs.set_limits(Some(0), Some(0));
+        fs.restore(&snapshot);
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (bytes, inodes));
+        assert!(errs_eq(fs.create_dir("/c").unwrap_err(), ENOSPC()));
+
+        // A filesystem whose current directory was removed restores with it removed.
+        assert!(fs.remove_dir_all("/a").is_ok());
+        fs.restore(&fs.snapshot());
+        assert!(errs_eq(fs.current_dir().unwrap_err(), ENOENT()));
+        assert!(fs.symlink_metadata("/sl").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn host() {
+        use std::env;
+        use std::fs as rs_fs;
+        use std::os::unix::fs::{MetadataExt, PermissionsExt as _PermissionsExt};
+        use std::os::unix::net::UnixListener;
+        use std::process;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use fs::Metadata;
+
+        let dir = env::temp_dir().join(format!("rsfs-mem-host-test-{}", process::id()));
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        let epoch = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        rs_fs::create_dir_all(src.join("a/b")).unwrap();
+        rs_fs::write(src.join("a/f"), b"hello").unwrap();
+        rs_fs::hard_link(src.join("a/f"), src.join("hl")).unwrap();
+        ::std::os::unix::fs::symlink("a/f", src.join("sl")).unwrap();
+        rs_fs::set_permissions(src.join("a/f"), rs_fs::Permissions::from_mode(0o640)).unwrap();
+        rs_fs::File::open(src.join("a/b")).unwrap()
+            .set_times(rs_fs::FileTimes::new().set_modified(epoch)).unwrap();
+        rs_fs::set_permissions(src.join("a/b"), rs_fs::Permissions::from_mode(0o500)).unwrap();
+        let _sock = UnixListener::bind(src.join("sock")).unwrap();
+
+        let fs = FS::new();
+        assert_eq!(fs.import_from(&src, "/in").unwrap(), vec![src.join("sock")]);
+        assert_eq!(fs.metadata("/in/a/f").unwrap().permissions().mode(), 0o640);
+        assert_eq!(fs.metadata("/in/a/b").unwrap().permissions().mode(), 0o500);
+        assert_eq!(fs.metadata("/in/a/b").unwrap().modified().unwrap(), epoch);
+        assert_eq!(fs.read_link("/in/sl").unwrap(), PathBuf::from("a/f"));
+        assert!(errs_eq(fs.metadata("/in/sock").unwrap_err(), ENOENT()));
+        assert!(fs.new_openopts().append(true).open("/in/hl").unwrap().write_all(b"!").is_ok());
+        assert_eq!(fs.metadata("/in/a/f").unwrap().len(), 6);
+
+        assert!(fs.export_to(&dst).is_ok());
+        let (f, hl) = (dst.join("in/a/f"), dst.join("in/hl"));
+        assert_eq!(rs_fs::read(&f).unwrap(), b"hello!");
+        assert_eq!(rs_fs::metadata(&f).unwrap().ino(), rs_fs::metadata(&hl).unwrap().ino());
+        assert_eq!(rs_fs::metadata(&f).unwrap().permissions().mode() & 0o7777, 0o640);
+        assert_eq!(rs_fs::metadata(dst.join("in/a/b")).unwrap().modified().unwrap(), epoch);
+        assert_eq!(rs_fs::read_link(dst.join("in/sl")).unwrap(), PathBuf::from("a/f"));
+
+        // Exporting again replaces what is there, without writing through symlinks.
+        let outside = dir.join("outside");
+        rs_fs::write(&outside, b"keep").unwrap();
+        rs_fs::remove_file(&f).unwrap();
+        ::std::os::unix::fs::symlink(&outside, &f).unwrap();
+        assert!(fs.export_to(&dst).is_ok());
+        assert_eq!(rs_fs::read(&outside).unwrap(), b"keep");
+        assert!(rs_fs::symlink_metadata(&f).unwrap().is_file());
+        assert_eq!(rs_fs::read(&f).unwrap(), b"hello!");
+        assert_eq!(rs_fs::metadata(&f).unwrap().ino(), rs_fs::metadata(&hl).unwrap().ino());
+        assert_eq!(rs_fs::read_link(dst.join("in/sl")).unwrap(), PathBuf::from("a/f"));
+
+        // Nor through other host links to the files there.
+        let linked = dir.join("linked");
+        rs_fs::write(&linked, b"mine").unwrap();
+        rs_fs::remove_file(&hl).unwrap();
+        rs_fs::hard_link(&linked, &hl).unwrap();
+        assert!(fs.export_to(&dst).is_ok());
+        assert_eq!(rs_fs::read(&linked).unwrap(), b"mine");
+        assert_eq!(rs_fs::read(&hl).unwrap(), b"hello!");
+
+        // Importing what was exported gives back the same tree.
+        let back = FS::new();
+        assert!(back.import_from(dst.join("in"), "/in").unwrap().is_empty());
+        assert!(back == fs);
+
+        // Exports leave the holes of sparse files unwritten.
+        let sparse = FS::new();
+        let f = sparse.create_file("/s").unwrap();
+        assert!(f.set_len(1 << 36).is_ok());
+        assert_eq!(f.write_at(b"x", 1 << 35).unwrap(), 1);
+        assert!(sparse.export_to(dir.join("sparse")).is_ok());
+        let s = rs_fs::File::open(dir.join("sparse/s")).unwrap();
+        assert_eq!(s.metadata().unwrap().len(), 1 << 36);
+        assert!(s.metadata().unwrap().blocks() < 1024);
+        let mut buf = [0; 2];
+        assert_eq!(::std::os::unix::fs::FileExt::read_at(&s, &mut buf, 1 << 35).unwrap(), 2);
+        assert_eq!(&buf, b"x\0");
+
+        for d in &["src/a/b", "dst/in/a/b"] {
+            rs_fs::set_permissions(dir.join(d), rs_fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        assert!(rs_fs::remove_dir_all(&dir).is_ok());
+    }
+
+    #[test]
+    fn tar() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use fs::Metadata;
+
+        let fs = FS::new();
+        let epoch = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let long = format!("/{}/{}", "d".repeat(90), "f".repeat(90));
+        assert!(fs.create_dir_all("/a/b").is_ok());
+        assert!(fs.create_file("/a/f").unwrap().write_all(b"hello").is_ok());
+        assert!(fs.hard_link("/a/f", "/hl").is_ok());
+        assert!(fs.symlink("a/f", "/sl").is_ok());
+        assert!(fs.create_dir_all(&long).is_ok());
+        assert!(fs.set_permissions("/a/f", Permissions::from_mode(0o640)).is_ok());
+        assert!(fs.set_times("/a/b", fs::FileTimes::new().set_modified(epoch)).is_ok());
+        assert!(fs.set_permissions("/a/b", Permissions::from_mode(0o500)).is_ok());
+
+        let mut tar = Vec::new();
+        assert!(fs.write_tar(&mut tar).is_ok());
+        assert_eq!(tar.len() % 512, 0);
+        let back = FS::from_tar(&tar[..]).unwrap();
+        assert!(back == fs);
+        assert_eq!(back.metadata("/a/b").unwrap().modified().unwrap(), epoch);
+        assert_eq!(back.read_link("/sl").unwrap(), PathBuf::from("a/f"));
+        assert!(back.metadata(&long).unwrap().is_dir());
+        assert!(back.new_openopts().append(true).open("/hl").unwrap().write_all(b"!").is_ok());
+        assert_eq!(back.metadata("/a/f").unwrap().len(), 6);
+
+        // Parents missing from an archive are implied; paths may not escape the root.
+        let at = tar.windows(4).position(|w| w == b"a/f\0").unwrap();
+        let entry = &tar[at..at + 1024];
+        let implied = FS::from_tar(&[entry, &[0; 1024][..]].concat()[..]).unwrap();
+        assert_eq!(implied.metadata("/a").unwrap().permissions().mode(), 0o755);
+        assert!(implied.metadata("/a/f").unwrap().is_file());
+
+        let mut bad = tar.clone();
+        bad[0] = b'X'; // breaks the checksum of "./"
+        assert_eq!(FS::from_tar(&bad[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+        let mut escape = entry.to_vec();
+        escape[..7].copy_from_slice(b"../f\0\0\0");
+        let sum: u32 = escape[..512].iter().enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { 32 } else { u32::from(b) })
+            .sum();
+        escape[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        assert_eq!(FS::from_tar(&escape[..]).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // Sparse files keep their holes through rendering, diffing, and archiving.
+        let fs = FS::new();
+        let (len, at) = (1 << 40, 1 << 39);
+        let f = fs.create_file("/s").unwrap();
+        assert!(f.set_len(len).is_ok());
+        assert_eq!(f.write_at(b"abc", at).unwrap(), 3);
+        assert!(fs.write("/t", b"dense").is_ok());
+        assert_eq!(fs.render(RenderOptions::new().sizes(true)), format!("\
+/
+    s (len {})
+    t (len 5)
+", len));
+        let mut tar = Vec::new();
+        assert!(fs.write_tar(&mut tar).is_ok());
+        assert!(tar.len() < 16 * 512);
+        let back = FS::from_tar(&tar[..]).unwrap();
+        assert!(back == fs);
+        assert!(super::diff(&fs, &back).unwrap().is_empty());
+        assert_eq!(back.metadata("/s").unwrap().blocks(), 8);
+        assert_eq!(back.read("/t").unwrap(), b"dense");
+        let s = back.new_openopts().write(true).open("/s").unwrap();
+        assert_eq!(s.write_at(b"X", 1).unwrap(), 1);
+        assert_eq!(s.write_at(b"X", at + 1).unwrap(), 1);
+        assert_eq!(super::diff(&fs, &back).unwrap(), vec![
+            Change::Content { path: PathBuf::from("/s"), ranges: vec![1..2, at + 1..at + 2] },
+        ]);
+    }
+
+    #[test]
+    fn diff() {
+        use mem::EntryKind;
+
+        let p = PathBuf::from;
+        let a = FS::new();
+        assert!(a.create_dir_all("/d/sub").is_ok());
+        assert!(a.create_file("/d/sub/f").is_ok());
+        assert!(a.create_file("/f").unwrap().write_all(b"hello world").is_ok());
+        assert!(a.hard_link("/f", "/hl").is_ok());
+        assert!(a.create_file("/g").is_ok());
+        assert!(a.symlink("f", "/sl").is_ok());
+        assert!(a.create_dir("/t").is_ok());
+        let b = FS::from_snapshot(&a.snapshot());
+        assert!(super::diff(&a, &b).unwrap().is_empty());
+
+        assert!(b.remove_dir_all("/d").is_ok());
+        assert!(b.new_openopts().write(true).open("/f").unwrap().write_at(b"J", 6).is_ok());
+        assert!(b.new_openopts().append(true).open("/f").unwrap().write_all(b"!!").is_ok());
+        assert!(b.remove_file("/hl").is_ok());
+        assert!(b.hard_link("/f", "/hl2").is_ok());
+        assert!(b.set_permissions("/g", Permissions::from_mode(0o600)).is_ok());
+        assert!(b.remove_file("/sl").is_ok());
+        assert!(b.symlink("g", "/sl").is_ok());
+        assert!(b.remove_dir("/t").is_ok());
+        assert!(b.create_file("/t").is_ok());
+
+        assert_eq!(super::diff(&a, &b).unwrap(), vec![
+            Change::Removed(p("/d")),
+            Change::Content { path: p("/f"), ranges: vec![6..7, 11..13] },
+            Change::Links { path: p("/f"), from: vec![p("/hl")], to: vec![p("/hl2")] },
+            Change::Mode { path: p("/g"), from: 0o666, to: 0o600 },
+            Change::Removed(p("/hl")),
+            Change::Added(p("/hl2")),
+            Change::Symlink { path: p("/sl"), from: p("f"), to: p("g") },
+            Change::Type { path: p("/t"), from: EntryKind::Dir, to: EntryKind::File },
+        ]);
+        assert_eq!(super::diff(&b, &a).unwrap()[0], Change::Added(p("/d")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn render() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use mem::clock::FrozenClock;
+        use mem::RenderOptions;
+
+        let fs = FS::with_clock(FrozenClock::new(UNIX_EPOCH + Duration::new(5, 20)));
+        assert!(fs.create_dir_all("/d/e/deep").is_ok());
+        assert!(fs.create_file("/d/f").unwrap().write_all(b"hello").is_ok());
+        assert!(fs.hard_link("/d/f", "/hl").is_ok());
+        assert!(fs.create_file("/g").is_ok());
+        assert!(fs.create_file(OsStr::from_bytes(b"/b\xffad\n\\")).is_ok());
+        assert!(fs.symlink("d/f", "/sl").is_ok());
+
+        assert_eq!(fs.to_string(), "\
+/
+    b\\xffad\\n\\\\
+    d/
+        e/
+            deep/
+        f
+    g
+    hl
+    sl -> d/f
+");
+        assert_eq!(fs.render(RenderOptions::new().sizes(true).inodes(true).max_depth(2)), "\
+/
+    b\\xffad\\n\\\\ (len 0)
+    d/
+        e/
+            ...
+        f (len 5, link 1)
+    g (len 0)
+    hl (len 5, link 1)
+    sl -> d/f
+");
+        assert_eq!(fs.render(RenderOptions::new().modes(true).times(true).max_depth(0)), "\
+/ (mode 0777, mtime 5.000000020)
+    ...
+");
+
+        assert!(fs.remove_dir_all("/").is_ok());
+        assert_eq!(fs.to_string(), "/ (deleted)\n");
+    }
+
+    #[test]
+    fn std_helpers() {
+        use std::io::ErrorKind;
+
+        let fs = FS::new();
+        assert!(fs.write("/f", b"hello").is_ok());
+        assert_eq!(fs.read("/f").unwrap(), b"hello");
+        assert!(fs.write("/f", "hi").is_ok());
+        assert_eq!(fs.read_to_string("/f").unwrap(), "hi");
+        assert!(errs_eq(fs.read("/missing").unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.write("/missing/f", b"").unwrap_err(), ENOENT()));
+        assert!(fs.read("/").is_err());
+
+        assert!(fs.write("/bin", b"\xff").is_ok());
+        assert_eq!(fs.read_to_string("/bin").unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // exists hides errors that try_exists returns.
+        assert!(fs.symlink("f", "/l").is_ok());
+        assert!(fs.symlink("missing", "/dangling").is_ok());
+        assert!(fs.exists("/l") && !fs.exists("/dangling"));
+        assert!(!fs.try_exists("/dangling").unwrap());
+        assert!(fs.create_dir("/d").is_ok());
+        assert!(fs.write("/d/f", b"").is_ok());
+        assert!(fs.set_permissions("/d", Permissions::from_mode(0o600)).is_ok());
+        assert!(!fs.exists("/d/f"));
+        assert!(errs_eq(fs.try_exists("/d/f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.write("/d/f", b"").unwrap_err(), EACCES()));
+    }
+
+    #[test]
+    fn open_dir() {
+        use fs::TryLockError;
+
+        let fs = FS::new();
+        assert!(fs.create_dir("/d").is_ok());
+        let errno = |r: Result<usize>| r.unwrap_err().raw_os_error().unwrap();
+
+        // Directories open to read, so that their inode can be used through the handle...
+        let d = fs.new_openopts().read(true).open("/d").unwrap();
+        assert!(d.metadata().unwrap().is_dir());
+        assert!(d.sync_all().is_ok() && d.sync_data().is_ok());
+        assert!(d.set_permissions(Permissions::from_mode(0o700)).is_ok());
+        assert_eq!(fs.metadata("/d").unwrap().permissions().mode(), 0o700);
+        assert!(d.set_xattr("user.a", b"1").is_ok());
+        assert_eq!(fs.get_xattr("/d", "user.a").unwrap(), b"1");
+        assert!(d.try_lock().is_ok());
+        let root = fs.open_file("/d/..").unwrap();
+        assert!(root.metadata().unwrap().is_dir());
+        assert!(matches!(fs.open_file("/d").unwrap().try_lock_shared(),
+                         Err(TryLockError::WouldBlock)));
+
+        // ...but they cannot be read, written, or linked.
+        assert_eq!(errno((&d).read(&mut [0; 4])), 21);
+        assert_eq!(errno(d.read_at(&mut [0; 4], 0)), 21);
+        assert_eq!(errno((&d).write(b"data")), 21);
+        assert_eq!(errno(d.write_at(b"data", 0)), 21);
+        assert_eq!(d.set_len(0).unwrap_err().raw_os_error(), Some(22));
+        assert_eq!(fs.link_file(&d, "/e").unwrap_err().raw_os_error(), Some(1));
+        assert_eq!(fs.new_openopts().write(true).open("/d").unwrap_err().raw_os_error(),
+                   Some(21));
+
+        // Like any other handle, a directory handle outlives the directory's name.
+        assert!(fs.remove_dir("/d").is_ok());
+        assert_eq!(d.metadata().unwrap().nlink(), 0);
+    }
+
+    #[test]
+    fn locks() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        use fs::{File as _File, TryLockError};
+
+        let would_block = |r| matches!(r, Err(TryLockError::WouldBlock));
+
+        let fs = FS::new();
+        let a = fs.create_file("/f").unwrap();
+        let b = fs.open_file("/f").unwrap();
+        assert!(fs.hard_link("/f", "/hl").is_ok());
+        let c = fs.open_file("/hl").unwrap();
+
+        // Clones share a lock; separate opens, even through other names, conflict.
+        assert!(a.try_lock().is_ok());
+        let a2 = a.try_clone().unwrap();
+        assert!(a2.try_lock().is_ok());
+        assert!(would_block(b.try_lock_shared()));
+        assert!(would_block(c.try_lock()));
+
+        // Dropping one clone keeps the lock; dropping the last releases it.
+        drop(a);
+        assert!(would_block(b.try_lock_shared()));
+        drop(a2);
+        assert!(b.try_lock_shared().is_ok());
+        assert!(c.try_lock_shared().is_ok());
+        assert!(would_block(b.try_lock()));
+        assert!(c.unlock().is_ok());
+        assert!(b.try_lock().is_ok());
+
+        // Blocking locks wait for the conflicting lock to be released.
+        let (tx, rx) = mpsc::channel();
+        let waiter = thread::spawn(move || {
+            assert!(c.lock().is_ok());
+            tx.send(()).unwrap();
+            c
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(b.unlock().is_ok());
+        let c = waiter.join().unwrap();
+        assert!(rx.recv().is_ok());
+        assert!(would_block(b.try_lock_shared()));
+        assert!(c.unlock().is_ok());
+        assert!(b.lock_shared().is_ok());
+    }
+
+    #[test]
+    fn sparse() {
+        let fs = FS::with_limits(1 << 20, 10);
+        let mut f = fs.new_openopts().read(true).write(true).create(true).open("/f").unwrap();
+        let at = 10 << 30;
+        assert_eq!(f.write_at(b"data", at).unwrap(), 4);
+        assert!(f.write_at(b"more", 4096).is_ok());
+
+        // Holes take no space or blocks and read as zeros.
+        let meta = fs.metadata("/f").unwrap();
+        assert_eq!((meta.len(), meta.blocks()), (at + 4, 16));
+        assert_eq!(fs.bytes_used(), 8);
+        let mut buf = [1; 8];
+        assert_eq!(f.read_at(&mut buf, at - 4).unwrap(), 8);
+        assert_eq!(&buf, b"\0\0\0\0data");
+
+        assert_eq!(f.seek_sparse(SparseSeek::Data(0)).unwrap(), 4096);
+        assert_eq!(f.seek_sparse(SparseSeek::Hole(4096)).unwrap(), 4100);
+        assert_eq!(f.seek_sparse(SparseSeek::Data(4100)).unwrap(), at);
+        assert_eq!(f.seek_sparse(SparseSeek::Hole(at)).unwrap(), at + 4);
+        assert_eq!(f.seek_sparse(SparseSeek::Hole(0)).unwrap(), 0);
+        assert!(errs_eq(f.seek_sparse(SparseSeek::Data(at + 4)).unwrap_err(), ENXIO()));
+
+        // Seeking moves the cursor.
+        assert!(f.seek_sparse(SparseSeek::Data(4097)).is_ok());
+        let mut buf = [0; 3];
+        assert!(f.read_exact(&mut buf).is_ok());
+        assert_eq!(&buf, b"ore");
+
+        // Extending only adds to the hole at the end; truncating frees data.
+        assert!(f.set_len(at * 2).is_ok());
+        assert!(errs_eq(f.seek_sparse(SparseSeek::Data(at + 4)).unwrap_err(), ENXIO()));
+        assert_eq!(fs.bytes_used(), 8);
+        assert!(f.set_len(4098).is_ok());
+        assert_eq!((fs.bytes_used(), fs.metadata("/f").unwrap().blocks()), (2, 8));
+        assert_eq!(fs.read("/f").unwrap().len(), 4098);
+        assert!(errs_eq(f.write_at(&[0; 1 << 20], 4098).unwrap_err(), ENOSPC()));
+        assert!(f.write_at(&[0; 1 << 20], 0).is_ok()); // overlaps the 2 bytes already written
+
+        // Writes after seeking past the end leave a hole too, and reads there find nothing.
+        fs.set_limits(None, None);
+        let mut g = fs.new_openopts().read(true).write(true).create(true).open("/g").unwrap();
+        assert_eq!(g.seek(SeekFrom::Start(at)).unwrap(), at);
+        assert_eq!(g.read(&mut buf).unwrap(), 0);
+        assert!(g.write_all(b"x").is_ok());
+        assert_eq!(g.stream_position().unwrap(), at + 1);
+        assert_eq!(fs.metadata("/g").unwrap().len(), at + 1);
+        assert_eq!(g.read_at(&mut buf, 0).unwrap(), 3);
+        assert_eq!(&buf, b"\0\0\0");
+        assert_eq!(g.seek(SeekFrom::End(2)).unwrap(), at + 3);
+        assert!(errs_eq(g.seek(SeekFrom::Current(-(at as i64) - 4)).unwrap_err(), EINVAL()));
+    }
+
+    #[test]
+    fn metadata_ext() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use mem::clock::MockClock;
+
+        let fs = FS::new();
+        let meta = |p| fs.symlink_metadata(p).unwrap();
+        assert!(fs.create_dir("/d").is_ok());
+        assert!(fs.write("/d/f", b"hello").is_ok());
+        assert!(fs.hard_link("/d/f", "/hl").is_ok());
+        assert!(fs.symlink("d/f", "/sl").is_ok());
+
+        // Hard links are the same file on the same device; everything else is not.
+        let (f, hl, sl) = (meta("/d/f"), meta("/hl"), meta("/sl"));
+        assert_eq!((f.dev(), f.ino()), (hl.dev(), hl.ino()));
+        assert_eq!(f.dev(), sl.dev());
+        assert_ne!(f.ino(), sl.ino());
+        assert_ne!(f.dev(), FS::new().metadata("/").unwrap().dev());
+        for entry in fs.read_dir("/").unwrap() {
+            let entry = entry.unwrap();
+            assert_eq!(entry.ino(), fs.symlink_metadata(entry.path()).unwrap().ino());
+        }
+
+        assert_eq!((f.mode(), meta("/d").mode(), sl.mode()), (0o100666, 0o040777, 0o120777));
+        assert_eq!((f.size(), f.blocks(), f.blksize()), (5, 8, 4096));
+        assert_eq!((sl.size(), sl.blocks()), (3, 0));
+        assert_eq!(meta("/d").blocks(), 8);
+
+        // Files count their names; directories count their parent, their ., and their children's
+        // .., and a directory that has been removed has no links.
+        assert_eq!((f.nlink(), sl.nlink()), (2, 1));
+        assert_eq!((meta("/").nlink(), meta("/d").nlink()), (3, 2));
+        assert!(fs.create_dir_all("/d/e/g").is_ok());
+        assert_eq!((meta("/").nlink(), meta("/d").nlink(), meta("/d/e").nlink()), (3, 3, 3));
+        assert!(fs.rename("/d/e", "/e").is_ok());
+        assert_eq!((meta("/").nlink(), meta("/d").nlink()), (4, 2));
+        assert!(fs.create_dir("/d/e").is_ok());
+        assert!(fs.rename("/d/e", "/e/g").is_ok());
+        assert_eq!((meta("/d").nlink(), meta("/e").nlink()), (2, 3));
+        let dir = fs.read_dir("/").unwrap()
+                    .map(Result::unwrap)
+                    .find(|entry| entry.file_name() == "e")
+                    .unwrap();
+        assert!(fs.remove_dir_all("/e").is_ok());
+        assert_eq!((meta("/").nlink(), dir.metadata().unwrap().nlink()), (3, 0));
+
+        // Renaming over and removing names unlinks them, down to zero for open files.
+        let file = fs.open_file("/hl").unwrap();
+        assert!(fs.write("/g", b"").is_ok());
+        assert!(fs.rename("/g", "/d/f").is_ok());
+        assert_eq!(file.metadata().unwrap().nlink(), 1);
+        assert!(fs.remove_file("/hl").is_ok());
+        assert_eq!(file.metadata().unwrap().nlink(), 0);
+
+        // Snapshots keep link counts.
+        assert!(fs.hard_link("/d/f", "/d/h").is_ok());
+        let copy = FS::from_snapshot(&fs.snapshot());
+        assert_eq!(copy.metadata("/d/h").unwrap().nlink(), 2);
+        assert_eq!(copy.metadata("/d/h").unwrap().ino(), copy.metadata("/d/f").unwrap().ino());
+
+        // Status changes with contents, permissions, owners, times, and links, but only contents
+        // change the modification time.
+        let clock = MockClock::new(UNIX_EPOCH);
+        let fs = FS::with_clock(clock.clone());
+        let meta = |p| fs.metadata(p).unwrap();
+        assert!(fs.write("/f", b"").is_ok());
+        let mut t = 0;
+        let mut changes = |change: &dyn Fn()| {
+            t += 10;
+            clock.set(UNIX_EPOCH + Duration::from_secs(t));
+            change();
+            assert_eq!((meta("/f").ctime(), meta("/f").ctime_nsec()), (t as i64, 0));
+        };
+        changes(&|| assert!(fs.set_permissions("/f", Permissions::from_mode(0o600)).is_ok()));
+        changes(&|| assert!(fs.chown("/f", None, Some(1)).is_ok()));
+        changes(&|| assert!(fs.hard_link("/f", "/hl").is_ok()));
+        changes(&|| assert!(fs.remove_file("/hl").is_ok()));
+        changes(&|| assert!(fs.set_times("/f", fs::FileTimes::new()).is_ok()));
+        assert_eq!(meta("/f").mtime(), 0);
+        changes(&|| assert!(fs.write("/f", b"hi").is_ok()));
+        assert_eq!(meta("/f").mtime(), 60);
+
+        clock.set(UNIX_EPOCH - Duration::from_millis(1500));
+        assert!(fs.write("/f", b"x").is_ok());
+        assert_eq!((meta("/f").mtime(), meta("/f").mtime_nsec()), (-2, 500_000_000));
+    }
+
+    #[test]
+    fn xattr() {
+        let fs = FS::new();
+        let errno = |r: Result<()>| r.unwrap_err().raw_os_error().unwrap();
+        assert!(fs.write("/f", b"").is_ok());
+        assert!(fs.hard_link("/f", "/hl").is_ok());
+        assert!(fs.symlink("f", "/sl").is_ok());
+
+        // Hard links share attributes, and paths and files see the same ones.
+        assert!(fs.set_xattr("/f", "user.a", b"1").is_ok());
+        assert_eq!(fs.get_xattr("/hl", "user.a").unwrap(), b"1");
+        let file = fs.open_file("/hl").unwrap();
+        assert!(file.set_xattr("user.b", b"2").is_ok());
+        assert_eq!(fs.list_xattr("/f").unwrap(), vec!["user.a", "user.b"]);
+        assert!(file.remove_xattr("user.a").is_ok());
+        assert_eq!(errno(fs.remove_xattr("/f", "user.a")), 61);
+        assert_eq!(errno(fs.get_xattr("/f", "user.a").map(|_| ())), 61);
+
+        // Paths follow symlinks, except for the symlink_ variants; symlinks cannot have user.
+        // attributes, but root can give them trusted. attributes.
+        assert_eq!(fs.get_xattr("/sl", "user.b").unwrap(), b"2");
+        assert_eq!(errno(fs.symlink_get_xattr("/sl", "user.b").map(|_| ())), 61);
+        assert_eq!(errno(fs.symlink_set_xattr("/sl", "user.b", b"")), 1);
+        assert!(fs.symlink_set_xattr("/sl", "trusted.t", b"").is_ok());
+        assert_eq!(fs.symlink_list_xattr("/sl").unwrap(), vec!["trusted.t"]);
+        assert!(fs.symlink_remove_xattr("/sl", "trusted.t").is_ok());
+        assert!(fs.set_xattr("/", "user.root", b"").is_ok());
+
+        assert_eq!(errno(fs.set_xattr("/f", "user.big", &[0; 65537])), 7);
+        assert_eq!(errno(fs.set_xattr("/f", "", b"")), 34);
+        assert_eq!(errno(fs.set_xattr("/f", "system.posix_acl_access", b"")), 95);
+        assert_eq!(errno(fs.set_xattr("/missing", "user.a", b"")), 2);
+
+        // Other users need read or write permission, and cannot see or touch trusted. attributes.
+        assert!(fs.set_xattr("/f", "trusted.t", b"").is_ok());
+        assert!(fs.set_permissions("/f", Permissions::from_mode(0o640)).is_ok());
+        fs.set_uid(1000);
+        assert_eq!(fs.list_xattr("/f").unwrap(), vec!["user.b"]);
+        assert_eq!(fs.get_xattr("/f", "user.b").unwrap(), b"2");
+        assert_eq!(errno(fs.set_xattr("/f", "user.b", b"")), 13);
+        assert_eq!(errno(fs.get_xattr("/f", "trusted.t").map(|_| ())), 61);
+        assert_eq!(errno(fs.remove_xattr("/f", "trusted.t")), 1);
+        fs.set_gid(1000);
+        assert_eq!(errno(fs.get_xattr("/f", "user.b").map(|_| ())), 13);
+
+        // Snapshots copy attributes rather than share them.
+        fs.set_uid(0);
+        fs.set_gid(0);
+        let copy = FS::from_snapshot(&fs.snapshot());
+        assert!(copy.set_xattr("/f", "user.b", b"3").is_ok());
+        assert_eq!(fs.get_xattr("/f", "user.b").unwrap(), b"2");
+        assert!(copy != fs);
+    }
+
+    #[test]
+    fn special_files() {
+        use std::env;
+        use std::fs as rs_fs;
+        use std::process;
+
+        use mem::EntryKind;
+
+        let fs = FS::new();
+        let errno = |r: Result<()>| r.unwrap_err().raw_os_error().unwrap();
+        assert!(fs.mkfifo("/fifo", 0o640).is_ok());
+        assert!(fs.mknod("/sock", 0o140600, 7).is_ok());
+        assert!(fs.mknod("/blk", 0o060660, 0x801).is_ok());
+        assert!(fs.mknod("/chr", 0o020666, 0x103).is_ok());
+        assert!(fs.mknod("/reg", 0o644, 0).is_ok());
+
+        let ftyp = |p| fs.metadata(p).unwrap().file_type();
+        assert!(ftyp("/fifo").is_fifo() && !ftyp("/fifo").is_file());
+        assert!(ftyp("/sock").is_socket());
+        assert!(ftyp("/blk").is_block_device());
+        assert!(ftyp("/chr").is_char_device());
+        assert!(ftyp("/reg").is_file());
+        let meta = |p| fs.metadata(p).unwrap();
+        assert_eq!((meta("/fifo").mode(), meta("/fifo").rdev()), (0o010640, 0));
+        assert_eq!((meta("/sock").mode(), meta("/sock").rdev()), (0o140600, 0));
+        assert_eq!((meta("/blk").mode(), meta("/blk").rdev()), (0o060660, 0x801));
+        assert_eq!((meta("/chr").mode(), meta("/chr").rdev()), (0o020666, 0x103));
+        assert_eq!((meta("/fifo").len(), meta("/fifo").blocks()), (0, 0));
+
+        assert_eq!(errno(fs.mkfifo("/fifo", 0o640)), 17);
+        assert_eq!(errno(fs.mknod("/dir", 0o040777, 0)), 22);
+        assert_eq!(errno(fs.mknod("/fifo/x", 0o010640, 0)), 20);
+        fs.set_uid(1000);
+        assert_eq!(errno(fs.mknod("/chr2", 0o020666, 0x103)), 1);
+        assert!(fs.mkfifo("/fifo2", 0o600).is_ok());
+        fs.set_uid(0);
+
+        // Nothing is behind special files, so opening them fails, and they cannot be copied.
+        assert_eq!(errno(fs.open_file("/fifo").map(|_| ())), 6);
+        assert_eq!(errno(fs.open_file("/chr").map(|_| ())), 6);
+        assert_eq!(errno(fs.new_openopts().write(true).open("/sock").map(|_| ())), 6);
+        assert_eq!(fs.copy("/fifo", "/copy").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(errno(fs.read_dir("/fifo").map(|_| ())), 20);
+
+        // Otherwise, they are entries like any other.
+        let mut names: Vec<_> = fs.read_dir("/").unwrap()
+                                  .map(Result::unwrap)
+                                  .filter(|entry| entry.file_type().unwrap().is_fifo())
+                                  .map(|entry| entry.file_name())
+                                  .collect();
+        names.sort();
+        assert_eq!(names, vec!["fifo", "fifo2"]);
+        assert!(fs.hard_link("/chr", "/chr2").is_ok());
+        assert_eq!((meta("/chr2").rdev(), meta("/chr2").nlink()), (0x103, 2));
+        assert!(fs.rename("/fifo2", "/fifo3").is_ok());
+        assert!(fs.symlink("blk", "/sl").is_ok());
+        assert!(ftyp("/sl").is_block_device());
+        assert_eq!(errno(fs.remove_dir("/sock")), 20);
+        assert!(fs.remove_file("/sock").is_ok());
+
+        // Snapshots keep special files, and renders and diffs show them.
+        let copy = FS::from_snapshot(&fs.snapshot());
+        assert!(copy == fs);
+        assert_eq!(copy.metadata("/chr").unwrap().rdev(), 0x103);
+        assert!(fs.mknod("/sock", 0o140600, 0).is_ok());
+        assert_eq!(fs.render(&RenderOptions::new()), "\
+/
+    blk (blk 8:1)
+    chr (chr 1:3)
+    chr2 (chr 1:3)
+    fifo (fifo)
+    fifo3 (fifo)
+    reg
+    sl -> blk
+    sock (socket)
+");
+        assert!(copy.remove_file("/chr2").is_ok() && copy.mknod("/chr2", 0o020666, 0x105).is_ok());
+        assert!(copy.remove_file("/fifo3").is_ok() && copy.mknod("/fifo3", 0o020600, 0).is_ok());
+        assert_eq!(super::diff(&fs, &copy).unwrap(), vec![
+            Change::Device { path: PathBuf::from("/chr2"), from: 0x103, to: 0x105 },
+            Change::Type {
+                path: PathBuf::from("/fifo3"),
+                from: EntryKind::Fifo,
+                to:   EntryKind::CharDevice,
+            },
+            Change::Removed(PathBuf::from("/sock")),
+        ]);
+
+        // Tar archives hold all but sockets, and exports report them rather than creating them.
+        let mut tar = Vec::new();
+        assert!(fs.write_tar(&mut tar).is_ok());
+        let back = FS::from_tar(&tar[..]).unwrap();
+        assert!(back.metadata("/fifo").unwrap().file_type().is_fifo());
+        assert_eq!(back.metadata("/fifo").unwrap().mode(), 0o010640);
+        assert_eq!(back.metadata("/blk").unwrap().rdev(), 0x801);
+        assert_eq!(back.metadata("/chr2").unwrap().rdev(), 0x103);
+        assert!(back.metadata("/sock").is_err());
+
+        let dir = env::temp_dir().join(format!("rsfs-mem-special-test-{}", process::id()));
+        let skipped = fs.export_to(&dir).unwrap();
+        let names = ["blk", "chr", "chr2", "fifo", "fifo3", "sock"];
+        assert_eq!(skipped, names.iter().map(|name| dir.join(name)).collect::<Vec<_>>());
+        assert!(rs_fs::symlink_metadata(dir.join("fifo")).is_err());
+        assert!(rs_fs::remove_dir_all(&dir).is_ok());
+    }
+
+    #[test]
+    fn custom_flags() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use fs::Metadata;
+        use mem::clock::MockClock;
+        use unix_ext::flags::{O_CLOEXEC, O_DSYNC};
+
+        let clock = MockClock::new(UNIX_EPOCH);
+        let fs = FS::with_clock(clock.clone());
+        let errno = |r: Result<super::File>| r.unwrap_err().raw_os_error().unwrap();
+        let open = |flags: i32, path: &str| {
+            fs.new_openopts().read(true).custom_flags(flags).open(path)
+        };
+        assert!(fs.write("/f", b"data").is_ok());
+        assert!(fs.create_dir("/d").is_ok());
+        assert!(fs.symlink("f", "/sl").is_ok());
+
+        // Flags are read like Linux reads them, and the access mode bits are ignored.
+        assert!(open(O_NOFOLLOW | 0o2, "/f").is_ok());
+        assert_eq!(errno(open(O_NOFOLLOW, "/sl")), 40);
+        assert_eq!(errno(open(O_DIRECTORY, "/f")), 20);
+        assert_eq!(errno(open(O_DIRECTORY, "/sl")), 20);
+        assert!(open(O_CLOEXEC | O_SYNC | O_DSYNC, "/f").is_ok());
+        let err = open(1 << 30, "/f").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        // Creation flags act like their Rust options.
+        let opts = |flags| fs.new_openopts().write(true).custom_flags(flags).clone();
+        assert!(opts(O_CREAT).open("/g").is_ok());
+        assert_eq!(errno(opts(O_CREAT | O_EXCL).open("/g")), 17);
+        assert_eq!(errno(opts(O_CREAT | O_DIRECTORY).open("/h")), 22);
+        assert!((&opts(O_APPEND).open("/f").unwrap()).write(b"!").is_ok());
+        assert_eq!(fs.read("/f").unwrap(), b"data!");
+        assert!(opts(O_TRUNC).open("/f").is_ok());
+        assert_eq!(fs.metadata("/f").unwrap().len(), 0);
+
+        // O_NOATIME keeps reads from touching atime, but only owners may ask for it.
+        assert!(fs.write("/f", b"data").is_ok());
+        clock.advance(Duration::from_secs(10));
+        let mut f = open(O_NOATIME, "/f").unwrap();
+        assert!(f.read(&mut [0; 4]).is_ok() && f.read_at(&mut [0; 4], 0).is_ok());
+        assert_eq!(fs.metadata("/f").unwrap().accessed().unwrap(), UNIX_EPOCH);
+        fs.set_uid(1000);
+        assert_eq!(errno(open(O_NOATIME, "/f")), 1);
+        assert_eq!(errno(opts(O_NOATIME | O_CREAT).open("/f")), 1);
+        fs.set_uid(0);
+
+        // O_TMPFILE files have no name until they are linked in, once.
+        assert_eq!(errno(open(O_TMPFILE, "/d")), 22);
+        assert_eq!(errno(opts(O_TMPFILE | O_CREAT).open("/d")), 22);
+        assert_eq!(errno(opts(O_TMPFILE).open("/f")), 20);
+        let tmp = opts(O_TMPFILE).open("/d").unwrap();
+        assert!((&tmp).write(b"tmp").is_ok());
+        assert_eq!(tmp.metadata().unwrap().nlink(), 0);
+        assert_eq!(fs.read_dir("/d").unwrap().count(), 0);
+        assert_eq!(fs.link_file(&tmp, "/f").unwrap_err().raw_os_error(), Some(17));
+        assert!(fs.link_file(&tmp, "/d/t").is_ok());
+        assert_eq!(fs.read("/d/t").unwrap(), b"tmp");
+        assert_eq!(tmp.metadata().unwrap().nlink(), 1);
+        assert!(fs.link_file(&tmp, "/t2").is_ok());
+        assert!(fs.remove_file("/d/t").is_ok() && fs.remove_file("/t2").is_ok());
+        assert_eq!(fs.link_file(&tmp, "/t3").unwrap_err().raw_os_error(), Some(2));
+
+        let excl = opts(O_TMPFILE | O_EXCL).open("/").unwrap();
+        assert_eq!(fs.link_file(&excl, "/t4").unwrap_err().raw_os_error(), Some(2));
+        let other = FS::new();
+        assert_eq!(other.link_file(&tmp, "/t5").unwrap_err().raw_os_error(), Some(18));
+    }
+
+    #[test]
+    fn crash() {
+        use fs::Metadata;
+        use unix_ext::flags::O_DSYNC;
+
+        let fs = FS::with_crash_tracking();
+        let crash = |fs: &FS| fs.crash(Crash::DropUnsynced);
+        let nodes = |fs: &FS| fs.0.lock().journal.lock().as_ref().map(|journal| journal.len());
+        let sync_dir = |path: &str| assert!(fs.open_file(path).unwrap().sync_all().is_ok());
+
+        // Nothing that was not synced survives, and a new FS starts out synced.
+        assert!(fs.create_dir("/a").is_ok());
+        assert!(fs.write("/a/f", b"hello").is_ok());
+        assert_eq!(crash(&fs).read_dir("/").unwrap().count(), 0);
+
+        // Syncing a directory makes its names survive, but not the data of its files.
+        sync_dir("/");
+        assert!(crash(&fs).metadata("/a").unwrap().is_dir());
+        assert!(errs_eq(crash(&fs).metadata("/a/f").unwrap_err(), ENOENT()));
+        sync_dir("/a");
+        assert_eq!(crash(&fs).read("/a/f").unwrap(), b"");
+
+        // A file's data survives sync_data, but its metadata only survives sync_all.
+        let f = fs.new_openopts().write(true).open("/a/f").unwrap();
+        assert!(fs.set_permissions("/a/f", Permissions::from_mode(0o600)).is_ok());
+        assert!(f.sync_data().is_ok());
+        let crashed = crash(&fs);
+        assert_eq!(crashed.read("/a/f").unwrap(), b"hello");
+        assert_ne!(crashed.metadata("/a/f").unwrap().permissions().mode(), 0o600);
+        assert!(f.sync_all().is_ok());
+        assert_eq!(crash(&fs).metadata("/a/f").unwrap().permissions().mode(), 0o600);
+
+        // Writes through files opened with O_SYNC or O_DSYNC sync the file.
+        let mut opts = fs.new_openopts();
+        let dsync = opts.write(true).custom_flags(O_DSYNC).open("/a/f").unwrap();
+        assert!((&dsync).write_all(b"HE").is_ok());
+        assert_eq!(crash(&fs).read("/a/f").unwrap(), b"HEllo");
+
+        // Syncing either directory of a rename makes the whole rename survive, and names that
+        // share an inode after a crash still share it.
+        assert!(fs.rename("/a/f", "/g").is_ok());
+        assert!(fs.hard_link("/g", "/a/h").is_ok());
+        sync_dir("/a");
+        let crashed = crash(&fs);
+        assert!(errs_eq(crashed.metadata("/a/f").unwrap_err(), ENOENT()));
+        assert_eq!(crashed.read("/g").unwrap(), b"HEllo");
+        assert_eq!(crashed.metadata("/g").unwrap().ino(), crashed.metadata("/a/h").unwrap().ino());
+        assert_eq!(crashed.metadata("/g").unwrap().nlink(), 2);
+
+        // Removed files are freed even though a crash brings them back, and are forgotten once
+        // their removal is synced.
+        drop((f, dsync));
+        assert!(fs.remove_file("/g").is_ok() && fs.remove_file("/a/h").is_ok());
+        assert_eq!(fs.bytes_used(), 0);
+        assert_eq!(crash(&fs).read("/g").unwrap(), b"HEllo");
+        assert_eq!(nodes(&fs), Some(3));
+        sync_dir("/a");
+        assert_eq!(nodes(&fs), Some(3));
+        sync_dir("/");
+        assert_eq!(nodes(&fs), Some(2));
+        assert!(errs_eq(crash(&fs).metadata("/g").unwrap_err(), ENOENT()));
+
+        // O_TMPFILE data synced before the file is named survives with the name.
+        let tmp = fs.new_openopts().write(true).custom_flags(O_TMPFILE).open("/a").unwrap();
+        assert!((&tmp).write_all(b"tmp").is_ok() && tmp.sync_data().is_ok());
+        assert!(fs.link_file(&tmp, "/a/t").is_ok());
+        sync_dir("/a");
+        assert_eq!(crash(&fs).read("/a/t").unwrap(), b"tmp");
+
+        // The crashed FS starts out synced, and is separate from the FS it crashed from.
+        let crashed = crash(&fs);
+        assert!(crashed.create_file("/new").is_ok());
+        assert!(errs_eq(crash(&crashed).metadata("/new").unwrap_err(), ENOENT()));
+        assert_eq!(crash(&crashed).read("/a/t").unwrap(), b"tmp");
+        assert!(errs_eq(fs.metadata("/new").unwrap_err(), ENOENT()));
+
+        // sync syncs everything.
+        assert!(fs.create_dir("/b").is_ok());
+        fs.sync();
+        assert!(crash(&fs).metadata("/b").unwrap().is_dir());
+
+        // A crash that keeps random unsynced changes always keeps the same ones for a seed.
+        assert!(fs.create_dir_all("/c/d").is_ok());
+        assert!(fs.write("/b/f", b"data").is_ok());
+        let random = |seed| fs.crash(Crash::KeepRandom(seed));
+        assert!(random(1) == random(1));
+        let (mut kept, mut dropped) = (false, false);
+        for seed in 0..32 {
+            let crashed = random(seed);
+            kept |= crashed.metadata("/c/d").is_ok();
+            dropped |= crashed.metadata("/c").is_err();
+            if let Ok(data) = crashed.read("/b/f") {
+                assert!(data == b"data" || data.is_empty());
+            }
+        }
+        assert!(kept && dropped);
+
+        // Restoring keeps whether crashes are tracked, and only FS::with_crash_tracking starts
+        // tracking them.
+        let snapshot = fs.snapshot();
+        fs.restore(&snapshot);
+        assert!(crash(&fs).metadata("/c/d").unwrap().is_dir());
+        let mut tar = Vec::new();
+        assert!(fs.write_tar(&mut tar).is_ok());
+        let untracked = FS::from_tar(&tar[..]).unwrap();
+        assert!(untracked.create_file("/new").is_ok() && untracked.open_file("/").is_ok());
+        untracked.sync();
+        assert_eq!(nodes(&untracked), None);
+        untracked.restore(&snapshot);
+        assert_eq!(nodes(&untracked), None);
+        assert_eq!(nodes(&FS::from_snapshot(&snapshot)), nodes(&fs));
+
+        // Even removing the root directory only survives once it is synced, which it cannot be.
+        assert!(fs.remove_dir_all("/").is_ok());
+        assert!(crash(&fs).metadata("/b").unwrap().is_dir());
+    }
+
+    #[test]
+    #[should_panic(expected = "crashes are only tracked by FS::with_crash_tracking")]
+    fn crash_untracked() {
+        let fs = FS::new();
+        assert!(fs.create_file("/f").is_ok());
+        fs.crash(Crash::DropUnsynced);
+    }
 }
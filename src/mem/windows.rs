@@ -0,0 +1,2578 @@
+//! This module provides an in-memory filesystem that follows Windows semantics.
+//!
+//! This module, when used directly, is cross-platform. Paths are always parsed as Windows paths,
+//! meaning both `\` and `/` are separators and drive letter (`C:`) and UNC (`\\server\share`)
+//! prefixes are understood regardless of the host.
+//!
+//! The filesystem imitates Windows (NTFS) behavior where it differs from Unix:
+//!
+//! - names are case-insensitive but case-preserving: `Foo.txt` can be opened as `FOO.TXT`, but
+//!   listing the directory returns `Foo.txt`
+//! - reserved device names (`CON`, `PRN`, `AUX`, `NUL`, `COM1`-`COM9`, `LPT1`-`LPT9`, with or
+//!   without an extension) and names containing any of `<>:"|?*` cannot be created; opening `NUL`
+//!   opens the null device
+//! - permissions are a single readonly attribute; readonly files cannot be written, removed, or
+//!   replaced, and readonly directories cannot be removed
+//! - symlinks are created as either file or directory symlinks with [`windows_ext`]
+//! - files that are open cannot be removed or renamed, nor can directories containing them
+//! - the current directory, and directories containing it, cannot be removed or renamed
+//! - errors that Windows reports with its own codes, such as access denied, are Win32 error
+//!   codes rather than Unix `errno` values (see [`errors::windows`])
+//!
+//! Every filesystem starts with a single, empty `C:` volume, which is also the initial current
+//! directory. Relative paths are resolved from the current directory, and drive relative paths
+//! (`D:foo`) are resolved from the current directory if it is on that drive or from the root of
+//! the drive otherwise. More volumes can be added with [`FS::add_volume`].
+//!
+//! Like Windows, `..` is resolved lexically before any symlinks are followed.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::{Read, Write};
+//!
+//! use rsfs::*;
+//! use rsfs::windows_ext::*;
+//! use rsfs::mem::windows::FS;
+//!
+//! let fs = FS::new();
+//! assert!(fs.create_dir_all(r"C:\Users\Public").is_ok());
+//!
+//! let mut wf = fs.create_file(r"c:\users\public\Notes.txt").unwrap();
+//! assert_eq!(wf.write(b"hello").unwrap(), 5);
+//!
+//! // open files cannot be removed
+//! assert!(fs.remove_file(r"C:\Users\Public\notes.TXT").is_err());
+//! drop(wf);
+//!
+//! fs.symlink_dir(r"C:\Users\Public", "public").unwrap();
+//! let mut rf = fs.open_file("public/notes.txt").unwrap();
+//! let mut output = [0u8; 5];
+//! assert_eq!(rf.read(&mut output).unwrap(), 5);
+//! assert_eq!(&output, b"hello");
+//!
+//! assert_eq!(fs.canonicalize("PUBLIC/NOTES.TXT").unwrap().to_str().unwrap(),
+//!            r"\\?\C:\Users\Public\Notes.txt");
+//! ```
+//!
+//! [`windows_ext`]: ../../windows_ext/index.html
+//! [`errors::windows`]: ../../errors/windows/index.html
+//! [`FS::add_volume`]: struct.FS.html#method.add_volume
+
+extern crate parking_lot;
+
+use self::parking_lot::{Mutex, RwLock};
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::vec::IntoIter;
+
+use fs::{self, DirBuilder as _DirBuilder, FileType as _FileType, Metadata as _Metadata};
+use windows_ext;
+
+use errors::*;
+use errors::windows::{EACCES, ELOOP, ENOTEMPTY};
+use mem::clock::{Clock, SharedClock, SystemClock};
+use mem::diff::{self, Change};
+use mem::host;
+use mem::lock::{self, Locks};
+use mem::render::{self, RenderOptions};
+use mem::tar;
+use mem::space::{Charge, Space};
+use mem::sparse::Data;
+use path_parts::{normalize_windows, Part, Prefix};
+
+/// `MAXLINKS` is the number of symlinks that will be followed when resolving a path before
+/// erroring. Windows allows 63 reparse points per path.
+const MAXLINKS: u8 = 63;
+
+/// `DEFAULT_VOLUME` is the drive every filesystem starts with and whose root is the initial
+/// current directory.
+const DEFAULT_VOLUME: Prefix = Prefix::Disk(b'C');
+
+/// `RESERVED` are the device names that cannot be used as file names, even with an extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A builder used to create directories in various manners.
+///
+/// This builder implements [`rsfs::DirBuilder`].
+///
+/// [`rsfs::DirBuilder`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.DirBuilder.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// let db = fs.new_dirbuilder();
+/// db.create(r"C:\dir")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DirBuilder {
+    /// fs is what we reach inside to create our directory.
+    fs:        FS,
+    /// recursive indicates that nested directories will be created recursively.
+    recursive: bool,
+}
+
+impl fs::DirBuilder for DirBuilder {
+    fn recursive(&mut self, recursive: bool) -> &mut Self {
+        self.recursive = recursive; self
+    }
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if self.recursive {
+            self.fs.0.lock().create_dir_all(path)
+        } else {
+            self.fs.0.lock().create_dir(path)
+        }
+    }
+}
+
+/// Entries returned by the [`ReadDir`] iterator.
+///
+/// An instance of `DirEntry` implements [`rsfs::DirEntry`] and represents an entry inside a
+/// directory on the in-memory filesystem.
+///
+/// [`rsfs::DirEntry`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.DirEntry.html
+/// [`ReadDir`]: struct.ReadDir.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// for entry in fs.read_dir(r"C:\")? {
+///     let entry = entry?;
+///     println!("{:?}: {:?}", entry.path(), entry.metadata()?.permissions());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DirEntry {
+    /// dir is the original path requested for ReadDir. We make no attempt to clean it.
+    dir:   PathBuf,
+    /// base is the case preserved name of the file/dir/symlink at this dirent.
+    base:  OsString,
+    /// inode is the backing "inode" for this DirEntry. Like the Unix implementation, metadata is
+    /// not cached in the DirEntry.
+    inode: Inode,
+}
+
+impl fs::DirEntry for DirEntry {
+    type Metadata = Metadata;
+    type FileType = FileType;
+
+    fn path(&self) -> PathBuf {
+        join(&self.dir, &self.base)
+    }
+    fn metadata(&self) -> Result<Self::Metadata> {
+        Ok(Metadata(*self.inode.read()))
+    }
+    fn file_type(&self) -> Result<Self::FileType> {
+        Ok(self.inode.read().ftyp)
+    }
+    fn file_name(&self) -> OsString {
+        self.base.clone()
+    }
+}
+
+/// `RawFile` is the underlying contents of a file in our filesystem.
+///
+/// Unlike the Unix implementation, a `RawFile` tracks how many `File`s are open on it: Windows
+/// refuses to remove or rename files that are open.
+#[derive(Debug)]
+struct RawFile {
+    /// data is the backing file data. Holes are only a way to save memory: unlike the Unix
+    /// implementation, files are charged for their full length, as NTFS files are not sparse.
+    data:    Data,
+    /// inode allows us to read and write the most up to date metadata.
+    inode:   Inode,
+    /// handles is the number of open `File`s viewing this file.
+    handles: usize,
+    /// null signifies this file is the `NUL` device: reads return nothing and writes are
+    /// discarded.
+    null:    bool,
+    /// locks tracks the advisory locks held on this file by open handles.
+    locks:   Arc<Locks>,
+}
+
+impl RawFile {
+    /// read_at reads contents of the file into dst from a given index in the file.
+    fn read_at(&self, at: usize, dst: &mut [u8]) -> Result<usize> {
+        self.inode.touch(ACCESSED);
+        Ok(self.data.read_at(at, dst))
+    }
+
+    /// write_at writes to the RawFile at a given index, zero extending the existing data if
+    /// necessary.
+    fn write_at(&mut self, at: usize, src: &[u8]) -> Result<usize> {
+        if src.is_empty() || self.null {
+            return Ok(src.len())
+        }
+        self.inode.charge.resize(cmp::max(self.data.len(), at + src.len()))?;
+        self.data.write_at(at, src);
+        self.inode.touch(MODIFIED);
+        self.inode.write().length = self.data.len();
+        Ok(src.len())
+    }
+}
+
+/// A view into a file on the filesystem.
+///
+/// An instance of `File` can be read or written to depending on the options it was opened with.
+/// Files also implement `Seek` to alter the logical cursor position of the internal file.
+///
+/// While a `File` (or any of its clones) is alive, the file it views cannot be removed or renamed.
+///
+/// This struct implements [`rsfs::File`].
+///
+/// [`rsfs::File`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.File.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # use std::io::Write;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// let mut f = fs.create_file("f")?;
+/// assert_eq!(f.write(&[1, 2, 3])?, 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct File {
+    /// read indicates this file view can read the underlying file.
+    read:   bool,
+    /// write indicates this file view can write to the underlying file.
+    write:  bool,
+    /// append indicates this file view will append to the current end of the underlying file on
+    /// every write.
+    append: bool,
+
+    /// cursor is wrapped in an `Arc<Mutex<_>>` solely to support `File`s probably-never-used
+    /// `try_clone` function.
+    cursor: Arc<Mutex<FileCursor>>,
+}
+
+impl File {
+    /// Opens a new handle on a raw file, registering the handle so that the file cannot be
+    /// removed while the handle is open.
+    fn new(file: Arc<RwLock<RawFile>>, opts: &OpenOptions) -> File {
+        file.write().handles += 1;
+        File {
+            read:   opts.read,
+            write:  opts.write || opts.append,
+            append: opts.append,
+            cursor: Arc::new(Mutex::new(FileCursor { file, at: 0 })),
+        }
+    }
+
+    /// locks returns the locks on our file along with the id of our open file description, which
+    /// is the address of our cursor. Locks are released when the cursor drops.
+    fn locks(&self) -> (usize, Arc<Locks>) {
+        let cursor = self.cursor.lock();
+        let id = &*cursor as *const FileCursor as usize;
+        let locks = cursor.file.read().locks.clone();
+        (id, locks)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        self.cursor.lock().file.write().handles -= 1;
+    }
+}
+
+impl fs::File for File {
+    type Metadata    = Metadata;
+    type Permissions = Permissions;
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_len(&self, size: u64) -> Result<()> {
+        if !self.write {
+            return Err(EACCES());
+        }
+        self.cursor.lock().set_len(size)
+    }
+
+    fn metadata(&self) -> Result<Self::Metadata> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.read();
+        let meta = Metadata(*file.inode.read());
+        Ok(meta)
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        let cursor = self.cursor.lock();
+        cursor.file.write().handles += 1;
+        Ok(File {
+            read:   self.read,
+            write:  self.write,
+            append: self.append,
+            cursor: self.cursor.clone(),
+        })
+    }
+
+    fn set_permissions(&self, perms: Self::Permissions) -> Result<()> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.write();
+        let mut inode = file.inode.write();
+        inode.perms = perms;
+        Ok(())
+    }
+
+    fn set_times(&self, times: fs::FileTimes) -> Result<()> {
+        let cursor = self.cursor.lock();
+        let file = cursor.file.write();
+        file.inode.write().times.set(times);
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<()> {
+        let (id, locks) = self.locks();
+        locks.lock(id, lock::Kind::Exclusive, true);
+        Ok(())
+    }
+
+    fn lock_shared(&self) -> Result<()> {
+        let (id, locks) = self.locks();
+        locks.lock(id, lock::Kind::Shared, true);
+        Ok(())
+    }
+
+    fn try_lock(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        let (id, locks) = self.locks();
+        if locks.lock(id, lock::Kind::Exclusive, false) {
+            Ok(())
+        } else {
+            Err(fs::TryLockError::WouldBlock)
+        }
+    }
+
+    fn try_lock_shared(&self) -> ::std::result::Result<(), fs::TryLockError> {
+        let (id, locks) = self.locks();
+        if locks.lock(id, lock::Kind::Shared, false) {
+            Ok(())
+        } else {
+            Err(fs::TryLockError::WouldBlock)
+        }
+    }
+
+    fn unlock(&self) -> Result<()> {
+        let (id, locks) = self.locks();
+        locks.unlock(id);
+        Ok(())
+    }
+}
+
+/// `FileCursor` corresponds to an actual file handle, which, "behind the scenes", keeps track of
+/// where we are in a file.
+#[derive(Debug)]
+struct FileCursor {
+    /// file is the actual underlying file. It is wrapped in an `Arc<RwLock<>>` because other file
+    /// handles can also be reading to or writing to this file.
+    file: Arc<RwLock<RawFile>>,
+    /// at tracks this cursor's position in the underlying file.
+    at:   usize,
+}
+
+impl Drop for FileCursor {
+    fn drop(&mut self) {
+        let id = self as *const FileCursor as usize;
+        self.file.read().locks.unlock(id);
+    }
+}
+
+impl FileCursor {
+    /// The backing function for `File`s `set_len`, this can truncate or zero extend the
+    /// underlying file.
+    fn set_len(&mut self, size: u64) -> Result<()> {
+        let mut file = self.file.write();
+        if file.null {
+            return Ok(());
+        }
+        file.inode.charge.resize(size as usize)?;
+        file.data.set_len(size as usize);
+        file.inode.touch(MODIFIED);
+        file.inode.write().length = size as usize;
+        Ok(())
+    }
+
+    /// The backing function for `File`s `read`.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.file.read().read_at(self.at, buf)?;
+        self.at += n;
+        Ok(n)
+    }
+
+    /// The backing function for `File`s `write`.
+    fn write(&mut self, buf: &[u8], append: bool) -> Result<usize> {
+        let mut file = self.file.write();
+        if append {
+            self.at = file.data.len();
+        }
+        let n = file.write_at(self.at, buf)?;
+        if !file.null {
+            self.at += n;
+        }
+        Ok(n)
+    }
+
+    /// The backing function for `File`s `seek`. Windows allows seeking past the end of a file;
+    /// like the Unix implementation, we clamp the cursor to the end of the file but return the
+    /// requested position.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let file = self.file.write();
+        file.inode.touch(ACCESSED);
+
+        // Seeking past the end of the file is allowed; writes there extend the file.
+        let at = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => (self.at as i64).saturating_add(offset),
+            SeekFrom::End(offset) => (file.data.len() as i64).saturating_add(offset),
+        };
+        if at < 0 {
+            return Err(EINVAL());
+        }
+        self.at = at as usize;
+        Ok(at as u64)
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (&mut &*self).read(buf)
+    }
+}
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (&mut &*self).write(buf)
+    }
+    fn flush(&mut self) -> Result<()> {
+        (&mut &*self).flush()
+    }
+}
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.cursor.lock().seek(pos)
+    }
+}
+
+// Now we actually do the impls for &'a File. Windows fails reads and writes on handles without
+// the proper access with ERROR_ACCESS_DENIED.
+
+impl Read for &File {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if !self.read {
+            return Err(EACCES());
+        }
+        self.cursor.lock().read(buf)
+    }
+}
+impl Write for &File {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if !self.write {
+            return Err(EACCES());
+        }
+        self.cursor.lock().write(buf, self.append)
+    }
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+impl Seek for &File {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.cursor.lock().seek(pos)
+    }
+}
+
+/// `Ftyp` is the actual underlying enum for a `FileType`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Ftyp {
+    File,
+    Dir,
+    SymlinkFile,
+    SymlinkDir,
+}
+
+/// Returned from [`Metadata::file_type`], this structure represents the type of a file.
+///
+/// This structure implements [`rsfs::FileType`] and has [windows extensions].
+///
+/// [`Metadata::file_type`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.Metadata.html#tymethod.file_type
+/// [`rsfs::FileType`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.FileType.html
+/// [windows extensions]: https://docs.rs/rsfs/0.4.1/rsfs/windows_ext/trait.FileTypeExt.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// let f = fs.create_file("f")?;
+/// assert!(fs.metadata("F")?.file_type().is_file());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FileType(Ftyp);
+
+impl fs::FileType for FileType {
+    fn is_dir(&self) -> bool {
+        self.0 == Ftyp::Dir
+    }
+    fn is_file(&self) -> bool {
+        self.0 == Ftyp::File
+    }
+    fn is_symlink(&self) -> bool {
+        self.0 == Ftyp::SymlinkFile || self.0 == Ftyp::SymlinkDir
+    }
+}
+
+impl windows_ext::FileTypeExt for FileType {
+    fn is_symlink_dir(&self) -> bool {
+        self.0 == Ftyp::SymlinkDir
+    }
+    fn is_symlink_file(&self) -> bool {
+        self.0 == Ftyp::SymlinkFile
+    }
+}
+
+/// Metadata information about a file.
+///
+/// This structure, which implements [`rsfs::Metadata`], is returned from the [`metadata`] or
+/// [`symlink_metadata`] methods and represents known metadata information about a file at the
+/// instant in time this structure is instantiated.
+///
+/// [`rsfs::Metadata`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.Metadata.html
+/// [`metadata`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.metadata
+/// [`symlink_metadata`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.symlink_metadata
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// fs.create_file("f")?;
+/// println!("{:?}", fs.metadata("f")?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Metadata(InodeData); // Metadata is a copy of InodeData at a point in time.
+
+impl fs::Metadata for Metadata {
+    type Permissions = Permissions;
+    type FileType    = FileType;
+
+    fn file_type(&self) -> Self::FileType {
+        self.0.ftyp
+    }
+    fn is_dir(&self) -> bool {
+        self.0.ftyp.is_dir()
+    }
+    fn is_file(&self) -> bool {
+        self.0.ftyp.is_file()
+    }
+    fn len(&self) -> u64 {
+        self.0.length as u64
+    }
+    fn permissions(&self) -> Self::Permissions {
+        self.0.perms
+    }
+    fn modified(&self) -> Result<SystemTime> {
+        Ok(self.0.times.modified)
+    }
+    fn accessed(&self) -> Result<SystemTime> {
+        Ok(self.0.times.accessed)
+    }
+    fn created(&self) -> Result<SystemTime> {
+        Ok(self.0.times.created)
+    }
+}
+
+/// Options and flags which can be used to configure how a file is opened.
+///
+/// This builder, created from `GenFS`s [`new_openopts`], exposes the ability to configure how a
+/// [`File`] is opened and what operations are permitted on the open file. `GenFS`s [`open_file`]
+/// and [`create_file`] methods are aliases for commonly used options with this builder.
+///
+/// Like Windows, opening fails with `EINVAL` if no access mode is set or if `create`,
+/// `create_new`, or `truncate` are used without write access.
+///
+/// This builder implements [`rsfs::OpenOptions`].
+///
+/// [`new_openopts`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.new_openopts
+/// [`open_file`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.open_file
+/// [`create_file`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html#tymethod.create_file
+/// [`rsfs::OpenOptions`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.OpenOptions.html
+/// [`File`]: struct.File.html
+///
+/// # Examples
+///
+/// Opening a file for both reading and writing, as well as creating it if it doesn't exist:
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// # let fs = FS::new();
+/// let mut f = fs.new_openopts()
+///               .read(true)
+///               .write(true)
+///               .create(true)
+///               .open(r"C:\f.txt")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    fs:     FS,
+    read:   bool,
+    write:  bool,
+    append: bool,
+    trunc:  bool,
+    create: bool,
+    excl:   bool,
+}
+
+impl fs::OpenOptions for OpenOptions {
+    type File = File;
+
+    fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read; self
+    }
+    fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write; self
+    }
+    fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append; self
+    }
+    fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.trunc = truncate; self
+    }
+    fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create; self
+    }
+    fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.excl = create_new; self
+    }
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        self.fs.0.lock().open(path, self)
+    }
+}
+
+/// Representation of the various permissions on a file.
+///
+/// Windows only has a readonly attribute. This struct implements [`rsfs::Permissions`].
+///
+/// [`rsfs::Permissions`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.Permissions.html
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::windows::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// # let fs = FS::new();
+/// # fs.create_file("foo.txt")?;
+/// let mut perms = fs.metadata("foo.txt")?.permissions();
+/// perms.set_readonly(true);
+/// fs.set_permissions("foo.txt", perms)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    readonly: bool,
+}
+
+impl fs::Permissions for Permissions {
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+    fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly
+    }
+}
+
+/// Iterator over entries in a directory.
+///
+/// This is returned from the [`read_dir`] method of `GenFS` and yields instances of
+/// `io::Result<DirEntry>`. Entries are returned in case-insensitive name order, which is the
+/// order NTFS returns them in.
+///
+/// [`read_dir`]: struct.FS.html#method.read_dir
+#[derive(Debug)]
+pub struct ReadDir {
+    ents: IntoIter<DirEntry>,
+}
+
+impl ReadDir {
+    fn new<P: AsRef<Path>>(path: P, dir: &Dirent) -> Result<ReadDir> {
+        let children = match dir.kind {
+            DeKind::Dir(ref children) => children,
+            _ => return Err(ENOTDIR()),
+        };
+        dir.inode.touch(ACCESSED);
+
+        let dirents = children.values().map(|child| DirEntry {
+            dir:   PathBuf::from(path.as_ref()),
+            base:  child.name.clone(),
+            inode: child.inode.clone(),
+        }).collect::<Vec<_>>();
+        Ok(ReadDir { ents: dirents.into_iter() })
+    }
+}
+
+impl Iterator for ReadDir {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ents.next().map(Ok)
+    }
+}
+
+/// An in-memory struct that satisfies [`rsfs::GenFS`] and follows Windows semantics.
+///
+/// `FS` is thread safe and copyable. It operates internally with an `Arc<Mutex<FileSystem>>`
+/// (`FileSystem` not being exported) and forces all filesystem calls to go through the mutex.
+///
+/// See the module [documentation] for the Windows behavior this filesystem mimics.
+///
+/// [`rsfs::GenFS`]: https://docs.rs/rsfs/0.4.1/rsfs/trait.GenFS.html
+/// [documentation]: index.html
+///
+/// # Examples
+///
+/// ```
+/// use rsfs::*;
+/// use rsfs::mem::windows::FS;
+///
+/// let fs = FS::new();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FS(Arc<Mutex<FileSystem>>);
+
+/// Renders the volume holding the current directory with the default [`RenderOptions`], which
+/// include only names.
+///
+/// [`RenderOptions`]: ../struct.RenderOptions.html
+impl fmt::Display for FS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.render(&RenderOptions::new()))
+    }
+}
+
+impl FS {
+    /// Creates an `FS` with a single, empty `C:` volume.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// let fs = FS::new();
+    /// ```
+    pub fn new() -> FS {
+        Self::with_clock(SystemClock)
+    }
+
+    /// Creates an `FS` with a single, empty `C:` volume that takes all of its timestamps from
+    /// `clock`.
+    ///
+    /// See the [`clock`] module for the available clocks.
+    ///
+    /// [`clock`]: ../clock/index.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// use std::time::UNIX_EPOCH;
+    /// use rsfs::mem::clock::FrozenClock;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::with_clock(FrozenClock::new(UNIX_EPOCH));
+    /// fs.create_file("f")?;
+    /// assert_eq!(fs.metadata("f")?.modified()?, UNIX_EPOCH);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_clock<C: Clock + 'static>(clock: C) -> FS {
+        let mut fs = FileSystem {
+            volumes: BTreeMap::new(),
+            clock:   Arc::new(clock),
+            space:   Arc::new(Space::default()),
+            cwd:     Cwd { prefix: DEFAULT_VOLUME, dirs: Vec::new() },
+        };
+        let root = fs.new_inode(Ftyp::Dir).expect("unlimited space is never full");
+        fs.volumes.insert(DEFAULT_VOLUME, Dirent::new_dir(OsString::new(), root));
+        FS(Arc::new(Mutex::new(fs)))
+    }
+
+    /// Creates an `FS` with a single, empty `C:` volume that can hold at most `bytes` bytes of
+    /// file data and `inodes` files, directories, and symlinks across all volumes, including each
+    /// volume's root directory.
+    ///
+    /// Creating an entry when no inodes are left, or writing or extending a file past the byte
+    /// limit, fails with `ENOSPC`. Writes are all or nothing: a write that does not entirely fit
+    /// writes nothing. Space is freed when a file is truncated or removed. See [`set_limits`] to
+    /// change the limits later.
+    ///
+    /// [`set_limits`]: #method.set_limits
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// use std::io::Write;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::with_limits(4, 100);
+    ///
+    /// let mut f = fs.create_file("f")?;
+    /// assert!(f.write(b"hello").is_err());
+    /// assert_eq!(f.write(b"hell")?, 4);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_limits(bytes: u64, inodes: u64) -> FS {
+        let fs = Self::new();
+        fs.set_limits(Some(bytes), Some(inodes));
+        fs
+    }
+
+    /// Sets the maximum bytes of file data and the maximum number of inodes this `FS` can hold,
+    /// where `None` is unlimited.
+    ///
+    /// Limits can be set below what is already in use. Operations that need more space then fail
+    /// with `ENOSPC` until enough is freed.
+    pub fn set_limits(&self, bytes: Option<u64>, inodes: Option<u64>) {
+        self.0.lock().space.set_limits(bytes, inodes)
+    }
+
+    /// Returns the number of bytes of file data currently held by this `FS`.
+    pub fn bytes_used(&self) -> u64 {
+        self.0.lock().space.bytes_used()
+    }
+
+    /// Returns the number of inodes currently held by this `FS`, including volume roots.
+    pub fn inodes_used(&self) -> u64 {
+        self.0.lock().space.inodes_used()
+    }
+
+    /// Returns an immutable copy of this `FS` that can later be passed to [`restore`] or
+    /// [`from_snapshot`].
+    ///
+    /// A snapshot copies every volume: file contents, readonly flags, timestamps, symlinks, which
+    /// names are hard links to the same file, and the current directory. A snapshot does not count
+    /// against this `FS`'s limits.
+    ///
+    /// [`restore`]: #method.restore
+    /// [`from_snapshot`]: #method.from_snapshot
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir("a")?;
+    /// let snapshot = fs.snapshot();
+    ///
+    /// fs.remove_dir("a")?;
+    /// fs.restore(&snapshot);
+    /// assert!(fs.metadata("a")?.is_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        let fs = self.0.lock();
+        let copy = fs.copy(fs.clock.clone(), Arc::new(Space::default()));
+        Snapshot(FS(Arc::new(Mutex::new(copy))))
+    }
+
+    /// Rolls this `FS` back to `snapshot`, which may have been taken from any `FS`.
+    ///
+    /// The restored volumes keep this `FS`'s clock and limits. Restored files count against the
+    /// limits but are never refused for going over them; see [`set_limits`]. Files that are open
+    /// when restoring keep referring to the files they were opened from, which are no longer in
+    /// the filesystem.
+    ///
+    /// [`set_limits`]: #method.set_limits
+    pub fn restore(&self, snapshot: &Snapshot) {
+        let mut fs = self.0.lock();
+        let copy = snapshot.0 .0.lock().copy(fs.clock.clone(), fs.space.clone());
+        *fs = copy;
+    }
+
+    /// Creates a new `FS` from `snapshot` with no limits and using the clock of the `FS` the
+    /// snapshot was taken from.
+    pub fn from_snapshot(snapshot: &Snapshot) -> FS {
+        let snap = snapshot.0 .0.lock();
+        FS(Arc::new(Mutex::new(snap.copy(snap.clock.clone(), Arc::new(Space::default())))))
+    }
+
+    /// Copies the host file or directory tree at `host` into this `FS` at `into`.
+    ///
+    /// File contents, read-only flags, symlinks, access and modification times, and, from Unix
+    /// hosts, hard links between files in the tree are kept; symlink timestamps are not.
+    /// Directories that already exist in this `FS` are reused and files are overwritten.
+    ///
+    /// Host entries that cannot be represented, such as sockets, FIFOs, and devices, are not
+    /// copied. Their host paths are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// let skipped = fs.import_from("tests/fixtures", r"C:\fixtures")?;
+    /// assert!(skipped.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn import_from<P: AsRef<Path>, Q: AsRef<Path>>(&self, host: P, into: Q)
+        -> Result<Vec<PathBuf>>
+    {
+        let (nodes, skipped) = host::read(host)?;
+        self.import(&nodes, into)?;
+        Ok(skipped)
+    }
+
+    /// Copies the volume holding the current directory to the host directory `host`, creating it
+    /// if necessary.
+    ///
+    /// File contents, read-only flags, symlinks, access and modification times, and hard links
+    /// are kept; symlink timestamps are not. Directories that already exist on the host are
+    /// reused; files and symlinks already there are replaced, never written through.
+    ///
+    /// Like the Unix `FS`, this returns the host paths of entries that are not copied. Every
+    /// entry of a Windows `FS` can be copied, so they are always empty.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir_all(r"C:")?;
+    /// let skipped = fs.export_to(r"C:\exported")?;
+    /// assert!(skipped.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn export_to<P: AsRef<Path>>(&self, host: P) -> Result<Vec<PathBuf>> {
+        let nodes = self.0.lock().nodes();
+        host::write(&nodes, host)
+    }
+
+    /// Creates an `FS` with a single `C:` volume holding the tar archive read from `reader`.
+    ///
+    /// Regular files, directories, symlinks, and hard links are recreated with the modification
+    /// time from their headers; a mode without any write bits makes the entry read-only. Symlinks
+    /// are created as file symlinks. Parent directories missing from the archive are created.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error of kind `InvalidData` if the archive is malformed, contains
+    /// a path that leaves the root with `..`, or contains an entry that cannot be represented,
+    /// such as a FIFO or device.
+    pub fn from_tar<R: Read>(reader: R) -> Result<FS> {
+        let fs = FS::new();
+        fs.import(&tar::read(reader)?, r"C:\")?;
+        Ok(fs)
+    }
+
+    /// Writes the volume holding the current directory to `writer` as a tar archive.
+    ///
+    /// Read-only entries are written without write bits in their mode. Hard links are written as
+    /// tar hard links to the first name of the file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir_all(r"a\b")?;
+    ///
+    /// let mut tar = Vec::new();
+    /// fs.write_tar(&mut tar)?;
+    /// assert!(FS::from_tar(&tar[..])?.metadata(r"C:\a\b")?.is_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_tar<W: Write>(&self, writer: W) -> Result<()> {
+        let nodes = self.0.lock().nodes();
+        tar::write(&nodes, writer)
+    }
+
+    /// Renders the volume holding the current directory as text, one entry per line, with details
+    /// chosen by `opts`. See [`RenderOptions`] for the format.
+    ///
+    /// Modes are `0666` for files and `0777` for directories and symlinks, without write bits for
+    /// read-only entries.
+    ///
+    /// [`RenderOptions`]: ../struct.RenderOptions.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::RenderOptions;
+    /// # use rsfs::mem::windows::FS;
+    /// use rsfs::windows_ext::*;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.create_dir_all(r"a\b")?;
+    /// fs.create_file(r"a\f")?;
+    /// fs.symlink_file(r"C:\a\f", "l")?;
+    ///
+    /// assert_eq!(fs.render(RenderOptions::new().max_depth(1)), r"C:\
+    ///     a\
+    ///         ...
+    ///     l -> C:\a\f
+    /// ");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn render(&self, opts: &RenderOptions) -> String {
+        let fs = self.0.lock();
+        render::render(&fs.nodes(), &format!(r"{}\", fs.cwd.prefix), '\\', opts)
+    }
+
+    /// import recreates nodes at into.
+    fn import<P: AsRef<Path>>(&self, nodes: &[host::Node], into: P) -> Result<()> {
+        use windows_ext::GenFSExt;
+        host::import(self,
+                     nodes,
+                     into,
+                     |fs, target, path, dir| if dir {
+                         fs.symlink_dir(target, path)
+                     } else {
+                         fs.symlink_file(target, path)
+                     },
+                     |_, _, _, _| Err(Error::new(ErrorKind::InvalidData,
+                                                 "special files cannot be represented")),
+                     |mode| Permissions { readonly: mode & 0o222 == 0 })
+    }
+
+    /// Adds a new, empty volume to the filesystem.
+    ///
+    /// The volume can be either a drive (`D:`) or a UNC share (`\\server\share`).
+    ///
+    /// # Errors
+    ///
+    /// This function returns `EINVAL` if `volume` is not just a drive or UNC prefix and `EEXIST`
+    /// if the volume already exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rsfs::*;
+    /// # use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    /// fs.add_volume("D:")?;
+    /// fs.add_volume(r"\\server\share")?;
+    /// fs.create_file(r"\\server\share\f.txt")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_volume<P: AsRef<Path>>(&self, volume: P) -> Result<()> {
+        let parts = normalize_windows(&volume);
+        let prefix = match parts.prefix {
+            Some(prefix) if parts.inner.is_empty() => prefix,
+            _ => return Err(EINVAL()),
+        };
+        let mut fs = self.0.lock();
+        if fs.volumes.contains_key(&prefix) {
+            return Err(EEXIST());
+        }
+        let root = fs.new_inode(Ftyp::Dir)?;
+        fs.volumes.insert(prefix, Dirent::new_dir(OsString::new(), root));
+        Ok(())
+    }
+}
+
+impl Default for FS {
+    fn default() -> Self {
+        FS::new()
+    }
+}
+
+/// An immutable copy of an [`FS`] at a point in time, created with [`FS::snapshot`].
+///
+/// Cloning a `Snapshot` is cheap; clones share the same copy.
+///
+/// [`FS`]: struct.FS.html
+/// [`FS::snapshot`]: struct.FS.html#method.snapshot
+#[derive(Clone, Debug)]
+pub struct Snapshot(FS);
+
+/// Returns the differences between the volumes holding the current directories of `a` and `b`, as
+/// changes that would turn `a` into `b`. Paths are reported on the volume of `a`.
+///
+/// Contents, read-only flags, types, symlink targets, and which names are hard links to the same
+/// file are compared; timestamps are not. Changes are sorted by path, compared case-sensitively.
+///
+/// # Examples
+///
+/// ```
+/// # use rsfs::*;
+/// # use rsfs::mem::Change;
+/// # use rsfs::mem::windows::{self, FS};
+/// use std::path::PathBuf;
+/// # fn foo() -> std::io::Result<()> {
+/// let (a, b) = (FS::new(), FS::new());
+/// a.create_dir("d")?;
+///
+/// assert_eq!(windows::diff(&a, &b)?, vec![Change::Removed(PathBuf::from(r"C:\d"))]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn diff(a: &FS, b: &FS) -> Result<Vec<Change>> {
+    let (from, root) = {
+        let fs = a.0.lock();
+        (fs.nodes(), PathBuf::from(format!(r"{}\", fs.cwd.prefix)))
+    };
+    let to = b.0.lock().nodes();
+    Ok(diff::changes(&from, &to, &root))
+}
+
+impl fs::GenFS for FS {
+    type DirBuilder  = DirBuilder;
+    type DirEntry    = DirEntry;
+    type File        = File;
+    type Metadata    = Metadata;
+    type OpenOptions = OpenOptions;
+    type Permissions = Permissions;
+    type ReadDir     = ReadDir;
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.0.lock().canonicalize(path)
+    }
+    fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> {
+        // Like the Unix implementation, we repeat the std::fs pattern of implementing copy with
+        // other filesystem functions.
+        use fs::OpenOptions;
+        use fs::File;
+
+        let (from, to) = (from.as_ref(), to.as_ref());
+        match self.metadata(from) {
+            Ok(ref meta) if meta.is_file() => (),
+            _ => return Err(Error::new(ErrorKind::InvalidInput,
+                                       "the source path is not an existing regular file")),
+        }
+
+        let mut reader = self.new_openopts().read(true).open(from)?;
+        let mut writer = self.new_openopts().write(true).truncate(true).create(true).open(to)?;
+        let perm = reader.metadata()?.permissions();
+        let ret = io::copy(&mut reader, &mut writer)?;
+        drop(writer); // a readonly permission would otherwise prevent the handle from closing
+        self.set_permissions(to, perm)?;
+        Ok(ret)
+    }
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.new_dirbuilder().create(path)
+    }
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.new_dirbuilder().recursive(true).create(path)
+    }
+    fn current_dir(&self) -> Result<PathBuf> {
+        Ok(self.0.lock().current_dir())
+    }
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        self.0.lock().hard_link(src, dst)
+    }
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        self.0.lock().metadata(path, true)
+    }
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadDir> {
+        self.0.lock().read_dir(path)
+    }
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.0.lock().read_link(path)
+    }
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.0.lock().remove_dir(path)
+    }
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.0.lock().remove_dir_all(path)
+    }
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.0.lock().remove_file(path)
+    }
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.0.lock().rename(from, to)
+    }
+    fn set_current_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.0.lock().set_current_dir(path)
+    }
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perms: Self::Permissions) -> Result<()> {
+        self.0.lock().set_permissions(path, perms)
+    }
+    fn set_times<P: AsRef<Path>>(&self, path: P, times: fs::FileTimes) -> Result<()> {
+        self.0.lock().set_times(path, times)
+    }
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Self::Metadata> {
+        self.0.lock().metadata(path, false)
+    }
+
+    fn new_openopts(&self) -> Self::OpenOptions {
+        OpenOptions {
+            fs:     self.clone(),
+            read:   false,
+            write:  false,
+            append: false,
+            trunc:  false,
+            create: false,
+            excl:   false,
+        }
+    }
+    fn new_dirbuilder(&self) -> Self::DirBuilder {
+        DirBuilder {
+            fs:        self.clone(),
+            recursive: false,
+        }
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        use fs::OpenOptions;
+        self.new_openopts().read(true).open(path.as_ref())
+    }
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File> {
+        use fs::OpenOptions;
+        self.new_openopts().write(true).create(true).truncate(true).open(path.as_ref())
+    }
+
+    // read and write hold our lock across opening and using the file.
+    fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        use fs::OpenOptions;
+        let mut opts = self.new_openopts();
+        opts.read(true);
+        let mut fs = self.0.lock();
+        let mut data = Vec::new();
+        (&fs.open(path, &opts)?).read_to_end(&mut data)?;
+        Ok(data)
+    }
+    fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> Result<()> {
+        use fs::OpenOptions;
+        let mut opts = self.new_openopts();
+        opts.write(true).create(true).truncate(true);
+        let mut fs = self.0.lock();
+        (&fs.open(path, &opts)?).write_all(contents.as_ref())
+    }
+}
+
+impl windows_ext::GenFSExt for FS {
+    fn symlink_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        self.0.lock().symlink(src, dst, Ftyp::SymlinkFile)
+    }
+    fn symlink_dir<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()> {
+        self.0.lock().symlink(src, dst, Ftyp::SymlinkDir)
+    }
+}
+
+/// Times tracks the modified, accessed, and created time for a Dirent.
+#[derive(Copy, Clone, Debug)]
+struct Times {
+    modified: SystemTime,
+    accessed: SystemTime,
+    created:  SystemTime,
+}
+
+/// Bitflag indicating a Dirent was modified.
+const MODIFIED: u8 = 1; // modified time
+/// Bitflag indicating a Dirent was accessed.
+const ACCESSED: u8 = 2; // accessed time
+
+impl Times {
+    fn new(now: SystemTime) -> Times {
+        Times {
+            modified: now,
+            accessed: now,
+            created:  now,
+        }
+    }
+
+    fn update(&mut self, fields: u8, now: SystemTime) {
+        if fields & MODIFIED != 0 {
+            self.modified = now;
+        }
+        if fields & ACCESSED != 0 {
+            self.accessed = now;
+        }
+    }
+
+    fn set(&mut self, times: fs::FileTimes) {
+        if let Some(accessed) = times.accessed() {
+            self.accessed = accessed;
+        }
+        if let Some(modified) = times.modified() {
+            self.modified = modified;
+        }
+    }
+}
+
+/// `InodeData` is the backing shared data of a file. NTFS supports hard links, so, like the Unix
+/// implementation, this data is shared between every name for a file.
+#[derive(Copy, Clone, Debug)]
+struct InodeData {
+    times:  Times,
+    perms:  Permissions,
+    ftyp:   FileType,
+    length: usize,
+}
+
+/// `Inode` is what makes sharing `InodeData` between hard links / dirents / raw files possible.
+/// Each inode also holds the filesystem's clock, which all of its time updates go through, and its
+/// charge against the filesystem's space, which is released when the last clone drops.
+#[derive(Clone, Debug)]
+struct Inode {
+    data:   Arc<RwLock<InodeData>>,
+    clock:  SharedClock,
+    charge: Arc<Charge>,
+}
+
+impl Inode {
+    /// Creates an inode, failing with ENOSPC if space has no inodes left.
+    fn new(ftyp: Ftyp, clock: &SharedClock, space: &Arc<Space>) -> Result<Inode> {
+        Ok(Inode {
+            data:   Arc::new(RwLock::new(InodeData {
+                times:  Times::new(clock.now()),
+                perms:  Permissions { readonly: false },
+                ftyp:   FileType(ftyp),
+                length: 0,
+            })),
+            clock:  clock.clone(),
+            charge: Arc::new(Space::charge(space)?),
+        })
+    }
+
+    /// touch updates the given times fields to the clock's current time.
+    fn touch(&self, fields: u8) {
+        let now = self.clock.now();
+        self.write().times.update(fields, now);
+    }
+
+    fn readonly(&self) -> bool {
+        self.read().perms.readonly
+    }
+}
+
+impl Deref for Inode {
+    type Target = Arc<RwLock<InodeData>>;
+
+    #[inline]
+    fn deref(&self) -> &Arc<RwLock<InodeData>> {
+        &self.data
+    }
+}
+
+/// `DeKind` differentiates between files, directories, and symlinks. The symlink flavor (file or
+/// directory) is only tracked in the inode's ftyp.
+#[derive(Debug)]
+enum DeKind {
+    File(Arc<RwLock<RawFile>>),
+    Dir(BTreeMap<String, Dirent>),
+    Symlink(PathBuf),
+}
+
+/// Dirent represents all information needed at a node in our filesystem tree.
+///
+/// Unlike the Unix implementation, dirents own their children and have no parent pointers: `..`
+/// is resolved lexically on Windows, so every operation can walk down from a volume root.
+/// Children are keyed by their uppercased name, which gives us case-insensitive lookups while
+/// `name` preserves the case the dirent was created with.
+#[derive(Debug)]
+struct Dirent {
+    name:  OsString,
+    kind:  DeKind,
+    inode: Inode,
+}
+
+impl Dirent {
+    fn new_dir(name: OsString, inode: Inode) -> Dirent {
+        Dirent {
+            name,
+            kind: DeKind::Dir(BTreeMap::new()),
+            inode,
+        }
+    }
+
+    fn children(&self) -> Option<&BTreeMap<String, Dirent>> {
+        match self.kind {
+            DeKind::Dir(ref children) => Some(children),
+            _ => None,
+        }
+    }
+    fn children_mut(&mut self) -> &mut BTreeMap<String, Dirent> {
+        match self.kind {
+            DeKind::Dir(ref mut children) => children,
+            _ => panic!("children_mut used on Dirent when not a dir"),
+        }
+    }
+    fn ftyp(&self) -> Ftyp {
+        self.inode.read().ftyp.0
+    }
+
+    /// in_use returns whether this dirent is an open file or is a directory containing an open
+    /// file, either of which prevent removing or renaming this dirent.
+    fn in_use(&self) -> bool {
+        match self.kind {
+            DeKind::File(ref file) => file.read().handles > 0,
+            DeKind::Dir(ref children) => children.values().any(Dirent::in_use),
+            DeKind::Symlink(_) => false,
+        }
+    }
+    /// any_readonly returns whether this dirent or any dirent below it is readonly.
+    fn any_readonly(&self) -> bool {
+        self.inode.readonly() ||
+            self.children().is_some_and(|c| c.values().any(Dirent::any_readonly))
+    }
+}
+
+/// `Loc` is a resolved location in the filesystem: every directory in `dirs` exists (keyed by
+/// uppercased name), while `base`, the final component, may or may not exist. A `None` base is
+/// the root of the volume.
+#[derive(Debug)]
+struct Loc {
+    prefix: Prefix,
+    dirs:   Vec<String>,
+    base:   Option<(String, OsString)>,
+}
+
+impl Loc {
+    /// base returns the key and case preserved name of the final component, failing with
+    /// `err` for the volume root.
+    fn base(&self, err: fn() -> Error) -> Result<(&String, &OsString)> {
+        match self.base {
+            Some((ref key, ref name)) => Ok((key, name)),
+            None => Err(err()),
+        }
+    }
+}
+
+/// key returns the case-insensitive lookup key for a name.
+fn key(name: &OsString) -> String {
+    name.to_string_lossy().to_uppercase()
+}
+
+/// device returns the uppercased device name if name is a reserved device name. Devices ignore
+/// extensions and trailing spaces: `nul.txt` and `NUL ` are both `NUL`.
+fn device(name: &OsString) -> Option<String> {
+    let name = key(name);
+    let stem = name.split('.').next().unwrap_or("").trim_end_matches(' ');
+    if RESERVED.contains(&stem) {
+        Some(stem.to_string())
+    } else {
+        None
+    }
+}
+
+/// valid_name returns `EINVAL` if name cannot be created on Windows.
+fn valid_name(name: &OsString) -> Result<()> {
+    if device(name).is_some() {
+        return Err(EINVAL());
+    }
+    let name = name.to_string_lossy();
+    if name.chars().any(|c| c < ' ' || "<>:\"|?*".contains(c)) {
+        return Err(EINVAL());
+    }
+    Ok(())
+}
+
+/// join joins a directory and a name with the Windows separator.
+fn join<P: AsRef<Path>>(dir: P, name: &OsString) -> PathBuf {
+    let mut path = dir.as_ref().as_os_str().to_os_string();
+    let lossy = path.to_string_lossy();
+    if !lossy.is_empty() && !lossy.ends_with(['\\', '/', ':']) {
+        path.push("\\");
+    }
+    path.push(name);
+    PathBuf::from(path)
+}
+
+/// `Cwd` is the current working directory: a volume and the keys of the directories leading to the
+/// current directory from the volume root. Because the current directory cannot be removed or
+/// renamed, these directories always exist.
+#[derive(Clone, Debug)]
+struct Cwd {
+    prefix: Prefix,
+    dirs:   Vec<String>,
+}
+
+/// `FileSystem` is the lock protected backing of an `FS`: a set of volumes, each with a root
+/// directory. All volumes share one clock, one space, and one current directory.
+#[derive(Debug)]
+struct FileSystem {
+    volumes: BTreeMap<Prefix, Dirent>,
+    clock:   SharedClock,
+    space:   Arc<Space>,
+    cwd:     Cwd,
+}
+
+impl FileSystem {
+    /// new_inode returns an inode using our clock and charged against our space.
+    fn new_inode(&self, ftyp: Ftyp) -> Result<Inode> {
+        Inode::new(ftyp, &self.clock, &self.space)
+    }
+
+    /// copy deep copies our volumes into a new `FileSystem` whose inodes use clock and are charged
+    /// against space. Names that share a file in our volumes share the copied file.
+    fn copy(&self, clock: SharedClock, space: Arc<Space>) -> FileSystem {
+        struct Copier<'a> {
+            clock:  &'a SharedClock,
+            space:  &'a Arc<Space>,
+            inodes: HashMap<*const RwLock<InodeData>, Inode>,
+            files:  HashMap<*const RwLock<RawFile>, Arc<RwLock<RawFile>>>,
+        }
+
+        impl<'a> Copier<'a> {
+            fn inode(&mut self, inode: &Inode, len: usize) -> Inode {
+                let (clock, space) = (self.clock, self.space);
+                self.inodes.entry(Arc::as_ptr(&inode.data)).or_insert_with(|| Inode {
+                    data:   Arc::new(RwLock::new(*inode.read())),
+                    clock:  clock.clone(),
+                    charge: Arc::new(Space::charge_over(space, len)),
+                }).clone()
+            }
+
+            fn dirent(&mut self, d: &Dirent) -> Dirent {
+                let kind = match d.kind {
+                    DeKind::File(ref file) => {
+                        let ptr = Arc::as_ptr(file);
+                        if !self.files.contains_key(&ptr) {
+                            let raw = file.read();
+                            let copied = RawFile {
+                                data:    raw.data.clone(),
+                                inode:   self.inode(&raw.inode, raw.data.len()),
+                                handles: 0,
+                                null:    raw.null,
+                                locks:   Arc::default(),
+                            };
+                            self.files.insert(ptr, Arc::new(RwLock::new(copied)));
+                        }
+                        DeKind::File(self.files[&ptr].clone())
+                    }
+                    DeKind::Dir(ref children) => DeKind::Dir(children.iter()
+                        .map(|(key, child)| (key.clone(), self.dirent(child)))
+                        .collect()),
+                    DeKind::Symlink(ref sl) => DeKind::Symlink(sl.clone()),
+                };
+                let inode = match kind {
+                    DeKind::File(ref file) => file.read().inode.clone(),
+                    _ => self.inode(&d.inode, 0),
+                };
+                Dirent {
+                    name: d.name.clone(),
+                    kind,
+                    inode,
+                }
+            }
+        }
+
+        let mut copier = Copier {
+            clock:  &clock,
+            space:  &space,
+            inodes: HashMap::new(),
+            files:  HashMap::new(),
+        };
+        let volumes = self.volumes.iter()
+            .map(|(prefix, root)| (prefix.clone(), copier.dirent(root)))
+            .collect();
+        FileSystem {
+            volumes,
+            clock,
+            space,
+            cwd: self.cwd.clone(),
+        }
+    }
+
+    /// nodes lists the volume holding the current directory for exporting, ignoring permissions.
+    /// Every file is given a link so that hard links stay hard links.
+    fn nodes(&self) -> Vec<host::Node> {
+        fn walk(d: &Dirent, path: PathBuf, nodes: &mut Vec<host::Node>) {
+            let inode = *d.inode.read();
+            let (kind, mode, link) = match d.kind {
+                DeKind::File(ref file) => {
+                    let link = Some((0, Arc::as_ptr(&d.inode.data) as u64));
+                    (host::Kind::File(file.read().data.clone()), 0o666, link)
+                }
+                DeKind::Dir(_) => (host::Kind::Dir, 0o777, None),
+                DeKind::Symlink(ref sl) => {
+                    let dir = inode.ftyp.0 == Ftyp::SymlinkDir;
+                    (host::Kind::Symlink { target: sl.clone(), dir }, 0o777, None)
+                }
+            };
+            nodes.push(host::Node {
+                path:     path.clone(),
+                kind,
+                mode:     if inode.perms.readonly { mode & !0o222 } else { mode },
+                accessed: Some(inode.times.accessed),
+                modified: Some(inode.times.modified),
+                link,
+            });
+            for child in d.children().into_iter().flat_map(|c| c.values()) {
+                walk(child, path.join(&child.name), nodes);
+            }
+        }
+
+        let mut nodes = Vec::new();
+        walk(&self.volumes[&self.cwd.prefix], PathBuf::new(), &mut nodes);
+        nodes
+    }
+
+    /// resolve walks path to a `Loc`, following every symlink, including the final component if
+    /// `follow` is true. All intermediate components must be existing directories.
+    fn resolve<P: AsRef<Path>>(&self, path: P, follow: bool, level: &mut u8) -> Result<Loc> {
+        let parts = normalize_windows(&path);
+        let prefix = parts.prefix.unwrap_or_else(|| self.cwd.prefix.clone());
+        let mut cur = self.volumes.get(&prefix).ok_or_else(ENOENT)?;
+
+        // Relative paths, including drive relative paths on the current drive, start at the
+        // current directory. ParentDirs can only lead a relative path and walk up from there.
+        let mut dirs: Vec<String> = Vec::new();
+        let mut inner = parts.inner.into_iter().peekable();
+        if !parts.at_root && prefix == self.cwd.prefix {
+            dirs = self.cwd.dirs.clone();
+            while inner.next_if_eq(&Part::ParentDir).is_some() {
+                dirs.pop();
+            }
+            for key in &dirs {
+                cur = &cur.children().expect("current directory is not a dir")[key];
+            }
+        }
+        while let Some(part) = inner.next() {
+            let name = match part {
+                Part::ParentDir => continue,
+                Part::Normal(name) => name,
+            };
+            let key = key(&name);
+            let last = inner.peek().is_none();
+            let child = match cur.children().and_then(|c| c.get(&key)) {
+                Some(child) => child,
+                None if last => return Ok(Loc { prefix, dirs, base: Some((key, name)) }),
+                None => return Err(ENOENT()),
+            };
+            match child.kind {
+                DeKind::Dir(_) if !last => {
+                    dirs.push(key);
+                    cur = child;
+                }
+                DeKind::File(_) if !last => return Err(ENOTDIR()),
+                DeKind::Symlink(ref target) if !last || follow => {
+                    *level += 1;
+                    if *level > MAXLINKS {
+                        return Err(ELOOP());
+                    }
+                    // Relative targets are relative to the directory containing the symlink. We
+                    // rebuild the full path with the remaining components and start over.
+                    let tparts = normalize_windows(target);
+                    let target = target.to_string_lossy();
+                    let mut next = if tparts.prefix.is_some() {
+                        target.into_owned()
+                    } else if tparts.at_root {
+                        format!("{}{}", prefix, target)
+                    } else {
+                        format!(r"{}\{}\{}", prefix, dirs.join(r"\"), target)
+                    };
+                    for part in inner {
+                        next.push('\\');
+                        next.push_str(&part.into_normal().unwrap_or_default().to_string_lossy());
+                    }
+                    return self.resolve(next, follow, level);
+                }
+                _ => return Ok(Loc { prefix, dirs, base: Some((key, name)) }),
+            }
+        }
+        Ok(Loc { prefix, dirs, base: None })
+    }
+
+    /// dir returns the (existing) directory containing loc's base.
+    fn dir(&self, loc: &Loc) -> &Dirent {
+        let mut cur = &self.volumes[&loc.prefix];
+        for key in &loc.dirs {
+            cur = &cur.children().expect("resolved dir is not a dir")[key];
+        }
+        cur
+    }
+    fn dir_mut(&mut self, loc: &Loc) -> &mut Dirent {
+        let mut cur = self.volumes.get_mut(&loc.prefix).expect("resolved volume does not exist");
+        for key in &loc.dirs {
+            cur = cur.children_mut().get_mut(key).expect("resolved dir does not exist");
+        }
+        cur
+    }
+    /// get returns the dirent at loc, if it exists.
+    fn get(&self, loc: &Loc) -> Option<&Dirent> {
+        let dir = self.dir(loc);
+        match loc.base {
+            Some((ref key, _)) => dir.children().and_then(|c| c.get(key)),
+            None => Some(dir),
+        }
+    }
+    fn get_mut(&mut self, loc: &Loc) -> Option<&mut Dirent> {
+        let dir = self.dir_mut(loc);
+        match loc.base {
+            Some((ref key, _)) => dir.children_mut().get_mut(key),
+            None => Some(dir),
+        }
+    }
+    /// insert adds a new dirent to the directory containing loc's base.
+    fn insert(&mut self, loc: &Loc, kind: DeKind, inode: Inode) -> Result<()> {
+        let (key, name) = loc.base(EEXIST)?;
+        valid_name(name)?;
+        let dir = self.dir_mut(loc);
+        dir.inode.touch(MODIFIED);
+        dir.children_mut().insert(key.clone(), Dirent { name: name.clone(), kind, inode });
+        Ok(())
+    }
+    /// holds_cwd returns whether the dirent at loc is the current directory or contains it.
+    fn holds_cwd(&self, loc: &Loc) -> bool {
+        match loc.base {
+            Some((ref key, _)) => {
+                loc.prefix == self.cwd.prefix &&
+                    self.cwd.dirs.len() > loc.dirs.len() &&
+                    self.cwd.dirs.starts_with(&loc.dirs) &&
+                    self.cwd.dirs[loc.dirs.len()] == *key
+            }
+            None => loc.prefix == self.cwd.prefix,
+        }
+    }
+    /// take removes and returns the dirent at loc, which must exist.
+    fn take(&mut self, loc: &Loc) -> Dirent {
+        let key = &loc.base.as_ref().expect("take on volume root").0;
+        let dir = self.dir_mut(loc);
+        dir.inode.touch(MODIFIED);
+        dir.children_mut().remove(key).expect("take on missing dirent")
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        let loc = self.resolve(path, true, &mut 0)?;
+        self.get(&loc).ok_or_else(ENOENT)?;
+
+        let mut canon = match loc.prefix {
+            Prefix::Disk(letter) => format!(r"\\?\{}:", letter as char),
+            Prefix::Unc(ref server, ref share) => format!(r"\\?\UNC\{}\{}", server, share),
+        };
+        let mut cur = &self.volumes[&loc.prefix];
+        for key in loc.dirs.iter().chain(loc.base.as_ref().map(|b| &b.0)) {
+            cur = &cur.children().expect("resolved dir is not a dir")[key];
+            canon.push('\\');
+            canon.push_str(&cur.name.to_string_lossy());
+        }
+        if loc.base.is_none() {
+            canon.push('\\');
+        }
+        Ok(PathBuf::from(canon))
+    }
+
+    fn create_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let loc = self.resolve(path, false, &mut 0)?;
+        if self.get(&loc).is_some() {
+            return Err(EEXIST());
+        }
+        let inode = self.new_inode(Ftyp::Dir)?;
+        self.insert(&loc, DeKind::Dir(BTreeMap::new()), inode)
+    }
+
+    fn current_dir(&self) -> PathBuf {
+        let mut path = format!(r"{}\", self.cwd.prefix);
+        let mut cur = &self.volumes[&self.cwd.prefix];
+        for key in &self.cwd.dirs {
+            cur = &cur.children().expect("current directory is not a dir")[key];
+            if !path.ends_with('\\') {
+                path.push('\\');
+            }
+            path.push_str(&cur.name.to_string_lossy());
+        }
+        PathBuf::from(path)
+    }
+
+    fn set_current_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let loc = self.resolve(path, true, &mut 0)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        if dirent.ftyp() != Ftyp::Dir {
+            return Err(ENOTDIR());
+        }
+        let mut dirs = loc.dirs;
+        dirs.extend(loc.base.map(|(key, _)| key));
+        self.cwd = Cwd { prefix: loc.prefix, dirs };
+        Ok(())
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        // We create every directory from the volume root down, skipping those that exist.
+        let parts = normalize_windows(&path);
+        let mut at = format!(r"{}\", parts.prefix.unwrap_or(DEFAULT_VOLUME));
+        for name in parts.inner.iter().filter_map(Part::as_normal) {
+            at.push_str(&name.to_string_lossy());
+            at.push('\\');
+            match self.create_dir(&at) {
+                Err(ref e) if e.raw_os_error() == EEXIST().raw_os_error() => {
+                    if !self.metadata(&at, true)?.is_dir() {
+                        return Err(EEXIST());
+                    }
+                }
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, src: P, dst: Q) -> Result<()> {
+        let from = self.resolve(src, false, &mut 0)?;
+        let (file, inode) = match self.get(&from).map(|d| (&d.kind, &d.inode)) {
+            Some((DeKind::File(file), inode)) => (file.clone(), inode.clone()),
+            Some(_) => return Err(EACCES()),
+            None => return Err(ENOENT()),
+        };
+        let to = self.resolve(dst, false, &mut 0)?;
+        if to.prefix != from.prefix {
+            return Err(EXDEV());
+        }
+        if self.get(&to).is_some() {
+            return Err(EEXIST());
+        }
+        self.insert(&to, DeKind::File(file), inode)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P, follow: bool) -> Result<Metadata> {
+        let loc = self.resolve(path, follow, &mut 0)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        Ok(Metadata(*dirent.inode.read()))
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<ReadDir> {
+        let loc = self.resolve(&path, true, &mut 0)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        ReadDir::new(path, dirent)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        let loc = self.resolve(path, false, &mut 0)?;
+        match self.get(&loc).map(|d| &d.kind) {
+            Some(DeKind::Symlink(target)) => Ok(target.clone()),
+            Some(_) => Err(EINVAL()),
+            None => Err(ENOENT()),
+        }
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let loc = self.resolve(path, false, &mut 0)?;
+        loc.base(EACCES)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        // Directory symlinks are removed as directories.
+        match dirent.ftyp() {
+            Ftyp::Dir | Ftyp::SymlinkDir => (),
+            _ => return Err(ENOTDIR()),
+        }
+        if dirent.children().is_some_and(|c| !c.is_empty()) {
+            return Err(ENOTEMPTY());
+        }
+        if dirent.inode.readonly() || self.holds_cwd(&loc) {
+            return Err(EACCES());
+        }
+        self.take(&loc);
+        Ok(())
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let loc = self.resolve(path, false, &mut 0)?;
+        loc.base(EACCES)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        match dirent.ftyp() {
+            Ftyp::Dir | Ftyp::SymlinkDir => (),
+            _ => return Err(ENOTDIR()),
+        }
+        // Rather than removing everything we can, we refuse to remove anything if something below
+        // cannot be removed.
+        if dirent.any_readonly() || dirent.in_use() || self.holds_cwd(&loc) {
+            return Err(EACCES());
+        }
+        self.take(&loc);
+        Ok(())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let loc = self.resolve(path, false, &mut 0)?;
+        loc.base(EACCES)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        match dirent.ftyp() {
+            Ftyp::File | Ftyp::SymlinkFile => (),
+            _ => return Err(EACCES()),
+        }
+        if dirent.inode.readonly() || dirent.in_use() {
+            return Err(EACCES());
+        }
+        self.take(&loc);
+        Ok(())
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> Result<()> {
+        let from = self.resolve(from, false, &mut 0)?;
+        let to = self.resolve(to, false, &mut 0)?;
+        from.base(EACCES)?;
+        let (to_key, to_name) = to.base(EACCES)?;
+
+        let src = self.get(&from).ok_or_else(ENOENT)?;
+        if from.prefix != to.prefix {
+            return Err(EXDEV());
+        }
+        if src.in_use() || self.holds_cwd(&from) {
+            return Err(EACCES());
+        }
+        valid_name(to_name)?;
+
+        let mut from_path = from.dirs.clone();
+        from_path.push(from.base.as_ref().unwrap().0.clone());
+        let mut to_path = to.dirs.clone();
+        to_path.push(to_key.clone());
+
+        // A rename to the same name (ignoring case) only changes the case preserved name.
+        if from_path == to_path {
+            let to_name = to_name.clone();
+            self.get_mut(&from).expect("rename source vanished").name = to_name;
+            return Ok(());
+        }
+        // Moving a directory inside itself is invalid.
+        if to_path.starts_with(&from_path) {
+            return Err(EINVAL());
+        }
+        let is_dir = src.ftyp() == Ftyp::Dir;
+        if let Some(dst) = self.get(&to) {
+            // Windows only replaces files, and only if they are not readonly or open.
+            match dst.ftyp() {
+                Ftyp::Dir | Ftyp::SymlinkDir => return Err(EACCES()),
+                _ if is_dir || dst.inode.readonly() || dst.in_use() => return Err(EACCES()),
+                _ => (),
+            }
+        }
+
+        let mut dirent = self.take(&from);
+        dirent.name = to_name.clone();
+        let dir = self.dir_mut(&to);
+        dir.inode.touch(MODIFIED);
+        dir.children_mut().insert(to_key.clone(), dirent);
+        Ok(())
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&mut self, path: P, perms: Permissions) -> Result<()> {
+        let loc = self.resolve(path, true, &mut 0)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        dirent.inode.write().perms = perms;
+        Ok(())
+    }
+
+    fn set_times<P: AsRef<Path>>(&mut self, path: P, times: fs::FileTimes) -> Result<()> {
+        let loc = self.resolve(path, true, &mut 0)?;
+        let dirent = self.get(&loc).ok_or_else(ENOENT)?;
+        dirent.inode.write().times.set(times);
+        Ok(())
+    }
+
+    fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, src: P, dst: Q, ftyp: Ftyp)
+        -> Result<()>
+    {
+        let loc = self.resolve(dst, false, &mut 0)?;
+        if self.get(&loc).is_some() {
+            return Err(EEXIST());
+        }
+        let target = PathBuf::from(src.as_ref());
+        let inode = self.new_inode(ftyp)?;
+        self.insert(&loc, DeKind::Symlink(target), inode)
+    }
+
+    fn open<P: AsRef<Path>>(&mut self, path: P, opts: &OpenOptions) -> Result<File> {
+        // Windows validates the access and creation modes before touching the filesystem.
+        if !opts.read && !opts.write && !opts.append {
+            return Err(EINVAL());
+        }
+        if !opts.write && !opts.append && (opts.trunc || opts.create || opts.excl) {
+            return Err(EINVAL());
+        }
+        if opts.append && opts.trunc && !opts.excl {
+            return Err(EINVAL());
+        }
+
+        // NUL is the null device in any directory.
+        let parts = normalize_windows(&path);
+        if let Some(Part::Normal(name)) = parts.inner.last() {
+            if device(name).is_some_and(|d| d == "NUL") {
+                // The null device takes no space from the filesystem.
+                let null = RawFile {
+                    data:    Data::default(),
+                    inode:   Inode::new(Ftyp::File, &self.clock, &Arc::new(Space::default()))?,
+                    handles: 0,
+                    null:    true,
+                    locks:   Arc::default(),
+                };
+                return Ok(File::new(Arc::new(RwLock::new(null)), opts));
+            }
+        }
+
+        let loc = self.resolve(path, true, &mut 0)?;
+        let file = match self.get(&loc).map(|d| &d.kind) {
+            Some(DeKind::File(file)) => {
+                if opts.excl {
+                    return Err(EEXIST());
+                }
+                let file = file.clone();
+                if (opts.write || opts.append) && file.read().inode.readonly() {
+                    return Err(EACCES());
+                }
+                if opts.trunc {
+                    FileCursor { file: file.clone(), at: 0 }.set_len(0)?;
+                }
+                file
+            }
+            // Opening directories requires backup semantics, which we do not support.
+            Some(_) => return Err(EACCES()),
+            None => {
+                if !opts.create && !opts.excl {
+                    return Err(ENOENT());
+                }
+                let inode = self.new_inode(Ftyp::File)?;
+                let file = Arc::new(RwLock::new(RawFile {
+                    data:    Data::default(),
+                    inode:   inode.clone(),
+                    handles: 0,
+                    null:    false,
+                    locks:   Arc::default(),
+                }));
+                self.insert(&loc, DeKind::File(file.clone()), inode)?;
+                file
+            }
+        };
+        Ok(File::new(file, opts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+
+    use fs::{DirEntry as DirEntryTrait, File as FileTrait, GenFS, OpenOptions as OpenOptionsTrait,
+             Permissions as PermissionsTrait};
+    use windows_ext::*;
+    use super::*;
+
+    fn errs_eq(l: Error, r: Error) -> bool {
+        l.raw_os_error() == r.raw_os_error()
+    }
+
+    fn names(fs: &FS, path: &str) -> Vec<OsString> {
+        fs.read_dir(path).unwrap().map(|e| e.unwrap().file_name()).collect()
+    }
+
+    #[test]
+    fn prefixes() {
+        let fs = FS::new();
+        fs.create_dir(r"C:\a").unwrap();
+        // Relative, rooted, drive relative, verbatim, and forward slash paths all hit C:.
+        assert!(fs.metadata("a").unwrap().is_dir());
+        assert!(fs.metadata(r"\a").unwrap().is_dir());
+        assert!(fs.metadata("c:a").unwrap().is_dir());
+        assert!(fs.metadata(r"\\?\C:\a").unwrap().is_dir());
+        assert!(fs.metadata("C:/a/").unwrap().is_dir());
+        assert!(fs.metadata(r"..\..\a").unwrap().is_dir());
+
+        // Unknown volumes do not exist until added.
+        assert!(errs_eq(fs.create_dir(r"D:\a").unwrap_err(), ENOENT()));
+        assert!(errs_eq(fs.add_volume(r"D:\a").unwrap_err(), EINVAL()));
+        fs.add_volume("d:").unwrap();
+        assert!(errs_eq(fs.add_volume("D:").unwrap_err(), EEXIST()));
+        fs.create_dir(r"D:\a").unwrap();
+
+        fs.add_volume(r"\\Server\Share").unwrap();
+        fs.create_dir(r"\\server\share\Dir").unwrap();
+        assert!(fs.metadata(r"\\?\UNC\SERVER\SHARE\dir").unwrap().is_dir());
+        assert_eq!(fs.canonicalize(r"//server/share/dir/.").unwrap(),
+                   PathBuf::from(r"\\?\UNC\SERVER\SHARE\Dir"));
+        assert_eq!(fs.canonicalize("d:").unwrap(), PathBuf::from(r"\\?\D:\"));
+
+        // Renames and hard links cannot cross volumes.
+        fs.create_file(r"C:\a\f").unwrap();
+        assert!(errs_eq(fs.rename(r"C:\a\f", r"D:\f").unwrap_err(), EXDEV()));
+        assert!(errs_eq(fs.hard_link(r"C:\a\f", r"D:\f").unwrap_err(), EXDEV()));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let fs = FS::new();
+        fs.create_dir("Dir").unwrap();
+        fs.new_openopts().write(true).create(true).open(r"DIR\Foo.TXT").unwrap()
+            .write_all(b"data").unwrap();
+
+        assert!(errs_eq(fs.create_dir("dir").unwrap_err(), EEXIST()));
+        assert!(errs_eq(fs.new_openopts().write(true).create_new(true).open(r"dir\FOO.txt")
+                          .unwrap_err(), EEXIST()));
+        let mut s = String::new();
+        fs.open_file(r"dir\foo.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "data");
+        assert_eq!(names(&fs, "dir"), vec![OsString::from("Foo.TXT")]);
+        assert_eq!(fs.canonicalize(r"dir\FOO.TXT").unwrap(), PathBuf::from(r"\\?\C:\Dir\Foo.TXT"));
+
+        // Trailing dots and spaces are ignored.
+        assert!(fs.metadata(r"dir\foo.txt. .").unwrap().is_file());
+
+        // A case only rename keeps the file but changes its name.
+        fs.rename(r"dir\foo.txt", r"dir\foo.txt").unwrap();
+        assert_eq!(names(&fs, "dir"), vec![OsString::from("foo.txt")]);
+        assert_eq!(fs.metadata(r"DIR\FOO.TXT").unwrap().len(), 4);
+
+        // read_dir is ordered without regard to case.
+        fs.create_file(r"dir\B").unwrap();
+        fs.create_file(r"dir\a").unwrap();
+        assert_eq!(names(&fs, "dir"), vec![OsString::from("a"), OsString::from("B"),
+                                           OsString::from("foo.txt")]);
+        let entry = fs.read_dir(r"C:\Dir").unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.path(), PathBuf::from(r"C:\Dir\a"));
+    }
+
+    #[test]
+    fn reserved() {
+        let fs = FS::new();
+        for name in &["con", "PRN.txt", "aux.tar.gz", "Com1", "lpt9 .log", "a<b", "a|b", "a?",
+                      "a*", "a\"b"] {
+            assert!(errs_eq(fs.create_file(name).unwrap_err(), EINVAL()), "{}", name);
+            assert!(errs_eq(fs.create_dir(name).unwrap_err(), EINVAL()), "{}", name);
+        }
+        // Names that only contain a reserved name are fine.
+        fs.create_file("console").unwrap();
+        fs.create_file("com10").unwrap();
+
+        // NUL is the null device, wherever it is.
+        let mut null = fs.create_file(r"C:\nowhere\nul.txt").unwrap();
+        assert_eq!(null.write(b"discarded").unwrap(), 9);
+        let mut null = fs.open_file("NUL").unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(null.read(&mut buf).unwrap(), 0);
+        assert!(errs_eq(fs.metadata("NUL").unwrap_err(), ENOENT()));
+    }
+
+    #[test]
+    fn readonly() {
+        let fs = FS::new();
+        fs.create_dir("d").unwrap();
+        fs.create_file(r"d\f").unwrap().write_all(b"data").unwrap();
+        fs.create_file(r"d\g").unwrap();
+
+        let mut perms = fs.metadata(r"d\f").unwrap().permissions();
+        assert!(!perms.readonly());
+        perms.set_readonly(true);
+        fs.set_permissions(r"d\f", perms).unwrap();
+
+        assert!(fs.open_file(r"d\f").is_ok());
+        assert!(errs_eq(fs.create_file(r"d\f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.new_openopts().append(true).open(r"d\f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.remove_file(r"d\f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename(r"d\g", r"d\f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.remove_dir_all("d").unwrap_err(), EACCES()));
+        // A readonly file can still be renamed and copied.
+        fs.rename(r"d\f", r"d\h").unwrap();
+        assert_eq!(fs.copy(r"d\h", r"d\i").unwrap(), 4);
+        assert!(fs.metadata(r"d\i").unwrap().permissions().readonly());
+
+        // Readonly directories can have children created, but cannot be removed.
+        fs.create_dir("ro").unwrap();
+        fs.set_permissions("ro", perms).unwrap();
+        fs.create_file(r"ro\f").unwrap();
+        fs.remove_file(r"ro\f").unwrap();
+        assert!(errs_eq(fs.remove_dir("ro").unwrap_err(), EACCES()));
+        perms.set_readonly(false);
+        fs.set_permissions("ro", perms).unwrap();
+        fs.remove_dir("ro").unwrap();
+    }
+
+    #[test]
+    fn symlinks() {
+        let fs = FS::new();
+        fs.create_dir_all(r"a\b").unwrap();
+        fs.create_file(r"a\b\f").unwrap().write_all(b"hi").unwrap();
+
+        fs.symlink_dir(r"a\b", "link_dir").unwrap();
+        fs.symlink_file(r"b\f", r"a\link_file").unwrap();
+        assert!(errs_eq(fs.symlink_file("x", "link_dir").unwrap_err(), EEXIST()));
+
+        let ft = fs.symlink_metadata("link_dir").unwrap().file_type();
+        assert!(ft.is_symlink() && ft.is_symlink_dir() && !ft.is_symlink_file());
+        let ft = fs.symlink_metadata(r"A\LINK_FILE").unwrap().file_type();
+        assert!(ft.is_symlink() && ft.is_symlink_file() && !ft.is_symlink_dir());
+        assert!(fs.metadata("link_dir").unwrap().is_dir());
+        assert_eq!(fs.metadata(r"a\link_file").unwrap().len(), 2);
+        assert_eq!(fs.metadata(r"link_dir\F").unwrap().len(), 2);
+        assert_eq!(fs.read_link(r"a\link_file").unwrap(), PathBuf::from(r"b\f"));
+        assert!(errs_eq(fs.read_link("a").unwrap_err(), EINVAL()));
+        assert_eq!(fs.canonicalize(r"link_dir\f").unwrap(), PathBuf::from(r"\\?\C:\a\b\f"));
+        // Renders do not escape the separators of Windows paths.
+        assert_eq!(fs.to_string(), r"C:\
+    a\
+        b\
+            f
+        link_file -> b\f
+    link_dir -> a\b
+");
+
+        // Directory symlinks are removed with remove_dir, file symlinks with remove_file.
+        assert!(errs_eq(fs.remove_file("link_dir").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.remove_dir(r"a\link_file").unwrap_err(), ENOTDIR()));
+        fs.remove_dir("link_dir").unwrap();
+        fs.remove_file(r"a\link_file").unwrap();
+        assert!(fs.metadata(r"a\b\f").unwrap().is_file());
+
+        fs.symlink_file("loop1", "loop2").unwrap();
+        fs.symlink_file("loop2", "loop1").unwrap();
+        assert!(errs_eq(fs.open_file("loop1").unwrap_err(), ELOOP()));
+        assert_eq!(ELOOP().raw_os_error(), Some(1921));
+    }
+
+    #[test]
+    fn open_files() {
+        let fs = FS::new();
+        fs.create_dir("d").unwrap();
+        let f = fs.create_file(r"d\f").unwrap();
+        fs.create_file(r"d\g").unwrap();
+
+        assert!(errs_eq(fs.remove_file(r"d\f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename(r"d\f", r"d\h").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename(r"d\g", r"d\f").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename("d", "e").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.remove_dir_all("d").unwrap_err(), EACCES()));
+
+        // Clones keep the file open.
+        let clone = f.try_clone().unwrap();
+        drop(f);
+        assert!(errs_eq(fs.remove_file(r"d\f").unwrap_err(), EACCES()));
+        drop(clone);
+
+        // Hard links share open handles.
+        fs.hard_link(r"d\f", r"d\link").unwrap();
+        let f = fs.open_file(r"d\link").unwrap();
+        assert!(errs_eq(fs.remove_file(r"d\f").unwrap_err(), EACCES()));
+        drop(f);
+
+        fs.rename(r"d\g", r"d\f").unwrap();
+        fs.rename("d", "e").unwrap();
+        fs.remove_dir_all("e").unwrap();
+        assert!(errs_eq(fs.metadata("d").unwrap_err(), ENOENT()));
+    }
+
+    #[test]
+    fn errors() {
+        let fs = FS::new();
+        fs.create_dir_all(r"d\sub").unwrap();
+        fs.create_file("f").unwrap();
+
+        let err = fs.remove_dir("d").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(145));
+        assert!(errs_eq(fs.remove_dir("f").unwrap_err(), ENOTDIR()));
+        assert!(errs_eq(fs.remove_dir(r"C:\").unwrap_err(), EACCES()));
+        assert_eq!(fs.remove_file("d").unwrap_err().raw_os_error(), Some(5));
+        assert!(errs_eq(fs.open_file("d").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename("f", "d").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename("d", r"d\sub\d").unwrap_err(), EINVAL()));
+        assert!(errs_eq(fs.create_file(r"f\g").unwrap_err(), ENOTDIR()));
+        assert!(errs_eq(fs.create_dir_all(r"f\g").unwrap_err(), EEXIST()));
+        assert!(errs_eq(fs.create_dir_all(r"f").unwrap_err(), EEXIST()));
+        fs.create_dir_all(r"d\sub").unwrap();
+
+        // Windows rejects option combinations that Unix accepts.
+        assert!(errs_eq(fs.new_openopts().open("f").unwrap_err(), EINVAL()));
+        assert!(errs_eq(fs.new_openopts().read(true).create(true).open("f").unwrap_err(),
+                        EINVAL()));
+        assert!(errs_eq(fs.new_openopts().append(true).truncate(true).open("f").unwrap_err(),
+                        EINVAL()));
+
+        // Accessing a handle without the right access is denied.
+        let mut r = fs.open_file("f").unwrap();
+        assert!(errs_eq(r.write(b"x").unwrap_err(), EACCES()));
+        let mut w = fs.new_openopts().write(true).open("f").unwrap();
+        assert!(errs_eq(w.read(&mut [0u8; 1]).unwrap_err(), EACCES()));
+        assert_eq!(fs.metadata("d").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn clock() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use fs::Metadata;
+        use mem::clock::MockClock;
+
+        let clock = MockClock::new(UNIX_EPOCH);
+        let fs = FS::with_clock(clock.clone());
+        let mut f = fs.create_file("f").unwrap();
+        clock.advance(Duration::from_secs(10));
+        f.write_all(b"data").unwrap();
+        fs.add_volume("D:").unwrap();
+
+        let meta = fs.metadata("f").unwrap();
+        assert_eq!(meta.created().unwrap(), UNIX_EPOCH);
+        assert_eq!(meta.modified().unwrap(), UNIX_EPOCH + Duration::from_secs(10));
+        assert_eq!(fs.metadata(r"C:").unwrap().modified().unwrap(), UNIX_EPOCH);
+        assert_eq!(fs.metadata(r"D:").unwrap().created().unwrap(),
+                   UNIX_EPOCH + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn set_times() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use fs::{FileTimes, Metadata};
+        use mem::clock::FrozenClock;
+
+        let later = UNIX_EPOCH + Duration::from_secs(10);
+        let fs = FS::with_clock(FrozenClock::new(UNIX_EPOCH));
+        let f = fs.create_file("f").unwrap();
+        assert!(f.set_modified(later).is_ok());
+        assert_eq!(fs.metadata("F").unwrap().modified().unwrap(), later);
+        assert_eq!(fs.metadata("F").unwrap().accessed().unwrap(), UNIX_EPOCH);
+
+        fs.create_dir("d").unwrap();
+        assert!(fs.set_times("D", FileTimes::new().set_accessed(later)).is_ok());
+        assert_eq!(fs.metadata("d").unwrap().accessed().unwrap(), later);
+        assert!(fs.set_times("missing", FileTimes::new()).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let fs = FS::with_limits(8, 2);
+        assert!(fs.add_volume("D:").is_ok());
+        assert!(errs_eq(fs.create_dir(r"D:\d").unwrap_err(), ENOSPC()));
+        assert!(errs_eq(fs.add_volume("E:").unwrap_err(), ENOSPC()));
+
+        fs.set_limits(Some(8), Some(3));
+        let mut f = fs.create_file("f").unwrap();
+        assert!(f.write_all(b"01234").is_ok());
+        assert!(errs_eq(f.write(b"5678").unwrap_err(), ENOSPC()));
+        assert!(errs_eq(f.set_len(9).unwrap_err(), ENOSPC()));
+        assert_eq!(fs.metadata("f").unwrap().len(), 5);
+
+        // The null device takes no space.
+        assert!(fs.create_file("NUL").unwrap().write_all(&[0; 100]).is_ok());
+
+        drop(f);
+        assert!(fs.create_file("f").is_ok()); // truncates
+        assert_eq!(fs.bytes_used(), 0);
+        assert!(fs.remove_file("f").is_ok());
+        assert_eq!(fs.inodes_used(), 2);
+        assert!(fs.create_dir(r"D:\d").is_ok());
+    }
+
+    #[test]
+    fn current_dir() {
+        let fs = FS::new();
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from(r"C:\"));
+        fs.create_dir_all(r"C:\Dir\Sub").unwrap();
+        fs.symlink_dir(r"C:\Dir\Sub", "link").unwrap();
+        fs.create_file(r"C:\Dir\f").unwrap();
+
+        assert!(fs.set_current_dir("dir").is_ok());
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from(r"C:\Dir"));
+        assert!(fs.metadata("F").unwrap().is_file());
+        assert!(fs.metadata("C:f").unwrap().is_file()); // drive relative on the current drive
+        assert!(fs.set_current_dir(r"\link").is_ok());
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from(r"C:\Dir\Sub"));
+        assert!(fs.metadata(r"..\f").unwrap().is_file());
+        assert!(fs.create_file("g").is_ok());
+        assert!(fs.metadata(r"C:\dir\sub\g").unwrap().is_file());
+
+        assert!(errs_eq(fs.set_current_dir(r"..\f").unwrap_err(), ENOTDIR()));
+        assert!(errs_eq(fs.set_current_dir("missing").unwrap_err(), ENOENT()));
+
+        // The current directory and its parents cannot be removed or renamed.
+        assert!(errs_eq(fs.remove_dir_all(r"C:\Dir").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.rename(r"C:\Dir", r"C:\Other").unwrap_err(), EACCES()));
+        assert!(fs.remove_file("g").is_ok());
+        assert!(errs_eq(fs.remove_dir(".").unwrap_err(), EACCES()));
+        assert!(errs_eq(fs.remove_dir(r"C:\Dir\Sub").unwrap_err(), EACCES()));
+
+        // Other volumes are rooted at their root unless they hold the current directory.
+        fs.add_volume("D:").unwrap();
+        fs.create_dir(r"D:\d").unwrap();
+        assert!(fs.metadata("D:d").unwrap().is_dir());
+        assert!(fs.set_current_dir(r"D:\d").is_ok());
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from(r"D:\d"));
+        assert!(fs.remove_dir_all(r"C:\Dir").is_ok());
+    }
+
+    #[test]
+    fn snapshot() {
+        let fs = FS::new();
+        fs.add_volume("D:").unwrap();
+        fs.create_dir(r"D:\d").unwrap();
+        fs.create_dir_all(r"C:\Dir\Sub").unwrap();
+        fs.create_file(r"C:\Dir\f").unwrap().write_all(b"hello").unwrap();
+        fs.hard_link(r"C:\Dir\f", r"C:\hl").unwrap();
+        fs.symlink_file(r"C:\Dir\f", "link").unwrap();
+        let mut perms = fs.metadata(r"C:\Dir\Sub").unwrap().permissions();
+        perms.set_readonly(true);
+        fs.set_permissions(r"C:\Dir\Sub", perms).unwrap();
+        fs.set_current_dir("Dir").unwrap();
+        let (bytes, inodes) = (fs.bytes_used(), fs.inodes_used());
+
+        let open = fs.open_file("f").unwrap(); // open files do not carry over
+        let snapshot = fs.snapshot();
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (bytes, inodes));
+        drop(open);
+
+        fs.set_current_dir(r"D:\").unwrap();
+        fs.remove_file(r"C:\Dir\f").unwrap();
+        fs.remove_file(r"C:\hl").unwrap();
+        fs.create_file(r"C:\g").unwrap();
+
+        fs.restore(&snapshot);
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (bytes, inodes));
+        assert_eq!(fs.current_dir().unwrap(), PathBuf::from(r"C:\Dir"));
+        assert_eq!(names(&fs, r"C:\"),
+                   vec![OsString::from("Dir"), OsString::from("hl"), OsString::from("link")]);
+        assert!(fs.metadata("sub").unwrap().permissions().readonly());
+        assert_eq!(fs.read_link(r"C:\link").unwrap(), PathBuf::from(r"C:\Dir\f"));
+        assert!(fs.metadata(r"D:\d").unwrap().is_dir());
+        assert!(fs.remove_file("f").is_ok());
+
+        // Hard links are still one file, and the snapshot is untouched by changes after restore.
+        fs.new_openopts().append(true).open(r"C:\hl").unwrap().write_all(b"!").unwrap();
+        let mut contents = String::new();
+        fs.open_file(r"C:\hl").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello!");
+        let copy = FS::from_snapshot(&snapshot);
+        assert_eq!(copy.metadata(r"C:\hl").unwrap().len(), 5);
+        assert_eq!(copy.metadata(r"C:\Dir\f").unwrap().len(), 5);
+
+        // Restoring counts against limits, but is not refused by them.
+        fs.set_limits(Some(0), Some(0));
+        fs.restore(&snapshot);
+        assert_eq!((fs.bytes_used(), fs.inodes_used()), (bytes, inodes));
+        assert!(errs_eq(fs.create_dir(r"C:\c").unwrap_err(), ENOSPC()));
+    }
+
+    #[test]
+    fn host() {
+        use std::env;
+        use std::fs as rs_fs;
+        use std::process;
+
+        let dir = env::temp_dir().join(format!("rsfs-mem-windows-host-test-{}", process::id()));
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        rs_fs::create_dir_all(src.join("Dir")).unwrap();
+        rs_fs::write(src.join("Dir").join("f"), b"hello").unwrap();
+        let mut perms = rs_fs::metadata(src.join("Dir").join("f")).unwrap().permissions();
+        perms.set_readonly(true);
+        rs_fs::set_permissions(src.join("Dir").join("f"), perms).unwrap();
+
+        let fs = FS::new();
+        assert!(fs.import_from(&src, r"C:\in").unwrap().is_empty());
+        assert!(fs.metadata(r"C:\IN\dir\F").unwrap().permissions().readonly());
+        let mut contents = String::new();
+        fs.open_file(r"C:\in\Dir\f").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+
+        // Only the current volume is exported, with names in their preserved case.
+        fs.add_volume("D:").unwrap();
+        fs.create_dir(r"D:\d").unwrap();
+        assert!(fs.export_to(&dst).unwrap().is_empty());
+        let f = dst.join("in").join("Dir").join("f");
+        assert_eq!(rs_fs::read(&f).unwrap(), b"hello");
+        assert!(rs_fs::metadata(&f).unwrap().permissions().readonly());
+        assert!(rs_fs::metadata(dst.join("d")).is_err());
+
+        // Windows hosts refuse to remove read-only files; Unix hosts only check the directory.
+        #[cfg(windows)]
+        for f in &[src.join("Dir").join("f"), f] {
+            let mut perms = rs_fs::metadata(f).unwrap().permissions();
+            perms.set_readonly(false);
+            rs_fs::set_permissions(f, perms).unwrap();
+        }
+        assert!(rs_fs::remove_dir_all(&dir).is_ok());
+    }
+
+    #[test]
+    fn std_helpers() {
+        let fs = FS::new();
+        assert!(fs.write(r"C:\f", b"hello").is_ok());
+        assert_eq!(fs.read(r"c:\F").unwrap(), b"hello");
+        assert!(fs.write(r"C:\f", "hi").is_ok());
+        assert_eq!(fs.read_to_string(r"C:\f").unwrap(), "hi");
+        assert!(errs_eq(fs.read(r"C:\missing").unwrap_err(), ENOENT()));
+
+        let mut perms = fs.metadata(r"C:\f").unwrap().permissions();
+        perms.set_readonly(true);
+        assert!(fs.set_permissions(r"C:\f", perms).is_ok());
+        assert!(errs_eq(fs.write(r"C:\f", b"").unwrap_err(), EACCES()));
+        assert!(fs.exists(r"C:\f") && !fs.exists(r"D:\f"));
+        assert!(fs.try_exists(r"C:\f").unwrap());
+    }
+
+    #[test]
+    fn locks() {
+        use fs::{File as _File, TryLockError};
+
+        let fs = FS::new();
+        let a = fs.create_file(r"C:\f").unwrap();
+        let b = fs.open_file(r"C:\F").unwrap();
+        assert!(a.try_lock().is_ok());
+        assert!(a.try_clone().unwrap().try_lock_shared().is_ok());
+        assert!(matches!(b.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(a);
+        assert!(b.try_lock().is_ok());
+    }
+
+    #[test]
+    fn large_files() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+        use fs::Metadata;
+
+        // Extended files are charged for their full length but are not held in memory.
+        let fs = FS::new();
+        let mut f = fs.create_file(r"C:\f").unwrap();
+        assert!(f.set_len(1 << 40).is_ok());
+        assert!(f.seek(SeekFrom::End(0)).is_ok());
+        assert!(f.write_all(b"end").is_ok());
+        assert_eq!(fs.metadata(r"C:\f").unwrap().len(), (1 << 40) + 3);
+        assert_eq!(fs.bytes_used(), (1 << 40) + 3);
+
+        let mut f = fs.open_file(r"C:\f").unwrap();
+        let mut buf = [1; 5];
+        assert!(f.seek(SeekFrom::End(-5)).is_ok());
+        assert!(f.read_exact(&mut buf).is_ok());
+        assert_eq!(&buf, b"\0\0end");
+    }
+}
@@ -0,0 +1,162 @@
+//! Extended attributes for the in-memory Unix filesystem.
+//!
+//! Attributes are stored on inodes, so every name of a file shares them. Which attributes a
+//! caller may read and write mirrors Linux's `xattr_permission`: the `user.` namespace is guarded
+//! by the permission bits of the file and only exists on regular files and directories, the
+//! `trusted.` namespace is only visible to root, and only root can write the `security.`
+//! namespace. Like a filesystem mounted without ACL support, the `system.` namespace is not
+//! supported.
+
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::Result;
+
+use errors::*;
+
+/// The longest attribute name Linux accepts.
+const NAME_MAX: usize = 255;
+/// The longest attribute value Linux accepts.
+const SIZE_MAX: usize = 65536;
+
+/// `Caller` describes who is using the attributes of an inode and what kind of inode it is.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Caller {
+    /// root is whether the caller is privileged.
+    pub(crate) root:   bool,
+    /// owner is whether the caller owns the inode.
+    pub(crate) owner:  bool,
+    /// access is the rwx bits of the inode that apply to the caller.
+    pub(crate) access: u32,
+    /// user is whether the inode can have attributes in the `user.` namespace, which only
+    /// regular files and directories can.
+    pub(crate) user:   bool,
+    /// sticky is whether the inode is a directory with its sticky bit set.
+    pub(crate) sticky: bool,
+}
+
+/// The namespaces of attribute names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Namespace {
+    User,
+    Trusted,
+    Security,
+}
+
+/// `Xattrs` are the extended attributes of an inode, sorted by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Xattrs(BTreeMap<OsString, Vec<u8>>);
+
+impl Xattrs {
+    /// get returns the value of the attribute name.
+    pub(crate) fn get(&self, name: &OsStr, caller: Caller) -> Result<Vec<u8>> {
+        permitted(namespace(name)?, caller, false)?;
+        self.0.get(name).cloned().ok_or_else(ENODATA)
+    }
+
+    /// set creates or replaces the attribute name.
+    pub(crate) fn set(&mut self, name: &OsStr, value: &[u8], caller: Caller) -> Result<()> {
+        let ns = namespace(name)?;
+        if value.len() > SIZE_MAX {
+            return Err(E2BIG());
+        }
+        permitted(ns, caller, true)?;
+        self.0.insert(name.to_os_string(), value.to_vec());
+        Ok(())
+    }
+
+    /// list returns the names of the attributes the caller can see.
+    pub(crate) fn list(&self, caller: Caller) -> Vec<OsString> {
+        self.0.keys()
+              .filter(|name| caller.root || namespace(name).ok() != Some(Namespace::Trusted))
+              .cloned()
+              .collect()
+    }
+
+    /// remove removes the attribute name.
+    pub(crate) fn remove(&mut self, name: &OsStr, caller: Caller) -> Result<()> {
+        permitted(namespace(name)?, caller, true)?;
+        self.0.remove(name).map(|_| ()).ok_or_else(ENODATA)
+    }
+}
+
+// namespace validates name and returns its namespace.
+fn namespace(name: &OsStr) -> Result<Namespace> {
+    let bytes = name.as_encoded_bytes();
+    if bytes.is_empty() || bytes.len() > NAME_MAX {
+        return Err(ERANGE());
+    }
+    let ns = if bytes.starts_with(b"user.") {
+        (Namespace::User, 5)
+    } else if bytes.starts_with(b"trusted.") {
+        (Namespace::Trusted, 8)
+    } else if bytes.starts_with(b"security.") {
+        (Namespace::Security, 9)
+    } else {
+        return Err(EOPNOTSUPP());
+    };
+    // Like Linux, a namespace prefix alone is not a name.
+    if bytes.len() == ns.1 {
+        return Err(EINVAL());
+    }
+    Ok(ns.0)
+}
+
+// permitted fails unless the caller may read, or write if write is true, attributes in ns.
+fn permitted(ns: Namespace, caller: Caller, write: bool) -> Result<()> {
+    // Denied reads look like missing attributes, while denied writes are not permitted.
+    let denied = || if write { EPERM() } else { ENODATA() };
+    match ns {
+        Namespace::Trusted if !caller.root => Err(denied()),
+        Namespace::Security if write && !caller.root => Err(EPERM()),
+        Namespace::User if !caller.user => Err(denied()),
+        Namespace::User if write && caller.sticky && !caller.owner && !caller.root => Err(EPERM()),
+        Namespace::User if caller.access & if write { 0o2 } else { 0o4 } == 0 => Err(EACCES()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsStr;
+
+    use super::{Caller, Xattrs};
+
+    #[test]
+    fn xattrs() {
+        let root = Caller { root: true, owner: true, access: 0o6, user: true, sticky: false };
+        let user = Caller { root: false, ..root };
+        let name = |name: &str| OsStr::new(name).to_os_string();
+
+        let mut xattrs = Xattrs::default();
+        assert!(xattrs.set(&name("user.a"), b"1", user).is_ok());
+        assert!(xattrs.set(&name("user.a"), b"2", user).is_ok());
+        assert!(xattrs.set(&name("trusted.b"), b"", root).is_ok());
+        assert_eq!(xattrs.get(&name("user.a"), user).unwrap(), b"2");
+        assert_eq!(xattrs.list(root), vec![name("trusted.b"), name("user.a")]);
+        assert_eq!(xattrs.list(user), vec![name("user.a")]);
+
+        let errno = |r: ::std::io::Result<()>| r.unwrap_err().raw_os_error().unwrap();
+        let get = |xattrs: &Xattrs, n: &str, c| xattrs.get(&name(n), c).map(|_| ());
+        assert_eq!(errno(get(&xattrs, "user.missing", user)), 61);
+        assert_eq!(errno(get(&xattrs, "trusted.b", user)), 61);
+        assert_eq!(errno(get(&xattrs, "", user)), 34);
+        assert_eq!(errno(get(&xattrs, &"x".repeat(256), user)), 34);
+        assert_eq!(errno(get(&xattrs, "user.", user)), 22);
+        assert_eq!(errno(get(&xattrs, "system.posix_acl_access", root)), 95);
+        assert_eq!(errno(get(&xattrs, "user.a", Caller { access: 0o2, ..user })), 13);
+        assert_eq!(errno(get(&xattrs, "user.a", Caller { user: false, ..root })), 61);
+
+        assert_eq!(errno(xattrs.set(&name("user.c"), &[0; 65537], root)), 7);
+        assert!(xattrs.set(&name("user.c"), &[0; 65536], root).is_ok());
+        assert_eq!(errno(xattrs.set(&name("trusted.c"), b"", user)), 1);
+        assert_eq!(errno(xattrs.set(&name("security.c"), b"", user)), 1);
+        assert_eq!(errno(xattrs.set(&name("user.c"), b"", Caller { user: false, ..root })), 1);
+        assert_eq!(errno(xattrs.set(&name("user.c"), b"", Caller { access: 0o4, ..user })), 13);
+        let sticky = Caller { owner: false, sticky: true, ..user };
+        assert_eq!(errno(xattrs.set(&name("user.c"), b"", sticky)), 1);
+
+        assert!(xattrs.remove(&name("user.a"), user).is_ok());
+        assert_eq!(errno(xattrs.remove(&name("user.a"), user)), 61);
+        assert_eq!(errno(xattrs.remove(&name("trusted.b"), user)), 1);
+    }
+}
@@ -1,11 +1,15 @@
 //! Convenience functions and types for working with [`std::path::Path`].
 //!
-//! This module deliberately ignores Window's prefixes for now, but I would welcome a change that
-//! adds it.
+//! [`normalize`] ignores Window's prefixes, as it follows the host's path parsing. The in-memory
+//! Windows filesystem needs to parse Windows paths on every host, which [`normalize_windows`]
+//! does.
 //!
+//! [`normalize`]: fn.normalize.html
+//! [`normalize_windows`]: fn.normalize_windows.html
 //! [`std::path::Path`]: https://doc.rust-lang.org/std/path/struct.Path.html
 
 use std::ffi::OsString;
+use std::fmt;
 use std::path::{Component, Path};
 
 /// An iterator with `peek()` and `peek2()` calls that return optional references to the next
@@ -132,6 +136,9 @@ impl Part {
 /// [`normalize`]: fn.normalize.html
 #[derive(Debug)]
 pub struct Parts {
+    // prefix is the Windows prefix the path began with, if any. This is only ever set by
+    // normalize_windows.
+    pub prefix: Option<Prefix>,
     // at_root signifies whether the original path, normalized, began at root.
     pub at_root: bool,
     // inner contains all normal inner of a path and, if not at root, may begin with a few
@@ -141,7 +148,59 @@ pub struct Parts {
 
 impl From<Part> for Parts {
     fn from(p: Part) -> Parts {
-        Parts { at_root: false, inner: vec![p] }
+        Parts { prefix: None, at_root: false, inner: vec![p] }
+    }
+}
+
+/// A Windows path prefix.
+///
+/// Windows compares prefixes case insensitively, so everything in a `Prefix` is uppercased.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Prefix {
+    /// A drive letter prefix, such as `C:`.
+    Disk(u8),
+    /// A UNC share prefix, such as `\\server\share`.
+    Unc(String, String),
+}
+
+impl Prefix {
+    /// Parses the prefix from the start of a (backslash separated) Windows path, returning the
+    /// prefix and the remainder of the path.
+    fn parse(path: &str) -> Option<(Prefix, &str)> {
+        // Verbatim paths (\\?\) are treated like their non-verbatim equivalents.
+        let (path, verbatim) = match path.strip_prefix(r"\\?\") {
+            Some(rest) => (rest, true),
+            None => (path, false),
+        };
+        let unc = if verbatim {
+            path.strip_prefix(r"UNC\")
+        } else {
+            path.strip_prefix(r"\\")
+        };
+        if let Some(rest) = unc {
+            let mut split = rest.splitn(3, '\\');
+            let server = split.next().unwrap_or("");
+            let share = split.next().unwrap_or("");
+            if server.is_empty() || share.is_empty() {
+                return None;
+            }
+            let rest = &rest[server.len() + 1 + share.len()..];
+            return Some((Prefix::Unc(server.to_uppercase(), share.to_uppercase()), rest));
+        }
+        let bytes = path.as_bytes();
+        if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            return Some((Prefix::Disk(bytes[0].to_ascii_uppercase()), &path[2..]));
+        }
+        None
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Prefix::Disk(letter) => write!(f, "{}:", letter as char),
+            Prefix::Unc(ref server, ref share) => write!(f, r"\\{}\{}", server, share),
+        }
     }
 }
 
@@ -157,6 +216,7 @@ impl From<Part> for Parts {
 /// [`std::fs::canonicalize`]: https://doc.rust-lang.org/std/fs/fn.canonicalize.html
 pub fn normalize<P: AsRef<Path>>(path: &P) -> Parts {
     let mut ps = Parts {
+        prefix: None,
         at_root: false,
         inner: Vec::new(),
     };
@@ -164,7 +224,7 @@ pub fn normalize<P: AsRef<Path>>(path: &P) -> Parts {
         match comp {
             Component::RootDir => ps.at_root = true,
             Component::ParentDir => {
-                if ps.at_root || ps.inner.last().map_or(false, |last| *last != Part::ParentDir) {
+                if ps.at_root || ps.inner.last().is_some_and(|last| *last != Part::ParentDir) {
                     ps.inner.pop();
                 } else {
                     ps.inner.push(Part::ParentDir);
@@ -178,3 +238,55 @@ pub fn normalize<P: AsRef<Path>>(path: &P) -> Parts {
     }
     ps
 }
+
+/// Returns the shortest [`Parts`] equivalent to a Windows path purely by lexical parsing.
+///
+/// This is [`normalize`] with Windows rules, regardless of the host platform:
+///
+/// - both `/` and `\` are path separators
+/// - drive letter (`C:`) and UNC (`\\server\share`) prefixes, verbatim or not, are parsed
+///   into the returned `prefix`
+/// - trailing dots and spaces are stripped from every component
+/// - `..` never climbs above the root of a prefixed or rooted path
+///
+/// Non-UTF-8 paths cannot be valid Windows paths; they are converted lossily.
+///
+/// [`Parts`]: struct.Parts.html
+/// [`normalize`]: fn.normalize.html
+pub fn normalize_windows<P: AsRef<Path>>(path: &P) -> Parts {
+    let path = path.as_ref().to_string_lossy().replace('/', "\\");
+    let mut ps = Parts {
+        prefix: None,
+        at_root: false,
+        inner: Vec::new(),
+    };
+    let mut rest = path.as_str();
+    if let Some((prefix, after)) = Prefix::parse(rest) {
+        // UNC paths are always rooted at their share.
+        ps.at_root = matches!(prefix, Prefix::Unc(..));
+        ps.prefix = Some(prefix);
+        rest = after;
+    }
+    if rest.starts_with('\\') {
+        ps.at_root = true;
+    }
+    for comp in rest.split('\\') {
+        match comp {
+            "" | "." => (),
+            ".." => {
+                if ps.at_root || ps.inner.last().is_some_and(|last| *last != Part::ParentDir) {
+                    ps.inner.pop();
+                } else {
+                    ps.inner.push(Part::ParentDir);
+                }
+            }
+            _ => {
+                let trimmed = comp.trim_end_matches(['.', ' ']);
+                if !trimmed.is_empty() {
+                    ps.inner.push(Part::Normal(OsString::from(trimmed)));
+                }
+            }
+        }
+    }
+    ps
+}
@@ -16,7 +16,7 @@ pub struct Raw<T: ?Sized> {
 
 impl<T: ?Sized> Raw<T> {
     pub fn new(ptr: *mut T) -> Raw<T> {
-        Raw { ptr: ptr, mkr: PhantomData }
+        Raw { ptr, mkr: PhantomData }
     }
 
     pub fn ptr(&self) -> *mut T {
@@ -24,7 +24,7 @@ impl<T: ?Sized> Raw<T> {
     }
 
     pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        this.ptr == other.ptr
+        core::ptr::eq(this.ptr, other.ptr)
     }
 }
 
@@ -0,0 +1,166 @@
+//! Linux `open(2)` flags.
+//!
+//! Linux gives some flags different values on different architectures. Most share the generic
+//! values; arm, aarch64, and m68k swap `O_DIRECTORY`, `O_NOFOLLOW`, and `O_LARGEFILE` around,
+//! powerpc swaps them differently, and mips and sparc move most flags elsewhere. Only Linux
+//! shares these values at all, so they are only exported there; the in-memory filesystem
+//! understands them on every host.
+
+// Flags holds the values of the flags on one architecture.
+struct Flags {
+    creat:     i32,
+    excl:      i32,
+    noctty:    i32,
+    trunc:     i32,
+    append:    i32,
+    nonblock:  i32,
+    dsync:     i32,
+    largefile: i32,
+    directory: i32,
+    nofollow:  i32,
+    noatime:   i32,
+    cloexec:   i32,
+    sync:      i32, // __O_SYNC, without O_DSYNC
+    tmpfile:   i32, // __O_TMPFILE, without O_DIRECTORY
+}
+
+#[cfg(any(target_arch = "arm", target_arch = "aarch64", target_arch = "m68k"))]
+const ARCH: Flags = Flags {
+    directory: 0o40000,
+    nofollow:  0o100000,
+    largefile: 0o400000,
+    ..GENERIC
+};
+
+#[cfg(any(target_arch = "powerpc", target_arch = "powerpc64"))]
+const ARCH: Flags = Flags {
+    directory: 0o40000,
+    nofollow:  0o100000,
+    largefile: 0o200000,
+    ..GENERIC
+};
+
+#[cfg(any(target_arch = "mips", target_arch = "mips64", target_arch = "mips32r6",
+          target_arch = "mips64r6"))]
+const ARCH: Flags = Flags {
+    append:    0x8,
+    dsync:     0x10,
+    nonblock:  0x80,
+    creat:     0x100,
+    trunc:     0x200,
+    excl:      0x400,
+    noctty:    0x800,
+    largefile: 0x2000,
+    sync:      0x4000,
+    ..GENERIC
+};
+
+#[cfg(any(target_arch = "sparc", target_arch = "sparc64"))]
+const ARCH: Flags = Flags {
+    creat:     0x200,
+    excl:      0x800,
+    noctty:    0x8000,
+    trunc:     0x400,
+    append:    0x8,
+    nonblock:  0x4000,
+    dsync:     0x2000,
+    largefile: 0x40000,
+    directory: 0x10000,
+    nofollow:  0x20000,
+    noatime:   0x200000,
+    cloexec:   0x400000,
+    sync:      0x800000,
+    tmpfile:   0x2000000,
+};
+
+#[cfg(not(any(target_arch = "arm", target_arch = "aarch64", target_arch = "m68k",
+              target_arch = "powerpc", target_arch = "powerpc64", target_arch = "mips",
+              target_arch = "mips64", target_arch = "mips32r6", target_arch = "mips64r6",
+              target_arch = "sparc", target_arch = "sparc64")))]
+const ARCH: Flags = GENERIC;
+
+#[cfg_attr(any(target_arch = "sparc", target_arch = "sparc64"), allow(dead_code))]
+const GENERIC: Flags = Flags {
+    creat:     0o100,
+    excl:      0o200,
+    noctty:    0o400,
+    trunc:     0o1000,
+    append:    0o2000,
+    nonblock:  0o4000,
+    dsync:     0o10000,
+    largefile: 0o100000,
+    directory: 0o200000,
+    nofollow:  0o400000,
+    noatime:   0o1000000,
+    cloexec:   0o2000000,
+    sync:      0o4000000,
+    tmpfile:   0o20000000,
+};
+
+/// Creates the file if it does not exist, like `O_CREAT`.
+pub const O_CREAT: i32 = ARCH.creat;
+/// Fails if `O_CREAT` is given and the file exists, like `O_EXCL`.
+pub const O_EXCL: i32 = ARCH.excl;
+/// Does not make a terminal the controlling terminal, like `O_NOCTTY`.
+pub const O_NOCTTY: i32 = ARCH.noctty;
+/// Truncates the file to zero length, like `O_TRUNC`.
+pub const O_TRUNC: i32 = ARCH.trunc;
+/// Writes at the end of the file, like `O_APPEND`.
+pub const O_APPEND: i32 = ARCH.append;
+/// Opens the file in non-blocking mode, like `O_NONBLOCK`.
+pub const O_NONBLOCK: i32 = ARCH.nonblock;
+/// Syncs the data of the file after every write, like `O_DSYNC`.
+pub const O_DSYNC: i32 = ARCH.dsync;
+/// Allows files too large for a 32 bit offset, like `O_LARGEFILE`.
+pub const O_LARGEFILE: i32 = ARCH.largefile;
+/// Fails unless the path is a directory, like `O_DIRECTORY`.
+pub const O_DIRECTORY: i32 = ARCH.directory;
+/// Fails if the last component of the path is a symlink, like `O_NOFOLLOW`.
+pub const O_NOFOLLOW: i32 = ARCH.nofollow;
+/// Does not update the access time of the file on reads, like `O_NOATIME`. Only the owner of the
+/// file may open it with this flag.
+pub const O_NOATIME: i32 = ARCH.noatime;
+/// Closes the file on `exec`, like `O_CLOEXEC`.
+pub const O_CLOEXEC: i32 = ARCH.cloexec;
+/// Syncs the data and metadata of the file after every write, like `O_SYNC`, which includes
+/// `O_DSYNC`.
+pub const O_SYNC: i32 = ARCH.sync | O_DSYNC;
+/// Opens a new file without a name in the given directory, like `O_TMPFILE`, which includes
+/// `O_DIRECTORY` so that kernels without it fail on the directory.
+pub const O_TMPFILE: i32 = ARCH.tmpfile | O_DIRECTORY;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let single = [O_CREAT, O_EXCL, O_NOCTTY, O_TRUNC, O_APPEND, O_NONBLOCK, O_DSYNC,
+                      O_LARGEFILE, O_DIRECTORY, O_NOFOLLOW, O_NOATIME, O_CLOEXEC];
+        let mut seen = 0;
+        for &flag in &single {
+            assert_eq!(flag.count_ones(), 1, "{:#o}", flag);
+            assert_eq!(seen & flag, 0, "{:#o}", flag);
+            seen |= flag;
+        }
+        // O_SYNC and O_TMPFILE each add one bit to the flag they include.
+        assert_eq!((O_SYNC & !O_DSYNC).count_ones(), 1);
+        assert_eq!(O_SYNC & O_DSYNC, O_DSYNC);
+        assert_eq!(seen & (O_SYNC & !O_DSYNC), 0);
+        assert_eq!((O_TMPFILE & !O_DIRECTORY).count_ones(), 1);
+        assert_eq!(O_TMPFILE & O_DIRECTORY, O_DIRECTORY);
+        assert_eq!(seen & (O_TMPFILE & !O_DIRECTORY), 0);
+
+        // The access modes are in the low two bits on every architecture.
+        assert_eq!(seen & 0o3, 0);
+
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!((O_CREAT, O_DIRECTORY, O_TMPFILE), (0o100, 0o200000, 0o20200000));
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!((O_DIRECTORY, O_NOFOLLOW, O_LARGEFILE), (0o40000, 0o100000, 0o400000));
+        #[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+        assert_eq!((O_CREAT, O_APPEND, O_SYNC), (0x100, 0x8, 0x4010));
+        #[cfg(target_arch = "sparc64")]
+        assert_eq!((O_CREAT, O_DIRECTORY, O_TMPFILE), (0x200, 0x10000, 0x2010000));
+    }
+}
@@ -36,9 +36,18 @@
 //!
 //! [`rsfs`]: ../index.html
 
-use std::io::Result;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, ErrorKind, Result};
 use std::path::Path;
 
+use fs::GenFS;
+
+#[cfg(target_os = "linux")]
+pub use self::flags::{O_APPEND, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_DSYNC, O_EXCL, O_LARGEFILE,
+                      O_NOATIME, O_NOCTTY, O_NOFOLLOW, O_NONBLOCK, O_SYNC, O_TMPFILE, O_TRUNC};
+
+pub(crate) mod flags;
+
 /// Unix specific [`rsfs::DirBuilder`] extensions.
 ///
 /// [`rsfs::DirBuilder`]: ../trait.DirBuilder.html
@@ -59,6 +68,35 @@ pub trait DirBuilderExt {
     fn mode(&mut self, mode: u32) -> &mut Self;
 }
 
+/// Unix specific [`rsfs::DirEntry`] extensions.
+///
+/// [`rsfs::DirEntry`]: ../trait.DirEntry.html
+pub trait DirEntryExt {
+    /// Returns the inode number of the entry, which is the same number that [`MetadataExt::ino`]
+    /// returns for the metadata of the entry.
+    ///
+    /// [`MetadataExt::ino`]: trait.MetadataExt.html#tymethod.ino
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_file("foo.txt")?;
+    /// for entry in fs.read_dir(".")? {
+    ///     let entry = entry?;
+    ///     assert_eq!(entry.ino(), entry.metadata()?.ino());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn ino(&self) -> u64;
+}
+
 /// Unix specific [`rsfs::File`] extensions.
 ///
 /// [`rsfs::File`]: ../trait.File.html
@@ -115,6 +153,217 @@ pub trait FileExt {
     /// # }
     /// ```
     fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize>;
+    /// Seeks to the next data or hole in a sparse file, like `lseek(2)` with `SEEK_DATA` or
+    /// `SEEK_HOLE`, and returns the new position from the start of the file.
+    ///
+    /// Every file ends in a hole at its length. Filesystems that do not track holes report a file
+    /// as all data followed by that hole.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Errors
+    ///
+    /// Seeking from an offset at or past the end of the file, or seeking to data when only holes
+    /// follow the offset, fails with `ENXIO`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let file = fs.create_file("foo.txt")?;
+    /// file.write_at(b"some bytes", 1 << 30)?;
+    ///
+    /// assert_eq!(file.seek_sparse(SparseSeek::Data(0))?, 1 << 30);
+    /// assert_eq!(file.seek_sparse(SparseSeek::Hole(1 << 30))?, (1 << 30) + 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn seek_sparse(&self, _pos: SparseSeek) -> Result<u64> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Returns the value of the extended attribute `name` of this file, like `fgetxattr(2)`.
+    ///
+    /// See [`GenFSExt::get_xattr`] for the errors this can return.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`GenFSExt::get_xattr`]: trait.GenFSExt.html#method.get_xattr
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let file = fs.create_file("foo.txt")?;
+    /// file.set_xattr("user.checksum", b"1234")?;
+    /// assert_eq!(file.get_xattr("user.checksum")?, b"1234");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_xattr<N: AsRef<OsStr>>(&self, _name: N) -> Result<Vec<u8>> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Creates or replaces the extended attribute `name` of this file, like `fsetxattr(2)`.
+    ///
+    /// See [`GenFSExt::set_xattr`] for the errors this can return.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`GenFSExt::set_xattr`]: trait.GenFSExt.html#method.set_xattr
+    fn set_xattr<N: AsRef<OsStr>>(&self, _name: N, _value: &[u8]) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Returns the names of the extended attributes of this file, like `flistxattr(2)`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    fn list_xattr(&self) -> Result<Vec<OsString>> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Removes the extended attribute `name` of this file, like `fremovexattr(2)`.
+    ///
+    /// See [`GenFSExt::remove_xattr`] for the errors this can return.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`GenFSExt::remove_xattr`]: trait.GenFSExt.html#method.remove_xattr
+    fn remove_xattr<N: AsRef<OsStr>>(&self, _name: N) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+}
+
+/// Possible methods to seek through the data and holes of a sparse file with
+/// [`FileExt::seek_sparse`].
+///
+/// [`FileExt::seek_sparse`]: trait.FileExt.html#method.seek_sparse
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SparseSeek {
+    /// Seeks to the first byte of data at or after the given offset, like `SEEK_DATA`.
+    Data(u64),
+    /// Seeks to the first byte of a hole at or after the given offset, like `SEEK_HOLE`.
+    Hole(u64),
+}
+
+/// Makes [`GenFSExt::rename_with_flags`] fail rather than replace an existing destination, like
+/// `RENAME_NOREPLACE`.
+///
+/// [`GenFSExt::rename_with_flags`]: trait.GenFSExt.html#method.rename_with_flags
+pub const RENAME_NOREPLACE: u32 = 1;
+/// Makes [`GenFSExt::rename_with_flags`] exchange its source and destination, like
+/// `RENAME_EXCHANGE`.
+///
+/// [`GenFSExt::rename_with_flags`]: trait.GenFSExt.html#method.rename_with_flags
+pub const RENAME_EXCHANGE: u32 = 2;
+
+/// Unix specific [`rsfs::FileType`] extensions.
+///
+/// [`rsfs::FileType`]: ../trait.FileType.html
+///
+/// # Examples
+///
+/// ```
+/// use rsfs::*;
+/// use rsfs::unix_ext::*;
+/// use rsfs::mem::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+///
+/// fs.mkfifo("pipe", 0o644)?;
+///
+/// let file_type = fs.metadata("pipe")?.file_type();
+/// assert!(file_type.is_fifo());
+/// assert!(!file_type.is_file());
+/// # Ok(())
+/// # }
+/// ```
+pub trait FileTypeExt {
+    /// Returns whether this file type is a block device.
+    fn is_block_device(&self) -> bool;
+    /// Returns whether this file type is a character device.
+    fn is_char_device(&self) -> bool;
+    /// Returns whether this file type is a FIFO, also known as a named pipe.
+    fn is_fifo(&self) -> bool;
+    /// Returns whether this file type is a Unix domain socket.
+    fn is_socket(&self) -> bool;
+}
+
+/// Unix specific [`rsfs::Metadata`] extensions.
+///
+/// These mirror the fields of `stat(2)`. Together, [`dev`] and [`ino`] identify a file: two paths
+/// with metadata that has the same device and inode numbers are names of the same file.
+///
+/// [`rsfs::Metadata`]: ../trait.Metadata.html
+/// [`dev`]: #tymethod.dev
+/// [`ino`]: #tymethod.ino
+///
+/// # Examples
+///
+/// ```
+/// use rsfs::*;
+/// use rsfs::unix_ext::*;
+/// use rsfs::mem::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+///
+/// fs.create_file("a.txt")?;
+/// fs.hard_link("a.txt", "b.txt")?;
+///
+/// let (a, b) = (fs.metadata("a.txt")?, fs.metadata("b.txt")?);
+/// assert_eq!((a.dev(), a.ino()), (b.dev(), b.ino()));
+/// assert_eq!(a.nlink(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub trait MetadataExt {
+    /// Returns the ID of the device containing the file.
+    fn dev(&self) -> u64;
+    /// Returns the inode number of the file, which is unique within its device.
+    fn ino(&self) -> u64;
+    /// Returns the file type and permission bits of the file, such as `0o100644` for a regular
+    /// file with permissions `0o644`.
+    fn mode(&self) -> u32;
+    /// Returns the number of hard links to the file.
+    ///
+    /// A directory has one link from its parent, one from its own `.` entry, and one from the
+    /// `..` entry of each of its subdirectories. A file that is removed while it is still open
+    /// has no links.
+    fn nlink(&self) -> u64;
+    /// Returns the user ID of the owner of the file.
+    fn uid(&self) -> u32;
+    /// Returns the group ID of the owner of the file.
+    fn gid(&self) -> u32;
+    /// Returns the device number of the file if it is a block or character device, and 0
+    /// otherwise.
+    fn rdev(&self) -> u64;
+    /// Returns the total size of the file in bytes.
+    fn size(&self) -> u64;
+    /// Returns the last access time of the file, in seconds since the Unix epoch.
+    fn atime(&self) -> i64;
+    /// Returns the nanoseconds part of the last access time of the file.
+    fn atime_nsec(&self) -> i64;
+    /// Returns the last modification time of the file, in seconds since the Unix epoch.
+    fn mtime(&self) -> i64;
+    /// Returns the nanoseconds part of the last modification time of the file.
+    fn mtime_nsec(&self) -> i64;
+    /// Returns the last status change time of the file, in seconds since the Unix epoch.
+    ///
+    /// The status of a file changes whenever its contents change and whenever its permissions,
+    /// owner, times, or links change.
+    fn ctime(&self) -> i64;
+    /// Returns the nanoseconds part of the last status change time of the file.
+    fn ctime_nsec(&self) -> i64;
+    /// Returns the block size for efficient filesystem I/O.
+    fn blksize(&self) -> u64;
+    /// Returns the number of 512 byte blocks allocated to the file.
+    fn blocks(&self) -> u64;
 }
 
 /// Unix specific [`rsfs::OpenOptions`] extensions.
@@ -150,6 +399,17 @@ pub trait OpenOptionsExt {
     /// `custom_flags` can only set flags, not remove flags set by Rust options. This option
     /// overwrites any previously set custom flags.
     ///
+    /// The disk filesystem passes flags to the host as they are. The in-memory filesystem
+    /// understands the common Linux flags, with their Linux values for the target architecture on
+    /// every host: `O_NOFOLLOW`, `O_DIRECTORY`, `O_NOATIME`, `O_SYNC`, `O_DSYNC`, `O_TMPFILE`, and
+    /// flags that change nothing in memory, such as `O_CLOEXEC`. Opening with any other flag fails
+    /// with `InvalidInput`. A file opened with `O_TMPFILE` has no name until it is given one with
+    /// [`GenFSExt::link_file`]. On Linux, this module exports these flags, such as [`O_NOFOLLOW`].
+    ///
+    /// [`O_NOFOLLOW`]: constant.O_NOFOLLOW.html
+    ///
+    /// [`GenFSExt::link_file`]: trait.GenFSExt.html#method.link_file
+    ///
     /// # Examples
     ///
     /// ```
@@ -157,14 +417,13 @@ pub trait OpenOptionsExt {
     /// use rsfs::unix_ext::*;
     /// use rsfs::mem::FS;
     ///
+    /// # #[cfg(target_os = "linux")]
     /// # fn foo() -> std::io::Result<()> {
     /// let fs = FS::new();
     /// 
     /// let mut options = fs.new_openopts();
     /// options.write(true);
-    /// if cfg!(unix) {
-    ///     options.custom_flags(0x8000); // O_NOFOLLOW (use libc in real code)
-    /// }
+    /// options.custom_flags(O_NOFOLLOW);
     /// let file = options.open("foo.txt")?;
     /// # Ok(())
     /// # }
@@ -259,4 +518,363 @@ pub trait GenFSExt {
     /// # }
     /// ```
     fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()>;
+    /// Changes the owner and group of the file at `path`, following symlinks.
+    ///
+    /// A `None` uid or gid leaves that id unchanged. This is the equivalent of
+    /// [`std::os::unix::fs::chown`].
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`std::os::unix::fs::chown`]: https://doc.rust-lang.org/std/os/unix/fs/fn.chown.html
+    ///
+    /// # Errors
+    ///
+    /// Like `chown(2)`, only root can change the owner of a file, and the owner of a file can only
+    /// change its group to a group it is in. Both fail with `EPERM`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_file("a.txt")?;
+    /// fs.chown("a.txt", Some(1000), Some(1000))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn chown<P: AsRef<Path>>(&self, _path: P, _uid: Option<u32>, _gid: Option<u32>)
+        -> Result<()>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Creates a new FIFO, also known as a named pipe, with the permission bits `mode`, like
+    /// `mkfifo(3)`.
+    ///
+    /// This is the equivalent of [`mknod`] with the FIFO file type bits `0o010000`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`mknod`]: #method.mknod
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.mkfifo("pipe", 0o600)?;
+    /// assert!(fs.metadata("pipe")?.file_type().is_fifo());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn mkfifo<P: AsRef<Path>>(&self, _path: P, _mode: u32) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Creates a new filesystem node, like `mknod(2)`.
+    ///
+    /// The file type bits of `mode` pick what to create: a regular file (`0o100000` or no type
+    /// bits), a character device (`0o020000`), a block device (`0o060000`), a FIFO (`0o010000`),
+    /// or a Unix domain socket (`0o140000`). The permission bits of `mode` are the permissions of
+    /// the new node. `dev` is the device number of a new device and is otherwise ignored.
+    ///
+    /// Symlinks at the end of `path` are not followed.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Errors
+    ///
+    /// Like Linux, this fails with `EEXIST` if `path` already exists, `EINVAL` if the file type
+    /// bits are not one of the above, and `EPERM` if a device is created by anyone but root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.mknod("null", 0o020666, 0x103)?;
+    ///
+    /// let meta = fs.metadata("null")?;
+    /// assert!(meta.file_type().is_char_device());
+    /// assert_eq!(meta.rdev(), 0x103);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn mknod<P: AsRef<Path>>(&self, _path: P, _mode: u32, _dev: u64) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Gives a name to the open `file`, like `linkat(2)` on `/proc/self/fd/N` with
+    /// `AT_SYMLINK_FOLLOW`.
+    ///
+    /// This is how a file opened with `O_TMPFILE` becomes visible once it is fully written.
+    /// Symlinks at the end of `dst` are not followed.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Errors
+    ///
+    /// Like Linux, this fails with `EEXIST` if `dst` already exists, `EXDEV` if `file` belongs to
+    /// another filesystem, and `ENOENT` if `file` has no names left, unless it was opened with
+    /// `O_TMPFILE` and without `O_EXCL` and has not been named yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # #[cfg(target_os = "linux")]
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// let file = fs.new_openopts()
+    ///              .write(true)
+    ///              .custom_flags(O_TMPFILE)
+    ///              .open("/")?;
+    /// fs.link_file(&file, "named")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn link_file<P: AsRef<Path>>(&self, _file: &<Self as GenFS>::File, _dst: P) -> Result<()>
+        where Self: GenFS
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Renames `from` to `to` with `flags`, like `renameat2(2)`.
+    ///
+    /// With no flags, this is the same as [`rsfs::GenFS::rename`]. With [`RENAME_NOREPLACE`],
+    /// an existing `to` is never replaced, and no other rename can create `to` between checking
+    /// for it and renaming. With [`RENAME_EXCHANGE`], `from` and `to` are swapped in one step, so
+    /// anyone opening either path sees one of the two entries and never neither. The two entries
+    /// may be of different types, and directories do not need to be empty.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`rsfs::GenFS::rename`]: ../trait.GenFS.html#tymethod.rename
+    /// [`RENAME_NOREPLACE`]: constant.RENAME_NOREPLACE.html
+    /// [`RENAME_EXCHANGE`]: constant.RENAME_EXCHANGE.html
+    ///
+    /// # Errors
+    ///
+    /// Besides the errors of `rename`, like Linux, this fails with:
+    ///
+    /// * `EINVAL` if `flags` has unknown bits or both flags.
+    /// * `EEXIST` if `to` exists and `flags` is `RENAME_NOREPLACE`.
+    /// * `ENOENT` if `to` does not exist and `flags` is `RENAME_EXCHANGE`.
+    ///
+    /// Whatever the flags, renaming the root directory fails with `EBUSY`, and moving a directory
+    /// into itself fails with `EINVAL`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.write("blue.conf", "blue")?;
+    /// fs.write("live.conf", "green")?;
+    /// fs.rename_with_flags("blue.conf", "live.conf", RENAME_EXCHANGE)?;
+    /// assert_eq!(fs.read("live.conf")?, b"blue");
+    /// assert_eq!(fs.read("blue.conf")?, b"green");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn rename_with_flags<P: AsRef<Path>, Q: AsRef<Path>>(&self, _from: P, _to: Q, _flags: u32)
+        -> Result<()>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Returns the value of the extended attribute `name` of the file at `path`, following
+    /// symlinks, like `getxattr(2)`.
+    ///
+    /// Attribute names start with their namespace: `user.`, `trusted.`, `security.` or
+    /// `system.`. Extended attributes belong to a file rather than to a name, so hard links share
+    /// them.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Errors
+    ///
+    /// Like Linux, this fails with:
+    ///
+    /// * `ENODATA` if the file has no attribute `name`, or if `name` is in the `user.` namespace
+    ///   and the file is neither a regular file nor a directory, or if `name` is in the
+    ///   `trusted.` namespace and the caller is not privileged.
+    /// * `ERANGE` if `name` is empty or longer than 255 bytes.
+    /// * `EOPNOTSUPP` if the namespace of `name` is not supported.
+    /// * `EACCES` if `name` is in the `user.` namespace and the file is not readable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_file("a.txt")?;
+    /// fs.set_xattr("a.txt", "user.origin", b"https://example.com")?;
+    /// assert_eq!(fs.get_xattr("a.txt", "user.origin")?, b"https://example.com");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, _path: P, _name: N)
+        -> Result<Vec<u8>>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Creates or replaces the extended attribute `name` of the file at `path`, following
+    /// symlinks, like `setxattr(2)`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Errors
+    ///
+    /// Like Linux, this fails with:
+    ///
+    /// * `ERANGE` if `name` is empty or longer than 255 bytes.
+    /// * `E2BIG` if `value` is longer than 65536 bytes.
+    /// * `EOPNOTSUPP` if the namespace of `name` is not supported.
+    /// * `EPERM` if `name` is in the `user.` namespace and the file is neither a regular file nor
+    ///   a directory, or is a sticky directory the caller does not own, or if `name` is in the
+    ///   `trusted.` or `security.` namespace and the caller is not privileged.
+    /// * `EACCES` if `name` is in the `user.` namespace and the file is not writable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_file("a.txt")?;
+    /// fs.hard_link("a.txt", "b.txt")?;
+    /// fs.set_xattr("a.txt", "user.checksum", b"1234")?;
+    /// assert_eq!(fs.get_xattr("b.txt", "user.checksum")?, b"1234");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, _path: P, _name: N, _value: &[u8])
+        -> Result<()>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Returns the names of the extended attributes of the file at `path`, following symlinks,
+    /// like `listxattr(2)`.
+    ///
+    /// Attributes in the `trusted.` namespace are only listed for privileged callers.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_file("a.txt")?;
+    /// fs.set_xattr("a.txt", "user.checksum", b"1234")?;
+    /// assert_eq!(fs.list_xattr("a.txt")?, vec!["user.checksum"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn list_xattr<P: AsRef<Path>>(&self, _path: P) -> Result<Vec<OsString>> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Removes the extended attribute `name` of the file at `path`, following symlinks, like
+    /// `removexattr(2)`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// # Errors
+    ///
+    /// This fails with `ENODATA` if the file has no attribute `name`, and otherwise fails like
+    /// [`set_xattr`].
+    ///
+    /// [`set_xattr`]: #method.set_xattr
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::unix_ext::*;
+    /// use rsfs::mem::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.create_file("a.txt")?;
+    /// fs.set_xattr("a.txt", "user.checksum", b"1234")?;
+    /// fs.remove_xattr("a.txt", "user.checksum")?;
+    /// assert!(fs.get_xattr("a.txt", "user.checksum").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, _path: P, _name: N) -> Result<()> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Like [`get_xattr`], but does not follow a symlink at the end of `path`, like
+    /// `lgetxattr(2)`.
+    ///
+    /// Symlinks cannot have attributes in the `user.` namespace.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`get_xattr`]: #method.get_xattr
+    fn symlink_get_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, _path: P, _name: N)
+        -> Result<Vec<u8>>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Like [`set_xattr`], but does not follow a symlink at the end of `path`, like
+    /// `lsetxattr(2)`.
+    ///
+    /// Setting an attribute in the `user.` namespace on a symlink fails with `EPERM`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`set_xattr`]: #method.set_xattr
+    fn symlink_set_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, _path: P, _name: N,
+                                                          _value: &[u8]) -> Result<()>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Like [`list_xattr`], but does not follow a symlink at the end of `path`, like
+    /// `llistxattr(2)`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`list_xattr`]: #method.list_xattr
+    fn symlink_list_xattr<P: AsRef<Path>>(&self, _path: P) -> Result<Vec<OsString>> {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
+    /// Like [`remove_xattr`], but does not follow a symlink at the end of `path`, like
+    /// `lremovexattr(2)`.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`remove_xattr`]: #method.remove_xattr
+    fn symlink_remove_xattr<P: AsRef<Path>, N: AsRef<OsStr>>(&self, _path: P, _name: N)
+        -> Result<()>
+    {
+        Err(io::Error::from(ErrorKind::Unsupported))
+    }
 }
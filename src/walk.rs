@@ -0,0 +1,493 @@
+//! Recursively walking a directory on any [`GenFS`].
+//!
+//! [`walk_dir`] returns a [`WalkDir`] iterator that yields every entry below a root path, root
+//! included. Options on the `WalkDir` control how deep to walk, whether to follow symlinks, the
+//! order entries are yielded in, and which subtrees to skip. Every error is an [`Error`] that
+//! names the path that failed.
+//!
+//! [`GenFS`]: ../trait.GenFS.html
+//! [`walk_dir`]: fn.walk_dir.html
+//! [`WalkDir`]: struct.WalkDir.html
+//! [`Error`]: struct.Error.html
+//!
+//! # Example
+//!
+//! ```
+//! use std::path::PathBuf;
+//!
+//! use rsfs::*;
+//! use rsfs::mem::FS;
+//! # fn foo() -> std::io::Result<()> {
+//! let fs = FS::new();
+//! fs.create_dir_all("a/b")?;
+//! fs.create_file("a/f")?;
+//!
+//! let paths = walk_dir(&fs, "a").sort_by_file_name()
+//!                               .map(|ent| ent.map(|ent| ent.into_path()))
+//!                               .collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(paths, vec![PathBuf::from("a"), PathBuf::from("a/b"), PathBuf::from("a/f")]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp::Ordering;
+use std::error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::vec::IntoIter;
+
+use errors::ELOOP;
+use fs::{DirEntry, FileType, GenFS, Metadata};
+
+/// Returns an iterator that recursively walks `root` on `fs`, yielding `root` itself first.
+///
+/// Every yielded path is `root` joined with the names below it. See [`WalkDir`] for the options.
+///
+/// [`WalkDir`]: walk/struct.WalkDir.html
+///
+/// # Examples
+///
+/// ```
+/// use rsfs::*;
+/// use rsfs::mem::FS;
+/// # fn foo() -> std::io::Result<()> {
+/// let fs = FS::new();
+/// fs.create_dir_all("a/b/c")?;
+///
+/// for ent in walk_dir(&fs, "a") {
+///     println!("{}", ent?.path().display());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn walk_dir<'a, F: GenFS, P: AsRef<Path>>(fs: &'a F, root: P) -> WalkDir<'a, F> {
+    WalkDir {
+        fs,
+        root:           Some(root.as_ref().to_path_buf()),
+        min_depth:      0,
+        max_depth:      usize::MAX,
+        follow_links:   false,
+        contents_first: false,
+        sort:           None,
+        filter:         None,
+        stack:          Vec::new(),
+    }
+}
+
+/// An entry yielded by [`WalkDir`].
+///
+/// [`WalkDir`]: struct.WalkDir.html
+#[derive(Clone, Debug)]
+pub struct Entry<M> {
+    path:     PathBuf,
+    depth:    usize,
+    metadata: M,
+    followed: bool,
+}
+
+impl<M: Metadata> Entry<M> {
+    /// Returns the path of this entry, which is the walked root joined with the names below it.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of this entry, consuming the entry.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Returns the file name of this entry, or the whole path for a root that has no file name.
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or_else(|| self.path.as_os_str())
+    }
+
+    /// Returns how far below the root this entry is; the root has depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the metadata of this entry, which is of the symlink's target if the entry is a
+    /// followed symlink.
+    pub fn metadata(&self) -> &M {
+        &self.metadata
+    }
+
+    /// Returns the file type of this entry, which is of the symlink's target if the entry is a
+    /// followed symlink.
+    pub fn file_type(&self) -> M::FileType {
+        self.metadata.file_type()
+    }
+
+    /// Returns whether this entry is a symlink that was followed.
+    pub fn path_is_symlink(&self) -> bool {
+        self.followed
+    }
+}
+
+/// An error encountered while walking, along with the path that failed.
+#[derive(Debug)]
+pub struct Error {
+    path:     PathBuf,
+    depth:    usize,
+    ancestor: Option<PathBuf>,
+    err:      io::Error,
+}
+
+impl Error {
+    // new wraps err as a failure at path.
+    pub(crate) fn new(path: &Path, depth: usize, err: io::Error) -> Error {
+        Error { path: path.to_path_buf(), depth, ancestor: None, err }
+    }
+
+    /// Returns the path that failed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the depth of the path that failed.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the ancestor that a followed symlink points back to if this error is a symlink
+    /// loop.
+    pub fn loop_ancestor(&self) -> Option<&Path> {
+        self.ancestor.as_deref()
+    }
+
+    /// Returns the underlying I/O error, which is `ELOOP` for a symlink loop.
+    pub fn io_error(&self) -> &io::Error {
+        &self.err
+    }
+
+    /// Returns the underlying I/O error, consuming this error.
+    pub fn into_io_error(self) -> io::Error {
+        self.err
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ancestor {
+            Some(ref ancestor) => write!(f, "symlink loop: {} points to ancestor {}",
+                                         self.path.display(), ancestor.display()),
+            None => write!(f, "{}: {}", self.path.display(), self.err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+impl From<Error> for io::Error {
+    /// Converts to an I/O error of the same kind that still names the failed path.
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.err.kind(), err)
+    }
+}
+
+type Sort<'a, M> = Box<dyn FnMut(&Entry<M>, &Entry<M>) -> Ordering + 'a>;
+type Filter<'a, M> = Box<dyn FnMut(&Entry<M>) -> bool + 'a>;
+
+// A directory being walked.
+struct Frame<M> {
+    entries:   IntoIter<Result<Entry<M>, Error>>,
+    // dir is yielded once entries run out if walking contents first.
+    dir:       Entry<M>,
+    // canonical is the resolved path of the directory, used to detect loops when following links.
+    canonical: Option<PathBuf>,
+}
+
+/// An iterator that recursively walks a directory, returned from [`walk_dir`].
+///
+/// Options are set by chaining methods before iterating. By default, every entry is yielded in
+/// the order returned from `read_dir`, every directory is yielded before its contents, and
+/// symlinks are not followed, not even a symlink root.
+///
+/// If a directory cannot be read, the directory is still yielded and the error follows it.
+///
+/// [`walk_dir`]: ../fn.walk_dir.html
+pub struct WalkDir<'a, F: GenFS + 'a> {
+    fs:             &'a F,
+    root:           Option<PathBuf>,
+    min_depth:      usize,
+    max_depth:      usize,
+    follow_links:   bool,
+    contents_first: bool,
+    sort:           Option<Sort<'a, F::Metadata>>,
+    filter:         Option<Filter<'a, F::Metadata>>,
+    stack:          Vec<Frame<F::Metadata>>,
+}
+
+impl<'a, F: GenFS + 'a> fmt::Debug for WalkDir<'a, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WalkDir")
+         .field("root", &self.root)
+         .field("min_depth", &self.min_depth)
+         .field("max_depth", &self.max_depth)
+         .field("follow_links", &self.follow_links)
+         .field("contents_first", &self.contents_first)
+         .field("sorted", &self.sort.is_some())
+         .field("filtered", &self.filter.is_some())
+         .finish()
+    }
+}
+
+impl<'a, F: GenFS + 'a> WalkDir<'a, F> {
+    /// Only yields entries at least `depth` below the root. Shallower directories are still
+    /// walked.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth; self
+    }
+
+    /// Does not walk more than `depth` below the root. A depth of 0 yields only the root.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth; self
+    }
+
+    /// Follows symlinks, including a symlink root, walking into the directories they point to.
+    ///
+    /// A symlink that points to one of its own ancestors is yielded as an [`Error`] with a
+    /// [`loop_ancestor`] rather than walked forever. Loops are detected by comparing canonical
+    /// paths.
+    ///
+    /// [`Error`]: struct.Error.html
+    /// [`loop_ancestor`]: struct.Error.html#method.loop_ancestor
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow; self
+    }
+
+    /// Yields the contents of every directory before the directory itself (post-order).
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.contents_first = contents_first; self
+    }
+
+    /// Yields the entries of every directory sorted by `cmp`. Errors from reading entries are
+    /// yielded before any entry of the same directory.
+    pub fn sort_by<C>(mut self, cmp: C) -> Self
+        where C: FnMut(&Entry<F::Metadata>, &Entry<F::Metadata>) -> Ordering + 'a
+    {
+        self.sort = Some(Box::new(cmp)); self
+    }
+
+    /// Yields the entries of every directory sorted by file name.
+    pub fn sort_by_file_name(self) -> Self {
+        self.sort_by(|l, r| l.file_name().cmp(r.file_name()))
+    }
+
+    /// Skips entries for which `predicate` returns false. A skipped directory is not walked,
+    /// pruning its entire subtree.
+    pub fn filter_entry<P>(mut self, predicate: P) -> Self
+        where P: FnMut(&Entry<F::Metadata>) -> bool + 'a
+    {
+        self.filter = Some(Box::new(predicate)); self
+    }
+
+    // entry stats path, following it if it is a symlink and we follow links.
+    fn entry(&self, path: PathBuf, depth: usize, metadata: F::Metadata)
+        -> Result<Entry<F::Metadata>, Error>
+    {
+        if !self.follow_links || !metadata.file_type().is_symlink() {
+            return Ok(Entry { path, depth, metadata, followed: false });
+        }
+        match self.fs.metadata(&path) {
+            Ok(metadata) => Ok(Entry { path, depth, metadata, followed: true }),
+            Err(e) => Err(Error::new(&path, depth, e)),
+        }
+    }
+
+    // read lists the entries of the directory dir.
+    fn read(&mut self, dir: &Entry<F::Metadata>) -> Vec<Result<Entry<F::Metadata>, Error>> {
+        let depth = dir.depth + 1;
+        let read_dir = match self.fs.read_dir(&dir.path) {
+            Ok(read_dir) => read_dir,
+            Err(e) => return vec![Err(Error::new(&dir.path, dir.depth, e))],
+        };
+        let (mut entries, mut errors) = (Vec::new(), Vec::new());
+        for de in read_dir {
+            let ent = de.map_err(|e| Error::new(&dir.path, dir.depth, e)).and_then(|de| {
+                let path = dir.path.join(de.file_name());
+                match self.fs.symlink_metadata(&path) {
+                    Ok(metadata) => self.entry(path, depth, metadata),
+                    Err(e) => Err(Error::new(&path, depth, e)),
+                }
+            });
+            match ent {
+                Ok(ent) => entries.push(ent),
+                Err(e) => errors.push(Err(e)),
+            }
+        }
+        if let Some(ref mut sort) = self.sort {
+            entries.sort_by(|l, r| sort(l, r));
+        }
+        errors.extend(entries.into_iter().map(Ok));
+        errors
+    }
+
+    // handle walks into ent if it is a directory and returns it if it should be yielded now.
+    fn handle(&mut self, ent: Entry<F::Metadata>) -> Option<Result<Entry<F::Metadata>, Error>> {
+        if let Some(ref mut filter) = self.filter {
+            if !filter(&ent) {
+                return None;
+            }
+        }
+        if !ent.metadata.is_dir() || ent.depth >= self.max_depth {
+            return if ent.depth >= self.min_depth { Some(Ok(ent)) } else { None };
+        }
+
+        let mut canonical = None;
+        if self.follow_links {
+            match self.fs.canonicalize(&ent.path) {
+                Ok(path) => canonical = Some(path),
+                Err(e) => return Some(Err(Error::new(&ent.path, ent.depth, e))),
+            }
+            if let Some(frame) = self.stack.iter().find(|frame| frame.canonical == canonical) {
+                return Some(Err(Error {
+                    path:     ent.path,
+                    depth:    ent.depth,
+                    ancestor: Some(frame.dir.path.clone()),
+                    err:      ELOOP(),
+                }));
+            }
+        }
+
+        let entries = self.read(&ent).into_iter();
+        self.stack.push(Frame { entries, dir: ent.clone(), canonical });
+        if ent.depth >= self.min_depth && !self.contents_first { Some(Ok(ent)) } else { None }
+    }
+}
+
+impl<'a, F: GenFS + 'a> Iterator for WalkDir<'a, F> {
+    type Item = Result<Entry<F::Metadata>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            let ent = match self.fs.symlink_metadata(&root) {
+                Ok(metadata) => self.entry(root, 0, metadata),
+                Err(e) => Err(Error::new(&root, 0, e)),
+            };
+            match ent {
+                Ok(ent) => if let Some(next) = self.handle(ent) {
+                    return Some(next);
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        while !self.stack.is_empty() {
+            let next = self.stack.last_mut().and_then(|frame| frame.entries.next());
+            match next {
+                Some(Ok(ent)) => if let Some(next) = self.handle(ent) {
+                    return Some(next);
+                },
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    let dir = self.stack.pop().expect("stack is not empty").dir;
+                    if self.contents_first && dir.depth >= self.min_depth {
+                        return Some(Ok(dir));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use errors::{EACCES, ELOOP, ENOENT};
+    use fs::{GenFS, Metadata};
+    use mem::unix::{FS, Permissions};
+    use unix_ext::{GenFSExt, PermissionsExt};
+
+    use super::{walk_dir, WalkDir};
+
+    fn paths(walk: WalkDir<FS>) -> Vec<String> {
+        walk.map(|ent| ent.unwrap().path().to_string_lossy().into_owned()).collect()
+    }
+
+    fn tree() -> FS {
+        let fs = FS::new();
+        assert!(fs.create_dir_all("/r/a/b").is_ok());
+        assert!(fs.create_file("/r/a/b/f").is_ok());
+        assert!(fs.create_file("/r/a/g").is_ok());
+        assert!(fs.create_dir("/r/c").is_ok());
+        assert!(fs.symlink("a", "/r/l").is_ok());
+        fs
+    }
+
+    #[test]
+    fn order() {
+        let fs = tree();
+        assert_eq!(paths(walk_dir(&fs, "/r").sort_by_file_name()),
+                   vec!["/r", "/r/a", "/r/a/b", "/r/a/b/f", "/r/a/g", "/r/c", "/r/l"]);
+        assert_eq!(paths(walk_dir(&fs, "/r").sort_by_file_name().contents_first(true)),
+                   vec!["/r/a/b/f", "/r/a/b", "/r/a/g", "/r/a", "/r/c", "/r/l", "/r"]);
+        assert_eq!(paths(walk_dir(&fs, "/r").sort_by(|l, r| r.file_name().cmp(l.file_name()))),
+                   vec!["/r", "/r/l", "/r/c", "/r/a", "/r/a/g", "/r/a/b", "/r/a/b/f"]);
+
+        let ent = walk_dir(&fs, "/r").sort_by_file_name().nth(3).unwrap().unwrap();
+        assert_eq!((ent.depth(), ent.file_name().to_str()), (3, Some("f")));
+        assert!(ent.metadata().is_file());
+    }
+
+    #[test]
+    fn depth_and_filter() {
+        let fs = tree();
+        assert_eq!(paths(walk_dir(&fs, "/r").sort_by_file_name().min_depth(2).max_depth(2)),
+                   vec!["/r/a/b", "/r/a/g"]);
+        assert_eq!(paths(walk_dir(&fs, "/r").max_depth(0)), vec!["/r"]);
+        assert_eq!(paths(walk_dir(&fs, "/r").sort_by_file_name()
+                                           .contents_first(true)
+                                           .filter_entry(|ent| ent.file_name() != "a")),
+                   vec!["/r/c", "/r/l", "/r"]);
+    }
+
+    #[test]
+    fn follow_links() {
+        let fs = tree();
+        assert_eq!(paths(walk_dir(&fs, "/r/l")), vec!["/r/l"]);
+        assert_eq!(paths(walk_dir(&fs, "/r/l").sort_by_file_name().follow_links(true)),
+                   vec!["/r/l", "/r/l/b", "/r/l/b/f", "/r/l/g"]);
+
+        // A link back to an ancestor is an error instead of a cycle, and walking continues.
+        assert!(fs.symlink("..", "/r/a/b/up").is_ok());
+        let walked: Vec<_> = walk_dir(&fs, "/r/a").sort_by_file_name().follow_links(true).collect();
+        assert_eq!(walked.len(), 5);
+        let err = walked[3].as_ref().unwrap_err();
+        assert_eq!(err.path(), Path::new("/r/a/b/up"));
+        assert_eq!(err.loop_ancestor(), Some(Path::new("/r/a")));
+        assert_eq!(err.io_error().raw_os_error(), ELOOP().raw_os_error());
+        assert_eq!(walked[4].as_ref().unwrap().path(), Path::new("/r/a/g"));
+
+        assert!(fs.symlink("missing", "/r/c/dangling").is_ok());
+        let err = walk_dir(&fs, "/r/c").follow_links(true).nth(1).unwrap().unwrap_err();
+        assert_eq!(err.path(), Path::new("/r/c/dangling"));
+        assert_eq!(err.into_io_error().raw_os_error(), ENOENT().raw_os_error());
+    }
+
+    #[test]
+    fn errors() {
+        let fs = tree();
+        assert!(fs.set_permissions("/r/a", Permissions::from_mode(0o300)).is_ok());
+        let walked: Vec<_> = walk_dir(&fs, "/r").sort_by_file_name().collect();
+        assert_eq!(walked[1].as_ref().unwrap().path(), Path::new("/r/a"));
+        let err = walked[2].as_ref().unwrap_err();
+        assert_eq!((err.path(), err.depth()), (Path::new("/r/a"), 1));
+        assert_eq!(err.io_error().raw_os_error(), EACCES().raw_os_error());
+        assert_eq!(walked.len(), 5);
+
+        let err = walk_dir(&fs, "/missing").next().unwrap().unwrap_err();
+        assert_eq!(err.path(), PathBuf::from("/missing"));
+        let err: ::std::io::Error = err.into();
+        assert!(err.to_string().starts_with("/missing: "));
+        assert!(walk_dir(&fs, "/missing").nth(1).is_none());
+    }
+}
@@ -0,0 +1,109 @@
+//! Windows specific traits that extend the traits in [`rsfs`].
+//!
+//! These traits are separate from `rsfs` traits to ensure users of these traits opt-in to Windows
+//! specific functionality.
+//!
+//! # Examples
+//!
+//! Windows differentiates between symlinks to files and symlinks to directories:
+//!
+//! ```
+//! use rsfs::*;
+//! use rsfs::windows_ext::*;
+//! use rsfs::mem::windows::FS;
+//! # fn foo() -> std::io::Result<()> {
+//! let fs = FS::new();
+//!
+//! fs.create_dir(r"C:\dir")?;
+//! fs.symlink_dir(r"C:\dir", r"C:\link")?;
+//! assert!(fs.symlink_metadata(r"C:\link")?.file_type().is_symlink_dir());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`rsfs`]: ../index.html
+
+use std::io::Result;
+use std::path::Path;
+
+/// Windows specific [`rsfs::FileType`] extensions.
+///
+/// [`rsfs::FileType`]: ../trait.FileType.html
+pub trait FileTypeExt {
+    /// Returns `true` if this file type is a symbolic link that is also a directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::windows_ext::*;
+    /// use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.symlink_dir("a", "b")?;
+    /// assert!(fs.symlink_metadata("b")?.file_type().is_symlink_dir());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn is_symlink_dir(&self) -> bool;
+    /// Returns `true` if this file type is a symbolic link that is also a file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::windows_ext::*;
+    /// use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.symlink_file("a.txt", "b.txt")?;
+    /// assert!(fs.symlink_metadata("b.txt")?.file_type().is_symlink_file());
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn is_symlink_file(&self) -> bool;
+}
+
+/// Windows specific [`rsfs::GenFS`] extensions.
+///
+/// [`rsfs::GenFS`]: ../trait.GenFS.html
+pub trait GenFSExt {
+    /// Creates a new file symbolic link on the filesystem.
+    ///
+    /// The `dst` path will be a file symbolic link pointing to the `src` path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::windows_ext::*;
+    /// use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.symlink_file("a.txt", "b.txt")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn symlink_file<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()>;
+    /// Creates a new directory symbolic link on the filesystem.
+    ///
+    /// The `dst` path will be a directory symbolic link pointing to the `src` path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rsfs::*;
+    /// use rsfs::windows_ext::*;
+    /// use rsfs::mem::windows::FS;
+    /// # fn foo() -> std::io::Result<()> {
+    /// let fs = FS::new();
+    ///
+    /// fs.symlink_dir("a", "b")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn symlink_dir<P: AsRef<Path>, Q: AsRef<Path>>(&self, src: P, dst: Q) -> Result<()>;
+}